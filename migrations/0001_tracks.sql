@@ -0,0 +1,20 @@
+CREATE TABLE tracks (
+    id BIGSERIAL PRIMARY KEY,
+    channel_id BIGINT NOT NULL,
+    message_id INTEGER NOT NULL,
+    file_id TEXT NOT NULL,
+    file_unique_id TEXT NOT NULL,
+    title TEXT,
+    performer TEXT,
+    file_name TEXT,
+    duration_secs INTEGER NOT NULL,
+    file_size BIGINT,
+    series TEXT NOT NULL,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    caption TEXT NOT NULL,
+    posted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (channel_id, message_id)
+);
+
+CREATE INDEX tracks_posted_at_idx ON tracks (posted_at);
+CREATE INDEX tracks_tags_idx ON tracks USING GIN (tags);
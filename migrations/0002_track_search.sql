@@ -0,0 +1,29 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+ALTER TABLE tracks ADD COLUMN album TEXT;
+
+-- array_to_string is only STABLE, but is immutable for TEXT[] which is all we use it for.
+CREATE FUNCTION tags_to_text(tags TEXT[]) RETURNS TEXT
+    LANGUAGE sql IMMUTABLE PARALLEL SAFE
+    AS $$ SELECT array_to_string(tags, ' ') $$;
+
+-- The 'simple' configuration avoids language-specific stemming, since titles mix
+-- Cyrillic and Latin scripts.
+ALTER TABLE tracks ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(performer, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(album, '')), 'B') ||
+    setweight(to_tsvector('simple', tags_to_text(tags)), 'B') ||
+    setweight(to_tsvector('simple', caption), 'C')
+) STORED;
+
+ALTER TABLE tracks ADD COLUMN search_text TEXT GENERATED ALWAYS AS (
+    lower(
+        coalesce(title, '') || ' ' ||
+        coalesce(performer, '') || ' ' ||
+        coalesce(album, '') || ' ' ||
+        tags_to_text(tags)
+    )
+) STORED;
+
+CREATE INDEX tracks_search_vector_idx ON tracks USING GIN (search_vector);
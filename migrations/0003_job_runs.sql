@@ -0,0 +1,6 @@
+CREATE TABLE job_runs (
+    job TEXT NOT NULL,
+    run_date DATE NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (job, run_date)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE failed_items (
+    id BIGSERIAL PRIMARY KEY,
+    message_id INTEGER NOT NULL,
+    audio JSONB NOT NULL,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    error TEXT NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,13 @@
+CREATE TABLE releases (
+    id BIGSERIAL PRIMARY KEY,
+    channel_id BIGINT NOT NULL,
+    message_id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    performer TEXT,
+    year INTEGER,
+    posted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (channel_id, message_id)
+);
+
+ALTER TABLE tracks ADD COLUMN release_id BIGINT REFERENCES releases (id) ON DELETE SET NULL;
+CREATE INDEX tracks_release_id_idx ON tracks (release_id);
@@ -0,0 +1,11 @@
+CREATE TABLE intruders (
+    user_id BIGINT PRIMARY KEY,
+    username TEXT,
+    full_name TEXT NOT NULL,
+    first_text TEXT,
+    attempts INTEGER NOT NULL DEFAULT 1,
+    first_seen TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX intruders_last_seen ON intruders (last_seen DESC);
@@ -0,0 +1,4 @@
+CREATE TABLE blocked_users (
+    user_id BIGINT PRIMARY KEY,
+    blocked_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+CREATE TABLE digest_subscribers (
+    chat_id BIGINT PRIMARY KEY,
+    subscribed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
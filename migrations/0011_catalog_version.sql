@@ -0,0 +1,23 @@
+-- A single row bumped whenever the catalog changes, so HTTP responses can be
+-- revalidated with one cheap lookup.
+CREATE TABLE catalog_version (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO catalog_version DEFAULT VALUES;
+
+CREATE FUNCTION touch_catalog_version() RETURNS trigger AS $$
+BEGIN
+    UPDATE catalog_version SET updated_at = clock_timestamp();
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER tracks_touch_catalog_version
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON tracks
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_catalog_version();
+
+CREATE TRIGGER releases_touch_catalog_version
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON releases
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_catalog_version();
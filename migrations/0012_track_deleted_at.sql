@@ -0,0 +1,3 @@
+-- Set when reconciliation finds the channel post gone. Deleted tracks stay for the
+-- record but are left out of listings, search and the jobs that link to posts.
+ALTER TABLE tracks ADD COLUMN deleted_at TIMESTAMPTZ;
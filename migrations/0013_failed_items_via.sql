@@ -0,0 +1 @@
+ALTER TABLE failed_items ADD COLUMN via JSONB;
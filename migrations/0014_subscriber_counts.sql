@@ -0,0 +1,10 @@
+CREATE TABLE subscriber_counts (
+    counted_on DATE PRIMARY KEY,
+    count INTEGER NOT NULL,
+    counted_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE subscriber_milestones (
+    milestone INTEGER PRIMARY KEY,
+    reached_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,19 @@
+-- Posts are numbered within their series, independently of Telegram message ids.
+ALTER TABLE tracks ADD COLUMN number INTEGER;
+
+UPDATE tracks SET number = numbered.number
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY series ORDER BY posted_at, id) AS number
+    FROM tracks
+) AS numbered
+WHERE tracks.id = numbered.id;
+
+CREATE UNIQUE INDEX tracks_series_number_idx ON tracks (series, number);
+
+CREATE TABLE series_counters (
+    series TEXT PRIMARY KEY,
+    next_number INTEGER NOT NULL
+);
+
+INSERT INTO series_counters (series, next_number)
+SELECT series, MAX(number) + 1 FROM tracks GROUP BY series;
@@ -0,0 +1,12 @@
+-- The latest anonymous reaction counts on channel posts, keyed by emoji, or by
+-- "custom:<id>" and "paid" for the other kinds.
+CREATE TABLE post_reactions (
+    channel_id BIGINT NOT NULL,
+    message_id INTEGER NOT NULL,
+    reaction TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (channel_id, message_id, reaction)
+);
+
+ALTER TABLE tracks ADD COLUMN reaction_count INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX tracks_reaction_count_idx ON tracks (reaction_count);
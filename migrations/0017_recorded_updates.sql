@@ -0,0 +1,11 @@
+-- Webhook updates as received, kept for RECORD_UPDATES days so they can be replayed.
+-- Telegram may reuse update ids after a quiet week, so rows get their own id.
+CREATE TABLE recorded_updates (
+    id BIGSERIAL PRIMARY KEY,
+    update_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX recorded_updates_received_at_idx ON recorded_updates (received_at);
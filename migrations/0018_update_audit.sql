@@ -0,0 +1,14 @@
+-- One row per update the webhook handled, whether or not anything came of it.
+CREATE TABLE update_audit (
+    id BIGSERIAL PRIMARY KEY,
+    update_id BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    chat_id BIGINT,
+    user_id BIGINT,
+    -- File name, title or the start of the text, to find an update again.
+    detail TEXT,
+    error TEXT,
+    handled_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX update_audit_handled_at_idx ON update_audit (handled_at);
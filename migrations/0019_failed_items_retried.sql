@@ -0,0 +1,2 @@
+-- Retried items are kept, marked, so /errors can still show them.
+ALTER TABLE failed_items ADD COLUMN retried_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- Tags accepted from AcoustID, so a retried item keeps them.
+ALTER TABLE failed_items ADD COLUMN retag JSONB;
@@ -0,0 +1,2 @@
+-- The caption text of a queued track, so a retried item keeps it.
+ALTER TABLE failed_items ADD COLUMN description TEXT;
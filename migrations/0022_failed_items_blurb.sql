@@ -0,0 +1,2 @@
+-- The accepted blurb of a queued track, so a retried item keeps it.
+ALTER TABLE failed_items ADD COLUMN blurb TEXT;
@@ -0,0 +1,3 @@
+-- Tempo and key estimated from the audio when it was posted.
+ALTER TABLE tracks ADD COLUMN bpm INTEGER;
+ALTER TABLE tracks ADD COLUMN musical_key TEXT;
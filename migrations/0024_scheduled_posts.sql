@@ -0,0 +1,11 @@
+-- Text and photo posts set to go out at a given time, next to the audio queue.
+CREATE TABLE scheduled_posts (
+    id BIGSERIAL PRIMARY KEY,
+    post_at TIMESTAMPTZ NOT NULL,
+    -- MarkdownV2; the caption when there is a photo.
+    text TEXT NOT NULL,
+    photo_file_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX scheduled_posts_post_at ON scheduled_posts (post_at);
@@ -0,0 +1,14 @@
+-- Copies of channel posts in the MIRROR_CHANNELS, one row per post and mirror.
+CREATE TABLE mirror_copies (
+    channel_id BIGINT NOT NULL,
+    message_id INTEGER NOT NULL,
+    mirror_id BIGINT NOT NULL,
+    -- Set once the copy went out.
+    mirror_message_id INTEGER,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (channel_id, message_id, mirror_id)
+);
+
+CREATE INDEX mirror_copies_pending ON mirror_copies (mirror_id) WHERE mirror_message_id IS NULL;
@@ -0,0 +1,19 @@
+-- Invite links made with /invite, and who joined the channel through which link.
+CREATE TABLE invite_links (
+    invite_link TEXT PRIMARY KEY,
+    name TEXT,
+    member_limit INTEGER,
+    expire_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE TABLE channel_joins (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    -- NULL for joins without a link, e.g. through the public username.
+    invite_link TEXT,
+    joined_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX channel_joins_invite_link ON channel_joins (invite_link);
@@ -0,0 +1,10 @@
+-- Requests to join the channel and what became of them, by rule or by the owner.
+CREATE TABLE join_decisions (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    invite_link TEXT,
+    approved BOOLEAN NOT NULL,
+    -- "owner", or the auto-approval rule, e.g. "rule lang:uk".
+    decided_by TEXT NOT NULL,
+    decided_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
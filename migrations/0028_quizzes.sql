@@ -0,0 +1,16 @@
+-- Quiz polls posted with /quiz, with their latest vote counts.
+CREATE TABLE quizzes (
+    id BIGSERIAL PRIMARY KEY,
+    poll_id TEXT NOT NULL UNIQUE,
+    chat_id BIGINT NOT NULL,
+    message_id INTEGER NOT NULL,
+    track_id BIGINT NOT NULL,
+    -- "artist" or "year".
+    kind TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    total_votes INTEGER NOT NULL DEFAULT 0,
+    correct_votes INTEGER NOT NULL DEFAULT 0,
+    reveal_at TIMESTAMPTZ NOT NULL,
+    revealed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
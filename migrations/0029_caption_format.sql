@@ -0,0 +1,2 @@
+-- The parse mode each caption is written in, so it can be sent again as it was.
+ALTER TABLE tracks ADD COLUMN caption_format TEXT NOT NULL DEFAULT 'MarkdownV2';
@@ -0,0 +1,30 @@
+-- Cyrillic titles and performers spelled in Latin letters, with TRANSLITERATE set.
+ALTER TABLE tracks ADD COLUMN title_latin TEXT;
+ALTER TABLE tracks ADD COLUMN performer_latin TEXT;
+
+-- Generated columns can't be altered, so search is rebuilt to take them in.
+ALTER TABLE tracks DROP COLUMN search_vector;
+ALTER TABLE tracks DROP COLUMN search_text;
+
+ALTER TABLE tracks ADD COLUMN search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('simple', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(performer, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(title_latin, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(performer_latin, '')), 'A') ||
+    setweight(to_tsvector('simple', coalesce(album, '')), 'B') ||
+    setweight(to_tsvector('simple', tags_to_text(tags)), 'B') ||
+    setweight(to_tsvector('simple', caption), 'C')
+) STORED;
+
+ALTER TABLE tracks ADD COLUMN search_text TEXT GENERATED ALWAYS AS (
+    lower(
+        coalesce(title, '') || ' ' ||
+        coalesce(performer, '') || ' ' ||
+        coalesce(title_latin, '') || ' ' ||
+        coalesce(performer_latin, '') || ' ' ||
+        coalesce(album, '') || ' ' ||
+        tags_to_text(tags)
+    )
+) STORED;
+
+CREATE INDEX tracks_search_vector_idx ON tracks USING GIN (search_vector);
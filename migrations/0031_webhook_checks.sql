@@ -0,0 +1,7 @@
+-- What each webhook check saw, kept for a week to tell a growing backlog from a blip.
+CREATE TABLE webhook_checks (
+    checked_at TIMESTAMPTZ PRIMARY KEY DEFAULT now(),
+    pending_update_count INTEGER NOT NULL,
+    last_error_message TEXT,
+    last_error_at TIMESTAMPTZ
+);
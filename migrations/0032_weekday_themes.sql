@@ -0,0 +1,16 @@
+-- Uploads waiting for their weekday theme's slot, as queued.
+CREATE TABLE themed_items (
+    id BIGSERIAL PRIMARY KEY,
+    theme TEXT NOT NULL,
+    item JSONB NOT NULL,
+    held_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Catalog tracks posted again by a theme, so the same ones don't keep coming back.
+CREATE TABLE theme_reposts (
+    id BIGSERIAL PRIMARY KEY,
+    track_id BIGINT NOT NULL REFERENCES tracks (id) ON DELETE CASCADE,
+    theme TEXT NOT NULL,
+    reposted_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX theme_reposts_track_id_idx ON theme_reposts (track_id);
@@ -0,0 +1,2 @@
+-- The Stars price of a paid upload, so a retried one is not posted for free.
+ALTER TABLE failed_items ADD COLUMN paid_stars INTEGER;
@@ -0,0 +1,10 @@
+-- Long mixes split into parts. Every later part points at the first, which is where
+-- its caption links back to.
+ALTER TABLE tracks ADD COLUMN part_number INTEGER;
+ALTER TABLE tracks ADD COLUMN part_base TEXT;
+ALTER TABLE tracks ADD COLUMN first_part_id BIGINT REFERENCES tracks (id) ON DELETE SET NULL;
+
+CREATE INDEX tracks_first_part_id_idx ON tracks (first_part_id);
+
+-- A part number marked with #partN, kept for retries.
+ALTER TABLE failed_items ADD COLUMN part_number INTEGER;
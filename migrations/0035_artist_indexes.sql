@@ -0,0 +1,9 @@
+-- The index post kept for each performer, listing all their tracks, keyed by the
+-- performer in lowercase so "Daft Punk" and "DAFT PUNK" share one.
+CREATE TABLE artist_indexes (
+    channel_id BIGINT NOT NULL,
+    performer_key TEXT NOT NULL,
+    message_id INTEGER NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (channel_id, performer_key)
+);
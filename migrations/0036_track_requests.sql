@@ -0,0 +1,20 @@
+-- Tracks listeners asked to have posted, waiting for the owner in the requests inbox.
+CREATE TABLE track_requests (
+    id BIGSERIAL PRIMARY KEY,
+    file_unique_id TEXT NOT NULL,
+    performer TEXT,
+    title TEXT,
+    -- The Telegram audio as sent, to queue it from.
+    audio JSONB NOT NULL,
+    -- Everyone who asked for it, the first one first.
+    requesters BIGINT[] NOT NULL,
+    requester_name TEXT NOT NULL,
+    -- Where the first request came from, to answer there.
+    chat_id BIGINT NOT NULL,
+    message_id INTEGER NOT NULL,
+    -- pending, queued or rejected.
+    status TEXT NOT NULL DEFAULT 'pending',
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    decided_at TIMESTAMPTZ
+);
+CREATE INDEX track_requests_file_unique_id_idx ON track_requests (file_unique_id);
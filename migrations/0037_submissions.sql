@@ -0,0 +1,2 @@
+-- When a stranger's submission was forwarded to the owner; each person gets one.
+ALTER TABLE intruders ADD COLUMN submitted_at TIMESTAMPTZ;
@@ -0,0 +1,9 @@
+-- Multi-step conversations with the owner, one per chat; see dialogue.rs.
+CREATE TABLE dialogues (
+    chat_id BIGINT PRIMARY KEY,
+    -- The flow and how far along it is, as the bot serializes it.
+    flow JSONB NOT NULL,
+    -- The question waiting for an answer, whose buttons are taken off once answered.
+    prompt_id INTEGER,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
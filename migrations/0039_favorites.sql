@@ -0,0 +1,8 @@
+-- Tracks starred by the owner and the people in FAVORITES_USERS.
+CREATE TABLE favorites (
+    user_id BIGINT NOT NULL,
+    track_id BIGINT NOT NULL REFERENCES tracks (id) ON DELETE CASCADE,
+    starred_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, track_id)
+);
+CREATE INDEX favorites_track_id_idx ON favorites (track_id);
@@ -0,0 +1,3 @@
+-- Tracks posted as paid media. Their file id is the free upload the paid video was
+-- made from, so they are kept out of everything that hands the file out.
+ALTER TABLE tracks ADD COLUMN paid BOOLEAN NOT NULL DEFAULT false;
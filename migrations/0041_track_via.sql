@@ -0,0 +1,3 @@
+-- Credit for tracks mirrored from a partner channel, so captions rebuilt later keep
+-- their "via" line.
+ALTER TABLE tracks ADD COLUMN via JSONB;
@@ -0,0 +1,5 @@
+-- Years whose recap went out, so an approval tapped twice posts it once.
+CREATE TABLE wrapped_posts (
+    year INTEGER PRIMARY KEY,
+    published_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
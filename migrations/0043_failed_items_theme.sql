@@ -0,0 +1,3 @@
+-- The weekday theme an upload was held for, so a retried one keeps its caption and
+-- is not held again.
+ALTER TABLE failed_items ADD COLUMN theme TEXT;
@@ -0,0 +1,250 @@
+use crate::{QueuedMessage, ServerSecretsState, blurbs, cleanup, media};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use teloxide::{
+    prelude::*,
+    types::{Audio, InlineKeyboardButton, InlineKeyboardMarkup},
+};
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "acid:";
+
+/// AcoustID matches scoring lower than this are not worth asking about.
+const MIN_SCORE: f64 = 0.8;
+
+/// Titles that say nothing about the track, as ripping software and phones leave them.
+const PLACEHOLDER_TITLES: [&str; 6] = ["track", "audio", "unknown", "untitled", "record", "voice"];
+
+/// Title and performer to publish a track under instead of its own tags.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Retag {
+    pub title: String,
+    pub performer: String,
+}
+
+impl Retag {
+    fn label(&self) -> String {
+        format!("{} – {}", self.performer, self.title)
+    }
+}
+
+/// Tracks waiting for the owner to accept or reject what AcoustID made of them, by
+/// the id of the owner's message. Kept in memory only: after a restart the file just
+/// has to be sent again.
+#[derive(Default)]
+pub struct Pending(Mutex<HashMap<i32, (QueuedMessage, Retag)>>);
+
+/// Whether the tags of `audio` are missing or meaningless: no performer, no title, a
+/// title that is just the file name, or something like "Track 03".
+pub fn is_untagged(audio: &Audio) -> bool {
+    let (Some(title), Some(performer)) = (&audio.title, &audio.performer) else {
+        return true;
+    };
+    let title = title.trim().to_lowercase();
+    let stem = audio
+        .file_name
+        .as_deref()
+        .and_then(|name| Path::new(name).file_stem())
+        .and_then(|stem| stem.to_str())
+        .map(str::to_lowercase);
+    let bare = title.trim_end_matches(|c: char| c.is_ascii_digit() || " _-.".contains(c));
+    performer.trim().is_empty()
+        || bare.is_empty()
+        || PLACEHOLDER_TITLES.contains(&bare)
+        || stem.as_deref() == Some(title.as_str())
+}
+
+/// Runs chromaprint's `fpcalc` on `path`, returning the duration in seconds and the
+/// fingerprint.
+async fn fingerprint(path: &Path) -> Result<(u32, String), Error> {
+    let output = Command::new("fpcalc")
+        .arg("-json")
+        .arg(path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(format!(
+            "fpcalc failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    let result: Value = serde_json::from_slice(&output.stdout)?;
+    let duration = result["duration"]
+        .as_f64()
+        .ok_or("fpcalc gave no duration")?;
+    let fingerprint = result["fingerprint"]
+        .as_str()
+        .ok_or("fpcalc gave no fingerprint")?;
+    Ok((duration as u32, fingerprint.to_string()))
+}
+
+/// Asks AcoustID which MusicBrainz recording the fingerprint belongs to, returning
+/// the best match and its score.
+async fn lookup(
+    key: &str,
+    duration: u32,
+    fingerprint: &str,
+) -> Result<Option<(Retag, f64)>, Error> {
+    let response: Value = reqwest::Client::new()
+        .post("https://api.acoustid.org/v2/lookup")
+        .form(&[
+            ("client", key),
+            ("meta", "recordings"),
+            ("duration", &duration.to_string()),
+            ("fingerprint", fingerprint),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if response["status"] != "ok" {
+        return Err(format!("AcoustID said: {}", response["error"]["message"]).into());
+    }
+
+    Ok(response["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|result| {
+            let score = result["score"].as_f64()?;
+            let recording = result["recordings"].as_array()?.first()?;
+            let performer = recording["artists"]
+                .as_array()?
+                .iter()
+                .filter_map(|artist| artist["name"].as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let title = recording["title"].as_str()?.to_string();
+            (!performer.is_empty()).then_some((Retag { title, performer }, score))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .filter(|(_, score)| *score >= MIN_SCORE))
+}
+
+async fn recognize(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    key: &str,
+    audio: &Audio,
+) -> Result<Option<(Retag, f64)>, Error> {
+    let local = media::fetch(bot, secrets, &audio.file).await?;
+    let (duration, fingerprint) = fingerprint(&local.path).await?;
+    lookup(key, duration, &fingerprint).await
+}
+
+fn keyboard(message_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            "✅ Use it",
+            format!("{}use:{}", CALLBACK_PREFIX, message_id),
+        ),
+        InlineKeyboardButton::callback(
+            "Keep the file's tags",
+            format!("{}keep:{}", CALLBACK_PREFIX, message_id),
+        ),
+    ]])
+}
+
+/// Fingerprints an untagged track the owner sent and, if AcoustID knows it, asks
+/// whether to publish it under the recognised title and performer. The track is held
+/// back until then; if nothing is found it moves on as it is.
+pub fn spawn(
+    bot: Arc<Bot>,
+    secrets: Arc<ServerSecretsState>,
+    chat_id: ChatId,
+    message: QueuedMessage,
+) {
+    let Some(key) = secrets.acoustid_key.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let found = recognize(&bot, &secrets, &key, &message.audio)
+            .await
+            .inspect_err(|e| tracing::warn!("Failed to recognize track: {}", e))
+            .ok()
+            .flatten();
+        let Some((retag, score)) = found else {
+            if let Err(e) = cleanup::reply(&bot, &secrets, chat_id, "Not recognized.").await {
+                tracing::warn!("Failed to reply: {}", e);
+            }
+            let result = blurbs::queue(&bot, &secrets, chat_id, message).await;
+            if let Err(e) = result {
+                tracing::warn!("Failed to queue unrecognized track: {}", e);
+            }
+            return;
+        };
+
+        let message_id = message.message_id;
+        let text = format!(
+            "🔎 Sounds like {} ({:.0}% match). Publish it under that name?",
+            retag.label(),
+            score * 100.0
+        );
+        secrets
+            .acoustid_pending
+            .0
+            .lock()
+            .expect("pending recognitions poisoned")
+            .insert(message_id, (message, retag));
+        if let Err(e) = bot
+            .send_message(chat_id, text)
+            .reply_markup(keyboard(message_id))
+            .await
+        {
+            tracing::warn!("Failed to propose recognized tags: {}", e);
+        }
+    });
+}
+
+/// The owner's answer to a proposal: queues the track, under the recognised name or
+/// its own, by way of a blurb if those are on.
+pub async fn handle_callback(
+    bot: &Arc<Bot>,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let (action, message_id) = data.split_once(':').ok_or("Malformed callback data")?;
+    let message_id: i32 = message_id.parse()?;
+    if !matches!(action, "use" | "keep") {
+        return Err("Unknown recognition action".into());
+    }
+    let pending = secrets
+        .acoustid_pending
+        .0
+        .lock()
+        .expect("pending recognitions poisoned")
+        .remove(&message_id);
+    let Some((mut message, retag)) = pending else {
+        bot.answer_callback_query(query.id.clone())
+            .text("Already answered, or the bot restarted since; send the file again")
+            .await?;
+        return Ok(());
+    };
+
+    let status = if action == "use" {
+        let status = format!("Publishing as {}.", retag.label());
+        message.retag = Some(retag);
+        status
+    } else {
+        "Keeping its tags.".to_string()
+    };
+    bot.answer_callback_query(query.id.clone()).await?;
+    let chat_id = match query.regular_message() {
+        Some(proposal) => {
+            bot.edit_message_text(proposal.chat.id, proposal.id, status)
+                .await?;
+            proposal.chat.id
+        }
+        None => secrets.me_id,
+    };
+    blurbs::queue(bot, secrets, chat_id, message).await
+}
@@ -0,0 +1,60 @@
+use crate::media;
+use std::path::Path;
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Tempo and key of a track, estimated when `AUDIO_ANALYSIS` is `true`. Either can be
+/// missing if its tool failed or was unsure.
+#[derive(Default)]
+pub struct Analysis {
+    pub bpm: Option<i32>,
+    /// In standard notation, e.g. "Am" or "F#".
+    pub key: Option<String>,
+}
+
+async fn stdout(command: &mut Command) -> Result<String, Error> {
+    let output = command.output().await?;
+    if !output.status.success() {
+        return Err(format!(
+            "{:?} failed with {}: {}",
+            command.as_std().get_program(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `aubio tempo` prints the tempo as "123.45 bpm".
+async fn bpm(path: &Path) -> Result<i32, Error> {
+    let output = stdout(Command::new("aubio").args(["tempo", "-i"]).arg(path)).await?;
+    let bpm: f64 = output
+        .split_whitespace()
+        .next()
+        .and_then(|bpm| bpm.parse().ok())
+        .ok_or_else(|| format!("Unexpected aubio output: {}", output))?;
+    Ok(bpm.round() as i32)
+}
+
+/// `keyfinder-cli` prints the key alone, or nothing for silence.
+async fn key(path: &Path) -> Result<Option<String>, Error> {
+    let output = stdout(Command::new("keyfinder-cli").arg(path)).await?;
+    Ok((!output.is_empty()).then_some(output))
+}
+
+/// Runs both estimates on a local copy of the track.
+pub async fn analyze(file: &media::LocalFile) -> Analysis {
+    let (bpm, key) = tokio::join!(bpm(&file.path), key(&file.path));
+    Analysis {
+        bpm: bpm
+            .inspect_err(|e| tracing::warn!("Failed to estimate BPM: {}", e))
+            .ok()
+            .filter(|bpm| *bpm > 0),
+        key: key
+            .inspect_err(|e| tracing::warn!("Failed to estimate key: {}", e))
+            .ok()
+            .flatten(),
+    }
+}
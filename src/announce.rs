@@ -0,0 +1,91 @@
+use crate::{ServerSecretsState, telegram::OutgoingText};
+use std::sync::atomic::Ordering;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+    utils::{markdown, render::RenderMessageTextHelper},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "announce:";
+
+/// The text or caption of `message` as MarkdownV2, keeping the bold, links and so on
+/// it was written with.
+pub fn markdown_text(message: &Message) -> Option<String> {
+    message
+        .markdown_text()
+        .or_else(|| message.markdown_caption())
+        .or_else(|| message.text().or(message.caption()).map(markdown::escape))
+}
+
+/// Answers `/announce <text>` with a preview of the post, formatted as it will appear
+/// in the channel, and buttons to post or drop it.
+pub async fn preview(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let text = markdown_text(message)
+        .and_then(|text| {
+            let (_, rest) = text.split_once(char::is_whitespace)?;
+            Some(rest.trim().to_string())
+        })
+        .filter(|text| !text.is_empty())
+        .ok_or("Nothing to announce")?;
+
+    let post = if secrets.publish_channel_id() == secrets.channel_id() {
+        "📣 Post"
+    } else {
+        "📣 Post to staging"
+    };
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(post, format!("{}post", CALLBACK_PREFIX)),
+        InlineKeyboardButton::callback("Cancel", format!("{}cancel", CALLBACK_PREFIX)),
+    ]]);
+    bot.send_message(message.chat.id, text)
+        .parse_mode(ParseMode::MarkdownV2)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// Handles the buttons under a preview. The preview itself is what gets posted, so
+/// it survives restarts.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let Some(preview) = query.regular_message() else {
+        bot.answer_callback_query(query.id.clone())
+            .text("The preview is too old; send /announce again")
+            .await?;
+        return Ok(());
+    };
+
+    let status = match data {
+        "post" => {
+            let text = markdown_text(preview).ok_or("The preview has no text")?;
+            let channel_id = secrets.publish_channel_id();
+            let outgoing = OutgoingText::markdown(text);
+            let sent = secrets
+                .retry_policy
+                .run(|| secrets.telegram.send_message(channel_id, &outgoing))
+                .await?;
+            secrets.last_message_id.store(sent.id.0, Ordering::Relaxed);
+            tracing::info!("Posted announcement (ID: {})", sent.id.0);
+            "Posted ✅"
+        }
+        "cancel" => "Cancelled",
+        _ => return Err("Unknown announcement action".into()),
+    };
+
+    bot.answer_callback_query(query.id.clone())
+        .text(status)
+        .await?;
+    bot.edit_message_reply_markup(preview.chat.id, preview.id)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,682 @@
+use crate::{
+    ServerSecretsState,
+    auth::{Authorized, scope},
+    catalog::{self, Cursor, Track, TrackFilter},
+    deep_link,
+    flags::{Flag, FlagState},
+    http_cache::{Cached, Conditional},
+    maintenance::{self, DrainState},
+    media,
+    replay::{self, RecordedUpdate, Replayed},
+    stream::AudioStream,
+    web::HttpError,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use teloxide::Bot;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// A track with the `t.me/<bot>?start=...` link that makes the bot DM it.
+#[derive(Serialize)]
+struct TrackItem {
+    #[serde(flatten)]
+    track: Track,
+    share_link: String,
+}
+
+#[derive(Serialize)]
+struct TrackPage {
+    items: Vec<TrackItem>,
+    total: i64,
+    next_cursor: Option<String>,
+}
+
+/// Accepts either an RFC 3339 timestamp or a plain `YYYY-MM-DD` date (midnight UTC).
+fn parse_date(raw: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(raw)
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        })
+}
+
+fn parse_optional_date(raw: Option<&str>) -> Result<Option<DateTime<Utc>>, HttpError> {
+    raw.map(|raw| parse_date(raw).ok_or_else(|| HttpError::new(400, "Invalid date")))
+        .transpose()
+}
+
+/// The query string of `GET /tracks`.
+#[derive(Default, Deserialize)]
+struct TracksQuery {
+    artist: Option<String>,
+    tag: Option<String>,
+    series: Option<String>,
+    q: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+async fn list_tracks(
+    secrets: &ServerSecretsState,
+    conditional: &Conditional,
+    query: TracksQuery,
+) -> Result<Cached<TrackPage>, HttpError> {
+    let filter = TrackFilter {
+        performer: query.artist,
+        tag: query.tag,
+        series: query.series,
+        query: query.q.filter(|q| !q.trim().is_empty()),
+        posted_after: parse_optional_date(query.from.as_deref())?,
+        posted_before: parse_optional_date(query.to.as_deref())?,
+    };
+    let cursor = query
+        .cursor
+        .map(|raw| Cursor::decode(&raw).ok_or_else(|| HttpError::new(400, "Invalid cursor")))
+        .transpose()?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let version = catalog::last_updated(&secrets.db)
+        .await
+        .map_err(|e| HttpError::internal("Failed to read catalog version", e))?;
+    if conditional.is_fresh(version) {
+        return Ok(Cached::NotModified(version));
+    }
+
+    let (items, total) = tokio::try_join!(
+        catalog::list_tracks_after(&secrets.db, &filter, cursor, limit + 1),
+        catalog::count_tracks(&secrets.db, &filter),
+    )
+    .map_err(|e| HttpError::internal("Failed to list tracks", e))?;
+
+    let mut items = items;
+    let next_cursor = if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(|track| Cursor::of(track).encode())
+    } else {
+        None
+    };
+
+    Ok(Cached::Fresh(
+        TrackPage {
+            items: items
+                .into_iter()
+                .map(|track| TrackItem {
+                    share_link: deep_link::url(secrets, deep_link::Link::Track(track.id)),
+                    track,
+                })
+                .collect(),
+            total,
+            next_cursor,
+        },
+        version,
+    ))
+}
+
+/// `GET /tracks/<id>/audio`: the track's file, or the byte range asked for.
+async fn track_audio(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    id: i64,
+    range: Option<&str>,
+) -> Result<AudioStream, HttpError> {
+    let track = catalog::get_track(&secrets.db, id)
+        .await
+        .map_err(|e| HttpError::internal("Failed to look up track", e))?
+        .ok_or_else(|| HttpError::new(404, "No such track"))?;
+    crate::stream::audio(bot, secrets, &track, range).await
+}
+
+#[derive(Serialize)]
+struct UploadReceipt {
+    position: usize,
+    queue_len: usize,
+    estimated_publish_time: DateTime<Utc>,
+}
+
+/// Queues an uploaded file once the backend has stored it at `path`.
+async fn queue_upload(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    path: &Path,
+    name: String,
+    metadata: media::Metadata,
+) -> Result<UploadReceipt, HttpError> {
+    if maintenance::is_on(secrets) {
+        return Err(HttpError::new(503, "Not taking uploads during maintenance"));
+    }
+    let position = media::queue_file(bot, secrets, path, name, metadata)
+        .await
+        .map_err(|e| HttpError::new(422, e.to_string()))?;
+    Ok(UploadReceipt {
+        position: position.position,
+        queue_len: position.queue_len,
+        estimated_publish_time: position.estimated_publish_time(),
+    })
+}
+
+fn upload_path(extension: &str) -> media::LocalFile {
+    media::LocalFile::scratch(&format!(
+        "upload-{}.{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        extension
+    ))
+}
+
+#[derive(Serialize)]
+struct QueueItem {
+    message_id: i32,
+    title: Option<String>,
+    performer: Option<String>,
+    duration_secs: u32,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct QueueState {
+    paused: bool,
+    items: Vec<QueueItem>,
+}
+
+async fn queue_state(secrets: &ServerSecretsState) -> QueueState {
+    QueueState {
+        paused: secrets.message_queue.is_paused(),
+        items: secrets
+            .message_queue
+            .snapshot()
+            .await
+            .into_iter()
+            .map(|msg| QueueItem {
+                message_id: msg.message_id,
+                title: msg.audio.title,
+                performer: msg.audio.performer,
+                duration_secs: msg.audio.duration.seconds(),
+                tags: msg.tags,
+            })
+            .collect(),
+    }
+}
+
+async fn set_paused(secrets: &ServerSecretsState, paused: bool) -> QueueState {
+    secrets.message_queue.set_paused(paused);
+    tracing::info!("Queue {}", if paused { "paused" } else { "resumed" });
+    queue_state(secrets).await
+}
+
+/// `{"enabled": true}` or `{"enabled": false}`; `null` goes back to the default.
+#[derive(Deserialize)]
+struct SetFlag {
+    enabled: Option<bool>,
+}
+
+async fn set_flag(
+    secrets: &ServerSecretsState,
+    name: &str,
+    body: SetFlag,
+) -> Result<Vec<FlagState>, HttpError> {
+    let flag = Flag::parse(name).ok_or_else(|| HttpError::new(404, "Unknown flag"))?;
+    secrets
+        .flags
+        .set(&secrets.db, flag, body.enabled)
+        .await
+        .map_err(|e| HttpError::internal("Failed to store feature flags", e))?;
+    Ok(secrets.flags.list())
+}
+
+/// The query string of `GET /updates`.
+#[derive(Default, Deserialize)]
+struct UpdatesQuery {
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<i64>,
+}
+
+async fn recorded_updates(
+    secrets: &ServerSecretsState,
+    query: UpdatesQuery,
+) -> Result<Vec<RecordedUpdate>, HttpError> {
+    replay::list(
+        &secrets.db,
+        parse_optional_date(query.from.as_deref())?,
+        parse_optional_date(query.to.as_deref())?,
+        query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+    )
+    .await
+    .map_err(|e| HttpError::internal("Failed to list recorded updates", e))
+}
+
+/// `{"ids": [12, 13]}`, ids as listed by `GET /updates`.
+#[derive(Deserialize)]
+struct ReplayRequest {
+    ids: Vec<i64>,
+}
+
+async fn replay_updates(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    request: ReplayRequest,
+) -> Result<Vec<Replayed>, HttpError> {
+    if secrets.dry_run.is_none() {
+        return Err(HttpError::new(
+            409,
+            "Updates can only be replayed with DRY_RUN on",
+        ));
+    }
+    replay::run(bot, secrets, &request.ids)
+        .await
+        .map_err(|e| HttpError::new(422, e.to_string()))
+}
+
+/// `POST /admin/drain` enters maintenance and `DELETE /admin/drain` leaves it; both,
+/// like `GET`, answer with how far the drain has got. Poll until `safe_to_redeploy`.
+async fn set_drain(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    on: bool,
+) -> Result<DrainState, HttpError> {
+    if on {
+        maintenance::start(bot, secrets);
+    } else {
+        maintenance::stop(secrets)
+            .await
+            .map_err(|e| HttpError::internal("Failed to leave maintenance", e))?;
+    }
+    Ok(maintenance::state(secrets).await)
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_routes::routes;
+
+#[cfg(feature = "rocket")]
+mod rocket_routes {
+    use super::*;
+    use crate::stream::Range;
+    use rocket::{
+        FromForm, Route, State, delete, form::Form, fs::TempFile, get, post, routes,
+        serde::json::Json,
+    };
+    use std::sync::Arc;
+
+    pub fn routes() -> Vec<Route> {
+        routes![
+            tracks,
+            audio,
+            upload,
+            queue,
+            pause_queue,
+            resume_queue,
+            flags,
+            flag,
+            updates,
+            replay,
+            drain,
+            start_drain,
+            stop_drain
+        ]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[get("/tracks?<artist>&<tag>&<series>&<q>&<from>&<to>&<cursor>&<limit>")]
+    async fn tracks(
+        secrets: &State<Arc<ServerSecretsState>>,
+        conditional: Conditional,
+        artist: Option<String>,
+        tag: Option<String>,
+        series: Option<String>,
+        q: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+        cursor: Option<String>,
+        limit: Option<i64>,
+    ) -> Result<Cached<Json<TrackPage>>, HttpError> {
+        let query = TracksQuery {
+            artist,
+            tag,
+            series,
+            q,
+            from,
+            to,
+            cursor,
+            limit,
+        };
+        Ok(list_tracks(secrets, &conditional, query).await?.map(Json))
+    }
+
+    #[get("/tracks/<id>/audio")]
+    async fn audio(
+        id: i64,
+        _auth: Authorized<scope::Audio>,
+        bot: &State<Arc<Bot>>,
+        secrets: &State<Arc<ServerSecretsState>>,
+        range: Range,
+    ) -> Result<AudioStream, HttpError> {
+        track_audio(bot, secrets, id, range.0.as_deref()).await
+    }
+
+    #[derive(FromForm)]
+    struct UploadForm<'r> {
+        file: TempFile<'r>,
+        metadata: Option<Json<media::Metadata>>,
+    }
+
+    /// Queues an audio file posted as `multipart/form-data`, with an optional
+    /// `metadata` field holding JSON like `{"title": "...", "tags": ["..."]}`.
+    #[post("/upload", data = "<form>")]
+    async fn upload(
+        _auth: Authorized<scope::Upload>,
+        bot: &State<Arc<Bot>>,
+        secrets: &State<Arc<ServerSecretsState>>,
+        form: Form<UploadForm<'_>>,
+    ) -> Result<Json<UploadReceipt>, HttpError> {
+        let UploadForm { mut file, metadata } = form.into_inner();
+        let extension = file
+            .content_type()
+            .and_then(|content_type| content_type.extension())
+            .map(|ext| ext.to_string())
+            .unwrap_or_else(|| "mp3".to_string());
+        let name = file.name().unwrap_or("upload").to_string();
+        let local = upload_path(&extension);
+        file.persist_to(&local.path)
+            .await
+            .map_err(|e| HttpError::internal("Failed to store upload", e))?;
+
+        let metadata = metadata.map(Json::into_inner).unwrap_or_default();
+        Ok(Json(
+            queue_upload(bot, secrets, &local.path, name, metadata).await?,
+        ))
+    }
+
+    #[get("/queue")]
+    async fn queue(
+        _auth: Authorized<scope::Queue>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Json<QueueState> {
+        Json(queue_state(secrets).await)
+    }
+
+    #[post("/queue/pause")]
+    async fn pause_queue(
+        _auth: Authorized<scope::Queue>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Json<QueueState> {
+        Json(set_paused(secrets, true).await)
+    }
+
+    #[post("/queue/resume")]
+    async fn resume_queue(
+        _auth: Authorized<scope::Queue>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Json<QueueState> {
+        Json(set_paused(secrets, false).await)
+    }
+
+    #[get("/flags")]
+    fn flags(
+        _auth: Authorized<scope::Flags>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Json<Vec<FlagState>> {
+        Json(secrets.flags.list())
+    }
+
+    #[post("/flags/<name>", data = "<body>")]
+    async fn flag(
+        name: &str,
+        _auth: Authorized<scope::Flags>,
+        secrets: &State<Arc<ServerSecretsState>>,
+        body: Json<SetFlag>,
+    ) -> Result<Json<Vec<FlagState>>, HttpError> {
+        Ok(Json(set_flag(secrets, name, body.into_inner()).await?))
+    }
+
+    #[get("/updates?<from>&<to>&<limit>")]
+    async fn updates(
+        _auth: Authorized<scope::Updates>,
+        secrets: &State<Arc<ServerSecretsState>>,
+        from: Option<String>,
+        to: Option<String>,
+        limit: Option<i64>,
+    ) -> Result<Json<Vec<RecordedUpdate>>, HttpError> {
+        let query = UpdatesQuery { from, to, limit };
+        Ok(Json(recorded_updates(secrets, query).await?))
+    }
+
+    #[post("/updates/replay", data = "<request>")]
+    async fn replay(
+        _auth: Authorized<scope::Updates>,
+        bot: &State<Arc<Bot>>,
+        secrets: &State<Arc<ServerSecretsState>>,
+        request: Json<ReplayRequest>,
+    ) -> Result<Json<Vec<Replayed>>, HttpError> {
+        Ok(Json(
+            replay_updates(bot, secrets, request.into_inner()).await?,
+        ))
+    }
+
+    #[get("/admin/drain")]
+    async fn drain(
+        _auth: Authorized<scope::Admin>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Json<DrainState> {
+        Json(maintenance::state(secrets).await)
+    }
+
+    #[post("/admin/drain")]
+    async fn start_drain(
+        _auth: Authorized<scope::Admin>,
+        bot: &State<Arc<Bot>>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Result<Json<DrainState>, HttpError> {
+        Ok(Json(set_drain(bot, secrets, true).await?))
+    }
+
+    #[delete("/admin/drain")]
+    async fn stop_drain(
+        _auth: Authorized<scope::Admin>,
+        bot: &State<Arc<Bot>>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Result<Json<DrainState>, HttpError> {
+        Ok(Json(set_drain(bot, secrets, false).await?))
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_routes::router;
+
+#[cfg(feature = "axum")]
+mod axum_routes {
+    use super::*;
+    use crate::web::AppState;
+    use axum::{
+        Json, Router,
+        extract::{DefaultBodyLimit, Multipart, Path as UrlPath, Query, State},
+        http::HeaderMap,
+        routing::{get, post},
+    };
+    use tokio::io::AsyncWriteExt;
+
+    /// The routes under `/api/v1`. Uploads may be as large as the Bot API accepts.
+    pub fn router(upload_limit: usize) -> Router<AppState> {
+        Router::new()
+            .route("/tracks", get(tracks))
+            .route("/tracks/{id}/audio", get(audio))
+            .route(
+                "/upload",
+                post(upload).layer(DefaultBodyLimit::max(upload_limit)),
+            )
+            .route("/queue", get(queue))
+            .route("/queue/pause", post(pause_queue))
+            .route("/queue/resume", post(resume_queue))
+            .route("/flags", get(flags))
+            .route("/flags/{name}", post(flag))
+            .route("/updates", get(updates))
+            .route("/updates/replay", post(replay))
+            .route(
+                "/admin/drain",
+                get(drain).post(start_drain).delete(stop_drain),
+            )
+    }
+
+    async fn tracks(
+        State(state): State<AppState>,
+        conditional: Conditional,
+        Query(query): Query<TracksQuery>,
+    ) -> Result<Cached<Json<TrackPage>>, HttpError> {
+        Ok(list_tracks(&state.secrets, &conditional, query)
+            .await?
+            .map(Json))
+    }
+
+    async fn audio(
+        _auth: Authorized<scope::Audio>,
+        State(state): State<AppState>,
+        UrlPath(id): UrlPath<i64>,
+        headers: HeaderMap,
+    ) -> Result<AudioStream, HttpError> {
+        let range = headers.get("Range").and_then(|value| value.to_str().ok());
+        track_audio(&state.bot, &state.secrets, id, range).await
+    }
+
+    fn bad_upload(e: impl std::fmt::Display) -> HttpError {
+        HttpError::new(400, e.to_string())
+    }
+
+    /// Queues an audio file posted as `multipart/form-data`, with an optional
+    /// `metadata` field holding JSON like `{"title": "...", "tags": ["..."]}`.
+    async fn upload(
+        _auth: Authorized<scope::Upload>,
+        State(state): State<AppState>,
+        mut form: Multipart,
+    ) -> Result<Json<UploadReceipt>, HttpError> {
+        let mut stored = None;
+        let mut metadata = media::Metadata::default();
+        while let Some(mut field) = form.next_field().await.map_err(bad_upload)? {
+            match field.name() {
+                Some("file") => {
+                    let name = Path::new(field.file_name().unwrap_or("upload")).to_path_buf();
+                    let extension = name
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .unwrap_or("mp3");
+                    let stem = name
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .unwrap_or("upload")
+                        .to_string();
+                    let local = upload_path(extension);
+                    let mut file = tokio::fs::File::create(&local.path)
+                        .await
+                        .map_err(|e| HttpError::internal("Failed to store upload", e))?;
+                    while let Some(chunk) = field.chunk().await.map_err(bad_upload)? {
+                        file.write_all(&chunk)
+                            .await
+                            .map_err(|e| HttpError::internal("Failed to store upload", e))?;
+                    }
+                    file.flush()
+                        .await
+                        .map_err(|e| HttpError::internal("Failed to store upload", e))?;
+                    stored = Some((local, stem));
+                }
+                Some("metadata") => {
+                    metadata = serde_json::from_str(&field.text().await.map_err(bad_upload)?)
+                        .map_err(bad_upload)?;
+                }
+                _ => {}
+            }
+        }
+
+        let (local, name) = stored.ok_or_else(|| HttpError::new(400, "No file field"))?;
+        Ok(Json(
+            queue_upload(&state.bot, &state.secrets, &local.path, name, metadata).await?,
+        ))
+    }
+
+    async fn queue(
+        _auth: Authorized<scope::Queue>,
+        State(state): State<AppState>,
+    ) -> Json<QueueState> {
+        Json(queue_state(&state.secrets).await)
+    }
+
+    async fn pause_queue(
+        _auth: Authorized<scope::Queue>,
+        State(state): State<AppState>,
+    ) -> Json<QueueState> {
+        Json(set_paused(&state.secrets, true).await)
+    }
+
+    async fn resume_queue(
+        _auth: Authorized<scope::Queue>,
+        State(state): State<AppState>,
+    ) -> Json<QueueState> {
+        Json(set_paused(&state.secrets, false).await)
+    }
+
+    async fn flags(
+        _auth: Authorized<scope::Flags>,
+        State(state): State<AppState>,
+    ) -> Json<Vec<FlagState>> {
+        Json(state.secrets.flags.list())
+    }
+
+    async fn flag(
+        _auth: Authorized<scope::Flags>,
+        State(state): State<AppState>,
+        UrlPath(name): UrlPath<String>,
+        Json(body): Json<SetFlag>,
+    ) -> Result<Json<Vec<FlagState>>, HttpError> {
+        Ok(Json(set_flag(&state.secrets, &name, body).await?))
+    }
+
+    async fn updates(
+        _auth: Authorized<scope::Updates>,
+        State(state): State<AppState>,
+        Query(query): Query<UpdatesQuery>,
+    ) -> Result<Json<Vec<RecordedUpdate>>, HttpError> {
+        Ok(Json(recorded_updates(&state.secrets, query).await?))
+    }
+
+    async fn replay(
+        _auth: Authorized<scope::Updates>,
+        State(state): State<AppState>,
+        Json(request): Json<ReplayRequest>,
+    ) -> Result<Json<Vec<Replayed>>, HttpError> {
+        Ok(Json(
+            replay_updates(&state.bot, &state.secrets, request).await?,
+        ))
+    }
+
+    async fn drain(
+        _auth: Authorized<scope::Admin>,
+        State(state): State<AppState>,
+    ) -> Json<DrainState> {
+        Json(maintenance::state(&state.secrets).await)
+    }
+
+    async fn start_drain(
+        _auth: Authorized<scope::Admin>,
+        State(state): State<AppState>,
+    ) -> Result<Json<DrainState>, HttpError> {
+        Ok(Json(set_drain(&state.bot, &state.secrets, true).await?))
+    }
+
+    async fn stop_drain(
+        _auth: Authorized<scope::Admin>,
+        State(state): State<AppState>,
+    ) -> Result<Json<DrainState>, HttpError> {
+        Ok(Json(set_drain(&state.bot, &state.secrets, false).await?))
+    }
+}
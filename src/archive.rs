@@ -0,0 +1,112 @@
+use crate::{QueuedMessage, ServerSecretsState, media};
+use std::path::Path;
+use std::sync::Arc;
+use teloxide::{prelude::*, types::Document};
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const AUDIO_EXTENSIONS: [&str; 8] = ["mp3", "m4a", "aac", "flac", "ogg", "opus", "wav", "wma"];
+
+pub fn is_zip(document: &Document) -> bool {
+    document
+        .mime_type
+        .as_ref()
+        .is_some_and(|mime| mime.essence_str() == "application/zip")
+        || document
+            .file_name
+            .as_ref()
+            .is_some_and(|name| name.to_lowercase().ends_with(".zip"))
+}
+
+pub fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Unpacks a .zip of audio files sent to the bot and queues its tracks in tracklist
+/// order. Each file is uploaded to the owner's chat first to get a file id the
+/// queue can repost, and those staging messages are removed once everything is
+/// queued. Returns the number of tracks queued.
+pub async fn ingest(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    document: &Document,
+    tags: &[String],
+) -> Result<usize, Error> {
+    let archive = media::fetch(bot, secrets, &document.file).await?;
+    let dir = media::LocalFile::scratch(&format!("{}-unzipped", document.file.unique_id.0));
+    media::run(
+        Command::new("unzip")
+            .args(["-q", "-o", "-j"])
+            .arg(&archive.path)
+            .arg("-d")
+            .arg(&dir.path),
+    )
+    .await?;
+
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(&dir.path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if is_audio(&path) {
+            let tags = media::probe_tags(&path).await.unwrap_or_default();
+            files.push((media::tracklist_position(&tags), path, tags));
+        }
+    }
+    files.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    let owner = secrets.me_id;
+    let mut staged = Vec::new();
+    for (_, path, file_tags) in &files {
+        match media::stage_audio(bot, secrets, path, file_tags).await {
+            Ok(message) => staged.push(message),
+            Err(e) => {
+                tracing::warn!("Skipping {} from archive: {}", path.display(), e);
+                bot.send_message(
+                    owner,
+                    format!(
+                        "Skipped {}: {}",
+                        path.file_name().unwrap_or_default().to_string_lossy(),
+                        e
+                    ),
+                )
+                .await?;
+            }
+        }
+    }
+
+    for message in &staged {
+        let Some(audio) = message.audio() else {
+            continue;
+        };
+        secrets
+            .message_queue
+            .add_message(
+                QueuedMessage {
+                    audio: audio.clone(),
+                    tags: tags.to_vec(),
+                    message_id: message.id.0,
+                    via: None,
+                    retag: None,
+                    description: None,
+                    blurb: None,
+                    queued_at: chrono::Utc::now(),
+                    theme: None,
+                    stars: None,
+                    part: None,
+                },
+                bot.clone(),
+                secrets.clone(),
+            )
+            .await;
+    }
+    for message in &staged {
+        if let Err(e) = bot.delete_message(message.chat.id, message.id).await {
+            tracing::warn!("Failed to delete staging message: {}", e);
+        }
+    }
+
+    Ok(staged.len())
+}
@@ -0,0 +1,171 @@
+use crate::{
+    ServerSecretsState,
+    catalog::{self, Track},
+    telegram::OutgoingText,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    ApiError, RequestError,
+    types::{ChatId, MessageId},
+    utils::markdown,
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long an index post may get, under Telegram's 4096 characters with room for
+/// the heading and the note about older tracks.
+const MAX_LENGTH: usize = 3900;
+
+/// Parses `ARTIST_INDEX`, how many tracks a performer needs before they get an index
+/// post; unset or `off` means no index posts.
+pub fn min_tracks_from_secret(raw: Option<&str>) -> anyhow::Result<Option<usize>> {
+    match raw.map(str::trim) {
+        None | Some("") | Some("off") => Ok(None),
+        Some(raw) => raw
+            .parse()
+            .ok()
+            .filter(|min: &usize| *min >= 1)
+            .map(Some)
+            .context("ARTIST_INDEX must be off or a number of tracks, at least 1"),
+    }
+}
+
+/// The performer as index posts are keyed: "Daft Punk" and "DAFT PUNK" are one.
+fn key(performer: &str) -> String {
+    performer.trim().to_lowercase()
+}
+
+/// The index post of `performer` in the channel, if they have one.
+pub async fn message_id(
+    db: &PgPool,
+    channel_id: ChatId,
+    performer: &str,
+) -> sqlx::Result<Option<i32>> {
+    sqlx::query_scalar(
+        "SELECT message_id FROM artist_indexes WHERE channel_id = $1 AND performer_key = $2",
+    )
+    .bind(channel_id.0)
+    .bind(key(performer))
+    .fetch_optional(db)
+    .await
+}
+
+async fn save(
+    db: &PgPool,
+    channel_id: ChatId,
+    performer: &str,
+    message_id: i32,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO artist_indexes (channel_id, performer_key, message_id)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (channel_id, performer_key)
+         DO UPDATE SET message_id = EXCLUDED.message_id, updated_at = now()",
+    )
+    .bind(channel_id.0)
+    .bind(key(performer))
+    .bind(message_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Everything `performer` has in the channel, oldest first.
+async fn tracks(db: &PgPool, channel_id: ChatId, performer: &str) -> sqlx::Result<Vec<Track>> {
+    sqlx::query_as(
+        "SELECT * FROM tracks
+         WHERE deleted_at IS NULL AND channel_id = $1 AND lower(trim(performer)) = $2
+         ORDER BY posted_at, message_id",
+    )
+    .bind(channel_id.0)
+    .bind(key(performer))
+    .fetch_all(db)
+    .await
+}
+
+/// "🎤 *Daft Punk*" over a linked line per track. When they do not all fit, the
+/// oldest are left out and counted.
+fn render(performer: &str, tracks: &[Track]) -> String {
+    let mut lines: Vec<String> = tracks
+        .iter()
+        .map(|track| {
+            let title = track.title.clone().unwrap_or_else(|| track.label());
+            let label = match track.number {
+                Some(number) => format!("№ {} · {}", number, title),
+                None => title,
+            };
+            markdown::link(
+                &markdown::escape_link_url(&catalog::permalink(track.message_id)),
+                &markdown::escape(&label),
+            )
+        })
+        .collect();
+    let mut length: usize = lines.iter().map(|line| line.chars().count() + 1).sum();
+    let mut left_out = 0;
+    while length > MAX_LENGTH && lines.len() > 1 {
+        length -= lines.remove(0).chars().count() + 1;
+        left_out += 1;
+    }
+
+    let mut text = format!(
+        "🎤 {}\n{}\n",
+        markdown::bold(&markdown::escape(performer.trim())),
+        match tracks.len() {
+            1 => "1 track".to_string(),
+            count => format!("{} tracks", count),
+        },
+    );
+    if left_out > 0 {
+        text.push_str(&markdown::escape(&format!("…and {} earlier\n", left_out)));
+    }
+    text.push_str(&lines.join("\n"));
+    text
+}
+
+/// Brings the index post of `performer` up to date after a track of theirs was
+/// posted, once they have at least `min_tracks`: edits the one there is, or posts a
+/// new one when there is none or it can no longer be edited. Index posts are not
+/// pinned, one per performer would bury the pinned post; captions link them with
+/// `{artist_index}`.
+pub async fn refresh(
+    secrets: &ServerSecretsState,
+    min_tracks: usize,
+    channel_id: ChatId,
+    performer: &str,
+) -> Result<(), Error> {
+    if performer.trim().is_empty() {
+        return Ok(());
+    }
+    let tracks = tracks(&secrets.db, channel_id, performer).await?;
+    if tracks.len() < min_tracks {
+        return Ok(());
+    }
+    let text = OutgoingText::markdown(render(performer, &tracks)).without_link_preview();
+
+    if let Some(message_id) = message_id(&secrets.db, channel_id, performer).await? {
+        match secrets
+            .telegram
+            .edit_text(channel_id, MessageId(message_id), &text)
+            .await
+        {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => return Ok(()),
+            Err(RequestError::Api(
+                ApiError::MessageToEditNotFound | ApiError::MessageCantBeEdited,
+            )) => tracing::info!("Index post of {} is gone, posting a new one", performer),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let text = text.silent();
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_message(channel_id, &text))
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    save(&secrets.db, channel_id, performer, message.id.0).await?;
+    Ok(())
+}
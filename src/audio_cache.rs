@@ -0,0 +1,190 @@
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// How much downloaded audio is kept when `AUDIO_CACHE_MB` does not say.
+const DEFAULT_LIMIT_MB: u64 = 512;
+
+/// Files downloaded from Telegram, kept on disk by `file_unique_id` so the stages
+/// that each need a track (tags, fingerprint, cover, waveform, processing) download
+/// it once between them. The least recently used files go first once the cache
+/// grows over its limit, unless they are in use.
+pub struct AudioCache {
+    dir: PathBuf,
+    limit: u64,
+    /// How many [`Pin`]s each file has, by `file_unique_id`.
+    pins: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+/// Keeps a cached file from being evicted for as long as it is held.
+pub struct Pin {
+    pins: Arc<Mutex<HashMap<String, usize>>>,
+    unique_id: String,
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        let mut pins = self.pins.lock().expect("audio cache pins poisoned");
+        if let Some(count) = pins.get_mut(&self.unique_id) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.unique_id);
+            }
+        }
+    }
+}
+
+impl AudioCache {
+    /// `AUDIO_CACHE_MB` is the most the cache may hold; 0 turns it off.
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        let megabytes: u64 = match raw {
+            Some(raw) => raw
+                .trim()
+                .parse()
+                .ok()
+                .context("AUDIO_CACHE_MB must be a number of megabytes")?,
+            None => DEFAULT_LIMIT_MB,
+        };
+        if megabytes == 0 {
+            return Ok(None);
+        }
+        Ok(Some(AudioCache {
+            dir: std::env::temp_dir().join("ankh-audio"),
+            limit: megabytes * 1024 * 1024,
+            pins: Arc::default(),
+        }))
+    }
+
+    /// Files bigger than the whole cache are downloaded for one use as before.
+    pub fn fits(&self, size: u64) -> bool {
+        size <= self.limit
+    }
+
+    fn pin(&self, unique_id: &str) -> Pin {
+        *self
+            .pins
+            .lock()
+            .unwrap()
+            .entry(unique_id.to_string())
+            .or_default() += 1;
+        Pin {
+            pins: self.pins.clone(),
+            unique_id: unique_id.to_string(),
+        }
+    }
+
+    /// The cached copy of the file with this `file_unique_id`, marked as just used and
+    /// kept until the pin is dropped.
+    pub fn get(&self, unique_id: &str) -> Option<(PathBuf, Pin)> {
+        let pin = self.pin(unique_id);
+        let path = self.dir.join(unique_id);
+        let file = std::fs::File::options().write(true).open(&path).ok()?;
+        if let Err(e) = file.set_modified(SystemTime::now()) {
+            tracing::warn!("Failed to touch {}: {}", path.display(), e);
+        }
+        Some((path, pin))
+    }
+
+    /// Moves a finished download into the cache and makes room for it. Returns where
+    /// it now lives, kept until the pin is dropped.
+    pub async fn insert(
+        &self,
+        unique_id: &str,
+        download: &Path,
+    ) -> std::io::Result<(PathBuf, Pin)> {
+        let pin = self.pin(unique_id);
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(unique_id);
+        tokio::fs::rename(download, &path).await?;
+        if let Err(e) = self.evict().await {
+            tracing::warn!("Failed to trim the audio cache: {}", e);
+        }
+        Ok((path, pin))
+    }
+
+    /// Removes the least recently used files that nothing holds a pin on until the
+    /// cache is back under its limit.
+    async fn evict(&self) -> std::io::Result<()> {
+        let mut files = Vec::new();
+        let mut total = 0;
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            total += metadata.len();
+            files.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+        if total <= self.limit {
+            return Ok(());
+        }
+
+        files.sort();
+        for (_, size, path) in files {
+            if total <= self.limit {
+                break;
+            }
+            // Checked and removed under the lock, so no one pins it in between.
+            let pins = self.pins.lock().expect("audio cache pins poisoned");
+            let name = path.file_name().and_then(|name| name.to_str());
+            if name.is_some_and(|name| pins.contains_key(name)) {
+                continue;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => total -= size,
+                Err(e) => tracing::warn!("Failed to evict {}: {}", path.display(), e),
+            }
+        }
+        tracing::debug!("Audio cache trimmed to {} bytes", total);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(dir: &str, limit: u64) -> AudioCache {
+        let dir = std::env::temp_dir().join(format!("ankh-audio-test-{}", dir));
+        let _ = std::fs::remove_dir_all(&dir);
+        AudioCache {
+            dir,
+            limit,
+            pins: Arc::default(),
+        }
+    }
+
+    async fn download(cache: &AudioCache, unique_id: &str) -> (PathBuf, Pin) {
+        let download = std::env::temp_dir().join(format!("ankh-test-download-{}", unique_id));
+        std::fs::write(&download, [0; 10]).unwrap();
+        cache.insert(unique_id, &download).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn eviction_skips_pinned_files() {
+        let cache = cache("pinned", 15);
+        let (first, pin) = download(&cache, "first").await;
+        let (second, _) = download(&cache, "second").await;
+        assert!(first.exists(), "a pinned file was evicted");
+        assert!(second.exists());
+        drop(pin);
+        let (third, _) = download(&cache, "third").await;
+        assert!(!first.exists() || !second.exists());
+        assert!(third.exists());
+        let _ = std::fs::remove_dir_all(&cache.dir);
+    }
+
+    #[test]
+    fn pins_are_counted() {
+        let cache = cache("counted", 15);
+        let one = cache.pin("x");
+        let two = cache.pin("x");
+        drop(one);
+        assert!(cache.pins.lock().unwrap().contains_key("x"));
+        drop(two);
+        assert!(cache.pins.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::{FromRow, PgPool};
+use teloxide::types::{Message, Update, UpdateKind};
+
+/// How long audit rows are kept, unless `AUDIT_RETENTION_DAYS` says otherwise.
+pub const DEFAULT_RETENTION_DAYS: u32 = 90;
+const DETAIL_CHARS: usize = 100;
+
+/// The update's field name in the Bot API, e.g. `message` or `callback_query`.
+pub fn kind(update: &Update) -> &'static str {
+    match &update.kind {
+        UpdateKind::Message(_) => "message",
+        UpdateKind::EditedMessage(_) => "edited_message",
+        UpdateKind::ChannelPost(_) => "channel_post",
+        UpdateKind::EditedChannelPost(_) => "edited_channel_post",
+        UpdateKind::BusinessConnection(_) => "business_connection",
+        UpdateKind::BusinessMessage(_) => "business_message",
+        UpdateKind::EditedBusinessMessage(_) => "edited_business_message",
+        UpdateKind::DeletedBusinessMessages(_) => "deleted_business_messages",
+        UpdateKind::MessageReaction(_) => "message_reaction",
+        UpdateKind::MessageReactionCount(_) => "message_reaction_count",
+        UpdateKind::InlineQuery(_) => "inline_query",
+        UpdateKind::ChosenInlineResult(_) => "chosen_inline_result",
+        UpdateKind::CallbackQuery(_) => "callback_query",
+        UpdateKind::ShippingQuery(_) => "shipping_query",
+        UpdateKind::PreCheckoutQuery(_) => "pre_checkout_query",
+        UpdateKind::PurchasedPaidMedia(_) => "purchased_paid_media",
+        UpdateKind::Poll(_) => "poll",
+        UpdateKind::PollAnswer(_) => "poll_answer",
+        UpdateKind::MyChatMember(_) => "my_chat_member",
+        UpdateKind::ChatMember(_) => "chat_member",
+        UpdateKind::ChatJoinRequest(_) => "chat_join_request",
+        UpdateKind::ChatBoost(_) => "chat_boost",
+        UpdateKind::RemovedChatBoost(_) => "removed_chat_boost",
+        UpdateKind::Error(_) => "unknown",
+    }
+}
+
+fn message_detail(message: &Message) -> Option<String> {
+    if let Some(audio) = message.audio() {
+        return match (&audio.performer, &audio.title) {
+            (Some(performer), Some(title)) => Some(format!("{} – {}", performer, title)),
+            (_, Some(title)) => Some(title.clone()),
+            _ => audio.file_name.clone(),
+        };
+    }
+    if let Some(document) = message.document() {
+        return document.file_name.clone();
+    }
+    message
+        .text()
+        .or(message.caption())
+        .map(|text| text.chars().take(DETAIL_CHARS).collect())
+}
+
+fn detail(update: &Update) -> Option<String> {
+    match &update.kind {
+        UpdateKind::Message(message)
+        | UpdateKind::EditedMessage(message)
+        | UpdateKind::ChannelPost(message)
+        | UpdateKind::EditedChannelPost(message) => message_detail(message),
+        UpdateKind::InlineQuery(query) => Some(query.query.clone()),
+        UpdateKind::CallbackQuery(query) => query.data.clone(),
+        _ => None,
+    }
+}
+
+/// Notes that `update` was handled, with the error it failed with, if any.
+pub async fn record(db: &PgPool, update: &Update, error: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO update_audit (update_id, kind, chat_id, user_id, detail, error)
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(i64::from(update.id.0))
+    .bind(kind(update))
+    .bind(update.chat().map(|chat| chat.id.0))
+    .bind(update.from().map(|user| user.id.0 as i64))
+    .bind(detail(update))
+    .bind(error)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+#[derive(FromRow)]
+pub struct Entry {
+    pub update_id: i64,
+    pub kind: String,
+    pub chat_id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub detail: Option<String>,
+    pub error: Option<String>,
+    pub handled_at: DateTime<Utc>,
+}
+
+/// The dashboard's search form. Empty fields match everything.
+#[derive(Default, Deserialize)]
+pub struct Filter {
+    /// Part of the detail, e.g. a file name.
+    pub q: Option<String>,
+    pub kind: Option<String>,
+    /// A chat or user id.
+    pub id: Option<String>,
+    /// `failed` for errors only.
+    pub outcome: Option<String>,
+}
+
+fn non_empty(field: &Option<String>) -> Option<&str> {
+    field
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// An `ILIKE` pattern matching text that contains `q` as typed.
+fn contains(q: &str) -> String {
+    format!("%{}%", crate::catalog::like_escape(q))
+}
+
+/// The newest entries matching `filter`.
+pub async fn search(db: &PgPool, filter: &Filter, limit: i64) -> sqlx::Result<Vec<Entry>> {
+    let id = non_empty(&filter.id).and_then(|id| id.parse::<i64>().ok());
+    sqlx::query_as(
+        "SELECT update_id, kind, chat_id, user_id, detail, error, handled_at
+         FROM update_audit
+         WHERE ($1::text IS NULL OR detail ILIKE $1 ESCAPE '\\')
+           AND ($2::text IS NULL OR kind = $2)
+           AND ($3::bigint IS NULL OR chat_id = $3 OR user_id = $3)
+           AND (NOT $4 OR error IS NOT NULL)
+         ORDER BY id DESC
+         LIMIT $5",
+    )
+    .bind(non_empty(&filter.q).map(contains))
+    .bind(non_empty(&filter.kind))
+    .bind(id)
+    .bind(non_empty(&filter.outcome) == Some("failed"))
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
+
+/// The latest updates whose handler failed, newest first.
+pub async fn recent_errors(db: &PgPool, limit: i64) -> sqlx::Result<Vec<Entry>> {
+    sqlx::query_as(
+        "SELECT update_id, kind, chat_id, user_id, detail, error, handled_at
+         FROM update_audit
+         WHERE error IS NOT NULL
+         ORDER BY id DESC
+         LIMIT $1",
+    )
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
+
+/// Forgets entries older than `days`.
+pub async fn prune(db: &PgPool, days: u32) -> sqlx::Result<u64> {
+    let result = sqlx::query(
+        "DELETE FROM update_audit WHERE handled_at < now() - make_interval(days => $1)",
+    )
+    .bind(days as i32)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_text_is_matched_literally() {
+        assert_eq!(contains("send_audio"), "%send\\_audio%");
+        assert_eq!(contains("100%"), "%100\\%%");
+        assert_eq!(contains(r"C:\temp"), r"%C:\\temp%");
+    }
+}
@@ -0,0 +1,403 @@
+use crate::ServerSecretsState;
+use anyhow::{Context, anyhow, bail};
+use chrono::Utc;
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use subtle::ConstantTimeEq;
+
+/// A permission an API token can be granted. Each admin route names the scope it needs
+/// through the type parameter of [`Authorized`].
+pub trait Scope {
+    const NAME: &'static str;
+}
+
+pub mod scope {
+    use super::Scope;
+
+    pub struct Logs;
+
+    impl Scope for Logs {
+        const NAME: &'static str = "logs";
+    }
+
+    pub struct Queue;
+
+    impl Scope for Queue {
+        const NAME: &'static str = "queue";
+    }
+
+    pub struct Flags;
+
+    impl Scope for Flags {
+        const NAME: &'static str = "flags";
+    }
+
+    pub struct Upload;
+
+    impl Scope for Upload {
+        const NAME: &'static str = "upload";
+    }
+
+    pub struct Updates;
+
+    impl Scope for Updates {
+        const NAME: &'static str = "updates";
+    }
+
+    pub struct Audio;
+
+    impl Scope for Audio {
+        const NAME: &'static str = "audio";
+    }
+
+    pub struct Schedule;
+
+    impl Scope for Schedule {
+        const NAME: &'static str = "schedule";
+    }
+
+    pub struct Admin;
+
+    impl Scope for Admin {
+        const NAME: &'static str = "admin";
+    }
+}
+
+/// Grants every scope.
+const WILDCARD_SCOPE: &str = "*";
+
+struct ApiToken {
+    digest: [u8; 32],
+    scopes: Vec<String>,
+}
+
+/// Bearer tokens accepted by the HTTP API. `ADMIN_TOKEN` grants every scope, while
+/// `API_TOKENS` can add restricted tokens as `token:scope,scope;token2:scope`.
+pub struct ApiTokens {
+    tokens: Vec<ApiToken>,
+}
+
+impl ApiTokens {
+    pub fn new(admin_token: Option<&str>, api_tokens: Option<&str>) -> anyhow::Result<Self> {
+        let mut tokens = Vec::new();
+
+        if let Some(token) = admin_token {
+            tokens.push(ApiToken {
+                digest: digest(token),
+                scopes: vec![WILDCARD_SCOPE.to_string()],
+            });
+        }
+
+        for entry in api_tokens
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (token, scopes) = entry
+                .split_once(':')
+                .context("API_TOKENS entries must look like token:scope,scope")?;
+            if token.is_empty() {
+                bail!("API_TOKENS contains an empty token");
+            }
+            tokens.push(ApiToken {
+                digest: digest(token),
+                scopes: scopes.split(',').map(|s| s.trim().to_string()).collect(),
+            });
+        }
+
+        Ok(Self { tokens })
+    }
+
+    /// Checks `token` against every configured token without short-circuiting, so the
+    /// time taken does not reveal how much of a valid token was guessed.
+    pub fn authorize(&self, token: &str, scope: &str) -> bool {
+        let presented = digest(token);
+        let mut granted = false;
+
+        for candidate in &self.tokens {
+            let matches: bool = candidate.digest.ct_eq(&presented).into();
+            let in_scope = candidate
+                .scopes
+                .iter()
+                .any(|s| s == scope || s == WILDCARD_SCOPE);
+            granted |= matches && in_scope;
+        }
+
+        granted
+    }
+}
+
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+/// The token of an `Authorization: Bearer <token>` header value.
+pub fn bearer_token(authorization: Option<&str>) -> Option<&str> {
+    authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Guards admin routes, requiring a bearer token that has been granted scope `S`.
+/// The backends turn it into a request guard or an extractor.
+pub struct Authorized<S: Scope>(PhantomData<S>);
+
+impl<S: Scope> Authorized<S> {
+    /// Fails with the status to answer with: 401 without a token, 403 when the token
+    /// lacks the scope.
+    pub fn check(secrets: &ServerSecretsState, authorization: Option<&str>) -> Result<Self, u16> {
+        let token = bearer_token(authorization).ok_or(401u16)?;
+        if secrets.api_tokens.authorize(token, S::NAME) {
+            Ok(Authorized(PhantomData))
+        } else {
+            Err(403)
+        }
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SESSION_COOKIE: &str = "session";
+const SESSION_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+const LOGIN_MAX_AGE_SECS: i64 = 24 * 60 * 60;
+
+/// Verifies the fields sent by the Telegram Login Widget as described in
+/// https://core.telegram.org/widgets/login#checking-authorization and returns the
+/// authenticated user id.
+pub fn verify_telegram_login(
+    fields: &HashMap<String, String>,
+    bot_token: &str,
+) -> anyhow::Result<i64> {
+    let hash = fields.get("hash").context("Login data has no hash")?;
+    let hash = hex::decode(hash).context("Login hash is not hex")?;
+
+    let mut pairs = fields
+        .iter()
+        .filter(|(key, _)| key.as_str() != "hash")
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>();
+    pairs.sort();
+
+    let mut mac = HmacSha256::new_from_slice(&Sha256::digest(bot_token.as_bytes()))
+        .expect("HMAC accepts keys of any length");
+    mac.update(pairs.join("\n").as_bytes());
+    mac.verify_slice(&hash)
+        .map_err(|_| anyhow!("Login hash does not match"))?;
+
+    let auth_date: i64 = fields
+        .get("auth_date")
+        .context("Login data has no auth_date")?
+        .parse()
+        .context("auth_date is not a number")?;
+    if Utc::now().timestamp() - auth_date > LOGIN_MAX_AGE_SECS {
+        bail!("Login data is too old");
+    }
+
+    fields
+        .get("id")
+        .context("Login data has no id")?
+        .parse()
+        .context("id is not a number")
+}
+
+/// Session cookies are `user_id:expires_at:signature`, signed with a key derived from
+/// the bot token so no separate secret has to be managed.
+fn session_signature(bot_token: &str, payload: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(format!("session:{}", bot_token).as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// The value of a new session cookie for `user_id`.
+pub fn session_value(bot_token: &str, user_id: i64) -> String {
+    let payload = format!("{}:{}", user_id, Utc::now().timestamp() + SESSION_TTL_SECS);
+    format!("{}:{}", payload, session_signature(bot_token, &payload))
+}
+
+fn session_user(value: &str, bot_token: &str) -> Option<i64> {
+    let (payload, signature) = value.rsplit_once(':')?;
+    let expected = session_signature(bot_token, payload);
+    if !bool::from(expected.as_bytes().ct_eq(signature.as_bytes())) {
+        return None;
+    }
+
+    let (user_id, expires_at) = payload.split_once(':')?;
+    if expires_at.parse::<i64>().ok()? < Utc::now().timestamp() {
+        return None;
+    }
+    user_id.parse().ok()
+}
+
+/// A dashboard visitor: a valid session cookie belonging to one of the configured
+/// dashboard admins.
+pub struct DashboardUser {
+    pub id: i64,
+}
+
+impl DashboardUser {
+    pub fn from_session(secrets: &ServerSecretsState, session: Option<&str>) -> Option<Self> {
+        session
+            .and_then(|value| session_user(value, &secrets.bot_token))
+            .filter(|id| secrets.dashboard_admin_ids.contains(id))
+            .map(|id| DashboardUser { id })
+    }
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_guards::{BearerToken, end_session, start_session};
+
+#[cfg(feature = "rocket")]
+mod rocket_guards {
+    use super::*;
+    use rocket::{
+        Request,
+        http::{Cookie, CookieJar, SameSite, Status},
+        request::{FromRequest, Outcome},
+    };
+    use std::sync::Arc;
+
+    fn secrets<'r>(req: &'r Request<'_>) -> Option<&'r Arc<ServerSecretsState>> {
+        req.rocket().state::<Arc<ServerSecretsState>>()
+    }
+
+    /// Request guard for routes whose scope depends on the request itself, which
+    /// check the token with [`ApiTokens::authorize`] once they know it.
+    pub struct BearerToken<'r>(pub &'r str);
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for BearerToken<'r> {
+        type Error = ();
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            match bearer_token(req.headers().get_one("Authorization")) {
+                Some(token) => Outcome::Success(BearerToken(token)),
+                None => Outcome::Error((Status::Unauthorized, ())),
+            }
+        }
+    }
+
+    #[rocket::async_trait]
+    impl<'r, S: Scope> FromRequest<'r> for Authorized<S> {
+        type Error = ();
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            let Some(secrets) = secrets(req) else {
+                return Outcome::Error((Status::InternalServerError, ()));
+            };
+            match Authorized::check(secrets, req.headers().get_one("Authorization")) {
+                Ok(authorized) => Outcome::Success(authorized),
+                Err(status) => Outcome::Error((Status::new(status), ())),
+            }
+        }
+    }
+
+    pub fn start_session(cookies: &CookieJar<'_>, bot_token: &str, user_id: i64) {
+        cookies.add(
+            Cookie::build((SESSION_COOKIE, session_value(bot_token, user_id)))
+                .http_only(true)
+                .secure(true)
+                .same_site(SameSite::Lax)
+                .max_age(rocket::time::Duration::seconds(SESSION_TTL_SECS)),
+        );
+    }
+
+    pub fn end_session(cookies: &CookieJar<'_>) {
+        cookies.remove(SESSION_COOKIE);
+    }
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for DashboardUser {
+        type Error = ();
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            let Some(secrets) = secrets(req) else {
+                return Outcome::Error((Status::InternalServerError, ()));
+            };
+            let session = req
+                .cookies()
+                .get(SESSION_COOKIE)
+                .map(|cookie| cookie.value());
+            match DashboardUser::from_session(secrets, session) {
+                Some(user) => Outcome::Success(user),
+                None => Outcome::Forward(Status::Unauthorized),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_extractors::{end_session, start_session};
+
+#[cfg(feature = "axum")]
+mod axum_extractors {
+    use super::*;
+    use crate::web::AppState;
+    use axum::{
+        extract::{FromRequestParts, OptionalFromRequestParts},
+        http::{HeaderMap, StatusCode, header, request::Parts},
+    };
+
+    fn authorization(headers: &HeaderMap) -> Option<&str> {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    impl<S: Scope> FromRequestParts<AppState> for Authorized<S> {
+        type Rejection = StatusCode;
+
+        async fn from_request_parts(
+            parts: &mut Parts,
+            state: &AppState,
+        ) -> Result<Self, Self::Rejection> {
+            Authorized::check(&state.secrets, authorization(&parts.headers)).map_err(|status| {
+                StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+            })
+        }
+    }
+
+    /// The `Set-Cookie` header value starting a session for `user_id`.
+    pub fn start_session(bot_token: &str, user_id: i64) -> String {
+        format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+            SESSION_COOKIE,
+            session_value(bot_token, user_id),
+            SESSION_TTL_SECS
+        )
+    }
+
+    /// The `Set-Cookie` header value ending the session.
+    pub fn end_session() -> String {
+        format!("{}=; Path=/; Max-Age=0", SESSION_COOKIE)
+    }
+
+    fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == SESSION_COOKIE)
+            .map(|(_, value)| value)
+    }
+
+    /// Lets handlers take `Option<DashboardUser>` and redirect to the login page.
+    impl OptionalFromRequestParts<AppState> for DashboardUser {
+        type Rejection = std::convert::Infallible;
+
+        async fn from_request_parts(
+            parts: &mut Parts,
+            state: &AppState,
+        ) -> Result<Option<Self>, Self::Rejection> {
+            Ok(DashboardUser::from_session(
+                &state.secrets,
+                session_cookie(&parts.headers),
+            ))
+        }
+    }
+}
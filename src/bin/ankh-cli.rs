@@ -0,0 +1,171 @@
+//! Command-line client for the ankh HTTP API.
+//!
+//! ```text
+//! ankh-cli push track.mp3 [--title TITLE] [--performer PERFORMER] [--tag TAG]...
+//! ankh-cli queue
+//! ankh-cli pause
+//! ankh-cli resume
+//! ```
+//!
+//! The server and token come from `ANKH_URL` and `ANKH_TOKEN`, or `--url` and `--token`.
+
+use anyhow::{Context, bail};
+use reqwest::blocking::{Client, RequestBuilder, multipart};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+
+const USAGE: &str = "usage: ankh-cli [--url URL] [--token TOKEN] <command>
+
+commands:
+  push FILE [--title TITLE] [--performer PERFORMER] [--tag TAG]...
+  queue
+  pause
+  resume";
+
+#[derive(Deserialize)]
+struct UploadReceipt {
+    position: usize,
+    queue_len: usize,
+    estimated_publish_time: String,
+}
+
+#[derive(Deserialize)]
+struct QueueItem {
+    message_id: i32,
+    title: Option<String>,
+    performer: Option<String>,
+    duration_secs: u32,
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct QueueState {
+    paused: bool,
+    items: Vec<QueueItem>,
+}
+
+struct Api {
+    client: Client,
+    url: String,
+    token: String,
+}
+
+impl Api {
+    fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}/api/v1{}", self.url, path))
+            .bearer_auth(&self.token)
+    }
+
+    fn send<T: for<'de> Deserialize<'de>>(&self, request: RequestBuilder) -> anyhow::Result<T> {
+        let response = request.send().context("Request failed")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            bail!("Server answered {}: {}", status, body.trim());
+        }
+        response.json().context("Unexpected response from server")
+    }
+}
+
+/// Pulls `--name value` out of `args`, leaving positional arguments behind.
+fn take_option(args: &mut Vec<String>, name: &str) -> anyhow::Result<Option<String>> {
+    let Some(index) = args.iter().position(|arg| arg == name) else {
+        return Ok(None);
+    };
+    if index + 1 >= args.len() {
+        bail!("{} needs a value", name);
+    }
+    let value = args.remove(index + 1);
+    args.remove(index);
+    Ok(Some(value))
+}
+
+fn take_all(args: &mut Vec<String>, name: &str) -> anyhow::Result<Vec<String>> {
+    let mut values = Vec::new();
+    while let Some(value) = take_option(args, name)? {
+        values.push(value);
+    }
+    Ok(values)
+}
+
+fn print_queue(state: &QueueState) {
+    if state.paused {
+        println!("Queue is paused");
+    }
+    if state.items.is_empty() {
+        println!("Queue is empty");
+    }
+    for (i, item) in state.items.iter().enumerate() {
+        let label = match (&item.performer, &item.title) {
+            (Some(performer), Some(title)) => format!("{} – {}", performer, title),
+            (_, Some(title)) => title.clone(),
+            _ => format!("message {}", item.message_id),
+        };
+        let mut line = format!(
+            "{:>3}. {} ({}:{:02})",
+            i + 1,
+            label,
+            item.duration_secs / 60,
+            item.duration_secs % 60
+        );
+        for tag in &item.tags {
+            line.push_str(&format!(" #{}", tag));
+        }
+        println!("{}", line);
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let url = take_option(&mut args, "--url")?
+        .or_else(|| std::env::var("ANKH_URL").ok())
+        .context("Set ANKH_URL or pass --url")?;
+    let token = take_option(&mut args, "--token")?
+        .or_else(|| std::env::var("ANKH_TOKEN").ok())
+        .context("Set ANKH_TOKEN or pass --token")?;
+    let api = Api {
+        client: Client::builder()
+            .timeout(Duration::from_secs(600))
+            .build()?,
+        url: url.trim_end_matches('/').to_string(),
+        token,
+    };
+
+    let command = if args.is_empty() {
+        String::new()
+    } else {
+        args.remove(0)
+    };
+    match command.as_str() {
+        "push" => {
+            let title = take_option(&mut args, "--title")?;
+            let performer = take_option(&mut args, "--performer")?;
+            let tags = take_all(&mut args, "--tag")?;
+            let [file] = args.as_slice() else {
+                bail!("{}", USAGE);
+            };
+
+            let metadata = json!({ "title": title, "performer": performer, "tags": tags });
+            let form = multipart::Form::new()
+                .file("file", file)
+                .with_context(|| format!("Failed to read {}", file))?
+                .text("metadata", metadata.to_string());
+            let receipt: UploadReceipt = api.send(
+                api.request(reqwest::Method::POST, "/upload")
+                    .multipart(form),
+            )?;
+            println!(
+                "Queued {}/{}, publishing around {}",
+                receipt.position, receipt.queue_len, receipt.estimated_publish_time
+            );
+        }
+        "queue" => print_queue(&api.send(api.request(reqwest::Method::GET, "/queue"))?),
+        "pause" => print_queue(&api.send(api.request(reqwest::Method::POST, "/queue/pause"))?),
+        "resume" => print_queue(&api.send(api.request(reqwest::Method::POST, "/queue/resume"))?),
+        _ => bail!("{}", USAGE),
+    }
+    Ok(())
+}
@@ -0,0 +1,212 @@
+use crate::{QueuedMessage, ServerSecretsState, cleanup, duplicates, flags::Flag, quality};
+use serde_json::{Value, json};
+use shuttle_runtime::SecretStore;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "blurb:";
+
+const DEFAULT_API_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+const PROMPT: &str = "You write for a Telegram music channel. Describe the track below in \
+     one sentence of at most 25 words: its genre, mood and where it fits. Do not invent \
+     facts you are unsure of, do not repeat the title or artist, and answer with the \
+     sentence only.";
+
+/// Writes one-sentence track descriptions with a chat completions API, configured
+/// with `BLURB_API_KEY` and optionally `BLURB_API_URL` (any OpenAI-compatible
+/// endpoint) and `BLURB_MODEL`. Only used while the `blurbs` flag is on.
+pub struct Writer {
+    url: String,
+    key: String,
+    model: String,
+}
+
+impl Writer {
+    pub fn from_secrets(secrets: &SecretStore) -> Option<Self> {
+        Some(Writer {
+            key: secrets.get("BLURB_API_KEY")?,
+            url: secrets
+                .get("BLURB_API_URL")
+                .unwrap_or_else(|| DEFAULT_API_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            model: secrets
+                .get("BLURB_MODEL")
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        })
+    }
+
+    async fn write(&self, message: &QueuedMessage) -> Result<String, Error> {
+        let audio = &message.audio;
+        let (title, performer) = match &message.retag {
+            Some(retag) => (Some(&retag.title), Some(&retag.performer)),
+            None => (audio.title.as_ref(), audio.performer.as_ref()),
+        };
+        let mut track = format!(
+            "Title: {}\nArtist: {}\nDuration: {}:{:02}",
+            title.map_or("unknown", |title| title.as_str()),
+            performer.map_or("unknown", |performer| performer.as_str()),
+            audio.duration.seconds() / 60,
+            audio.duration.seconds() % 60
+        );
+        if !message.tags.is_empty() {
+            track.push_str(&format!("\nTags: {}", message.tags.join(", ")));
+        }
+
+        let response: Value = reqwest::Client::new()
+            .post(format!("{}/chat/completions", self.url))
+            .bearer_auth(&self.key)
+            .json(&json!({
+                "model": self.model,
+                "messages": [
+                    {"role": "system", "content": PROMPT},
+                    {"role": "user", "content": track},
+                ],
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        response["choices"][0]["message"]["content"]
+            .as_str()
+            .map(|text| text.trim().trim_matches('"').to_string())
+            .filter(|text| !text.is_empty())
+            .ok_or_else(|| "The model returned no text".into())
+    }
+}
+
+/// Tracks waiting for the owner to accept or reject their blurb, by the id of the
+/// owner's message. Kept in memory only, like recognitions.
+#[derive(Default)]
+pub struct Pending(Mutex<HashMap<i32, (QueuedMessage, String)>>);
+
+fn keyboard(message_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            "✅ Add it",
+            format!("{}add:{}", CALLBACK_PREFIX, message_id),
+        ),
+        InlineKeyboardButton::callback("Skip", format!("{}skip:{}", CALLBACK_PREFIX, message_id)),
+    ]])
+}
+
+async fn enqueue(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    chat_id: ChatId,
+    message: QueuedMessage,
+) -> Result<(), Error> {
+    let message_id = message.message_id;
+    let position = secrets
+        .message_queue
+        .add_message(message, bot.clone(), secrets.clone())
+        .await;
+    tracing::info!("Added audio to queue (ID: {})", message_id);
+    cleanup::reply(bot, secrets, chat_id, crate::queued_text(&position)).await?;
+    Ok(())
+}
+
+/// Queues a track the owner sent once it is through the quality gate; see
+/// [`queue_checked`].
+pub async fn queue(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    chat_id: ChatId,
+    message: QueuedMessage,
+) -> Result<(), Error> {
+    match quality::check(bot, secrets, chat_id, message).await? {
+        Some(message) => queue_checked(bot, secrets, chat_id, message).await,
+        None => Ok(()),
+    }
+}
+
+/// Queues a track past the quality gate, warning first if it looks already posted.
+/// With blurbs on, a blurb is written first and the track held back until the owner
+/// has accepted or skipped it; if writing fails the track is queued without one.
+pub async fn queue_checked(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    chat_id: ChatId,
+    message: QueuedMessage,
+) -> Result<(), Error> {
+    duplicates::warn(secrets, chat_id, &message).await;
+    let Some(writer) = &secrets.blurb_writer else {
+        return enqueue(bot, secrets, chat_id, message).await;
+    };
+    if !secrets.flags.is_enabled(Flag::Blurbs) {
+        return enqueue(bot, secrets, chat_id, message).await;
+    }
+    let blurb = match writer.write(&message).await {
+        Ok(blurb) => blurb,
+        Err(e) => {
+            tracing::warn!("Failed to write blurb: {}", e);
+            return enqueue(bot, secrets, chat_id, message).await;
+        }
+    };
+
+    let message_id = message.message_id;
+    let text = format!("✍️ {}\n\nAdd this to the caption?", blurb);
+    secrets
+        .blurbs_pending
+        .0
+        .lock()
+        .expect("pending blurbs poisoned")
+        .insert(message_id, (message, blurb));
+    bot.send_message(chat_id, text)
+        .reply_markup(keyboard(message_id))
+        .await?;
+    Ok(())
+}
+
+/// The owner's answer to a blurb: queues the track, with the blurb or without.
+pub async fn handle_callback(
+    bot: &Arc<Bot>,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let (action, message_id) = data.split_once(':').ok_or("Malformed callback data")?;
+    let message_id: i32 = message_id.parse()?;
+    if !matches!(action, "add" | "skip") {
+        return Err("Unknown blurb action".into());
+    }
+    let pending = secrets
+        .blurbs_pending
+        .0
+        .lock()
+        .expect("pending blurbs poisoned")
+        .remove(&message_id);
+    let Some((mut message, blurb)) = pending else {
+        bot.answer_callback_query(query.id.clone())
+            .text("Already answered, or the bot restarted since; send the file again")
+            .await?;
+        return Ok(());
+    };
+
+    let status = if action == "add" {
+        let status = format!("✍️ {}\n\nAdded ✅", blurb);
+        message.blurb = Some(blurb);
+        status
+    } else {
+        "Blurb skipped".to_string()
+    };
+    bot.answer_callback_query(query.id.clone()).await?;
+    let chat_id = match query.regular_message() {
+        Some(proposal) => {
+            bot.edit_message_text(proposal.chat.id, proposal.id, status)
+                .await?;
+            proposal.chat.id
+        }
+        None => secrets.me_id,
+    };
+    enqueue(bot, secrets, chat_id, message).await
+}
@@ -0,0 +1,130 @@
+use chrono::{Days, NaiveDate, Utc};
+use sqlx::PgPool;
+
+/// Hours with fewer posts than this say too little to be ranked.
+const MIN_POSTS_PER_HOUR: i64 = 3;
+/// How many of the best posting hours are shown.
+const BEST_HOURS: i64 = 3;
+
+/// How regularly the channel posts, worked out from the catalog. Days and hours are
+/// UTC.
+pub struct Cadence {
+    /// Days in a row with at least one post, up to today or yesterday.
+    current_streak: u32,
+    longest_streak: u32,
+    /// The longest stretch without posts, as the last day before it and the first
+    /// day after it.
+    longest_gap: Option<(NaiveDate, NaiveDate)>,
+    average_interval: Option<chrono::Duration>,
+    /// Hour of day, posts at that hour and their average reactions, best first.
+    best_hours: Vec<(i32, i64, f64)>,
+}
+
+impl Cadence {
+    /// What `/stats` and the dashboard show, a line each.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Streak: {} day(s), longest {}",
+            self.current_streak, self.longest_streak
+        )];
+        if let Some(interval) = self.average_interval {
+            let hours = interval.num_minutes() as f64 / 60.0;
+            lines.push(if hours < 48.0 {
+                format!("A post every {:.1} hours on average", hours)
+            } else {
+                format!("A post every {:.1} days on average", hours / 24.0)
+            });
+        }
+        if let Some((from, to)) = self.longest_gap {
+            lines.push(format!(
+                "Longest gap: {} days, {} to {}",
+                (to - from).num_days() - 1,
+                from.format("%Y-%m-%d"),
+                to.format("%Y-%m-%d")
+            ));
+        }
+        if !self.best_hours.is_empty() {
+            let hours = self
+                .best_hours
+                .iter()
+                .map(|(hour, posts, reactions)| {
+                    format!(
+                        "{:02}:00 ({:.1} reactions over {} posts)",
+                        hour, reactions, posts
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(format!("Best hours (UTC): {}", hours));
+        }
+        lines
+    }
+}
+
+/// Streaks and the longest gap, from the days with posts in order.
+fn streaks(days: &[NaiveDate], today: NaiveDate) -> (u32, u32, Option<(NaiveDate, NaiveDate)>) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut gap: Option<(NaiveDate, NaiveDate)> = None;
+    for (i, &day) in days.iter().enumerate() {
+        match i.checked_sub(1).map(|i| days[i]) {
+            Some(previous) if previous + Days::new(1) == day => run += 1,
+            Some(previous) => {
+                run = 1;
+                if gap.is_none_or(|(from, to)| day - previous > to - from) {
+                    gap = Some((previous, day));
+                }
+            }
+            None => run = 1,
+        }
+        longest = longest.max(run);
+    }
+    let current = match days.last() {
+        Some(&last) if last + Days::new(1) >= today => run,
+        _ => 0,
+    };
+    (current, longest, gap)
+}
+
+/// `None` while the catalog is empty.
+pub async fn analyze(db: &PgPool) -> sqlx::Result<Option<Cadence>> {
+    let days: Vec<NaiveDate> = sqlx::query_scalar(
+        "SELECT DISTINCT (posted_at AT TIME ZONE 'UTC')::date FROM tracks
+         WHERE deleted_at IS NULL ORDER BY 1",
+    )
+    .fetch_all(db)
+    .await?;
+    if days.is_empty() {
+        return Ok(None);
+    }
+    let (current_streak, longest_streak, longest_gap) = streaks(&days, Utc::now().date_naive());
+
+    let average_secs: Option<f64> = sqlx::query_scalar(
+        "SELECT EXTRACT(EPOCH FROM MAX(posted_at) - MIN(posted_at))::float8
+             / NULLIF(COUNT(*) - 1, 0)
+         FROM tracks WHERE deleted_at IS NULL",
+    )
+    .fetch_one(db)
+    .await?;
+
+    let best_hours = sqlx::query_as(
+        "SELECT EXTRACT(HOUR FROM posted_at AT TIME ZONE 'UTC')::int AS hour,
+                COUNT(*) AS posts,
+                AVG(reaction_count)::float8 AS reactions
+         FROM tracks WHERE deleted_at IS NULL
+         GROUP BY hour HAVING COUNT(*) >= $1
+         ORDER BY reactions DESC, posts DESC LIMIT $2",
+    )
+    .bind(MIN_POSTS_PER_HOUR)
+    .bind(BEST_HOURS)
+    .fetch_all(db)
+    .await?;
+
+    Ok(Some(Cadence {
+        current_streak,
+        longest_streak,
+        longest_gap,
+        average_interval: average_secs.map(|secs| chrono::Duration::seconds(secs as i64)),
+        best_hours,
+    }))
+}
@@ -0,0 +1,434 @@
+use crate::{
+    ServerSecretsState,
+    auth::{Scope, scope},
+    jobs, scheduled,
+    themes::Themes,
+    web::HttpError,
+};
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+use teloxide::{prelude::*, types::ParseMode, utils::markdown};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How far ahead the calendar looks.
+const DAYS_AHEAD: u64 = 14;
+/// How far ahead `/schedule.ics` looks; calendar apps show more than two weeks.
+const FEED_DAYS_AHEAD: u64 = 60;
+/// How long each entry lasts in `/schedule.ics`, since posts have no end.
+const FEED_EVENT_MINUTES: u32 = 15;
+/// iCalendar content lines are folded after this many bytes.
+const FEED_LINE_BYTES: usize = 75;
+
+/// Something going out to the channel or subscribers.
+pub struct Entry {
+    pub at: DateTime<Utc>,
+    pub icon: &'static str,
+    pub label: String,
+}
+
+/// The jobs that post something, named for the calendar; housekeeping is left out,
+/// and theme slots come from [`Themes::slots_between`] instead. The year in review
+/// runs daily but only posts on December 31, so only that run is listed.
+fn job_entry(name: &str, at: DateTime<Utc>) -> Option<Entry> {
+    let (icon, label) = match name {
+        "on_this_day" => ("🕰", "On this day"),
+        "now_playing" => ("🎧", "Now playing"),
+        "digest" => ("📬", "Weekly digest"),
+        "year_in_review" if (at.month(), at.day()) == (12, 31) => ("🎁", "Year in review"),
+        _ => return None,
+    };
+    Some(Entry {
+        at,
+        icon,
+        label: label.to_string(),
+    })
+}
+
+/// Scheduled posts, theme slots and posting job runs as one list, soonest first.
+fn entries(
+    scheduled: Vec<(DateTime<Utc>, String)>,
+    themes: &Themes,
+    jobs: Vec<(&str, DateTime<Utc>)>,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = scheduled
+        .into_iter()
+        .map(|(at, label)| Entry {
+            at,
+            icon: "🗓",
+            label,
+        })
+        .collect();
+    entries.extend(
+        themes
+            .slots_between(from, until)
+            .into_iter()
+            .map(|(at, name)| Entry {
+                at,
+                icon: "🎨",
+                label: name.to_string(),
+            }),
+    );
+    entries.extend(
+        jobs.into_iter()
+            .filter_map(|(name, at)| job_entry(name, at)),
+    );
+    entries.sort_by_key(|entry| entry.at);
+    entries
+}
+
+/// Scheduled posts, theme slots and the posting jobs over the next two weeks,
+/// soonest first.
+pub async fn upcoming(secrets: &ServerSecretsState) -> sqlx::Result<Vec<Entry>> {
+    within(secrets, DAYS_AHEAD).await
+}
+
+/// Like [`upcoming`], over the next `days`.
+async fn within(secrets: &ServerSecretsState, days: u64) -> sqlx::Result<Vec<Entry>> {
+    let now = Utc::now();
+    let until = now + chrono::Duration::days(days as i64);
+    Ok(entries(
+        scheduled::upcoming(&secrets.db, until).await?,
+        &secrets.themes,
+        jobs::runs_between(now, until),
+        now,
+        until,
+    ))
+}
+
+/// A week per row, Monday first, with how many things happen each day:
+///
+/// ```text
+///        Mo Tu We Th Fr Sa Su
+/// 13 Oct           ·  2  1  ·
+/// 20 Oct  1  ·  ·  2  1  ·  ·
+/// ```
+fn grid(entries: &[Entry], today: NaiveDate) -> String {
+    let last = today + Days::new(DAYS_AHEAD);
+    let mut week = today - Days::new(u64::from(today.weekday().num_days_from_monday()));
+    let mut text = "       Mo Tu We Th Fr Sa Su".to_string();
+    while week <= last {
+        text.push_str(&format!("\n{}", week.format("%d %b")));
+        for offset in 0..7 {
+            let day = week + Days::new(offset);
+            let cell = if day < today || day > last {
+                String::new()
+            } else {
+                match entries.iter().filter(|e| e.at.date_naive() == day).count() {
+                    0 => "·".to_string(),
+                    count => count.to_string(),
+                }
+            };
+            text.push_str(&format!(" {:>2}", cell));
+        }
+        week = week + Days::new(7);
+    }
+    text
+}
+
+/// `/calendar`: the next two weeks as a grid, then what happens when.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let entries = upcoming(secrets).await?;
+    let mut text = markdown::code_block(&grid(&entries, Utc::now().date_naive()));
+    if entries.is_empty() {
+        text.push_str(&markdown::escape("\nNothing planned."));
+    }
+    let mut day = None;
+    for entry in &entries {
+        if day != Some(entry.at.date_naive()) {
+            day = Some(entry.at.date_naive());
+            text.push_str(&format!(
+                "\n*{}*\n",
+                markdown::escape(&entry.at.format("%a %-d %b").to_string())
+            ));
+        }
+        text.push_str(&markdown::escape(&format!(
+            "{} UTC {} {}\n",
+            entry.at.format("%H:%M"),
+            entry.icon,
+            entry.label
+        )));
+    }
+    bot.send_message(message.chat.id, text)
+        .parse_mode(ParseMode::MarkdownV2)
+        .await?;
+    Ok(())
+}
+
+/// Escapes `text` for an iCalendar property value.
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Folds a content line after every 75 bytes, not splitting characters, as
+/// iCalendar requires.
+fn ics_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > FEED_LINE_BYTES {
+            out.push_str("\r\n ");
+            // The space that starts a continuation counts towards its length.
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn ics_time(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// The entries as an iCalendar feed. An entry keeps its UID from one fetch to the
+/// next as long as its time and label stay the same.
+fn ics(entries: &[Entry], now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    for line in [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//ankh//schedule//EN",
+        "CALSCALE:GREGORIAN",
+        "METHOD:PUBLISH",
+        "X-WR-CALNAME:Channel schedule",
+    ] {
+        ics_line(&mut out, line);
+    }
+    for entry in entries {
+        let uid = hex::encode(Sha256::digest(
+            format!("{}|{}", entry.at.timestamp(), entry.label).as_bytes(),
+        ));
+        ics_line(&mut out, "BEGIN:VEVENT");
+        ics_line(&mut out, &format!("UID:{}@ankh", &uid[..32]));
+        ics_line(&mut out, &format!("DTSTAMP:{}", ics_time(now)));
+        ics_line(&mut out, &format!("DTSTART:{}", ics_time(entry.at)));
+        ics_line(&mut out, &format!("DURATION:PT{}M", FEED_EVENT_MINUTES));
+        ics_line(
+            &mut out,
+            &format!(
+                "SUMMARY:{}",
+                ics_escape(&format!("{} {}", entry.icon, entry.label))
+            ),
+        );
+        ics_line(&mut out, "TRANSP:TRANSPARENT");
+        ics_line(&mut out, "END:VEVENT");
+    }
+    ics_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// `GET /schedule.ics`: the next two months of [`upcoming`] for calendar apps. They
+/// cannot send headers, so the token, one granted the `schedule` scope, may also
+/// come as `?token=`; [`logs::redact`](crate::logs::redact) keeps it out of the logs.
+async fn schedule_ics(
+    secrets: &ServerSecretsState,
+    token: Option<&str>,
+) -> Result<String, HttpError> {
+    let token = token.ok_or_else(|| HttpError::new(401, "Missing token"))?;
+    if !secrets.api_tokens.authorize(token, scope::Schedule::NAME) {
+        return Err(HttpError::new(403, "Token not valid for the schedule"));
+    }
+    let entries = within(secrets, FEED_DAYS_AHEAD)
+        .await
+        .map_err(|e| HttpError::internal("Failed to list upcoming posts", e))?;
+    Ok(ics(&entries, Utc::now()))
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_routes::routes;
+
+#[cfg(feature = "rocket")]
+mod rocket_routes {
+    use super::*;
+    use crate::auth::BearerToken;
+    use rocket::{Route, State, get, http::ContentType, routes};
+    use std::sync::Arc;
+
+    pub fn routes() -> Vec<Route> {
+        routes![schedule]
+    }
+
+    #[get("/schedule.ics?<token>")]
+    async fn schedule(
+        token: Option<&str>,
+        bearer: Option<BearerToken<'_>>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Result<(ContentType, String), HttpError> {
+        let token = bearer.map(|bearer| bearer.0).or(token);
+        Ok((ContentType::Calendar, schedule_ics(secrets, token).await?))
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_routes::router;
+
+#[cfg(feature = "axum")]
+mod axum_routes {
+    use super::*;
+    use crate::{auth::bearer_token, web::AppState};
+    use axum::{
+        Router,
+        extract::{Query, State},
+        http::{
+            HeaderMap,
+            header::{AUTHORIZATION, CONTENT_TYPE},
+        },
+        response::IntoResponse,
+        routing::get,
+    };
+    use serde::Deserialize;
+
+    pub fn router() -> Router<AppState> {
+        Router::new().route("/schedule.ics", get(schedule))
+    }
+
+    #[derive(Deserialize)]
+    struct ScheduleQuery {
+        token: Option<String>,
+    }
+
+    async fn schedule(
+        State(state): State<AppState>,
+        Query(query): Query<ScheduleQuery>,
+        headers: HeaderMap,
+    ) -> Result<impl IntoResponse, HttpError> {
+        let token = bearer_token(
+            headers
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok()),
+        )
+        .or(query.token.as_deref());
+        let body = schedule_ics(&state.secrets, token).await?;
+        Ok(([(CONTENT_TYPE, "text/calendar; charset=utf-8")], body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Format;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        // 2025-10-13 is a Monday.
+        Utc.with_ymd_and_hms(2025, 10, day, hour, 0, 0).unwrap()
+    }
+
+    fn themes() -> Themes {
+        Themes::from_secret(
+            Some(r#"[{"day": "thu", "name": "Throwback Thursday", "hour": 18, "source": "catalog"}]"#),
+            Format::MarkdownV2,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn theme_slots_are_listed_on_their_weekday() {
+        let themes = themes();
+        let slots = themes.slots_between(at(13, 12), at(27, 12));
+        assert_eq!(
+            slots,
+            [
+                (at(16, 18), "Throwback Thursday"),
+                (at(23, 18), "Throwback Thursday")
+            ]
+        );
+        assert!(themes.slots_between(at(16, 18), at(16, 23)).is_empty());
+    }
+
+    #[test]
+    fn entries_merge_soonest_first_and_skip_housekeeping() {
+        let entries = entries(
+            vec![(at(15, 9), "Release day".to_string())],
+            &themes(),
+            vec![("digest", at(17, 10)), ("prune_audit", at(14, 3))],
+            at(13, 12),
+            at(18, 12),
+        );
+        let listed: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.at, entry.icon, entry.label.as_str()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                (at(15, 9), "🗓", "Release day"),
+                (at(16, 18), "🎨", "Throwback Thursday"),
+                (at(17, 10), "📬", "Weekly digest"),
+            ]
+        );
+    }
+
+    #[test]
+    fn year_in_review_is_listed_on_new_years_eve_only() {
+        let eve = Utc.with_ymd_and_hms(2025, 12, 31, 10, 0, 0).unwrap();
+        let entry = job_entry("year_in_review", eve).unwrap();
+        assert_eq!((entry.icon, entry.label.as_str()), ("🎁", "Year in review"));
+        assert!(job_entry("year_in_review", at(16, 10)).is_none());
+    }
+
+    #[test]
+    fn feed_includes_the_year_in_review() {
+        let from = Utc.with_ymd_and_hms(2025, 12, 20, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(2026, 1, 10, 0, 0, 0).unwrap();
+        let runs = (0..21)
+            .map(|day| {
+                (
+                    "year_in_review",
+                    from + chrono::Duration::days(day) + chrono::Duration::hours(10),
+                )
+            })
+            .collect();
+        let entries = entries(Vec::new(), &Themes::default(), runs, from, until);
+        let feed = ics(&entries, from);
+        assert_eq!(feed.matches("SUMMARY:🎁 Year in review").count(), 1);
+        assert!(feed.contains("DTSTART:20251231T100000Z"));
+    }
+
+    #[test]
+    fn grid_counts_entries_per_day() {
+        let entries = [
+            Entry {
+                at: at(15, 9),
+                icon: "🗓",
+                label: String::new(),
+            },
+            Entry {
+                at: at(15, 18),
+                icon: "🎨",
+                label: String::new(),
+            },
+        ];
+        let grid = grid(&entries, at(14, 0).date_naive());
+        let lines: Vec<_> = grid.lines().collect();
+        assert_eq!(lines[0], "       Mo Tu We Th Fr Sa Su");
+        assert_eq!(lines[1], "13 Oct     ·  2  ·  ·  ·  ·");
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn feed_escapes_folds_and_keeps_uids() {
+        let entry = Entry {
+            at: at(16, 18),
+            icon: "🎨",
+            label: format!("Throwback, part; 1 {}", "x".repeat(80)),
+        };
+        let feed = ics(std::slice::from_ref(&entry), at(13, 12));
+        assert!(feed.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(feed.ends_with("END:VCALENDAR\r\n"));
+        assert!(feed.contains("DTSTART:20251016T180000Z\r\n"));
+        assert!(feed.contains("SUMMARY:🎨 Throwback\\, part\\; 1 "));
+        assert!(feed.split("\r\n").all(|line| line.len() <= FEED_LINE_BYTES));
+        assert!(feed.contains("\r\n x"));
+        assert_eq!(feed, ics(&[entry], at(13, 12)));
+    }
+}
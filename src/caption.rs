@@ -0,0 +1,287 @@
+use crate::{
+    catalog::{self, Track},
+    format::Format,
+    media,
+    translit::Scheme,
+};
+use std::path::Path;
+use teloxide::types::Audio;
+
+/// The caption when `CAPTION_TEMPLATE` is not set: just the numbered link.
+pub const DEFAULT_TEMPLATE: &str = "{series}";
+
+/// What a caption template can say about the track. The template itself is
+/// MarkdownV2, or HTML with `CAPTION_PARSE_MODE=html`, so any literal text in it must
+/// be escaped for that mode; filled-in values are escaped here.
+///
+/// - `{series}`: the numbered link to the post, e.g. "Music: Reborn № 42"
+/// - `{title}`, `{performer}`: from the audio's metadata, empty when missing
+/// - `{title_latin}`, `{performer_latin}`: the same in Latin letters, e.g. "Kino";
+///   empty unless `TRANSLITERATE` is set and the original is in Cyrillic
+/// - `{duration}`: e.g. "3:25"
+/// - `{size}`: e.g. "8.1 MB"
+/// - `{bitrate}`: e.g. "320 kbps"
+/// - `{bpm}`: e.g. "128 BPM", and `{key}`: e.g. "Am"; empty unless `AUDIO_ANALYSIS`
+///   is on and the estimate worked
+/// - `{blurb}`: the one-sentence description the owner accepted, if any; put above
+///   everything else when the template does not place it
+/// - `{theme}`: the name of the weekday theme the post belongs to, if any
+/// - `{part}`: e.g. "Part 2", for mixes split into parts
+/// - `{first_part}`: a link back to part 1 on every later part; put below everything
+///   else when the template does not place it
+/// - `{artist_index}`: a link to the performer's index post, once `ARTIST_INDEX` has
+///   made one; empty otherwise
+/// - `{hashtags}`: the ones `AUTO_HASHTAGS` made up, e.g. "#daft_punk #y1997"; put
+///   last when the template does not place them
+/// - `{description}`: what the owner wrote in the caption of the audio, hashtags
+///   aside, followed by a second block with its translation when `TRANSLATOR` is set
+#[derive(Default)]
+pub struct Facts {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub title_latin: Option<String>,
+    pub performer_latin: Option<String>,
+    pub duration_secs: u32,
+    pub size_bytes: Option<u64>,
+    /// Measured on the file when it was downloaded; otherwise worked out from the size
+    /// and duration.
+    pub bitrate_kbps: Option<u32>,
+    pub description: Option<String>,
+    pub translation: Option<String>,
+    pub blurb: Option<String>,
+    pub bpm: Option<i32>,
+    pub key: Option<String>,
+    pub theme: Option<String>,
+    pub part: Option<i32>,
+    /// The message id of the first part, for the later ones.
+    pub first_part: Option<i32>,
+    /// The message id of the performer's index post, if there is one.
+    pub artist_index: Option<i32>,
+    /// Without their `#`.
+    pub hashtags: Vec<String>,
+}
+
+impl Facts {
+    pub fn of_audio(audio: &Audio) -> Self {
+        Facts {
+            title: audio.title.clone(),
+            performer: audio.performer.clone(),
+            title_latin: None,
+            performer_latin: None,
+            duration_secs: audio.duration.seconds(),
+            size_bytes: Some(audio.file.size.into()),
+            bitrate_kbps: None,
+            description: None,
+            translation: None,
+            blurb: None,
+            bpm: None,
+            key: None,
+            theme: None,
+            part: None,
+            first_part: None,
+            artist_index: None,
+            hashtags: Vec::new(),
+        }
+    }
+
+    pub fn of_track(track: &Track) -> Self {
+        Facts {
+            title: track.title.clone(),
+            performer: track.performer.clone(),
+            title_latin: track.title_latin.clone(),
+            performer_latin: track.performer_latin.clone(),
+            duration_secs: track.duration_secs.max(0) as u32,
+            size_bytes: track.file_size.map(|size| size.max(0) as u64),
+            bitrate_kbps: None,
+            description: None,
+            translation: None,
+            blurb: None,
+            bpm: track.bpm,
+            key: track.musical_key.clone(),
+            theme: None,
+            part: track.part_number,
+            first_part: None,
+            artist_index: None,
+            hashtags: Vec::new(),
+        }
+    }
+
+    /// Spells Cyrillic title and performer out in Latin letters too.
+    pub fn transliterate(&mut self, scheme: Scheme) {
+        self.title_latin = self.title.as_deref().and_then(|title| scheme.latin(title));
+        self.performer_latin = self
+            .performer
+            .as_deref()
+            .and_then(|performer| scheme.latin(performer));
+    }
+
+    /// Takes size and bitrate from the file that is actually going to be posted.
+    pub async fn measure(&mut self, path: &Path) {
+        match tokio::fs::metadata(path).await {
+            Ok(metadata) => self.size_bytes = Some(metadata.len()),
+            Err(e) => tracing::warn!("Failed to read size of {}: {}", path.display(), e),
+        }
+        match media::probe_bitrate(path).await {
+            Ok(kbps) => self.bitrate_kbps = Some(kbps),
+            Err(e) => tracing::warn!("Failed to probe bitrate: {}", e),
+        }
+    }
+
+    fn description(&self, format: Format) -> String {
+        let mut blocks = self.description.iter().chain(&self.translation);
+        let mut text = blocks
+            .next()
+            .map(|block| format.escape(block))
+            .unwrap_or_default();
+        for block in blocks {
+            text.push_str(&format!("\n\n{}", format.escape(block)));
+        }
+        text
+    }
+
+    fn bitrate(&self) -> Option<u32> {
+        self.bitrate_kbps.or_else(|| {
+            let size = self.size_bytes?;
+            (self.duration_secs > 0)
+                .then(|| (size * 8 / 1000 / u64::from(self.duration_secs)) as u32)
+        })
+    }
+}
+
+/// Fills `template` in. `series` is the numbered link, already in `format`.
+pub fn render(format: Format, template: &str, series: &str, facts: &Facts) -> String {
+    let mut template = match &facts.blurb {
+        Some(_) if !template.contains("{blurb}") => format!("{{blurb}}\n\n{}", template),
+        _ => template.to_string(),
+    };
+    if facts.first_part.is_some() && !template.contains("{first_part}") {
+        template.push_str("\n\n{first_part}");
+    }
+    if !facts.hashtags.is_empty() && !template.contains("{hashtags}") {
+        template.push_str("\n\n{hashtags}");
+    }
+    let hashtags = facts
+        .hashtags
+        .iter()
+        .map(|tag| format!("#{}", tag))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let first_part = facts
+        .first_part
+        .map(|message_id| format.link(&catalog::permalink(message_id), &format.escape("⏮ Part 1")))
+        .unwrap_or_default();
+    let artist_index = facts
+        .artist_index
+        .map(|message_id| {
+            format.link(
+                &catalog::permalink(message_id),
+                &format.escape("🎤 Discography"),
+            )
+        })
+        .unwrap_or_default();
+    let part = facts
+        .part
+        .map(|part| format!("Part {}", part))
+        .unwrap_or_default();
+    let duration = format!(
+        "{}:{:02}",
+        facts.duration_secs / 60,
+        facts.duration_secs % 60
+    );
+    let size = facts
+        .size_bytes
+        .map(|size| format!("{:.1} MB", size as f64 / 1_000_000.0))
+        .unwrap_or_default();
+    let bitrate = facts
+        .bitrate()
+        .map(|kbps| format!("{} kbps", kbps))
+        .unwrap_or_default();
+    let bpm = facts
+        .bpm
+        .map(|bpm| format!("{} BPM", bpm))
+        .unwrap_or_default();
+    template
+        .replace("{series}", series)
+        .replace(
+            "{title}",
+            &format.escape(facts.title.as_deref().unwrap_or_default()),
+        )
+        .replace(
+            "{performer}",
+            &format.escape(facts.performer.as_deref().unwrap_or_default()),
+        )
+        .replace(
+            "{title_latin}",
+            &format.escape(facts.title_latin.as_deref().unwrap_or_default()),
+        )
+        .replace(
+            "{performer_latin}",
+            &format.escape(facts.performer_latin.as_deref().unwrap_or_default()),
+        )
+        .replace("{duration}", &format.escape(&duration))
+        .replace("{size}", &format.escape(&size))
+        .replace("{bitrate}", &format.escape(&bitrate))
+        .replace("{bpm}", &format.escape(&bpm))
+        .replace(
+            "{key}",
+            &format.escape(facts.key.as_deref().unwrap_or_default()),
+        )
+        .replace(
+            "{theme}",
+            &format.escape(facts.theme.as_deref().unwrap_or_default()),
+        )
+        .replace("{part}", &format.escape(&part))
+        .replace("{first_part}", &first_part)
+        .replace("{artist_index}", &artist_index)
+        .replace("{hashtags}", &format.escape(&hashtags))
+        .replace("{description}", &facts.description(format))
+        .replace(
+            "{blurb}",
+            &format.escape(facts.blurb.as_deref().unwrap_or_default()),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> Facts {
+        Facts {
+            title: Some("Rock & Roll".to_string()),
+            performer: Some("A.B.".to_string()),
+            duration_secs: 185,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_escapes_for_html() {
+        let caption = render(
+            Format::Html,
+            "<b>{title}</b> — {performer} ({duration})\n{series}",
+            "№ 1",
+            &facts(),
+        );
+        assert_eq!(caption, "<b>Rock &amp; Roll</b> — A.B. (3:05)\n№ 1");
+    }
+
+    #[test]
+    fn render_escapes_for_markdown() {
+        let caption = render(Format::MarkdownV2, "*{title}* — {performer}", "", &facts());
+        assert_eq!(caption, "*Rock & Roll* — A\\.B\\.");
+    }
+
+    #[test]
+    fn render_puts_hashtags_last_unless_placed() {
+        let mut facts = facts();
+        facts.hashtags = vec!["house".to_string(), "y1997".to_string()];
+        assert_eq!(
+            render(Format::Html, "{title}", "", &facts),
+            "Rock &amp; Roll\n\n#house #y1997"
+        );
+        assert_eq!(
+            render(Format::Html, "{hashtags} {title}", "", &facts),
+            "#house #y1997 Rock &amp; Roll"
+        );
+    }
+}
@@ -0,0 +1,818 @@
+use crate::{format::Format, mirror::Attribution};
+use async_graphql::{ComplexObject, SimpleObject};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder, types::Json};
+use std::sync::RwLock;
+
+/// The series new posts go into, unless they continue a part in another one.
+pub const SERIES: &str = "Music: Reborn";
+
+/// Public link prefix of the channel, used for caption links and permalinks.
+const DEFAULT_CHANNEL_LINK: &str = "https://t.me/the_ankh_music";
+
+/// The public link of the active channel, replaced when the channel is migrated.
+static CHANNEL_LINK: RwLock<Option<String>> = RwLock::new(None);
+
+pub fn channel_link() -> String {
+    CHANNEL_LINK
+        .read()
+        .expect("channel link lock poisoned")
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CHANNEL_LINK.to_string())
+}
+
+pub fn set_channel_link(link: String) {
+    *CHANNEL_LINK.write().expect("channel link lock poisoned") = Some(link);
+}
+
+/// A published channel post, as recorded after a successful send.
+#[derive(Clone, FromRow, Serialize, SimpleObject)]
+#[graphql(complex)]
+pub struct Track {
+    pub id: i64,
+    /// The channel the post is in; older than the current one after a `/migrate`.
+    #[graphql(skip)]
+    pub channel_id: i64,
+    pub message_id: i32,
+    #[graphql(skip)]
+    #[serde(skip)]
+    pub file_id: String,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub album: Option<String>,
+    pub release_id: Option<i64>,
+    pub file_name: Option<String>,
+    pub duration_secs: i32,
+    pub file_size: Option<i64>,
+    pub series: String,
+    /// "№" of the post within its series; tracks imported from elsewhere have none.
+    pub number: Option<i32>,
+    pub tags: Vec<String>,
+    pub caption: String,
+    /// The [`Format`] `caption` is written in, by name.
+    #[graphql(skip)]
+    pub caption_format: String,
+    pub posted_at: DateTime<Utc>,
+    /// Reactions on the channel post, all kinds summed.
+    pub reaction_count: i32,
+    /// Estimated when posted, with `AUDIO_ANALYSIS` on.
+    pub bpm: Option<i32>,
+    /// E.g. "Am"; estimated along with the tempo.
+    pub musical_key: Option<String>,
+    /// Set for Cyrillic titles when `TRANSLITERATE` is on.
+    pub title_latin: Option<String>,
+    pub performer_latin: Option<String>,
+    /// Which part of a mix split into parts this is; see [`crate::parts`].
+    pub part_number: Option<i32>,
+    /// Set on every part after the first, pointing at the first.
+    pub first_part_id: Option<i64>,
+    /// Posted as paid media; only the channel post may be handed out.
+    pub paid: bool,
+    /// Credit under the caption for tracks mirrored from a partner channel.
+    #[graphql(skip)]
+    #[serde(skip)]
+    pub via: Option<Json<Attribution>>,
+}
+
+impl Track {
+    /// "Performer – Title", falling back to whatever metadata the track has.
+    pub fn label(&self) -> String {
+        match (&self.performer, &self.title) {
+            (Some(performer), Some(title)) => format!("{} – {}", performer, title),
+            (None, Some(title)) => title.clone(),
+            (Some(performer), None) => performer.clone(),
+            (None, None) => self
+                .file_name
+                .clone()
+                .unwrap_or_else(|| format!("Track #{}", self.message_id)),
+        }
+    }
+
+    pub fn caption_format(&self) -> Format {
+        Format::from_name(&self.caption_format)
+    }
+
+    /// "3:25 · 8.1 MB", for showing a track's weight before downloading.
+    pub fn size_label(&self) -> String {
+        let mut label = format!("{}:{:02}", self.duration_secs / 60, self.duration_secs % 60);
+        if let Some(size) = self.file_size {
+            label.push_str(&format!(" · {:.1} MB", size as f64 / 1_000_000.0));
+        }
+        label
+    }
+}
+
+#[ComplexObject]
+impl Track {
+    async fn permalink(&self) -> String {
+        permalink(self.message_id)
+    }
+
+    async fn release(
+        &self,
+        ctx: &async_graphql::Context<'_>,
+    ) -> async_graphql::Result<Option<Release>> {
+        match self.release_id {
+            Some(id) => Ok(get_release(ctx.data::<PgPool>()?, id).await?),
+            None => Ok(None),
+        }
+    }
+
+    /// All the parts of the mix this track is a part of, in order; empty for tracks
+    /// that are not.
+    async fn parts(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Vec<Track>> {
+        if self.part_number.is_none() {
+            return Ok(Vec::new());
+        }
+        let first = self.first_part_id.unwrap_or(self.id);
+        Ok(parts(ctx.data::<PgPool>()?, first).await?)
+    }
+}
+
+pub fn permalink(message_id: i32) -> String {
+    format!("{}/{}", channel_link(), message_id)
+}
+
+pub struct NewTrack<'a> {
+    pub channel_id: i64,
+    pub message_id: i32,
+    pub file_id: &'a str,
+    pub file_unique_id: &'a str,
+    pub title: Option<&'a str>,
+    pub performer: Option<&'a str>,
+    pub album: Option<&'a str>,
+    pub release_id: Option<i64>,
+    pub file_name: Option<&'a str>,
+    pub duration_secs: i32,
+    pub file_size: Option<i64>,
+    pub series: &'a str,
+    /// Taken from [`next_number`] right before posting.
+    pub number: Option<i32>,
+    pub tags: &'a [String],
+    pub caption: &'a str,
+    pub caption_format: Format,
+    /// When the post went up, if not just now; set when importing older posts.
+    pub posted_at: Option<DateTime<Utc>>,
+    pub bpm: Option<i32>,
+    pub musical_key: Option<&'a str>,
+    pub title_latin: Option<&'a str>,
+    pub performer_latin: Option<&'a str>,
+    pub part_number: Option<i32>,
+    pub part_base: Option<&'a str>,
+    pub first_part_id: Option<i64>,
+    pub paid: bool,
+    pub via: Option<&'a Attribution>,
+}
+
+/// The number the next post in `series` gets. It is only used up once a track with
+/// it is recorded, so a post that fails to go out leaves no gap.
+pub async fn next_number(pool: &PgPool, series: &str) -> sqlx::Result<i32> {
+    let next: Option<i32> =
+        sqlx::query_scalar("SELECT next_number FROM series_counters WHERE series = $1")
+            .bind(series)
+            .fetch_optional(pool)
+            .await?;
+    Ok(next.unwrap_or(1))
+}
+
+/// The track posted at `message_id`, for rebuilding its caption.
+pub async fn track_at(
+    pool: &PgPool,
+    channel_id: i64,
+    message_id: i32,
+) -> sqlx::Result<Option<Track>> {
+    sqlx::query_as("SELECT * FROM tracks WHERE channel_id = $1 AND message_id = $2")
+        .bind(channel_id)
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn record_track(pool: &PgPool, track: &NewTrack<'_>) -> sqlx::Result<Track> {
+    let mut tx = pool.begin().await?;
+    if let Some(number) = track.number {
+        sqlx::query(
+            "INSERT INTO series_counters (series, next_number) VALUES ($1, $2)
+             ON CONFLICT (series) DO UPDATE
+             SET next_number = GREATEST(series_counters.next_number, EXCLUDED.next_number)",
+        )
+        .bind(track.series)
+        .bind(number + 1)
+        .execute(&mut *tx)
+        .await?;
+    }
+    let recorded = sqlx::query_as(
+        "INSERT INTO tracks (channel_id, message_id, file_id, file_unique_id, title, performer,
+             album, file_name, duration_secs, file_size, series, tags, caption, release_id,
+             posted_at, number, bpm, musical_key, caption_format, title_latin, performer_latin,
+             part_number, part_base, first_part_id, paid, via)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14,
+             COALESCE($15, now()), $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26)
+         ON CONFLICT (channel_id, message_id) DO UPDATE SET
+             file_id = EXCLUDED.file_id,
+             file_unique_id = EXCLUDED.file_unique_id,
+             title = EXCLUDED.title,
+             performer = EXCLUDED.performer,
+             album = EXCLUDED.album,
+             file_name = EXCLUDED.file_name,
+             duration_secs = EXCLUDED.duration_secs,
+             file_size = EXCLUDED.file_size,
+             series = EXCLUDED.series,
+             tags = EXCLUDED.tags,
+             caption = EXCLUDED.caption,
+             caption_format = EXCLUDED.caption_format,
+             release_id = EXCLUDED.release_id,
+             number = COALESCE(EXCLUDED.number, tracks.number),
+             bpm = COALESCE(EXCLUDED.bpm, tracks.bpm),
+             musical_key = COALESCE(EXCLUDED.musical_key, tracks.musical_key),
+             title_latin = COALESCE(EXCLUDED.title_latin, tracks.title_latin),
+             performer_latin = COALESCE(EXCLUDED.performer_latin, tracks.performer_latin),
+             part_number = COALESCE(EXCLUDED.part_number, tracks.part_number),
+             part_base = COALESCE(EXCLUDED.part_base, tracks.part_base),
+             first_part_id = COALESCE(EXCLUDED.first_part_id, tracks.first_part_id),
+             paid = EXCLUDED.paid,
+             via = COALESCE(EXCLUDED.via, tracks.via)
+         RETURNING *",
+    )
+    .bind(track.channel_id)
+    .bind(track.message_id)
+    .bind(track.file_id)
+    .bind(track.file_unique_id)
+    .bind(track.title)
+    .bind(track.performer)
+    .bind(track.album)
+    .bind(track.file_name)
+    .bind(track.duration_secs)
+    .bind(track.file_size)
+    .bind(track.series)
+    .bind(track.tags)
+    .bind(track.caption)
+    .bind(track.release_id)
+    .bind(track.posted_at)
+    .bind(track.number)
+    .bind(track.bpm)
+    .bind(track.musical_key)
+    .bind(track.caption_format.name())
+    .bind(track.title_latin)
+    .bind(track.performer_latin)
+    .bind(track.part_number)
+    .bind(track.part_base)
+    .bind(track.first_part_id)
+    .bind(track.paid)
+    .bind(track.via.map(Json))
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(recorded)
+}
+
+/// Several tracks from one album, posted together under a lead post.
+#[derive(Clone, FromRow, Serialize, SimpleObject)]
+#[graphql(complex)]
+pub struct Release {
+    pub id: i64,
+    pub message_id: i32,
+    pub title: String,
+    pub performer: Option<String>,
+    pub year: Option<i32>,
+    pub posted_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Release {
+    async fn permalink(&self) -> String {
+        permalink(self.message_id)
+    }
+
+    async fn tracks(&self, ctx: &async_graphql::Context<'_>) -> async_graphql::Result<Vec<Track>> {
+        Ok(release_tracks(ctx.data::<PgPool>()?, self.id).await?)
+    }
+}
+
+pub struct NewRelease<'a> {
+    pub channel_id: i64,
+    pub message_id: i32,
+    pub title: &'a str,
+    pub performer: Option<&'a str>,
+    pub year: Option<i32>,
+}
+
+pub async fn record_release(pool: &PgPool, release: &NewRelease<'_>) -> sqlx::Result<Release> {
+    sqlx::query_as(
+        "INSERT INTO releases (channel_id, message_id, title, performer, year)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING *",
+    )
+    .bind(release.channel_id)
+    .bind(release.message_id)
+    .bind(release.title)
+    .bind(release.performer)
+    .bind(release.year)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_release(pool: &PgPool, id: i64) -> sqlx::Result<Option<Release>> {
+    sqlx::query_as("SELECT * FROM releases WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// The first part of a mix and the parts after it, in order.
+pub async fn parts(pool: &PgPool, first_part_id: i64) -> sqlx::Result<Vec<Track>> {
+    sqlx::query_as(
+        "SELECT * FROM tracks
+         WHERE (id = $1 OR first_part_id = $1) AND deleted_at IS NULL
+         ORDER BY part_number NULLS FIRST, posted_at",
+    )
+    .bind(first_part_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// The tracks of a release in the order they were posted.
+pub async fn release_tracks(pool: &PgPool, release_id: i64) -> sqlx::Result<Vec<Track>> {
+    sqlx::query_as(
+        "SELECT * FROM tracks WHERE release_id = $1 AND deleted_at IS NULL ORDER BY message_id",
+    )
+    .bind(release_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_track(pool: &PgPool, id: i64) -> sqlx::Result<Option<Track>> {
+    sqlx::query_as("SELECT * FROM tracks WHERE id = $1 AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn update_caption(
+    pool: &PgPool,
+    channel_id: i64,
+    message_id: i32,
+    caption: &str,
+    format: Format,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        "UPDATE tracks SET caption = $3, caption_format = $4
+         WHERE channel_id = $1 AND message_id = $2",
+    )
+    .bind(channel_id)
+    .bind(message_id)
+    .bind(caption)
+    .bind(format.name())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn update_tags(
+    pool: &PgPool,
+    channel_id: i64,
+    message_id: i32,
+    tags: &[String],
+) -> sqlx::Result<()> {
+    sqlx::query("UPDATE tracks SET tags = $3 WHERE channel_id = $1 AND message_id = $2")
+        .bind(channel_id)
+        .bind(message_id)
+        .bind(tags)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Sets the title, performer and series of the track posted at `message_id`, along
+/// with their transliterations, returning it as it is now. A numbered track moved to
+/// another series takes the next free number there. Deleted tracks are left alone.
+#[allow(clippy::too_many_arguments)]
+pub async fn update_metadata(
+    pool: &PgPool,
+    channel_id: i64,
+    message_id: i32,
+    title: Option<&str>,
+    performer: Option<&str>,
+    series: &str,
+    title_latin: Option<&str>,
+    performer_latin: Option<&str>,
+) -> sqlx::Result<Option<Track>> {
+    let mut tx = pool.begin().await?;
+    let current: Option<(String, Option<i32>)> = sqlx::query_as(
+        "SELECT series, number FROM tracks
+         WHERE channel_id = $1 AND message_id = $2 AND deleted_at IS NULL
+         FOR UPDATE",
+    )
+    .bind(channel_id)
+    .bind(message_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((current_series, number)) = current else {
+        return Ok(None);
+    };
+    let number = match number {
+        Some(_) if current_series != series => Some(
+            sqlx::query_scalar(
+                "INSERT INTO series_counters (series, next_number) VALUES ($1, 2)
+                 ON CONFLICT (series) DO UPDATE
+                 SET next_number = series_counters.next_number + 1
+                 RETURNING next_number - 1",
+            )
+            .bind(series)
+            .fetch_one(&mut *tx)
+            .await?,
+        ),
+        number => number,
+    };
+    let track = sqlx::query_as(
+        "UPDATE tracks SET title = $3, performer = $4, series = $5, title_latin = $6,
+             performer_latin = $7, number = $8
+         WHERE channel_id = $1 AND message_id = $2 AND deleted_at IS NULL
+         RETURNING *",
+    )
+    .bind(channel_id)
+    .bind(message_id)
+    .bind(title)
+    .bind(performer)
+    .bind(series)
+    .bind(title_latin)
+    .bind(performer_latin)
+    .bind(number)
+    .fetch_optional(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(track)
+}
+
+/// Posts in `channel_id` not yet known to be deleted, as `(id, message_id)`, oldest first.
+pub async fn live_posts(pool: &PgPool, channel_id: i64) -> sqlx::Result<Vec<(i64, i32)>> {
+    sqlx::query_as(
+        "SELECT id, message_id FROM tracks
+         WHERE channel_id = $1 AND deleted_at IS NULL ORDER BY message_id",
+    )
+    .bind(channel_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn mark_deleted(pool: &PgPool, id: i64) -> sqlx::Result<()> {
+    sqlx::query("UPDATE tracks SET deleted_at = now() WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Releases whose lead post is in `channel_id`.
+pub async fn channel_releases(pool: &PgPool, channel_id: i64) -> sqlx::Result<Vec<Release>> {
+    sqlx::query_as("SELECT * FROM releases WHERE channel_id = $1 ORDER BY message_id")
+        .bind(channel_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Points a track at its copy in another channel.
+pub async fn move_track(
+    pool: &PgPool,
+    id: i64,
+    channel_id: i64,
+    message_id: i32,
+    caption: &str,
+) -> sqlx::Result<()> {
+    sqlx::query("UPDATE tracks SET channel_id = $2, message_id = $3, caption = $4 WHERE id = $1")
+        .bind(id)
+        .bind(channel_id)
+        .bind(message_id)
+        .bind(caption)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn move_release(
+    pool: &PgPool,
+    id: i64,
+    channel_id: i64,
+    message_id: i32,
+) -> sqlx::Result<()> {
+    sqlx::query("UPDATE releases SET channel_id = $2, message_id = $3 WHERE id = $1")
+        .bind(id)
+        .bind(channel_id)
+        .bind(message_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_track(pool: &PgPool, channel_id: i64, message_id: i32) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM tracks WHERE channel_id = $1 AND message_id = $2")
+        .bind(channel_id)
+        .bind(message_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Picks up to `count` random tracks, optionally only ones carrying `tag`. Paid
+/// tracks are left out, since the file handed out would be the free upload.
+pub async fn random_tracks(
+    pool: &PgPool,
+    tag: Option<&str>,
+    count: i64,
+) -> sqlx::Result<Vec<Track>> {
+    sqlx::query_as(
+        "SELECT * FROM tracks
+         WHERE ($1::TEXT IS NULL OR $1 = ANY(tags)) AND deleted_at IS NULL AND NOT paid
+         ORDER BY random() LIMIT $2",
+    )
+    .bind(tag.map(|tag| tag.trim_start_matches('#').to_lowercase()))
+    .bind(count)
+    .fetch_all(pool)
+    .await
+}
+
+/// Tracks posted on the same month and day as `date` in earlier years, oldest first.
+pub async fn posted_on_this_day(pool: &PgPool, date: NaiveDate) -> sqlx::Result<Vec<Track>> {
+    sqlx::query_as(
+        "SELECT * FROM tracks
+         WHERE EXTRACT(MONTH FROM posted_at AT TIME ZONE 'UTC') = $1
+           AND EXTRACT(DAY FROM posted_at AT TIME ZONE 'UTC') = $2
+           AND EXTRACT(YEAR FROM posted_at AT TIME ZONE 'UTC') < $3
+           AND deleted_at IS NULL
+         ORDER BY posted_at",
+    )
+    .bind(date.month() as i32)
+    .bind(date.day() as i32)
+    .bind(date.year())
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(Default)]
+pub struct TrackFilter {
+    pub performer: Option<String>,
+    pub tag: Option<String>,
+    pub series: Option<String>,
+    pub query: Option<String>,
+    pub posted_after: Option<DateTime<Utc>>,
+    pub posted_before: Option<DateTime<Utc>>,
+}
+
+impl TrackFilter {
+    fn push_where(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push(" WHERE deleted_at IS NULL");
+        if let Some(performer) = &self.performer {
+            builder
+                .push(" AND performer ILIKE ")
+                .push_bind(like_escape(performer))
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(tag) = &self.tag {
+            builder
+                .push(" AND ")
+                .push_bind(tag.trim_start_matches('#').to_lowercase())
+                .push(" = ANY(tags)");
+        }
+        if let Some(series) = &self.series {
+            builder.push(" AND series = ").push_bind(series.clone());
+        }
+        if let Some(query) = &self.query {
+            builder.push(" AND ");
+            push_text_match(builder, query);
+        }
+        if let Some(after) = self.posted_after {
+            builder.push(" AND posted_at >= ").push_bind(after);
+        }
+        if let Some(before) = self.posted_before {
+            builder.push(" AND posted_at < ").push_bind(before);
+        }
+    }
+}
+
+/// Lists tracks matching `filter`, newest first.
+pub async fn list_tracks(
+    pool: &PgPool,
+    filter: &TrackFilter,
+    limit: i64,
+    offset: i64,
+) -> sqlx::Result<Vec<Track>> {
+    let mut builder = QueryBuilder::new("SELECT * FROM tracks");
+    filter.push_where(&mut builder);
+    builder
+        .push(" ORDER BY posted_at DESC, id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    builder.build_query_as().fetch_all(pool).await
+}
+
+/// Minimum trigram word similarity for a fuzzy match; low enough to forgive a typo or
+/// two in short words.
+const FUZZY_THRESHOLD: f32 = 0.3;
+
+/// Turns free text into a tsquery where every word may match as a prefix, so
+/// "amb nig" finds "Ambient Nights".
+fn prefix_tsquery(query: &str) -> Option<String> {
+    let words = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word.to_lowercase()))
+        .collect::<Vec<_>>();
+    (!words.is_empty()).then(|| words.join(" & "))
+}
+
+/// Escapes `\`, `%` and `_` in `text` so that `LIKE ... ESCAPE '\'` matches it
+/// literally.
+pub fn like_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Matches the full-text index, falling back to trigram similarity so typos and
+/// transliteration differences still find something.
+fn push_text_match(builder: &mut QueryBuilder<'_, Postgres>, query: &str) {
+    builder.push("(");
+    if let Some(tsquery) = prefix_tsquery(query) {
+        builder
+            .push("search_vector @@ to_tsquery('simple', ")
+            .push_bind(tsquery)
+            .push(") OR ");
+    }
+    builder
+        .push("word_similarity(")
+        .push_bind(query.to_lowercase())
+        .push(", search_text) >= ")
+        .push_bind(FUZZY_THRESHOLD)
+        .push(")");
+}
+
+/// Full-text search over title, performer, album, tags and caption, best matches first.
+pub async fn search(pool: &PgPool, query: &str, limit: i64) -> sqlx::Result<Vec<Track>> {
+    let mut builder = QueryBuilder::new("SELECT * FROM tracks WHERE deleted_at IS NULL AND ");
+    push_text_match(&mut builder, query);
+    builder.push(" ORDER BY ");
+    if let Some(tsquery) = prefix_tsquery(query) {
+        builder
+            .push("ts_rank(search_vector, to_tsquery('simple', ")
+            .push_bind(tsquery)
+            .push(")) + ");
+    }
+    builder
+        .push("word_similarity(")
+        .push_bind(query.to_lowercase())
+        .push(", search_text) DESC, posted_at DESC LIMIT ")
+        .push_bind(limit);
+    builder.build_query_as().fetch_all(pool).await
+}
+
+pub async fn count_tracks(pool: &PgPool, filter: &TrackFilter) -> sqlx::Result<i64> {
+    let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM tracks");
+    filter.push_where(&mut builder);
+    builder.build_query_scalar().fetch_one(pool).await
+}
+
+/// Position in the `posted_at DESC, id DESC` ordering, used for keyset pagination so
+/// pages stay stable while new tracks are being published.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cursor {
+    pub posted_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn of(track: &Track) -> Self {
+        Self {
+            posted_at: track.posted_at,
+            id: track.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}.{}", self.posted_at.timestamp_micros(), self.id)
+    }
+
+    pub fn decode(raw: &str) -> Option<Self> {
+        let (micros, id) = raw.split_once('.')?;
+        Some(Self {
+            posted_at: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// Lists up to `limit` tracks matching `filter` that come after `after`, newest first.
+pub async fn list_tracks_after(
+    pool: &PgPool,
+    filter: &TrackFilter,
+    after: Option<Cursor>,
+    limit: i64,
+) -> sqlx::Result<Vec<Track>> {
+    let mut builder = QueryBuilder::new("SELECT * FROM tracks");
+    filter.push_where(&mut builder);
+    if let Some(cursor) = after {
+        builder
+            .push(" AND (posted_at, id) < (")
+            .push_bind(cursor.posted_at)
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+    builder
+        .push(" ORDER BY posted_at DESC, id DESC LIMIT ")
+        .push_bind(limit);
+    builder.build_query_as().fetch_all(pool).await
+}
+
+#[derive(FromRow, Serialize, SimpleObject)]
+pub struct SeriesSummary {
+    pub name: String,
+    pub track_count: i64,
+    pub last_posted_at: DateTime<Utc>,
+}
+
+pub async fn list_series(pool: &PgPool) -> sqlx::Result<Vec<SeriesSummary>> {
+    sqlx::query_as(
+        "SELECT series AS name, COUNT(*) AS track_count, MAX(posted_at) AS last_posted_at
+         FROM tracks WHERE deleted_at IS NULL GROUP BY series ORDER BY series",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(FromRow, Serialize, SimpleObject)]
+pub struct TagSummary {
+    pub name: String,
+    pub track_count: i64,
+}
+
+pub async fn list_tags(pool: &PgPool) -> sqlx::Result<Vec<TagSummary>> {
+    sqlx::query_as(
+        "SELECT tag AS name, COUNT(*) AS track_count
+         FROM tracks, UNNEST(tags) AS tag WHERE deleted_at IS NULL
+         GROUP BY tag ORDER BY track_count DESC, tag",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[derive(FromRow, Serialize, SimpleObject)]
+pub struct CatalogStats {
+    pub track_count: i64,
+    pub total_duration_secs: i64,
+    pub performer_count: i64,
+    pub reaction_count: i64,
+    pub first_posted_at: Option<DateTime<Utc>>,
+    pub last_posted_at: Option<DateTime<Utc>>,
+}
+
+/// When anything in the catalog last changed, bumped by triggers on `tracks` and
+/// `releases`.
+pub async fn last_updated(pool: &PgPool) -> sqlx::Result<DateTime<Utc>> {
+    sqlx::query_scalar("SELECT updated_at FROM catalog_version")
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn stats(pool: &PgPool) -> sqlx::Result<CatalogStats> {
+    sqlx::query_as(
+        "SELECT COUNT(*) AS track_count,
+                COALESCE(SUM(duration_secs), 0)::BIGINT AS total_duration_secs,
+                COUNT(DISTINCT performer) AS performer_count,
+                COALESCE(SUM(reaction_count), 0)::BIGINT AS reaction_count,
+                MIN(posted_at) AS first_posted_at,
+                MAX(posted_at) AS last_posted_at
+         FROM tracks WHERE deleted_at IS NULL",
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn like_escape_makes_wildcards_literal() {
+        assert_eq!(like_escape("100% Pure_Love"), "100\\% Pure\\_Love");
+        assert_eq!(like_escape(r"AC\DC"), r"AC\\DC");
+        assert_eq!(like_escape("Ёлка"), "Ёлка");
+    }
+
+    #[test]
+    fn performer_filter_escapes_its_pattern() {
+        let filter = TrackFilter {
+            performer: Some("50%_off".to_string()),
+            ..TrackFilter::default()
+        };
+        let mut builder = QueryBuilder::new("SELECT * FROM tracks");
+        filter.push_where(&mut builder);
+        assert!(builder.sql().contains("performer ILIKE $1 ESCAPE '\\'"));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::ServerSecretsState;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool};
+use teloxide::{prelude::*, types::MessageId};
+use tokio::time::{Duration, sleep};
+
+/// How long transient replies stay up unless `REPLY_TTL_SECS` says otherwise.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(FromRow)]
+struct PendingDeletion {
+    chat_id: i64,
+    message_id: i32,
+    delete_at: DateTime<Utc>,
+}
+
+fn spawn_deletion(bot: Bot, db: PgPool, deletion: PendingDeletion) {
+    tokio::spawn(async move {
+        let delay = (deletion.delete_at - Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        sleep(delay).await;
+
+        let chat_id = ChatId(deletion.chat_id);
+        if let Err(e) = bot
+            .delete_message(chat_id, MessageId(deletion.message_id))
+            .await
+        {
+            tracing::debug!("Failed to delete expired reply: {}", e);
+        }
+        if let Err(e) =
+            sqlx::query("DELETE FROM pending_deletions WHERE chat_id = $1 AND message_id = $2")
+                .bind(deletion.chat_id)
+                .bind(deletion.message_id)
+                .execute(&db)
+                .await
+        {
+            tracing::warn!("Failed to forget expired reply: {}", e);
+        }
+    });
+}
+
+/// Deletes one of the bot's transient replies (acknowledgements, notices) once
+/// `REPLY_TTL_SECS` has passed. The deletion is persisted so it still happens if
+/// the bot restarts in the meantime.
+pub async fn expire(bot: &Bot, secrets: &ServerSecretsState, message: &Message) {
+    let Some(ttl) = secrets.reply_ttl else {
+        return;
+    };
+    let deletion = PendingDeletion {
+        chat_id: message.chat.id.0,
+        message_id: message.id.0,
+        delete_at: Utc::now() + chrono::Duration::from_std(ttl).unwrap_or_default(),
+    };
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO pending_deletions (chat_id, message_id, delete_at) VALUES ($1, $2, $3)
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(deletion.chat_id)
+    .bind(deletion.message_id)
+    .bind(deletion.delete_at)
+    .execute(&secrets.db)
+    .await
+    {
+        tracing::warn!("Failed to schedule reply deletion: {}", e);
+    }
+    spawn_deletion(bot.clone(), secrets.db.clone(), deletion);
+}
+
+/// Sends `text` to `chat_id` as a transient reply that expires after the TTL.
+pub async fn reply(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    chat_id: ChatId,
+    text: impl Into<String>,
+) -> Result<Message, teloxide::RequestError> {
+    let message = bot.send_message(chat_id, text).await?;
+    expire(bot, secrets, &message).await;
+    Ok(message)
+}
+
+/// Picks up deletions scheduled before a restart.
+pub async fn resume(bot: &Bot, db: &PgPool) -> sqlx::Result<()> {
+    let pending: Vec<PendingDeletion> = sqlx::query_as("SELECT * FROM pending_deletions")
+        .fetch_all(db)
+        .await?;
+    if !pending.is_empty() {
+        tracing::info!("Resuming {} pending reply deletions", pending.len());
+    }
+    for deletion in pending {
+        spawn_deletion(bot.clone(), db.clone(), deletion);
+    }
+    Ok(())
+}
@@ -0,0 +1,594 @@
+use crate::{
+    ServerSecretsState, announce, audit, cadence, calendar, catalog, cleanup, dead_letter,
+    deep_link, favorites, flags, growth, intruders, invites, listener_requests, logs, maintenance,
+    migrate, now_playing, queue_export, quiz, reactions, reconcile, scheduled, status, test_mode,
+    themes, vacation, webhook, welcome,
+};
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{BotCommand, FileId, InlineKeyboardMarkup, InputFile, LinkPreviewOptions},
+    utils::command::BotCommands,
+};
+
+const DEFAULT_LOG_LINES: usize = 20;
+const MAX_LOG_LINES: usize = 200;
+const SEARCH_RESULTS: i64 = 10;
+const TOP_REACTED: i64 = 5;
+const DEFAULT_ERRORS: i64 = 10;
+const MAX_ERRORS: i64 = 50;
+/// Errors can be whole HTTP responses; /errors only shows their start.
+const ERROR_SUMMARY_CHARS: usize = 200;
+/// How long the /errors message may get, under Telegram's 4096 characters with room
+/// for the "…and N more" line.
+const ERRORS_CHARS: usize = 4000;
+
+#[derive(BotCommands, Clone)]
+#[command(rename_rule = "lowercase")]
+pub enum Command {
+    #[command(description = "check that the bot is up")]
+    Start,
+    #[command(description = "how to send in a track")]
+    Submit,
+    #[command(description = "show uptime, the queue, the next post and webhook and storage health")]
+    Status,
+    #[command(description = "show the latest log lines, optionally how many")]
+    Logs(String),
+    #[command(description = "show catalog totals and the most reacted tracks")]
+    Stats,
+    #[command(description = "chart subscribers, optionally over the last given number of days")]
+    Growth(String),
+    #[command(description = "search the catalog")]
+    Search(String),
+    #[command(description = "send a random track, optionally with a given tag")]
+    Random(String),
+    #[command(description = "list items that failed to publish")]
+    Failed,
+    #[command(description = "requeue a failed item by id, or all of them")]
+    Retry(String),
+    #[command(description = "show the latest handler and send failures, optionally how many")]
+    Errors(String),
+    #[command(description = "post what the media server is playing to the channel")]
+    NowPlaying,
+    #[command(description = "post a guess-the-artist or guess-the-year quiz, or show \"stats\"")]
+    Quiz(String),
+    #[command(description = "preview a text post for the channel, then post it")]
+    Announce(String),
+    #[command(
+        description = "post text, or a photo with this caption, at a UTC time: YYYY-MM-DD HH:MM <text>"
+    )]
+    Schedule(String),
+    #[command(description = "list posts scheduled with /schedule")]
+    Scheduled,
+    #[command(description = "cancel a scheduled post by id")]
+    Unschedule(String),
+    #[command(description = "make a channel invite link: [limit=<members>] [days=<days>] [name]")]
+    Invite(String),
+    #[command(description = "list invite links and how many joined through each")]
+    Invites,
+    #[command(description = "revoke an invite link by link or name")]
+    Revoke(String),
+    #[command(description = "pause automatic posting between two dates, or \"off\"")]
+    Vacation(String),
+    #[command(description = "list recent messages from people other than the owner")]
+    Intruders,
+    #[command(description = "go through the tracks listeners requested")]
+    Requests,
+    #[command(description = "drop all updates from a user id, or list blocked users")]
+    Block(String),
+    #[command(description = "stop dropping updates from a user id")]
+    Unblock(String),
+    #[command(description = "change the /start reply, or \"reset\"")]
+    SetWelcome(String),
+    #[command(description = "make a signed t.me link: \"track <id>\" or \"digest\"")]
+    DeepLink(String),
+    #[command(description = "check that cataloged posts still exist in the channel")]
+    Reconcile,
+    #[command(description = "copy the catalog to a new channel and switch to it")]
+    Migrate(String),
+    #[command(description = "publish to the staging channel instead: \"on\" or \"off\"")]
+    TestMode(String),
+    #[command(description = "list the weekday themes and the uploads waiting for them")]
+    Themes,
+    #[command(description = "show what goes out over the next two weeks")]
+    Calendar,
+    #[command(description = "list feature flags, or switch one: <name> on|off|default")]
+    Flags(String),
+    #[command(
+        description = "register the webhook again, optionally for other update types: <list>, +name -name or default"
+    )]
+    SetWebhook(String),
+    #[command(
+        description = "stop taking uploads and drain the queue for a redeploy: \"on\" or \"off\""
+    )]
+    Maintenance(String),
+    #[command(description = "star a post by id or link, or the track replied to; again to unstar")]
+    Fav(String),
+    #[command(description = "list the tracks you starred")]
+    Favorites,
+    #[command(
+        description = "post the starred tracks to the channel and keep it updated, or \"off\""
+    )]
+    Essentials(String),
+    #[command(description = "stop the question the bot is asking")]
+    Cancel,
+    #[command(description = "send the queue as a JSON file")]
+    ExportQueue,
+    #[command(description = "restore the queue from an /exportqueue file sent with this caption")]
+    ImportQueue,
+}
+
+/// Who a command is offered to in the bot's menus, besides the owner, who gets
+/// every command.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Audience {
+    Owner,
+    /// People sending in tracks under `STRANGER_POLICY=suggest` or `submit`.
+    Contributors,
+    /// Everyone in the channel's discussion group.
+    Public,
+    /// People in `FAVORITES_USERS`, who star tracks along with the owner.
+    Curators,
+}
+
+impl Command {
+    pub fn audience(&self) -> Audience {
+        match self {
+            Command::Start | Command::Submit => Audience::Contributors,
+            Command::Random(_) | Command::Search(_) => Audience::Public,
+            Command::Fav(_) | Command::Favorites => Audience::Curators,
+            _ => Audience::Owner,
+        }
+    }
+
+    /// The menu entries of the commands for `audience`, in the order they are
+    /// declared; [`Command::bot_commands`] is the menu of the owner.
+    pub fn menu(audience: Audience) -> Vec<BotCommand> {
+        Command::bot_commands()
+            .into_iter()
+            .filter(|entry| {
+                Command::parse(&entry.command, "")
+                    .is_ok_and(|command| command.audience() == audience)
+            })
+            .collect()
+    }
+}
+
+pub async fn handle_command(
+    bot: &Arc<Bot>,
+    message: &Message,
+    command: Command,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match command {
+        Command::Start => {
+            welcome::send(bot, message, secrets).await?;
+        }
+        Command::Submit if message.chat.id != secrets.me_id => {
+            let text = match &secrets.stranger_policy {
+                intruders::Policy::Submit(prompt) => {
+                    welcome::render(prompt, secrets, message).await
+                }
+                _ => listener_requests::HINT.to_string(),
+            };
+            bot.send_message(message.chat.id, text).await?;
+        }
+        Command::Submit => {
+            cleanup::reply(
+                bot,
+                secrets,
+                message.chat.id,
+                "🎵 Send audio files here to queue them; /requests has what listeners sent in.",
+            )
+            .await?;
+        }
+        Command::Fav(args) => {
+            favorites::handle_fav(bot, message, &args, secrets).await?;
+        }
+        Command::Favorites => {
+            favorites::handle_list(bot, message, secrets).await?;
+        }
+        Command::Essentials(args) => {
+            favorites::handle_essentials(bot, message, &args, secrets).await?;
+        }
+        Command::Cancel => {
+            // A conversation under way takes its /cancel before commands get here.
+            cleanup::reply(bot, secrets, message.chat.id, "Nothing to cancel.").await?;
+        }
+        Command::Status => {
+            let text = status::report(bot, secrets).await;
+            bot.send_message(message.chat.id, text)
+                .link_preview_options(no_link_preview())
+                .await?;
+        }
+        Command::Logs(arg) => {
+            let limit = arg
+                .trim()
+                .parse()
+                .unwrap_or(DEFAULT_LOG_LINES)
+                .min(MAX_LOG_LINES);
+            let text = logs::format_tail(&secrets.logs.tail(limit), 4000);
+            let text = if text.is_empty() {
+                "No log records yet.".to_string()
+            } else {
+                text
+            };
+            bot.send_message(message.chat.id, text).await?;
+        }
+        Command::Growth(args) => {
+            growth::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::Search(query) => {
+            let query = query.trim();
+            if query.is_empty() {
+                cleanup::reply(bot, secrets, message.chat.id, "Usage: /search <text>").await?;
+                return Ok(());
+            }
+
+            let tracks = catalog::search(&secrets.db, query, SEARCH_RESULTS).await?;
+            let text = if tracks.is_empty() {
+                "Nothing found.".to_string()
+            } else {
+                tracks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, track)| {
+                        format!(
+                            "{}. {}\n{}",
+                            i + 1,
+                            track.label(),
+                            catalog::permalink(track.message_id)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            bot.send_message(message.chat.id, text)
+                .link_preview_options(no_link_preview())
+                .await?;
+        }
+        Command::Stats => {
+            let stats = catalog::stats(&secrets.db).await?;
+            let mut text = format!(
+                "{} tracks by {} performers, {}h {}m in total\n{} reactions",
+                stats.track_count,
+                stats.performer_count,
+                stats.total_duration_secs / 3600,
+                stats.total_duration_secs % 3600 / 60,
+                stats.reaction_count
+            );
+            if let Some(cadence) = cadence::analyze(&secrets.db).await? {
+                text.push_str(&format!("\n\n{}", cadence.lines().join("\n")));
+            }
+            let top = reactions::most_reacted(&secrets.db, TOP_REACTED).await?;
+            if !top.is_empty() {
+                text.push_str("\n\nMost reacted:");
+                for (i, track) in top.iter().enumerate() {
+                    text.push_str(&format!(
+                        "\n{}. {}\n{}",
+                        i + 1,
+                        reactions::label(track),
+                        catalog::permalink(track.message_id)
+                    ));
+                }
+            }
+            bot.send_message(message.chat.id, text)
+                .link_preview_options(no_link_preview())
+                .await?;
+        }
+        Command::Random(tag) => {
+            let tag = Some(tag.trim()).filter(|tag| !tag.is_empty());
+            match catalog::random_tracks(&secrets.db, tag, 1).await?.pop() {
+                Some(track) => {
+                    let mut request = bot
+                        .send_audio(message.chat.id, InputFile::file_id(FileId(track.file_id)))
+                        .caption(catalog::permalink(track.message_id));
+                    if message.chat.is_private() {
+                        request =
+                            request.reply_markup(InlineKeyboardMarkup::new([[favorites::button(
+                                track.message_id,
+                            )]]));
+                    }
+                    request.await?;
+                }
+                None => {
+                    bot.send_message(message.chat.id, "No matching tracks in the catalog.")
+                        .await?;
+                }
+            }
+        }
+        Command::Failed => {
+            let items = dead_letter::list(&secrets.db).await?;
+            let text = if items.is_empty() {
+                "No failed items.".to_string()
+            } else {
+                items
+                    .iter()
+                    .map(|item| {
+                        format!(
+                            "#{} {} ({} UTC)\n{}",
+                            item.id,
+                            item.label(),
+                            item.failed_at.format("%Y-%m-%d %H:%M"),
+                            item.error
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            };
+            bot.send_message(message.chat.id, text).await?;
+        }
+        Command::Errors(arg) => {
+            let limit = arg
+                .trim()
+                .parse()
+                .unwrap_or(DEFAULT_ERRORS)
+                .clamp(1, MAX_ERRORS);
+            let text = recent_errors(secrets, limit).await?;
+            bot.send_message(message.chat.id, text).await?;
+        }
+        Command::Retry(arg) => {
+            let arg = arg.trim();
+            let items = if arg == "all" {
+                dead_letter::take_all(&secrets.db).await?
+            } else if let Ok(id) = arg.trim_start_matches('#').parse() {
+                dead_letter::take(&secrets.db, id)
+                    .await?
+                    .into_iter()
+                    .collect()
+            } else {
+                cleanup::reply(
+                    bot,
+                    secrets,
+                    message.chat.id,
+                    "Usage: /retry <id> or /retry all",
+                )
+                .await?;
+                return Ok(());
+            };
+
+            let count = items.len();
+            for item in items {
+                secrets
+                    .message_queue
+                    .add_message(item.into_queued(), bot.clone(), secrets.clone())
+                    .await;
+            }
+            cleanup::reply(
+                bot,
+                secrets,
+                message.chat.id,
+                format!("Requeued {} item(s).", count),
+            )
+            .await?;
+        }
+        Command::NowPlaying => {
+            let reply = match now_playing::share(secrets).await? {
+                Some(label) => format!("Posted {}", label),
+                None => "Nothing new is playing.".to_string(),
+            };
+            cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+        }
+        Command::Vacation(args) => {
+            vacation::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::Intruders => {
+            let text = intruders::report(secrets).await?;
+            bot.send_message(message.chat.id, text).await?;
+        }
+        Command::SetWelcome(args) => {
+            welcome::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::DeepLink(args) => {
+            deep_link::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::Reconcile => {
+            reconcile::handle_command(bot, message, secrets);
+            cleanup::reply(bot, secrets, message.chat.id, "Checking the channel…").await?;
+        }
+        Command::Migrate(args) => {
+            migrate::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::TestMode(args) => {
+            test_mode::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::Flags(args) => {
+            flags::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::Calendar => {
+            calendar::handle_command(bot, message, secrets).await?;
+        }
+        Command::Themes => {
+            themes::handle_list(bot, message, secrets).await?;
+        }
+        Command::SetWebhook(args) => {
+            webhook::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::Announce(text) => {
+            if text.trim().is_empty() {
+                bot.send_message(message.chat.id, "Usage: /announce <text>")
+                    .await?;
+            } else {
+                announce::preview(bot, message, secrets).await?;
+            }
+        }
+        Command::Quiz(args) => {
+            quiz::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::Schedule(args) => {
+            scheduled::handle_schedule(bot, message, &args, secrets).await?;
+        }
+        Command::Scheduled => {
+            scheduled::handle_list(bot, message, secrets).await?;
+        }
+        Command::Unschedule(args) => {
+            scheduled::handle_unschedule(bot, message, &args, secrets).await?;
+        }
+        Command::Invite(args) => {
+            invites::handle_create(bot, message, &args, secrets).await?;
+        }
+        Command::Invites => {
+            invites::handle_list(bot, message, secrets).await?;
+        }
+        Command::Revoke(args) => {
+            invites::handle_revoke(bot, message, &args, secrets).await?;
+        }
+        Command::Maintenance(args) => {
+            maintenance::handle_command(bot, message, &args, secrets).await?;
+        }
+        Command::ExportQueue => {
+            queue_export::export(bot, message, secrets).await?;
+        }
+        Command::ImportQueue => {
+            queue_export::import(bot, message, secrets).await?;
+        }
+        Command::Requests => {
+            listener_requests::handle_list(bot, message, secrets).await?;
+        }
+        Command::Block(args) => {
+            intruders::handle_block_command(bot, message, &args, true, secrets).await?;
+        }
+        Command::Unblock(args) => {
+            intruders::handle_block_command(bot, message, &args, false, secrets).await?;
+        }
+    }
+    Ok(())
+}
+
+pub fn no_link_preview() -> LinkPreviewOptions {
+    LinkPreviewOptions {
+        is_disabled: true,
+        url: None,
+        prefer_small_media: false,
+        prefer_large_media: false,
+        show_above_text: false,
+    }
+}
+
+fn error_summary(error: &str) -> String {
+    let mut summary = error.chars().take(ERROR_SUMMARY_CHARS).collect::<String>();
+    if summary.len() < error.len() {
+        summary.push('…');
+    }
+    summary
+}
+
+/// Failed sends from the dead-letter store and failed handlers from the audit log,
+/// merged newest first.
+async fn recent_errors(
+    secrets: &ServerSecretsState,
+    limit: i64,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (sends, handlers) = tokio::try_join!(
+        dead_letter::recent(&secrets.db, limit),
+        audit::recent_errors(&secrets.db, limit),
+    )?;
+
+    let mut errors = sends
+        .iter()
+        .map(|item| {
+            let retried = match item.retried_at {
+                Some(at) => format!("retried {} UTC", at.format("%m-%d %H:%M")),
+                None => format!("not retried, /retry {}", item.id),
+            };
+            (
+                item.failed_at,
+                format!(
+                    "📤 Send of message {} ({}), {}\n{}",
+                    item.message_id,
+                    item.label(),
+                    retried,
+                    error_summary(&item.error)
+                ),
+            )
+        })
+        .chain(handlers.iter().map(|entry| {
+            (
+                entry.handled_at,
+                format!(
+                    "⚙️ Handling update {} ({}), not retried\n{}",
+                    entry.update_id,
+                    entry.kind,
+                    error_summary(entry.error.as_deref().unwrap_or_default())
+                ),
+            )
+        }))
+        .collect::<Vec<_>>();
+    errors.sort_by_key(|(at, _)| std::cmp::Reverse(*at));
+    errors.truncate(limit as usize);
+
+    if errors.is_empty() {
+        return Ok("No errors recorded.".to_string());
+    }
+    let entries = errors
+        .iter()
+        .map(|(at, text)| format!("{} UTC {}", at.format("%Y-%m-%d %H:%M"), text))
+        .collect::<Vec<_>>();
+    Ok(join_capped(&entries, ERRORS_CHARS))
+}
+
+/// `entries` a blank line apart, as many as fit in `max_chars`, followed by how many
+/// were left out. The first one always goes in.
+fn join_capped(entries: &[String], max_chars: usize) -> String {
+    let mut text = String::new();
+    let mut chars = 0;
+    for (shown, entry) in entries.iter().enumerate() {
+        let entry_chars = entry.chars().count();
+        let separator = if shown == 0 { 0 } else { 2 };
+        if shown > 0 && chars + separator + entry_chars > max_chars {
+            text.push_str(&format!("\n\n…and {} more", entries.len() - shown));
+            break;
+        }
+        if shown > 0 {
+            text.push_str("\n\n");
+        }
+        text.push_str(entry);
+        chars += separator + entry_chars;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_capped_keeps_what_fits() {
+        let entries = vec!["a".repeat(10), "b".repeat(10), "c".repeat(10)];
+        assert_eq!(
+            join_capped(&entries, 100),
+            format!("{}\n\n{}\n\n{}", entries[0], entries[1], entries[2])
+        );
+    }
+
+    #[test]
+    fn join_capped_counts_what_is_left_out() {
+        let entries = vec!["a".repeat(10), "b".repeat(10), "c".repeat(10)];
+        assert_eq!(
+            join_capped(&entries, 22),
+            format!("{}\n\n{}\n\n…and 1 more", entries[0], entries[1])
+        );
+        assert_eq!(
+            join_capped(&entries, 21),
+            format!("{}\n\n…and 2 more", entries[0])
+        );
+    }
+
+    #[test]
+    fn join_capped_stays_under_telegrams_limit() {
+        let entries: Vec<String> = (0..MAX_ERRORS)
+            .map(|_| "é".repeat(ERROR_SUMMARY_CHARS + 60))
+            .collect();
+        let text = join_capped(&entries, ERRORS_CHARS);
+        assert!(text.chars().count() <= 4096);
+        assert!(text.ends_with("more"));
+    }
+
+    #[test]
+    fn error_summary_cuts_long_errors() {
+        let summary = error_summary(&"x".repeat(ERROR_SUMMARY_CHARS * 2));
+        assert_eq!(summary.chars().count(), ERROR_SUMMARY_CHARS + 1);
+        assert!(summary.ends_with('…'));
+        assert_eq!(error_summary("short"), "short");
+    }
+}
@@ -0,0 +1,142 @@
+/// How long browsers may cache a preflight answer, in seconds.
+const MAX_AGE: &str = "86400";
+
+/// Adds CORS headers to `/api` responses for the origins listed in `CORS_ORIGINS`,
+/// comma separated, or `*` for any.
+pub struct Cors {
+    origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn from_secret(raw: Option<&str>) -> Option<Self> {
+        let origins = raw
+            .unwrap_or_default()
+            .split(',')
+            .map(|origin| origin.trim().trim_end_matches('/').to_string())
+            .filter(|origin| !origin.is_empty())
+            .collect::<Vec<_>>();
+        (!origins.is_empty()).then_some(Self { origins })
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == origin)
+    }
+
+    /// The headers to add to a response for a request to `path` from `origin`; the
+    /// first one, `Vary`, is appended to rather than replaced.
+    fn headers(&self, path: &str, origin: Option<&str>) -> Vec<(&'static str, String)> {
+        let (true, Some(origin)) = (path.starts_with("/api/"), origin) else {
+            return Vec::new();
+        };
+        let mut headers = vec![("Vary", "Origin".to_string())];
+        if self.allows(origin) {
+            headers.extend([
+                ("Access-Control-Allow-Origin", origin.to_string()),
+                (
+                    "Access-Control-Allow-Methods",
+                    "GET, POST, OPTIONS".to_string(),
+                ),
+                (
+                    "Access-Control-Allow-Headers",
+                    "Authorization, Content-Type".to_string(),
+                ),
+                ("Access-Control-Max-Age", MAX_AGE.to_string()),
+            ]);
+        }
+        headers
+    }
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_fairing::routes;
+
+#[cfg(feature = "rocket")]
+mod rocket_fairing {
+    use super::Cors;
+    use rocket::{
+        Request, Response, Route,
+        fairing::{Fairing, Info, Kind},
+        http::{Header, Status},
+        options, routes,
+    };
+    use std::path::PathBuf;
+
+    pub fn routes() -> Vec<Route> {
+        routes![preflight]
+    }
+
+    #[rocket::async_trait]
+    impl Fairing for Cors {
+        fn info(&self) -> Info {
+            Info {
+                name: "CORS",
+                kind: Kind::Response,
+            }
+        }
+
+        async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+            let headers = self.headers(req.uri().path().as_str(), req.headers().get_one("Origin"));
+            for (i, (name, value)) in headers.into_iter().enumerate() {
+                match i {
+                    0 => res.adjoin_header(Header::new(name, value)),
+                    _ => {
+                        res.set_header(Header::new(name, value));
+                    }
+                };
+            }
+        }
+    }
+
+    /// Answers browser preflight requests; the fairing adds the headers.
+    #[options("/<_path..>")]
+    fn preflight(_path: PathBuf) -> Status {
+        Status::NoContent
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_middleware::{add_headers, preflight};
+
+#[cfg(feature = "axum")]
+mod axum_middleware {
+    use super::Cors;
+    use axum::{
+        extract::{Request, State},
+        http::{HeaderName, HeaderValue, StatusCode, header},
+        middleware::Next,
+        response::Response,
+    };
+    use std::sync::Arc;
+
+    pub async fn add_headers(State(cors): State<Arc<Cors>>, req: Request, next: Next) -> Response {
+        let path = req.uri().path().to_string();
+        let origin = req
+            .headers()
+            .get(header::ORIGIN)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let mut response = next.run(req).await;
+        for (i, (name, value)) in cors
+            .headers(&path, origin.as_deref())
+            .into_iter()
+            .enumerate()
+        {
+            let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value))
+            else {
+                continue;
+            };
+            match i {
+                0 => response.headers_mut().append(name, value),
+                _ => response.headers_mut().insert(name, value).is_some(),
+            };
+        }
+        response
+    }
+
+    /// Answers browser preflight requests; the middleware adds the headers.
+    pub async fn preflight() -> StatusCode {
+        StatusCode::NO_CONTENT
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{ServerSecretsState, media};
+use chrono::Utc;
+use std::path::{Path, PathBuf};
+use teloxide::{prelude::*, types::Audio};
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Telegram only shows thumbnails that are JPEGs of at most 320px a side and 200 kB.
+const MAX_SIDE: u32 = 320;
+const MAX_BYTES: u64 = 200 * 1024;
+/// ffmpeg's mjpeg qscale, from best to worst, tried until the image fits.
+const QUALITIES: [u32; 5] = [2, 5, 10, 20, 31];
+
+fn cache_dir() -> PathBuf {
+    std::env::temp_dir().join("ankh-covers")
+}
+
+async fn has_cover(input: &Path) -> Result<bool, Error> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v"])
+        .args(["-show_entries", "stream=index", "-of", "csv=p=0"])
+        .arg(input)
+        .output()
+        .await?;
+    Ok(output.status.success() && !output.stdout.trim_ascii().is_empty())
+}
+
+/// Extracts the cover art embedded in `audio` and crops, scales and re-encodes it
+/// until it meets Telegram's thumbnail constraints. Returns `None` for files without
+/// art. Conformed covers are cached by `file_unique_id`, and only land in the cache
+/// once they fit.
+pub async fn extract(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    audio: &Audio,
+) -> Result<Option<PathBuf>, Error> {
+    let path = cache_dir().join(format!("{}.jpg", audio.file.unique_id.0));
+    if tokio::fs::try_exists(&path).await? {
+        return Ok(Some(path));
+    }
+
+    let input = media::fetch(bot, secrets, &audio.file).await?;
+    if !has_cover(&input.path).await? {
+        return Ok(None);
+    }
+
+    tokio::fs::create_dir_all(cache_dir()).await?;
+    let conformed = media::LocalFile::scratch(&format!(
+        "cover-{}-{}.jpg",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        audio.file.unique_id.0
+    ));
+    for quality in QUALITIES {
+        media::run(
+            Command::new("ffmpeg")
+                .args(["-y", "-v", "error", "-i"])
+                .arg(&input.path)
+                .args(["-an", "-frames:v", "1", "-vf"])
+                .arg(format!(
+                    "crop='min(iw,ih)':'min(iw,ih)',scale='min({0},iw)':'min({0},ih)'",
+                    MAX_SIDE
+                ))
+                .args(["-c:v", "mjpeg", "-q:v", &quality.to_string()])
+                .arg(&conformed.path),
+        )
+        .await?;
+
+        let size = tokio::fs::metadata(&conformed.path).await?.len();
+        if size <= MAX_BYTES {
+            conformed.persist(&path).await?;
+            return Ok(Some(path));
+        }
+        tracing::debug!("Cover is {} bytes at quality {}, retrying", size, quality);
+    }
+
+    Err(format!("Cover art does not fit in {} bytes", MAX_BYTES).into())
+}
+
+/// Picks the thumbnail for a track that is about to be posted: its own cover art
+/// when `COVER_ART` is on and it has any, otherwise the waveform if that is enabled.
+pub async fn thumbnail(bot: &Bot, secrets: &ServerSecretsState, audio: &Audio) -> Option<PathBuf> {
+    if secrets.cover_art {
+        match extract(bot, secrets, audio).await {
+            Ok(Some(path)) => return Some(path),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to conform cover art: {}", e),
+        }
+    }
+    crate::waveform::thumbnail(bot, secrets, audio).await
+}
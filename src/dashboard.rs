@@ -0,0 +1,412 @@
+use crate::{
+    ServerSecretsState, audit, auth, cadence, calendar,
+    catalog::{self, TrackFilter},
+    deep_link, growth,
+};
+use std::collections::HashMap;
+
+const RECENT_TRACKS: i64 = 20;
+const AUDIT_ROWS: i64 = 50;
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>{}</title></head><body>{}</body></html>",
+        escape_html(title),
+        body
+    )
+}
+
+fn login_page(secrets: &ServerSecretsState) -> String {
+    page(
+        "Ankh login",
+        &format!(
+            "<h1>Ankh</h1>\
+             <script async src=\"https://telegram.org/js/telegram-widget.js?22\" \
+             data-telegram-login=\"{}\" data-size=\"large\" \
+             data-auth-url=\"/auth/telegram\" data-request-access=\"write\"></script>",
+            escape_html(&secrets.bot_username)
+        ),
+    )
+}
+
+/// Checks the Login Widget's fields, returning the admin's id or the status to
+/// answer with.
+fn telegram_login(
+    secrets: &ServerSecretsState,
+    fields: &HashMap<String, String>,
+) -> Result<i64, u16> {
+    let user_id = match auth::verify_telegram_login(fields, &secrets.bot_token) {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            tracing::warn!("Rejected dashboard login: {}", e);
+            return Err(401);
+        }
+    };
+
+    if !secrets.dashboard_admin_ids.contains(&user_id) {
+        tracing::warn!("Dashboard login from non-admin user {}", user_id);
+        return Err(403);
+    }
+
+    tracing::info!("Dashboard login by {}", user_id);
+    Ok(user_id)
+}
+
+/// The update search form, filled in with the current filter, and its results.
+async fn audit_section(secrets: &ServerSecretsState, filter: &audit::Filter) -> String {
+    let field = |value: &Option<String>| escape_html(value.as_deref().unwrap_or_default());
+    let failed_only = if filter.outcome.as_deref() == Some("failed") {
+        " checked"
+    } else {
+        ""
+    };
+    let rows = audit::search(&secrets.db, filter, AUDIT_ROWS)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to search update audit: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(|entry| {
+            let id = |id: Option<i64>| id.map(|id| id.to_string()).unwrap_or_default();
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+                 <td>{}</td></tr>",
+                entry.handled_at.format("%Y-%m-%d %H:%M:%S"),
+                entry.update_id,
+                escape_html(&entry.kind),
+                id(entry.chat_id),
+                id(entry.user_id),
+                escape_html(entry.detail.as_deref().unwrap_or_default()),
+                match &entry.error {
+                    Some(error) => format!("❌ {}", escape_html(error)),
+                    None => "✅".to_string(),
+                },
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        "<h2>Updates</h2>\
+         <form method=\"get\" action=\"/dashboard\">\
+         <input name=\"q\" placeholder=\"File name or text\" value=\"{}\"> \
+         <input name=\"kind\" placeholder=\"Kind, e.g. message\" value=\"{}\"> \
+         <input name=\"id\" placeholder=\"Chat or user id\" value=\"{}\"> \
+         <label><input type=\"checkbox\" name=\"outcome\" value=\"failed\"{}> Failed only</label> \
+         <button>Search</button></form>\
+         <table><tr><th>Handled</th><th>Update</th><th>Kind</th><th>Chat</th><th>User</th>\
+         <th>Detail</th><th>Outcome</th></tr>{}</table>",
+        field(&filter.q),
+        field(&filter.kind),
+        field(&filter.id),
+        failed_only,
+        rows
+    )
+}
+
+/// Streaks, gaps and the best hours to post, as on `/stats`.
+async fn cadence_section(secrets: &ServerSecretsState) -> String {
+    match cadence::analyze(&secrets.db).await {
+        Ok(Some(cadence)) => format!(
+            "<h2>Cadence</h2><ul>{}</ul>",
+            cadence
+                .lines()
+                .iter()
+                .map(|line| format!("<li>{}</li>", escape_html(line)))
+                .collect::<String>()
+        ),
+        Ok(None) => String::new(),
+        Err(e) => {
+            tracing::error!("Failed to analyze posting cadence: {}", e);
+            String::new()
+        }
+    }
+}
+
+/// What goes out over the next two weeks, as on `/calendar`.
+async fn calendar_section(secrets: &ServerSecretsState) -> String {
+    let rows = calendar::upcoming(secrets)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to list upcoming posts: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(|entry| {
+            format!(
+                "<tr><td>{}</td><td>{} {}</td></tr>",
+                entry.at.format("%a %Y-%m-%d %H:%M UTC"),
+                entry.icon,
+                escape_html(&entry.label)
+            )
+        })
+        .collect::<String>();
+    if rows.is_empty() {
+        return "<h2>Next two weeks</h2><p>Nothing planned.</p>".to_string();
+    }
+    format!("<h2>Next two weeks</h2><table>{}</table>", rows)
+}
+
+/// The subscriber chart, served by `/dashboard/growth.png`, and its numbers.
+async fn growth_section(secrets: &ServerSecretsState) -> String {
+    let growth =
+        match growth::Growth::between(&secrets.db, None, chrono::Utc::now().date_naive()).await {
+            Ok(growth) => growth,
+            Err(e) => {
+                tracing::error!("Failed to load subscriber counts: {}", e);
+                return String::new();
+            }
+        };
+    if !growth.is_chartable() {
+        return String::new();
+    }
+    format!(
+        "<h2>Subscribers</h2>\
+         <img src=\"/dashboard/growth.png\" alt=\"Subscribers over time\" width=\"600\">\
+         <p>{}</p>",
+        escape_html(&growth.summary()).replace('\n', "<br>")
+    )
+}
+
+/// The PNG of [`growth_section`], or `None` before there are two counts.
+async fn growth_png(secrets: &ServerSecretsState) -> Option<Vec<u8>> {
+    let growth = growth::Growth::between(&secrets.db, None, chrono::Utc::now().date_naive())
+        .await
+        .inspect_err(|e| tracing::error!("Failed to load subscriber counts: {}", e))
+        .ok()?;
+    if !growth.is_chartable() {
+        return None;
+    }
+    growth
+        .png()
+        .await
+        .inspect_err(|e| tracing::error!("Failed to draw the subscriber chart: {}", e))
+        .ok()
+}
+
+async fn dashboard_page(
+    secrets: &ServerSecretsState,
+    user: auth::DashboardUser,
+    filter: &audit::Filter,
+) -> String {
+    let logs = secrets
+        .logs
+        .tail(50)
+        .iter()
+        .map(|r| {
+            escape_html(&format!(
+                "{} {} {}: {}",
+                r.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                r.level,
+                r.target,
+                r.message
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let tracks = catalog::list_tracks(&secrets.db, &TrackFilter::default(), RECENT_TRACKS, 0)
+        .await
+        .inspect_err(|e| tracing::error!("Failed to list recent tracks: {}", e))
+        .unwrap_or_default()
+        .iter()
+        .map(|track| {
+            format!(
+                "<tr><td>{}</td><td>{} ❤</td><td><a href=\"{}\">post</a></td>\
+                 <td><a href=\"{}\">share</a></td></tr>",
+                escape_html(&track.label()),
+                track.reaction_count,
+                escape_html(&catalog::permalink(track.message_id)),
+                escape_html(&deep_link::url(secrets, deep_link::Link::Track(track.id))),
+            )
+        })
+        .collect::<String>();
+
+    page(
+        "Ankh dashboard",
+        &format!(
+            "<h1>Ankh</h1><p>Logged in as {}.</p>\
+             <form method=\"post\" action=\"/logout\"><button>Log out</button></form>\
+             <h2>Recent tracks</h2>\
+             <p>Share links make the bot send the track to whoever opens them.</p>\
+             <table>{}</table>\
+             {}\
+             {}\
+             {}\
+             {}\
+             <h2>Recent logs</h2><pre>{}</pre>",
+            user.id,
+            tracks,
+            growth_section(secrets).await,
+            cadence_section(secrets).await,
+            calendar_section(secrets).await,
+            audit_section(secrets, filter).await,
+            logs
+        ),
+    )
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_routes::routes;
+
+#[cfg(feature = "rocket")]
+mod rocket_routes {
+    use super::*;
+    use rocket::{
+        Route, State, get,
+        http::{ContentType, CookieJar, Status},
+        post,
+        response::{Redirect, content::RawHtml},
+        routes,
+    };
+    use std::sync::Arc;
+
+    pub fn routes() -> Vec<Route> {
+        routes![
+            login,
+            auth_telegram,
+            logout,
+            dashboard,
+            dashboard_login_redirect,
+            growth_chart
+        ]
+    }
+
+    #[get("/login")]
+    fn login(secrets: &State<Arc<ServerSecretsState>>) -> RawHtml<String> {
+        RawHtml(login_page(secrets))
+    }
+
+    #[get("/auth/telegram?<fields..>")]
+    fn auth_telegram(
+        fields: HashMap<String, String>,
+        cookies: &CookieJar<'_>,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Result<Redirect, Status> {
+        let user_id = telegram_login(secrets, &fields).map_err(Status::new)?;
+        auth::start_session(cookies, &secrets.bot_token, user_id);
+        Ok(Redirect::to("/dashboard"))
+    }
+
+    #[post("/logout")]
+    fn logout(cookies: &CookieJar<'_>) -> Redirect {
+        auth::end_session(cookies);
+        Redirect::to("/login")
+    }
+
+    #[get("/dashboard?<q>&<kind>&<id>&<outcome>")]
+    async fn dashboard(
+        user: auth::DashboardUser,
+        secrets: &State<Arc<ServerSecretsState>>,
+        q: Option<String>,
+        kind: Option<String>,
+        id: Option<String>,
+        outcome: Option<String>,
+    ) -> RawHtml<String> {
+        let filter = audit::Filter {
+            q,
+            kind,
+            id,
+            outcome,
+        };
+        RawHtml(dashboard_page(secrets, user, &filter).await)
+    }
+
+    #[get("/dashboard", rank = 2)]
+    fn dashboard_login_redirect() -> Redirect {
+        Redirect::to("/login")
+    }
+
+    #[get("/dashboard/growth.png")]
+    async fn growth_chart(
+        _user: auth::DashboardUser,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> Option<(ContentType, Vec<u8>)> {
+        growth_png(secrets).await.map(|png| (ContentType::PNG, png))
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_routes::router;
+
+#[cfg(feature = "axum")]
+mod axum_routes {
+    use super::*;
+    use crate::web::AppState;
+    use axum::{
+        Router,
+        extract::{Query, State},
+        http::{
+            StatusCode,
+            header::{CONTENT_TYPE, SET_COOKIE},
+        },
+        response::{Html, IntoResponse, Redirect, Response},
+        routing::{get, post},
+    };
+
+    pub fn router() -> Router<AppState> {
+        Router::new()
+            .route("/login", get(login))
+            .route("/auth/telegram", get(auth_telegram))
+            .route("/logout", post(logout))
+            .route("/dashboard", get(dashboard))
+            .route("/dashboard/growth.png", get(growth_chart))
+    }
+
+    async fn login(State(state): State<AppState>) -> Html<String> {
+        Html(login_page(&state.secrets))
+    }
+
+    async fn auth_telegram(
+        State(state): State<AppState>,
+        Query(fields): Query<HashMap<String, String>>,
+    ) -> Response {
+        match telegram_login(&state.secrets, &fields) {
+            Ok(user_id) => (
+                [(
+                    SET_COOKIE,
+                    auth::start_session(&state.secrets.bot_token, user_id),
+                )],
+                Redirect::to("/dashboard"),
+            )
+                .into_response(),
+            Err(status) => StatusCode::from_u16(status)
+                .unwrap_or(StatusCode::UNAUTHORIZED)
+                .into_response(),
+        }
+    }
+
+    async fn logout() -> impl IntoResponse {
+        ([(SET_COOKIE, auth::end_session())], Redirect::to("/login"))
+    }
+
+    async fn dashboard(
+        user: Option<auth::DashboardUser>,
+        State(state): State<AppState>,
+        Query(filter): Query<audit::Filter>,
+    ) -> Response {
+        match user {
+            Some(user) => Html(dashboard_page(&state.secrets, user, &filter).await).into_response(),
+            None => Redirect::to("/login").into_response(),
+        }
+    }
+
+    async fn growth_chart(
+        user: Option<auth::DashboardUser>,
+        State(state): State<AppState>,
+    ) -> Response {
+        if user.is_none() {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        match growth_png(&state.secrets).await {
+            Some(png) => ([(CONTENT_TYPE, "image/png")], png).into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
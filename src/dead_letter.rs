@@ -0,0 +1,139 @@
+use crate::{
+    QueuedMessage, ServerSecretsState, acoustid::Retag, mirror::Attribution, telegram::OutgoingText,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgPool, types::Json};
+use teloxide::types::Audio;
+
+/// A queued message that could not be published. Retrying requeues it and marks it
+/// as retried.
+#[derive(FromRow)]
+pub struct FailedItem {
+    pub id: i64,
+    pub message_id: i32,
+    pub audio: Json<Audio>,
+    pub tags: Vec<String>,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+    pub via: Option<Json<Attribution>>,
+    pub retag: Option<Json<Retag>>,
+    pub description: Option<String>,
+    pub blurb: Option<String>,
+    pub paid_stars: Option<i32>,
+    pub part_number: Option<i32>,
+    pub theme: Option<String>,
+    pub retried_at: Option<DateTime<Utc>>,
+}
+
+impl FailedItem {
+    pub fn into_queued(self) -> QueuedMessage {
+        QueuedMessage {
+            audio: self.audio.0,
+            tags: self.tags,
+            message_id: self.message_id,
+            via: self.via.map(|via| via.0),
+            retag: self.retag.map(|retag| retag.0),
+            description: self.description,
+            blurb: self.blurb,
+            queued_at: Utc::now(),
+            theme: self.theme,
+            stars: self.paid_stars.map(|stars| stars as u32),
+            part: self.part_number,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let audio = &self.audio.0;
+        match (&audio.performer, &audio.title, &audio.file_name) {
+            (Some(performer), Some(title), _) => format!("{} – {}", performer, title),
+            (_, Some(title), _) => title.clone(),
+            (_, _, Some(file_name)) => file_name.clone(),
+            _ => format!("message {}", self.message_id),
+        }
+    }
+}
+
+pub async fn push(db: &PgPool, message: &QueuedMessage, error: &str) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO failed_items
+             (message_id, audio, tags, error, via, retag, description, blurb, paid_stars,
+             part_number, theme)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+    )
+    .bind(message.message_id)
+    .bind(Json(&message.audio))
+    .bind(&message.tags)
+    .bind(error)
+    .bind(message.via.as_ref().map(Json))
+    .bind(message.retag.as_ref().map(Json))
+    .bind(&message.description)
+    .bind(&message.blurb)
+    .bind(message.stars.map(|stars| stars as i32))
+    .bind(message.part)
+    .bind(&message.theme)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Items waiting to be retried.
+pub async fn list(db: &PgPool) -> sqlx::Result<Vec<FailedItem>> {
+    sqlx::query_as("SELECT * FROM failed_items WHERE retried_at IS NULL ORDER BY id")
+        .fetch_all(db)
+        .await
+}
+
+pub async fn count(db: &PgPool) -> sqlx::Result<i64> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM failed_items WHERE retried_at IS NULL")
+        .fetch_one(db)
+        .await
+}
+
+/// Marks an item as retried so it can be requeued.
+pub async fn take(db: &PgPool, id: i64) -> sqlx::Result<Option<FailedItem>> {
+    sqlx::query_as(
+        "UPDATE failed_items SET retried_at = now()
+         WHERE id = $1 AND retried_at IS NULL
+         RETURNING *",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn take_all(db: &PgPool) -> sqlx::Result<Vec<FailedItem>> {
+    sqlx::query_as(
+        "UPDATE failed_items SET retried_at = now() WHERE retried_at IS NULL RETURNING *",
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// The latest failures, retried or not, newest first.
+pub async fn recent(db: &PgPool, limit: i64) -> sqlx::Result<Vec<FailedItem>> {
+    sqlx::query_as("SELECT * FROM failed_items ORDER BY failed_at DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(db)
+        .await
+}
+
+/// Reminds the owner that there are failed items waiting, if there are any.
+pub async fn alert(secrets: &ServerSecretsState) {
+    match count(&secrets.db).await {
+        Ok(0) => {}
+        Ok(n) => {
+            let text = format!(
+                "⚠️ {} item(s) failed to publish. See /failed, or /retry all.",
+                n
+            );
+            if let Err(e) = secrets
+                .telegram
+                .send_message(secrets.me_id, &OutgoingText::plain(text))
+                .await
+            {
+                tracing::warn!("Failed to send dead-letter alert: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("Failed to count failed items: {}", e),
+    }
+}
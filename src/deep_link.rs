@@ -0,0 +1,195 @@
+use crate::{ServerSecretsState, catalog, cleanup, digest};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use teloxide::{
+    prelude::*,
+    types::{FileId, InputFile},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Bytes of HMAC kept in a payload. Telegram allows 64 characters, and 48 bits are
+/// plenty to stop anyone guessing their way to other links.
+const SIGNATURE_BYTES: usize = 6;
+
+/// What a `t.me/<bot>?start=<payload>` link asks the bot to do.
+#[derive(Clone, Copy)]
+pub enum Link {
+    /// Send the catalog track with this id to whoever opened the link.
+    Track(i64),
+    /// Subscribe them to the weekly digest.
+    Digest,
+    /// Unsubscribe them from it.
+    StopDigest,
+}
+
+impl Link {
+    fn body(self) -> String {
+        match self {
+            Link::Track(id) => format!("track_{}", id),
+            Link::Digest => "digest".to_string(),
+            Link::StopDigest => "nodigest".to_string(),
+        }
+    }
+
+    fn parse(body: &str) -> Option<Self> {
+        match body {
+            "digest" => Some(Link::Digest),
+            "nodigest" => Some(Link::StopDigest),
+            _ => body.strip_prefix("track_")?.parse().ok().map(Link::Track),
+        }
+    }
+}
+
+/// Payloads are signed with a key derived from the bot token, like dashboard
+/// sessions, so links only work if the bot made them.
+fn signature(bot_token: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(format!("deeplink:{}", bot_token).as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    hex::encode(&mac.finalize().into_bytes()[..SIGNATURE_BYTES])
+}
+
+/// The `start` payload for `link`, e.g. `track_42_1a2b3c4d5e6f`.
+pub fn encode(bot_token: &str, link: Link) -> String {
+    let body = link.body();
+    format!("{}_{}", body, signature(bot_token, &body))
+}
+
+pub fn decode(bot_token: &str, payload: &str) -> Option<Link> {
+    let (body, presented) = payload.rsplit_once('_')?;
+    let expected = signature(bot_token, body);
+    if !bool::from(expected.as_bytes().ct_eq(presented.as_bytes())) {
+        return None;
+    }
+    Link::parse(body)
+}
+
+pub fn url(secrets: &ServerSecretsState, link: Link) -> String {
+    format!(
+        "https://t.me/{}?start={}",
+        secrets.bot_username,
+        encode(&secrets.bot_token, link)
+    )
+}
+
+/// Handles `/start <payload>` from anyone, owner or not. Returns `false` when the
+/// message is not a valid deep link, so it goes through the usual handling.
+pub async fn handle_start(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<bool, Error> {
+    let Some(payload) = message
+        .text()
+        .and_then(|text| text.strip_prefix("/start "))
+        .map(str::trim)
+    else {
+        return Ok(false);
+    };
+    let Some(link) = decode(&secrets.bot_token, payload) else {
+        return Ok(false);
+    };
+    tracing::info!(payload, chat_id = message.chat.id.0, "Deep link opened");
+
+    match link {
+        Link::Track(id) => match catalog::get_track(&secrets.db, id).await? {
+            // The stored file is the free upload, so paid tracks only get their post.
+            Some(track) if track.paid => {
+                bot.send_message(message.chat.id, catalog::permalink(track.message_id))
+                    .await?;
+            }
+            Some(track) => {
+                bot.send_audio(message.chat.id, InputFile::file_id(FileId(track.file_id)))
+                    .caption(catalog::permalink(track.message_id))
+                    .await?;
+            }
+            None => {
+                bot.send_message(message.chat.id, "That track is no longer available.")
+                    .await?;
+            }
+        },
+        Link::Digest => {
+            digest::subscribe(&secrets.db, message.chat.id).await?;
+            bot.send_message(
+                message.chat.id,
+                format!(
+                    "Subscribed! You'll get what was posted each week on Mondays.\n\
+                     Changed your mind? {}",
+                    url(secrets, Link::StopDigest)
+                ),
+            )
+            .await?;
+        }
+        Link::StopDigest => {
+            digest::unsubscribe(&secrets.db, message.chat.id).await?;
+            bot.send_message(message.chat.id, "Unsubscribed from the weekly digest.")
+                .await?;
+        }
+    }
+    Ok(true)
+}
+
+/// `/deeplink track <id>` or `/deeplink digest`, for the owner to hand out.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let link = match args.as_slice() {
+        ["track", id] => match id.trim_start_matches('#').parse() {
+            Ok(id) if catalog::get_track(&secrets.db, id).await?.is_some() => Some(Link::Track(id)),
+            _ => None,
+        },
+        ["digest"] => Some(Link::Digest),
+        _ => None,
+    };
+    let reply = match link {
+        Some(link) => url(secrets, link),
+        None => "Usage: /deeplink track <catalog id> or /deeplink digest".to_string(),
+    };
+    cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "123456:bot-token";
+
+    #[test]
+    fn payloads_round_trip() {
+        let payload = encode(TOKEN, Link::Track(42));
+        assert!(payload.starts_with("track_42_"));
+        assert!(payload.len() <= 64);
+        assert!(matches!(decode(TOKEN, &payload), Some(Link::Track(42))));
+        assert!(matches!(
+            decode(TOKEN, &encode(TOKEN, Link::Digest)),
+            Some(Link::Digest)
+        ));
+        assert!(matches!(
+            decode(TOKEN, &encode(TOKEN, Link::StopDigest)),
+            Some(Link::StopDigest)
+        ));
+    }
+
+    #[test]
+    fn tampered_payloads_are_rejected() {
+        let payload = encode(TOKEN, Link::Track(42));
+        let (_, signature) = payload.rsplit_once('_').unwrap();
+        assert!(decode(TOKEN, &format!("track_43_{}", signature)).is_none());
+
+        let mut forged = payload.clone();
+        let last = if forged.ends_with('0') { "1" } else { "0" };
+        forged.replace_range(forged.len() - 1.., last);
+        assert!(decode(TOKEN, &forged).is_none());
+
+        assert!(decode("654321:other-token", &payload).is_none());
+        assert!(decode(TOKEN, "track_42").is_none());
+        assert!(decode(TOKEN, "").is_none());
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{ServerSecretsState, fix_metadata};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, types::Json};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "dlg:";
+
+/// A conversation left alone this long is over; the next message is not taken as
+/// an answer to it.
+const TIMEOUT_MINUTES: i32 = 60;
+/// The button data every prompt understands.
+const SKIP: &str = "skip";
+const CANCEL: &str = "cancel";
+
+/// A guided conversation under way and how far along it is, kept per chat in
+/// `dialogues` so a restart does not lose it. Each flow asks one thing at a time
+/// and checks every answer before moving on.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "flow", rename_all = "snake_case")]
+pub enum Flow {
+    /// Title, artist and series of a posted track; see [`fix_metadata`].
+    FixMetadata(fix_metadata::Draft),
+}
+
+impl Flow {
+    fn prompt(&self) -> Prompt {
+        match self {
+            Flow::FixMetadata(draft) => draft.prompt(),
+        }
+    }
+
+    async fn answer(self, secrets: &ServerSecretsState, input: Input<'_>) -> Result<Step, Error> {
+        match self {
+            Flow::FixMetadata(draft) => draft.answer(secrets, input).await,
+        }
+    }
+}
+
+/// An answer to the current question.
+pub enum Input<'a> {
+    /// Typed out.
+    Text(&'a str),
+    /// One of the prompt's own buttons, by its data.
+    Choice(&'a str),
+    /// `/skip` or the Skip button: keep what there is.
+    Skip,
+}
+
+/// A question, with buttons for the answers that can be picked. Every prompt also
+/// gets a Cancel button.
+pub struct Prompt {
+    pub text: String,
+    /// Label and data of each button, one per row.
+    pub choices: Vec<(String, String)>,
+    /// Whether the question can be skipped with `/skip` or its button.
+    pub skippable: bool,
+}
+
+impl Prompt {
+    fn keyboard(&self) -> InlineKeyboardMarkup {
+        let mut rows: Vec<Vec<InlineKeyboardButton>> = self
+            .choices
+            .iter()
+            .map(|(label, data)| {
+                vec![InlineKeyboardButton::callback(
+                    label.clone(),
+                    format!("{}{}", CALLBACK_PREFIX, data),
+                )]
+            })
+            .collect();
+        let mut last = Vec::new();
+        if self.skippable {
+            last.push(InlineKeyboardButton::callback(
+                "Keep",
+                format!("{}{}", CALLBACK_PREFIX, SKIP),
+            ));
+        }
+        last.push(InlineKeyboardButton::callback(
+            "Cancel",
+            format!("{}{}", CALLBACK_PREFIX, CANCEL),
+        ));
+        rows.push(last);
+        InlineKeyboardMarkup::new(rows)
+    }
+}
+
+/// Where a flow goes after an answer.
+pub enum Step {
+    /// On to the next question.
+    Ask(Flow),
+    /// The answer will not do, for the reason given; the question stands.
+    Invalid(Flow, String),
+    /// Finished, with a last word for the owner.
+    Done(String),
+}
+
+async fn load(db: &PgPool, chat_id: ChatId) -> sqlx::Result<Option<(Json<Flow>, Option<i32>)>> {
+    sqlx::query_as(
+        "SELECT flow, prompt_id FROM dialogues
+         WHERE chat_id = $1 AND updated_at > now() - make_interval(mins => $2)",
+    )
+    .bind(chat_id.0)
+    .bind(TIMEOUT_MINUTES)
+    .fetch_optional(db)
+    .await
+}
+
+/// Whether a conversation is waiting for an answer in `chat_id`.
+pub async fn is_open(db: &PgPool, chat_id: ChatId) -> sqlx::Result<bool> {
+    Ok(load(db, chat_id).await?.is_some())
+}
+
+async fn save(
+    db: &PgPool,
+    chat_id: ChatId,
+    flow: &Flow,
+    prompt_id: Option<i32>,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO dialogues (chat_id, flow, prompt_id) VALUES ($1, $2, $3)
+         ON CONFLICT (chat_id) DO UPDATE SET
+             flow = EXCLUDED.flow, prompt_id = EXCLUDED.prompt_id, updated_at = now()",
+    )
+    .bind(chat_id.0)
+    .bind(Json(flow))
+    .bind(prompt_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Ends the conversation in `chat_id`, if there is one, returning its open question.
+async fn clear(db: &PgPool, chat_id: ChatId) -> sqlx::Result<Option<i32>> {
+    sqlx::query_scalar("DELETE FROM dialogues WHERE chat_id = $1 RETURNING prompt_id")
+        .bind(chat_id.0)
+        .fetch_optional(db)
+        .await
+        .map(Option::flatten)
+}
+
+/// Takes the buttons off a question that was answered.
+async fn close_prompt(bot: &Bot, chat_id: ChatId, prompt_id: Option<i32>) {
+    if let Some(prompt_id) = prompt_id
+        && let Err(e) = bot
+            .edit_message_reply_markup(chat_id, MessageId(prompt_id))
+            .await
+    {
+        tracing::debug!("Failed to close dialogue prompt: {}", e);
+    }
+}
+
+/// Asks the flow's current question, after `note` when there is one.
+async fn ask(
+    bot: &Bot,
+    db: &PgPool,
+    chat_id: ChatId,
+    flow: Flow,
+    note: Option<String>,
+    previous: Option<i32>,
+) -> Result<(), Error> {
+    close_prompt(bot, chat_id, previous).await;
+    let prompt = flow.prompt();
+    let text = match note {
+        Some(note) => format!("{}\n\n{}", note, prompt.text),
+        None => prompt.text.clone(),
+    };
+    let sent = bot
+        .send_message(chat_id, text)
+        .reply_markup(prompt.keyboard())
+        .await?;
+    save(db, chat_id, &flow, Some(sent.id.0)).await?;
+    Ok(())
+}
+
+/// Starts `flow` in `chat_id`, dropping whatever conversation was going on there.
+pub async fn start(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    chat_id: ChatId,
+    flow: Flow,
+) -> Result<(), Error> {
+    let previous = clear(&secrets.db, chat_id).await?;
+    ask(bot, &secrets.db, chat_id, flow, None, previous).await
+}
+
+async fn advance(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    chat_id: ChatId,
+    flow: Flow,
+    prompt_id: Option<i32>,
+    input: Input<'_>,
+) -> Result<(), Error> {
+    match flow.answer(secrets, input).await? {
+        Step::Ask(flow) => ask(bot, &secrets.db, chat_id, flow, None, prompt_id).await?,
+        Step::Invalid(flow, reason) => {
+            ask(bot, &secrets.db, chat_id, flow, Some(reason), prompt_id).await?
+        }
+        Step::Done(text) => {
+            clear(&secrets.db, chat_id).await?;
+            close_prompt(bot, chat_id, prompt_id).await;
+            bot.send_message(chat_id, text).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn cancel(bot: &Bot, secrets: &ServerSecretsState, chat_id: ChatId) -> Result<(), Error> {
+    let prompt_id = clear(&secrets.db, chat_id).await?;
+    close_prompt(bot, chat_id, prompt_id).await;
+    bot.send_message(chat_id, "Cancelled, nothing was changed.")
+        .await?;
+    Ok(())
+}
+
+/// Takes a text message as the answer to the question open in its chat, with
+/// `/skip` and `/cancel` on top. Returns `false` when there is no conversation or
+/// the message is something else, like another command or an upload.
+pub async fn handle_message(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<bool, Error> {
+    let Some(text) = message.text().map(str::trim) else {
+        return Ok(false);
+    };
+    let command = text
+        .strip_prefix('/')
+        .map(|command| command.split('@').next().unwrap_or_default());
+    if matches!(command, Some(command) if command != SKIP && command != CANCEL) {
+        return Ok(false);
+    }
+    let Some((Json(flow), prompt_id)) = load(&secrets.db, message.chat.id).await? else {
+        return Ok(false);
+    };
+
+    let input = match command {
+        Some(CANCEL) => {
+            cancel(bot, secrets, message.chat.id).await?;
+            return Ok(true);
+        }
+        Some(_) => Input::Skip,
+        None => Input::Text(text),
+    };
+    advance(bot, secrets, message.chat.id, flow, prompt_id, input).await?;
+    Ok(true)
+}
+
+/// Handles a button under a question. Buttons of questions that were answered
+/// already, or whose conversation timed out, do nothing.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let question = query.regular_message().ok_or("Dialogue prompt is gone")?;
+    let chat_id = question.chat.id;
+    let current = load(&secrets.db, chat_id)
+        .await?
+        .filter(|(_, prompt_id)| *prompt_id == Some(question.id.0));
+    let Some((Json(flow), prompt_id)) = current else {
+        bot.answer_callback_query(query.id.clone())
+            .text("That question is over")
+            .await?;
+        close_prompt(bot, chat_id, Some(question.id.0)).await;
+        return Ok(());
+    };
+    bot.answer_callback_query(query.id.clone()).await?;
+
+    let input = match data {
+        CANCEL => return cancel(bot, secrets, chat_id).await,
+        SKIP => Input::Skip,
+        choice => Input::Choice(choice),
+    };
+    advance(bot, secrets, chat_id, flow, prompt_id, input).await
+}
@@ -0,0 +1,88 @@
+use crate::{ServerSecretsState, catalog, commands::no_link_preview, flags::Flag, reactions};
+use chrono::{Days, NaiveDate};
+use sqlx::PgPool;
+use teloxide::{ApiError, RequestError, prelude::*};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const MAX_TRACKS: i64 = 50;
+
+pub async fn subscribe(db: &PgPool, chat_id: ChatId) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO digest_subscribers (chat_id) VALUES ($1) ON CONFLICT DO NOTHING")
+        .bind(chat_id.0)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+pub async fn unsubscribe(db: &PgPool, chat_id: ChatId) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM digest_subscribers WHERE chat_id = $1")
+        .bind(chat_id.0)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+async fn subscribers(db: &PgPool) -> sqlx::Result<Vec<i64>> {
+    sqlx::query_scalar("SELECT chat_id FROM digest_subscribers")
+        .fetch_all(db)
+        .await
+}
+
+fn render(tracks: &[catalog::Track]) -> String {
+    let mut text = "🗞 This week on the channel\n".to_string();
+    for track in tracks.iter().rev() {
+        text.push_str(&format!(
+            "\n{}\n{}",
+            reactions::label(track),
+            catalog::permalink(track.message_id)
+        ));
+    }
+    text
+}
+
+/// Weekly job, on Mondays, that DMs subscribers the tracks posted over the past week.
+pub async fn run(bot: &Bot, secrets: &ServerSecretsState, date: NaiveDate) -> Result<(), Error> {
+    if !secrets.flags.is_enabled(Flag::Digests) {
+        return Ok(());
+    }
+    let subscribers = subscribers(&secrets.db).await?;
+    if subscribers.is_empty() {
+        return Ok(());
+    }
+    let filter = catalog::TrackFilter {
+        posted_after: Some(
+            (date - Days::new(7))
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc(),
+        ),
+        posted_before: Some(date.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+        ..Default::default()
+    };
+    let tracks = catalog::list_tracks(&secrets.db, &filter, MAX_TRACKS, 0).await?;
+    if tracks.is_empty() {
+        return Ok(());
+    }
+
+    let text = render(&tracks);
+    for chat_id in subscribers {
+        let chat_id = ChatId(chat_id);
+        match secrets
+            .retry_policy
+            .run(|| {
+                bot.send_message(chat_id, text.clone())
+                    .link_preview_options(no_link_preview())
+                    .send()
+            })
+            .await
+        {
+            Ok(_) => {}
+            Err(RequestError::Api(ApiError::BotBlocked | ApiError::UserDeactivated)) => {
+                unsubscribe(&secrets.db, chat_id).await?;
+            }
+            Err(e) => tracing::warn!("Failed to send digest to {}: {}", chat_id, e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use crate::{
+    ServerSecretsState,
+    telegram::{Call, MemoryClient},
+};
+use std::sync::Arc;
+use teloxide::prelude::*;
+
+/// How many calls a summary lists before it just counts the rest.
+const SUMMARY_CALLS: usize = 30;
+/// Captions can be long; each listed call is cut to this many characters.
+const CALL_CHARS: usize = 120;
+
+/// Parses the `DRY_RUN` secret. When it is `true`, the queue goes through every step
+/// of publishing (processing, captions, releases, pacing) but its Telegram calls are
+/// logged instead of made, daily jobs are not scheduled, and nothing is written to
+/// the catalog.
+pub fn from_secret(raw: Option<&str>) -> Option<Arc<MemoryClient>> {
+    if raw.map(str::trim) != Some("true") {
+        return None;
+    }
+    tracing::warn!("Dry run: nothing will be posted");
+    Some(Arc::new(MemoryClient::new(1)))
+}
+
+pub fn is_active(secrets: &ServerSecretsState) -> bool {
+    secrets.dry_run.is_some()
+}
+
+/// For Telegram calls that do not go through [`crate::telegram::TelegramClient`]:
+/// during a dry run, records `what` as not sent and returns `true` so the caller
+/// leaves it out.
+pub fn skips(secrets: &ServerSecretsState, what: impl Into<String>) -> bool {
+    match &secrets.dry_run {
+        Some(client) => {
+            client.record(Call::Skipped(what.into()));
+            true
+        }
+        None => false,
+    }
+}
+
+fn summary(calls: &[impl std::fmt::Display]) -> String {
+    let mut text = format!("🧪 Dry run: {} call(s) not sent\n", calls.len());
+    for call in calls.iter().take(SUMMARY_CALLS) {
+        let call = call.to_string();
+        let mut line = call.chars().take(CALL_CHARS).collect::<String>();
+        if line.len() < call.len() {
+            line.push('…');
+        }
+        text.push_str(&format!("\n• {}", line));
+    }
+    if calls.len() > SUMMARY_CALLS {
+        text.push_str(&format!("\n…and {} more", calls.len() - SUMMARY_CALLS));
+    }
+    text
+}
+
+/// DMs the owner what the queue would have done since the last report.
+pub async fn report(bot: &Bot, secrets: &ServerSecretsState) {
+    let Some(client) = &secrets.dry_run else {
+        return;
+    };
+    let calls = client.take_calls();
+    if calls.is_empty() {
+        return;
+    }
+    if let Err(e) = bot.send_message(secrets.me_id, summary(&calls)).await {
+        tracing::warn!("Failed to send dry run summary: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_lists_calls_and_counts_the_rest() {
+        let calls: Vec<String> = (1..=SUMMARY_CALLS + 5)
+            .map(|n| format!("send message {}", n))
+            .collect();
+        let text = summary(&calls);
+        assert!(text.starts_with(&format!(
+            "🧪 Dry run: {} call(s) not sent",
+            SUMMARY_CALLS + 5
+        )));
+        assert!(text.contains(&format!("• send message {}", SUMMARY_CALLS)));
+        assert!(!text.contains(&format!("• send message {}", SUMMARY_CALLS + 1)));
+        assert!(text.ends_with("…and 5 more"));
+    }
+
+    #[test]
+    fn summary_cuts_long_calls() {
+        let long = "é".repeat(CALL_CHARS * 2);
+        let text = summary(&[long]);
+        let line = text.lines().last().unwrap();
+        assert_eq!(line.chars().count(), "• ".chars().count() + CALL_CHARS + 1);
+        assert!(line.ends_with('…'));
+    }
+
+    #[test]
+    fn summary_keeps_short_calls_whole() {
+        let text = summary(&["pin 7 in -100"]);
+        assert!(text.ends_with("• pin 7 in -100"));
+    }
+}
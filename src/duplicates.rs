@@ -0,0 +1,114 @@
+use crate::{
+    QueuedMessage, ServerSecretsState,
+    catalog::{self, Track},
+    telegram::OutgoingText,
+};
+use sqlx::{FromRow, PgPool};
+use teloxide::prelude::*;
+
+/// Trigram similarity of "performer title" above which a cataloged track is worth
+/// pointing out; high enough that sharing an artist alone does not reach it.
+const THRESHOLD: f32 = 0.6;
+/// How many candidates a warning lists at most.
+const MAX_CANDIDATES: i64 = 3;
+
+#[derive(FromRow)]
+struct Candidate {
+    #[sqlx(flatten)]
+    track: Track,
+    /// 1 for the very same file.
+    similarity: f32,
+}
+
+/// Cataloged tracks that look like the one titled `title` by `performer`, most alike
+/// first. Remastered, live or re-tagged copies match by name; the same file always
+/// matches.
+async fn similar(
+    db: &PgPool,
+    performer: Option<&str>,
+    title: &str,
+    file_unique_id: &str,
+) -> sqlx::Result<Vec<Candidate>> {
+    let name = match performer {
+        Some(performer) => format!("{} {}", performer, title),
+        None => title.to_string(),
+    };
+    sqlx::query_as(
+        "SELECT * FROM (
+             SELECT *, CASE WHEN file_unique_id = $2 THEN 1
+                 ELSE similarity(lower(concat_ws(' ', performer, title)), $1)
+             END::float4 AS similarity
+             FROM tracks WHERE deleted_at IS NULL
+         ) candidates
+         WHERE similarity >= $3
+         ORDER BY similarity DESC, posted_at DESC LIMIT $4",
+    )
+    .bind(name.to_lowercase())
+    .bind(file_unique_id)
+    .bind(THRESHOLD)
+    .bind(MAX_CANDIDATES)
+    .fetch_all(db)
+    .await
+}
+
+/// Tells the owner when an upload looks like something already posted, with links to
+/// the posts, so they can drop it from the queue. The upload is queued either way.
+pub async fn warn(secrets: &ServerSecretsState, chat_id: ChatId, message: &QueuedMessage) {
+    let (performer, title) = match &message.retag {
+        Some(retag) => (Some(retag.performer.as_str()), Some(retag.title.as_str())),
+        None => (
+            message.audio.performer.as_deref(),
+            message.audio.title.as_deref(),
+        ),
+    };
+    let Some(title) = title.filter(|title| !title.trim().is_empty()) else {
+        return;
+    };
+    let candidates = match similar(
+        &secrets.db,
+        performer,
+        title,
+        &message.audio.file.unique_id.0,
+    )
+    .await
+    {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            tracing::warn!("Failed to look for similar tracks: {}", e);
+            return;
+        }
+    };
+    if candidates.is_empty() {
+        return;
+    }
+
+    let lines = candidates
+        .iter()
+        .map(|candidate| {
+            let likeness = if candidate.similarity >= 1.0 {
+                "the same file".to_string()
+            } else {
+                format!("{:.0}% alike", candidate.similarity * 100.0)
+            };
+            format!(
+                "• {} ({}, {}): {}",
+                candidate.track.label(),
+                candidate.track.posted_at.format("%Y-%m-%d"),
+                likeness,
+                catalog::permalink(candidate.track.message_id)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = format!(
+        "⚠️ {} looks like something already posted:\n{}",
+        title, lines
+    );
+    if let Err(e) = secrets
+        .telegram
+        .send_message(chat_id, &OutgoingText::plain(text).without_link_preview())
+        .await
+    {
+        tracing::warn!("Failed to warn about a possible duplicate: {}", e);
+    }
+}
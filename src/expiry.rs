@@ -0,0 +1,64 @@
+use crate::{QueuedMessage, ServerSecretsState, dead_letter, telegram::OutgoingText};
+use anyhow::Context;
+use chrono::Utc;
+
+/// Parses `QUEUE_TTL_HOURS`; unset means queued items never go stale.
+pub fn ttl_from_secret(raw: Option<&str>) -> anyhow::Result<Option<chrono::Duration>> {
+    let Some(raw) = raw else {
+        return Ok(None);
+    };
+    let hours: i64 = raw
+        .trim()
+        .parse()
+        .ok()
+        .filter(|hours| *hours > 0)
+        .context("QUEUE_TTL_HOURS must be a positive number of hours")?;
+    Ok(Some(chrono::Duration::hours(hours)))
+}
+
+/// Takes out of a batch the items that have waited longer than `QUEUE_TTL_HOURS`,
+/// say through a long pause, and sets them aside with the failed items so they only
+/// go out once the owner confirms them with /retry. Returns the rest.
+pub async fn hold_stale(
+    secrets: &ServerSecretsState,
+    batch: Vec<QueuedMessage>,
+) -> Vec<QueuedMessage> {
+    let Some(ttl) = secrets.queue_ttl else {
+        return batch;
+    };
+    let now = Utc::now();
+    let (stale, fresh): (Vec<_>, Vec<_>) = batch
+        .into_iter()
+        .partition(|message| now - message.queued_at > ttl);
+    if stale.is_empty() {
+        return fresh;
+    }
+
+    let mut held = 0;
+    for message in &stale {
+        let error = format!(
+            "Expired: queued {} UTC, older than QUEUE_TTL_HOURS",
+            message.queued_at.format("%Y-%m-%d %H:%M")
+        );
+        match dead_letter::push(&secrets.db, message, &error).await {
+            Ok(()) => held += 1,
+            Err(e) => tracing::error!("Failed to hold stale item {}: {}", message.message_id, e),
+        }
+    }
+    tracing::warn!("Held back {} stale queued item(s)", held);
+
+    let text = format!(
+        "⏳ {} item(s) sat in the queue for over {} hours and were not published. \
+         See /failed, and /retry <id> or /retry all to post them anyway.",
+        held,
+        ttl.num_hours()
+    );
+    if let Err(e) = secrets
+        .telegram
+        .send_message(secrets.me_id, &OutgoingText::plain(text))
+        .await
+    {
+        tracing::warn!("Failed to report stale items: {}", e);
+    }
+    fresh
+}
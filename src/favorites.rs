@@ -0,0 +1,345 @@
+use crate::{
+    ServerSecretsState,
+    catalog::{self, Track},
+    cleanup, settings,
+    telegram::OutgoingText,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    ApiError, RequestError,
+    prelude::*,
+    types::{InlineKeyboardButton, MessageId},
+    utils::markdown,
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "fav:";
+
+/// Where the essentials post is, once `/essentials` made one.
+const SETTING: &str = "essentials_post";
+/// How many starred tracks the essentials post and `/favorites` list at most.
+const MAX_TRACKS: i64 = 50;
+/// Under Telegram's 4096 characters, with room for the heading.
+const MAX_LENGTH: usize = 3900;
+
+/// Parses `FAVORITES_USERS`, comma-separated ids of people who may star tracks
+/// besides the owner.
+pub fn users_from_secret(raw: Option<&str>) -> anyhow::Result<Vec<UserId>> {
+    raw.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.parse()
+                .map(UserId)
+                .with_context(|| format!("FAVORITES_USERS must be user ids, not {}", id))
+        })
+        .collect()
+}
+
+/// Whether `user` may star tracks: the owner or someone in `FAVORITES_USERS`.
+pub fn may_star(secrets: &ServerSecretsState, user: UserId) -> bool {
+    ChatId::from(user) == secrets.me_id || secrets.favorites_users.contains(&user)
+}
+
+/// "⭐ Star" for the post at `message_id`, starring it or taking the star back.
+pub fn button(message_id: i32) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback("⭐ Star", format!("{}{}", CALLBACK_PREFIX, message_id))
+}
+
+#[derive(Serialize, Deserialize)]
+struct EssentialsPost {
+    channel_id: i64,
+    message_id: i32,
+}
+
+/// Stars `track_id` for `user`, or takes the star back. Returns whether it is
+/// starred now.
+async fn toggle(db: &PgPool, user: UserId, track_id: i64) -> sqlx::Result<bool> {
+    let removed = sqlx::query("DELETE FROM favorites WHERE user_id = $1 AND track_id = $2")
+        .bind(user.0 as i64)
+        .bind(track_id)
+        .execute(db)
+        .await?;
+    if removed.rows_affected() > 0 {
+        return Ok(false);
+    }
+    sqlx::query("INSERT INTO favorites (user_id, track_id) VALUES ($1, $2)")
+        .bind(user.0 as i64)
+        .bind(track_id)
+        .execute(db)
+        .await?;
+    Ok(true)
+}
+
+/// What `user` starred, latest first.
+async fn starred_by(db: &PgPool, user: UserId) -> sqlx::Result<Vec<Track>> {
+    sqlx::query_as(
+        "SELECT t.* FROM favorites f JOIN tracks t ON t.id = f.track_id
+         WHERE f.user_id = $1 AND t.deleted_at IS NULL
+         ORDER BY f.starred_at DESC LIMIT $2",
+    )
+    .bind(user.0 as i64)
+    .bind(MAX_TRACKS)
+    .fetch_all(db)
+    .await
+}
+
+/// Everything starred by anyone, the most starred first and, among those, the most
+/// recently starred.
+async fn essentials(db: &PgPool) -> sqlx::Result<Vec<Track>> {
+    sqlx::query_as(
+        "SELECT t.* FROM tracks t
+         JOIN (SELECT track_id, count(*) AS stars, max(starred_at) AS last_starred
+               FROM favorites GROUP BY track_id) f ON f.track_id = t.id
+         WHERE t.deleted_at IS NULL
+         ORDER BY f.stars DESC, f.last_starred DESC LIMIT $1",
+    )
+    .bind(MAX_TRACKS)
+    .fetch_all(db)
+    .await
+}
+
+/// "⭐ *Essentials*" over a linked line per track, cut short when they do not all fit.
+fn render(tracks: &[Track]) -> String {
+    let mut text = format!("⭐ {}\n", markdown::bold("Essentials"));
+    let mut length = text.chars().count();
+    for (index, track) in tracks.iter().enumerate() {
+        let line = format!(
+            "{} {}\n",
+            markdown::escape(&format!("{}.", index + 1)),
+            markdown::link(
+                &markdown::escape_link_url(&catalog::permalink(track.message_id)),
+                &markdown::escape(&track.label()),
+            )
+        );
+        length += line.chars().count();
+        if length > MAX_LENGTH {
+            text.push_str(&markdown::escape(&format!(
+                "…and {} more",
+                tracks.len() - index
+            )));
+            break;
+        }
+        text.push_str(&line);
+    }
+    text
+}
+
+/// Brings the essentials post up to date, if `/essentials` made one: edits it, or
+/// posts it again when it can no longer be edited.
+async fn refresh(secrets: &ServerSecretsState) -> Result<(), Error> {
+    let Some(post) = settings::get::<EssentialsPost>(&secrets.db, SETTING).await? else {
+        return Ok(());
+    };
+    let channel_id = ChatId(post.channel_id);
+    let text =
+        OutgoingText::markdown(render(&essentials(&secrets.db).await?)).without_link_preview();
+    match secrets
+        .telegram
+        .edit_text(channel_id, MessageId(post.message_id), &text)
+        .await
+    {
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        Err(RequestError::Api(ApiError::MessageToEditNotFound | ApiError::MessageCantBeEdited)) => {
+            tracing::info!("Essentials post is gone, posting a new one");
+            post_essentials(secrets, channel_id, text).await
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn post_essentials(
+    secrets: &ServerSecretsState,
+    channel_id: ChatId,
+    text: OutgoingText,
+) -> Result<(), Error> {
+    let text = text.silent();
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_message(channel_id, &text))
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    settings::set(
+        &secrets.db,
+        SETTING,
+        &EssentialsPost {
+            channel_id: channel_id.0,
+            message_id: message.id.0,
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Stars the post at `message_id` for `user` or takes the star back, then updates
+/// the essentials post. Returns what to tell them.
+async fn star(
+    secrets: &ServerSecretsState,
+    user: UserId,
+    message_id: i32,
+) -> Result<String, Error> {
+    let Some(track) = catalog::track_at(&secrets.db, secrets.channel_id().0, message_id).await?
+    else {
+        return Ok("That post is not in the catalog.".to_string());
+    };
+    let starred = toggle(&secrets.db, user, track.id).await?;
+    if let Err(e) = refresh(secrets).await {
+        tracing::warn!("Failed to update the essentials post: {}", e);
+    }
+    Ok(if starred {
+        format!("⭐ Starred {}", track.label())
+    } else {
+        format!("Unstarred {}", track.label())
+    })
+}
+
+/// The post a `/fav` argument points at: its id or its t.me link.
+fn message_id_of(arg: &str) -> Option<i32> {
+    arg.trim()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .and_then(|id| id.parse().ok())
+}
+
+/// `/fav <post id or link>`, or `/fav` in reply to a track the bot sent: stars it, or
+/// takes the star back.
+pub async fn handle_fav(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let Some(user) = message.from.as_ref().map(|user| user.id) else {
+        return Ok(());
+    };
+    let replied = match message.reply_to_message().and_then(|reply| reply.audio()) {
+        Some(audio) => sqlx::query_scalar(
+            "SELECT message_id FROM tracks
+             WHERE file_unique_id = $1 AND channel_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(&audio.file.unique_id.0)
+        .bind(secrets.channel_id().0)
+        .fetch_optional(&secrets.db)
+        .await?
+        .flatten(),
+        None => None,
+    };
+    let text = match replied.or_else(|| message_id_of(args)) {
+        Some(message_id) => star(secrets, user, message_id).await?,
+        None => "Usage: /fav <post id or link>, or reply /fav to a track".to_string(),
+    };
+    cleanup::reply(bot, secrets, message.chat.id, text).await?;
+    Ok(())
+}
+
+/// `/favorites`: what the sender starred, latest first.
+pub async fn handle_list(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let Some(user) = message.from.as_ref().map(|user| user.id) else {
+        return Ok(());
+    };
+    let tracks = starred_by(&secrets.db, user).await?;
+    if tracks.is_empty() {
+        cleanup::reply(
+            bot,
+            secrets,
+            message.chat.id,
+            "Nothing starred yet. Star tracks with /fav or the ⭐ button.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let text = tracks
+        .iter()
+        .map(|track| {
+            format!(
+                "⭐ {}\n{}",
+                track.label(),
+                catalog::permalink(track.message_id)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    bot.send_message(message.chat.id, text)
+        .link_preview_options(crate::commands::no_link_preview())
+        .await?;
+    Ok(())
+}
+
+/// `/essentials`: posts the list of starred tracks to the channel, kept up to date as
+/// tracks are starred from then on; `/essentials off` stops updating it.
+pub async fn handle_essentials(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    if args.trim() == "off" {
+        settings::clear(&secrets.db, SETTING).await?;
+        cleanup::reply(
+            bot,
+            secrets,
+            message.chat.id,
+            "The essentials post is no longer updated.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let tracks = essentials(&secrets.db).await?;
+    if tracks.is_empty() {
+        cleanup::reply(
+            bot,
+            secrets,
+            message.chat.id,
+            "Nothing starred yet, so no essentials to post.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let channel_id = secrets.publish_channel_id();
+    let existing = settings::get::<EssentialsPost>(&secrets.db, SETTING)
+        .await?
+        .filter(|post| post.channel_id == channel_id.0);
+    let text = if existing.is_some() {
+        refresh(secrets).await?;
+        "Essentials post updated ✅"
+    } else {
+        let text = OutgoingText::markdown(render(&tracks)).without_link_preview();
+        post_essentials(secrets, channel_id, text).await?;
+        "Essentials posted ✅ It updates as tracks are starred."
+    };
+    cleanup::reply(bot, secrets, message.chat.id, text).await?;
+    Ok(())
+}
+
+/// Handles the ⭐ button under a post, for whoever may star tracks.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    if !may_star(secrets, query.from.id) {
+        bot.answer_callback_query(query.id.clone())
+            .text("Not allowed")
+            .await?;
+        return Ok(());
+    }
+    let message_id: i32 = data.parse()?;
+    let text = star(secrets, query.from.id, message_id).await?;
+    bot.answer_callback_query(query.id.clone())
+        .text(text)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,317 @@
+use crate::{
+    ServerSecretsState,
+    catalog::{self, Track},
+    dialogue::{Flow, Input, Prompt, Step},
+    format::Format,
+};
+use serde::{Deserialize, Serialize};
+use teloxide::{ApiError, RequestError, types::MessageId};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Longest title or artist name taken, about what Telegram shows in a player.
+const MAX_FIELD_CHARS: usize = 128;
+const MAX_SERIES_CHARS: usize = 64;
+/// How many of the known series are offered as buttons.
+const MAX_CHOICES: usize = 8;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Stage {
+    Title,
+    Performer,
+    Series,
+    Confirm,
+}
+
+/// The fix of one post's metadata as it is being put together: title, then artist,
+/// then series, then a last look before anything is saved.
+#[derive(Serialize, Deserialize)]
+pub struct Draft {
+    message_id: i32,
+    stage: Stage,
+    title: Option<String>,
+    performer: Option<String>,
+    series: String,
+    /// The series offered as buttons, in the order shown.
+    choices: Vec<String>,
+}
+
+/// Starts fixing the track posted at `message_id`; `None` when it is not in the
+/// catalog.
+pub async fn start(secrets: &ServerSecretsState, message_id: i32) -> Result<Option<Flow>, Error> {
+    let Some(track) = catalog::track_at(&secrets.db, secrets.channel_id().0, message_id).await?
+    else {
+        return Ok(None);
+    };
+    let choices = catalog::list_series(&secrets.db)
+        .await?
+        .into_iter()
+        .map(|series| series.name)
+        .filter(|name| *name != track.series)
+        .take(MAX_CHOICES)
+        .collect();
+    Ok(Some(Flow::FixMetadata(Draft {
+        message_id,
+        stage: Stage::Title,
+        title: track.title,
+        performer: track.performer,
+        series: track.series,
+        choices,
+    })))
+}
+
+/// A typed title, artist or series, trimmed, when it is one line of at most `max`
+/// characters.
+fn field(text: &str, max: usize) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("That was empty.".to_string());
+    }
+    if text.contains('\n') {
+        return Err("Keep it to one line.".to_string());
+    }
+    if text.chars().count() > max {
+        return Err(format!("That is too long, {} characters at most.", max));
+    }
+    Ok(text.to_string())
+}
+
+fn current(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("“{}”", value),
+        None => "none".to_string(),
+    }
+}
+
+impl Draft {
+    pub fn prompt(&self) -> Prompt {
+        match self.stage {
+            Stage::Title => Prompt {
+                text: format!(
+                    "✏️ Title of post {}? Now {}.\nSend the new one, or /skip to keep it.",
+                    self.message_id,
+                    current(&self.title)
+                ),
+                choices: Vec::new(),
+                skippable: true,
+            },
+            Stage::Performer => Prompt {
+                text: format!(
+                    "🎤 Artist? Now {}.\nSend the new one, or /skip to keep it.",
+                    current(&self.performer)
+                ),
+                choices: Vec::new(),
+                skippable: true,
+            },
+            Stage::Series => Prompt {
+                text: format!(
+                    "📚 Series? Now “{}”.\nPick one, send a new name, or /skip to keep it.",
+                    self.series
+                ),
+                choices: self
+                    .choices
+                    .iter()
+                    .enumerate()
+                    .map(|(index, name)| (name.clone(), format!("series:{}", index)))
+                    .collect(),
+                skippable: true,
+            },
+            Stage::Confirm => Prompt {
+                text: format!(
+                    "Save these for post {}?\nTitle: {}\nArtist: {}\nSeries: “{}”\n\n\
+                     Only these change in the caption.",
+                    self.message_id,
+                    current(&self.title),
+                    current(&self.performer),
+                    self.series
+                ),
+                choices: vec![("✅ Save".to_string(), "save".to_string())],
+                skippable: false,
+            },
+        }
+    }
+
+    pub async fn answer(
+        mut self,
+        secrets: &ServerSecretsState,
+        input: Input<'_>,
+    ) -> Result<Step, Error> {
+        let checked = match (self.stage, input) {
+            (Stage::Title, Input::Text(text)) => {
+                field(text, MAX_FIELD_CHARS).map(|title| self.title = Some(title))
+            }
+            (Stage::Performer, Input::Text(text)) => {
+                field(text, MAX_FIELD_CHARS).map(|performer| self.performer = Some(performer))
+            }
+            (Stage::Series, Input::Text(text)) => {
+                field(text, MAX_SERIES_CHARS).map(|series| self.series = series)
+            }
+            (Stage::Series, Input::Choice(choice)) => {
+                match choice
+                    .strip_prefix("series:")
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .and_then(|index| self.choices.get(index))
+                {
+                    Some(series) => {
+                        self.series = series.clone();
+                        Ok(())
+                    }
+                    None => Err("That series is not on the list.".to_string()),
+                }
+            }
+            (Stage::Title | Stage::Performer | Stage::Series, Input::Skip) => Ok(()),
+            (Stage::Confirm, Input::Choice("save")) => return self.save(secrets).await,
+            (Stage::Confirm, _) => Err("Save or cancel with the buttons.".to_string()),
+            (_, Input::Choice(_)) => Err("That button is not for this question.".to_string()),
+        };
+        if let Err(reason) = checked {
+            return Ok(Step::Invalid(Flow::FixMetadata(self), reason));
+        }
+        self.stage = match self.stage {
+            Stage::Title => Stage::Performer,
+            Stage::Performer => Stage::Series,
+            Stage::Series | Stage::Confirm => Stage::Confirm,
+        };
+        Ok(Step::Ask(Flow::FixMetadata(self)))
+    }
+
+    /// Writes the fix to the catalog and changes the same fields in the post's
+    /// caption, leaving the rest of it as it was posted.
+    async fn save(self, secrets: &ServerSecretsState) -> Result<Step, Error> {
+        let channel_id = secrets.channel_id();
+        let gone = || {
+            Ok(Step::Done(
+                "That post is no longer in the catalog.".to_string(),
+            ))
+        };
+        let Some(before) = catalog::track_at(&secrets.db, channel_id.0, self.message_id).await?
+        else {
+            return gone();
+        };
+        let latin = |text: &Option<String>| {
+            secrets
+                .transliteration
+                .zip(text.as_deref())
+                .and_then(|(scheme, text)| scheme.latin(text))
+        };
+        let Some(track) = catalog::update_metadata(
+            &secrets.db,
+            channel_id.0,
+            self.message_id,
+            self.title.as_deref(),
+            self.performer.as_deref(),
+            &self.series,
+            latin(&self.title).as_deref(),
+            latin(&self.performer).as_deref(),
+        )
+        .await?
+        else {
+            return gone();
+        };
+
+        let format = track.caption_format();
+        let caption = swap(&track.caption, format, &changes(&before, &track));
+        match secrets
+            .telegram
+            .edit_caption(channel_id, MessageId(track.message_id), &caption, format)
+            .await
+        {
+            Ok(()) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            Err(e) => {
+                tracing::warn!("Failed to update caption of {}: {}", track.message_id, e);
+                return Ok(Step::Done(format!(
+                    "Saved to the catalog, but the caption could not be updated: {}",
+                    e
+                )));
+            }
+        }
+        catalog::update_caption(
+            &secrets.db,
+            channel_id.0,
+            track.message_id,
+            &caption,
+            format,
+        )
+        .await?;
+        tracing::info!("Fixed metadata of post {}", track.message_id);
+        Ok(Step::Done(format!("Saved ✅ {}", track.label())))
+    }
+}
+
+/// "Series № 12", as the series link in a caption reads.
+fn series_label(track: &Track) -> String {
+    match track.number {
+        Some(number) => format!("{} № {}", track.series, number),
+        None => track.series.clone(),
+    }
+}
+
+/// What the fixed fields read in the caption before, next to what they read now.
+/// The series comes first, as the title may well be part of it.
+fn changes(before: &Track, after: &Track) -> Vec<(String, String)> {
+    [
+        (Some(series_label(before)), Some(series_label(after))),
+        (before.title.clone(), after.title.clone()),
+        (before.performer.clone(), after.performer.clone()),
+        (before.title_latin.clone(), after.title_latin.clone()),
+        (
+            before.performer_latin.clone(),
+            after.performer_latin.clone(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|change| match change {
+        (Some(old), Some(new)) if old != new => Some((old, new)),
+        _ => None,
+    })
+    .collect()
+}
+
+/// `caption` with the first place each old value appears replaced by the new one,
+/// both escaped for `format`. A value the caption does not show is left out.
+fn swap(caption: &str, format: Format, changes: &[(String, String)]) -> String {
+    changes
+        .iter()
+        .fold(caption.to_string(), |caption, (old, new)| {
+            caption.replacen(&format.escape(old), &format.escape(new), 1)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_keeps_everything_but_the_fields() {
+        let caption = "<b>Old &amp; Gold</b> — Someone\n<a href=\"https://t.me/c/1/5\">Mix № 3</a>\n\
+                       Found it on a tape. #house";
+        let changes = vec![
+            ("Mix № 3".to_string(), "Live № 1".to_string()),
+            ("Old & Gold".to_string(), "New & Gold".to_string()),
+        ];
+        assert_eq!(
+            swap(caption, Format::Html, &changes),
+            "<b>New &amp; Gold</b> — Someone\n<a href=\"https://t.me/c/1/5\">Live № 1</a>\n\
+             Found it on a tape. #house"
+        );
+    }
+
+    #[test]
+    fn swap_escapes_for_markdown() {
+        let changes = vec![("A.B".to_string(), "C-D".to_string())];
+        assert_eq!(
+            swap("*A\\.B* by X", Format::MarkdownV2, &changes),
+            "*C\\-D* by X"
+        );
+    }
+
+    #[test]
+    fn swap_leaves_missing_values_alone() {
+        let changes = vec![("Nowhere".to_string(), "Somewhere".to_string())];
+        assert_eq!(
+            swap("Just a caption", Format::Html, &changes),
+            "Just a caption"
+        );
+    }
+}
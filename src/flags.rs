@@ -0,0 +1,201 @@
+use crate::{ServerSecretsState, cleanup, settings};
+use anyhow::{Context, bail};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use teloxide::prelude::*;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const SETTING: &str = "flags";
+
+/// A subsystem that can be switched off per deployment while it is rolled out.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    /// Running `PROCESS_COMMAND` on tracks before posting.
+    Transcoding,
+    /// Posting previews to a separate channel, mirroring partner channels and copying
+    /// posts to the `MIRROR_CHANNELS`.
+    CrossPosting,
+    /// The weekly digest DMs.
+    Digests,
+    /// LLM-written track blurbs offered for approval; needs `BLURB_API_KEY` too.
+    Blurbs,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 4] = [
+        Flag::Transcoding,
+        Flag::CrossPosting,
+        Flag::Digests,
+        Flag::Blurbs,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::Transcoding => "transcoding",
+            Flag::CrossPosting => "cross_posting",
+            Flag::Digests => "digests",
+            Flag::Blurbs => "blurbs",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|flag| flag.name() == name)
+    }
+}
+
+#[derive(Serialize)]
+pub struct FlagState {
+    pub name: &'static str,
+    pub enabled: bool,
+    /// Whether the value was set at runtime rather than coming from `FEATURE_FLAGS`.
+    pub overridden: bool,
+}
+
+/// Defaults from the `FEATURE_FLAGS` secret (`digests=off,transcoding=on`; anything
+/// not listed is on), overridden at runtime through `/flags` or the admin API.
+pub struct Flags {
+    defaults: HashMap<Flag, bool>,
+    overrides: RwLock<HashMap<Flag, bool>>,
+}
+
+impl Flags {
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Self> {
+        let mut defaults = HashMap::new();
+        for entry in raw
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (name, value) = entry
+                .split_once('=')
+                .context("FEATURE_FLAGS entries must look like flag=on or flag=off")?;
+            let flag = Flag::parse(name.trim())
+                .with_context(|| format!("Unknown feature flag {}", name.trim()))?;
+            let enabled = match value.trim() {
+                "on" => true,
+                "off" => false,
+                other => bail!("Feature flags must be on or off, not {}", other),
+            };
+            defaults.insert(flag, enabled);
+        }
+        Ok(Self {
+            defaults,
+            overrides: RwLock::default(),
+        })
+    }
+
+    pub async fn load(&self, db: &PgPool) -> sqlx::Result<()> {
+        let overrides: HashMap<Flag, bool> = settings::get(db, SETTING).await?.unwrap_or_default();
+        *self.overrides.write().expect("flags lock poisoned") = overrides;
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, flag: Flag) -> bool {
+        let overrides = self.overrides.read().expect("flags lock poisoned");
+        overrides
+            .get(&flag)
+            .or_else(|| self.defaults.get(&flag))
+            .copied()
+            .unwrap_or(true)
+    }
+
+    pub fn list(&self) -> Vec<FlagState> {
+        let overrides = self.overrides.read().expect("flags lock poisoned");
+        Flag::ALL
+            .into_iter()
+            .map(|flag| FlagState {
+                name: flag.name(),
+                enabled: overrides
+                    .get(&flag)
+                    .or_else(|| self.defaults.get(&flag))
+                    .copied()
+                    .unwrap_or(true),
+                overridden: overrides.contains_key(&flag),
+            })
+            .collect()
+    }
+
+    /// Switches `flag` on or off, or back to its default with `None`, and persists
+    /// the overrides.
+    pub async fn set(&self, db: &PgPool, flag: Flag, enabled: Option<bool>) -> sqlx::Result<()> {
+        let overrides = {
+            let mut overrides = self.overrides.write().expect("flags lock poisoned");
+            match enabled {
+                Some(enabled) => overrides.insert(flag, enabled),
+                None => overrides.remove(&flag),
+            };
+            overrides.clone()
+        };
+        settings::set(db, SETTING, &overrides).await?;
+        tracing::info!(flag = flag.name(), ?enabled, "Feature flag changed");
+        Ok(())
+    }
+}
+
+fn render(flags: &Flags) -> String {
+    flags
+        .list()
+        .iter()
+        .map(|flag| {
+            format!(
+                "{} {}{}",
+                if flag.enabled { "✅" } else { "⛔" },
+                flag.name,
+                if flag.overridden {
+                    " (set at runtime)"
+                } else {
+                    ""
+                }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `/flags [name on|off|default]`.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let reply = match args.as_slice() {
+        [] => render(&secrets.flags),
+        [name, value] => {
+            let enabled = match *value {
+                "on" => Some(true),
+                "off" => Some(false),
+                "default" => None,
+                _ => {
+                    cleanup::reply(bot, secrets, message.chat.id, usage()).await?;
+                    return Ok(());
+                }
+            };
+            let Some(flag) = Flag::parse(name) else {
+                cleanup::reply(
+                    bot,
+                    secrets,
+                    message.chat.id,
+                    format!("Unknown flag {}.\n\n{}", name, render(&secrets.flags)),
+                )
+                .await?;
+                return Ok(());
+            };
+            secrets.flags.set(&secrets.db, flag, enabled).await?;
+            render(&secrets.flags)
+        }
+        _ => usage(),
+    };
+    cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+    Ok(())
+}
+
+fn usage() -> String {
+    "Usage: /flags <name> on|off|default, or /flags alone to list them.".to_string()
+}
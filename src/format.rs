@@ -0,0 +1,108 @@
+use anyhow::bail;
+use teloxide::{
+    types::ParseMode,
+    utils::{html, markdown},
+};
+
+/// A caption template and the markup it is written in. Every template carries its
+/// own, so a theme can be written in HTML while `CAPTION_TEMPLATE` stays Markdown.
+#[derive(Clone, Debug)]
+pub struct Template {
+    pub text: String,
+    pub format: Format,
+}
+
+/// The markup captions are written and sent in. Everything that puts text into a
+/// caption goes through here, so values are escaped for whichever mode the template
+/// is written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    MarkdownV2,
+    Html,
+}
+
+impl Format {
+    /// `markdown` (the default) or `html`, from a secret such as `CAPTION_PARSE_MODE`.
+    pub fn from_secret(name: &str, raw: Option<&str>) -> anyhow::Result<Self> {
+        match raw.map(|raw| raw.trim().to_lowercase()).as_deref() {
+            None | Some("" | "markdown" | "markdownv2") => Ok(Format::MarkdownV2),
+            Some("html") => Ok(Format::Html),
+            Some(other) => bail!("{} must be markdown or html, not {}", name, other),
+        }
+    }
+
+    /// As stored in the catalog next to each caption; Telegram's own name for it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::MarkdownV2 => "MarkdownV2",
+            Format::Html => "HTML",
+        }
+    }
+
+    /// The inverse of [`Format::name`]. Anything unknown is taken to be MarkdownV2,
+    /// which is what the catalog held before the mode was configurable.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "HTML" => Format::Html,
+            _ => Format::MarkdownV2,
+        }
+    }
+
+    pub fn parse_mode(self) -> ParseMode {
+        match self {
+            Format::MarkdownV2 => ParseMode::MarkdownV2,
+            Format::Html => ParseMode::Html,
+        }
+    }
+
+    pub fn escape(self, text: &str) -> String {
+        match self {
+            Format::MarkdownV2 => markdown::escape(text),
+            Format::Html => html::escape(text),
+        }
+    }
+
+    /// `text` linking to `url`; `text` is already escaped, the URL is not.
+    pub fn link(self, url: &str, text: &str) -> String {
+        match self {
+            // teloxide's own `link` escapes again what is escaped here already.
+            Format::MarkdownV2 => format!("[{}]({})", text, markdown::escape_link_url(url)),
+            Format::Html => format!("<a href=\"{}\">{}</a>", html::escape(url), text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_secret_defaults_to_markdown() {
+        assert_eq!(Format::from_secret("X", None).unwrap(), Format::MarkdownV2);
+        assert_eq!(
+            Format::from_secret("X", Some(" HTML ")).unwrap(),
+            Format::Html
+        );
+        assert!(Format::from_secret("X", Some("bbcode")).is_err());
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for format in [Format::MarkdownV2, Format::Html] {
+            assert_eq!(Format::from_name(format.name()), format);
+        }
+        assert_eq!(Format::from_name("Markdown"), Format::MarkdownV2);
+    }
+
+    #[test]
+    fn links_escape_the_url_only() {
+        assert_eq!(
+            Format::Html.link("https://t.me/a?b=1&c=2", "x &amp; y"),
+            "<a href=\"https://t.me/a?b=1&amp;c=2\">x &amp; y</a>"
+        );
+        assert_eq!(
+            Format::MarkdownV2.link("https://t.me/a_(b)", "x\\.y"),
+            "[x\\.y](https://t.me/a_(b\\))"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use crate::catalog::{self, CatalogStats, Release, SeriesSummary, TagSummary, Track, TrackFilter};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Object, Schema, http::GraphiQLSource,
+};
+use sqlx::PgPool;
+
+const MAX_PAGE_SIZE: i64 = 100;
+
+pub type CatalogSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn schema(pool: PgPool) -> CatalogSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(pool)
+        .limit_depth(8)
+        .limit_complexity(500)
+        .finish()
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Published tracks, newest first.
+    #[allow(clippy::too_many_arguments)]
+    async fn tracks(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] first: i64,
+        #[graphql(default = 0)] offset: i64,
+        performer: Option<String>,
+        tag: Option<String>,
+        series: Option<String>,
+        query: Option<String>,
+    ) -> async_graphql::Result<Vec<Track>> {
+        let filter = TrackFilter {
+            performer,
+            tag,
+            series,
+            query,
+            ..Default::default()
+        };
+        Ok(catalog::list_tracks(
+            ctx.data::<PgPool>()?,
+            &filter,
+            first.clamp(0, MAX_PAGE_SIZE),
+            offset.max(0),
+        )
+        .await?)
+    }
+
+    /// Full-text search over title, performer, album, tags and caption, with prefix
+    /// and fuzzy matching, best matches first.
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        #[graphql(default = 20)] first: i64,
+    ) -> async_graphql::Result<Vec<Track>> {
+        Ok(catalog::search(ctx.data::<PgPool>()?, &query, first.clamp(0, MAX_PAGE_SIZE)).await?)
+    }
+
+    async fn track(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<Track>> {
+        Ok(catalog::get_track(ctx.data::<PgPool>()?, id).await?)
+    }
+
+    async fn release(&self, ctx: &Context<'_>, id: i64) -> async_graphql::Result<Option<Release>> {
+        Ok(catalog::get_release(ctx.data::<PgPool>()?, id).await?)
+    }
+
+    async fn series(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<SeriesSummary>> {
+        Ok(catalog::list_series(ctx.data::<PgPool>()?).await?)
+    }
+
+    async fn tags(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<TagSummary>> {
+        Ok(catalog::list_tags(ctx.data::<PgPool>()?).await?)
+    }
+
+    async fn stats(&self, ctx: &Context<'_>) -> async_graphql::Result<CatalogStats> {
+        Ok(catalog::stats(ctx.data::<PgPool>()?).await?)
+    }
+}
+
+fn graphiql_html() -> String {
+    GraphiQLSource::build().endpoint("/graphql").finish()
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_routes::routes;
+
+#[cfg(feature = "rocket")]
+mod rocket_routes {
+    use super::*;
+    use async_graphql_rocket::{GraphQLQuery, GraphQLRequest, GraphQLResponse};
+    use rocket::{Route, State, get, post, response::content::RawHtml, routes};
+
+    pub fn routes() -> Vec<Route> {
+        routes![graphql_query, graphql_request, graphiql]
+    }
+
+    #[get("/graphql?<query..>")]
+    async fn graphql_query(schema: &State<CatalogSchema>, query: GraphQLQuery) -> GraphQLResponse {
+        query.execute(schema.inner()).await
+    }
+
+    #[post("/graphql", data = "<request>", format = "application/json")]
+    async fn graphql_request(
+        schema: &State<CatalogSchema>,
+        request: GraphQLRequest,
+    ) -> GraphQLResponse {
+        request.execute(schema.inner()).await
+    }
+
+    #[get("/graphiql")]
+    fn graphiql() -> RawHtml<String> {
+        RawHtml(graphiql_html())
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_routes::router;
+
+#[cfg(feature = "axum")]
+mod axum_routes {
+    use super::*;
+    use crate::web::{AppState, HttpError};
+    use axum::{
+        Json, Router,
+        extract::{RawQuery, State},
+        response::Html,
+        routing::get,
+    };
+
+    pub fn router() -> Router<AppState> {
+        Router::new()
+            .route("/graphql", get(graphql_query).post(graphql_request))
+            .route("/graphiql", get(graphiql))
+    }
+
+    async fn graphql_query(
+        State(state): State<AppState>,
+        RawQuery(query): RawQuery,
+    ) -> Result<Json<async_graphql::Response>, HttpError> {
+        let request = async_graphql::http::parse_query_string(query.as_deref().unwrap_or_default())
+            .map_err(|e| HttpError::new(400, e.to_string()))?;
+        Ok(Json(state.schema.execute(request).await))
+    }
+
+    async fn graphql_request(
+        State(state): State<AppState>,
+        Json(request): Json<async_graphql::Request>,
+    ) -> Json<async_graphql::Response> {
+        Json(state.schema.execute(request).await)
+    }
+
+    async fn graphiql() -> Html<String> {
+        Html(graphiql_html())
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{ServerSecretsState, media, subscribers::thousands, telegram};
+use anyhow::bail;
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use plotters::prelude::*;
+use sqlx::PgPool;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, ParseMode},
+    utils::markdown,
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "growth:";
+
+/// How far back the chart of the monthly recap goes.
+const RECAP_MONTHS: u32 = 12;
+
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 600;
+const MARGIN: u32 = 40;
+const GRID_LINES: i32 = 4;
+const BACKGROUND: RGBColor = RGBColor(0x14, 0x16, 0x1c);
+const GRID: RGBColor = RGBColor(0x2c, 0x31, 0x3a);
+const LINE: RGBColor = RGBColor(0x61, 0xaf, 0xef);
+
+#[derive(Clone, Copy)]
+pub enum Mode {
+    /// Post the recap to the channel on the first of the month.
+    Publish,
+    /// DM the owner a preview with publish/skip buttons.
+    Approve,
+}
+
+impl Mode {
+    /// Parses the `GROWTH_RECAP` secret; a missing value or `off` disables it.
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match raw.map(str::trim) {
+            None | Some("") | Some("off") => Ok(None),
+            Some("publish") => Ok(Some(Mode::Publish)),
+            Some("approve") => Ok(Some(Mode::Approve)),
+            Some(other) => bail!(
+                "GROWTH_RECAP must be off, publish or approve, not {}",
+                other
+            ),
+        }
+    }
+}
+
+/// The daily subscriber counts over a stretch of days, oldest first.
+pub struct Growth(Vec<(NaiveDate, i32)>);
+
+impl Growth {
+    /// The counts from `from` up to and including `until`; everything up to `until`
+    /// when `from` is `None`.
+    pub async fn between(
+        db: &PgPool,
+        from: Option<NaiveDate>,
+        until: NaiveDate,
+    ) -> sqlx::Result<Self> {
+        sqlx::query_as(
+            "SELECT counted_on, count FROM subscriber_counts
+             WHERE ($1::date IS NULL OR counted_on >= $1) AND counted_on <= $2
+             ORDER BY counted_on",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(db)
+        .await
+        .map(Growth)
+    }
+
+    /// A line takes two counts.
+    pub fn is_chartable(&self) -> bool {
+        self.0.len() >= 2
+    }
+
+    /// "1,204 → 1,290 (+86) from 2026-09-01 to 2026-10-01", and the peak when it is not
+    /// the last count.
+    pub fn summary(&self) -> String {
+        let (Some(&(from, first)), Some(&(until, last))) = (self.0.first(), self.0.last()) else {
+            return "No subscriber counts yet.".to_string();
+        };
+        let mut text = format!(
+            "{} → {} ({:+}) from {} to {}",
+            thousands(first),
+            thousands(last),
+            last - first,
+            from,
+            until
+        );
+        if let Some(&(date, peak)) = self.0.iter().rev().max_by_key(|(_, count)| *count)
+            && peak > last
+        {
+            text.push_str(&format!("\nPeak: {} on {}", thousands(peak), date));
+        }
+        text
+    }
+
+    /// The counts as a line over a shaded area, scaled to fit. Like the year in review
+    /// chart it carries no text, so no fonts are needed; the numbers go in the caption.
+    pub async fn chart(&self) -> Result<media::LocalFile, Error> {
+        if !self.is_chartable() {
+            return Err("Not enough subscriber counts for a chart".into());
+        }
+        let output = media::LocalFile::scratch(&format!(
+            "growth-{}.png",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        let path = output.path.clone();
+        let points = self.0.clone();
+        tokio::task::spawn_blocking(move || draw(&path, &points)).await??;
+        Ok(output)
+    }
+
+    /// The chart as PNG bytes, for the dashboard.
+    pub async fn png(&self) -> Result<Vec<u8>, Error> {
+        let chart = self.chart().await?;
+        Ok(tokio::fs::read(&chart.path).await?)
+    }
+}
+
+fn draw(path: &Path, points: &[(NaiveDate, i32)]) -> Result<(), Error> {
+    let start = points[0].0;
+    let data: Vec<(i64, i32)> = points
+        .iter()
+        .map(|&(date, count)| ((date - start).num_days(), count))
+        .collect();
+    let span = data.last().map_or(1, |&(day, _)| day.max(1));
+    let min = data.iter().map(|&(_, count)| count).min().unwrap_or(0);
+    let max = data.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    let padding = ((max - min) / 10).max(1);
+    let (low, high) = ((min - padding).max(0), max + padding);
+
+    let root = BitMapBackend::new(path, (WIDTH, HEIGHT)).into_drawing_area();
+    root.fill(&BACKGROUND)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(MARGIN)
+        .build_cartesian_2d(0..span, low..high)?;
+    for step in 1..GRID_LINES {
+        let y = low + (high - low) * step / GRID_LINES;
+        chart.draw_series(LineSeries::new([(0, y), (span, y)], &GRID))?;
+    }
+    chart.draw_series(AreaSeries::new(data.clone(), low, LINE.mix(0.25)))?;
+    chart.draw_series(LineSeries::new(data, LINE.stroke_width(3)))?;
+    root.present()?;
+    Ok(())
+}
+
+/// `/growth`: the subscriber chart over the last given number of days, or all of it.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let today = Utc::now().date_naive();
+    let from = match args.trim() {
+        "" => None,
+        days => match days.parse::<u64>() {
+            Ok(days) => today.checked_sub_days(chrono::Days::new(days)),
+            Err(_) => {
+                bot.send_message(message.chat.id, "Usage: /growth [days]")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+    let growth = Growth::between(&secrets.db, from, today).await?;
+    if !growth.is_chartable() {
+        bot.send_message(
+            message.chat.id,
+            "Not enough subscriber counts yet; they are taken once a day.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let chart = growth.chart().await?;
+    bot.send_photo(message.chat.id, InputFile::file(chart.path.clone()))
+        .caption(format!("📈 Subscribers\n{}", growth.summary()))
+        .await?;
+    Ok(())
+}
+
+/// The month before `date`, for the recap posted on the first: its name and its
+/// first day.
+fn previous_month(date: NaiveDate) -> (String, NaiveDate) {
+    let first = date.with_day(1).unwrap_or(date) - Months::new(1);
+    (first.format("%B %Y").to_string(), first)
+}
+
+/// The recap of the month before `date`: a heading with the month's change over the
+/// chart of the last year.
+async fn recap(db: &PgPool, date: NaiveDate) -> Result<Option<(String, media::LocalFile)>, Error> {
+    let (month, month_start) = previous_month(date);
+    let month_growth = Growth::between(db, Some(month_start), date).await?;
+    if !month_growth.is_chartable() {
+        return Ok(None);
+    }
+    let year = Growth::between(db, Some(date - Months::new(RECAP_MONTHS)), date).await?;
+    let caption = format!(
+        "📈 *{}*\n{}",
+        markdown::escape(&format!("Subscribers in {}", month)),
+        markdown::escape(&month_growth.summary())
+    );
+    Ok(Some((caption, year.chart().await?)))
+}
+
+async fn publish(secrets: &ServerSecretsState, date: NaiveDate) -> Result<bool, Error> {
+    let Some((caption, chart)) = recap(&secrets.db, date).await? else {
+        return Ok(false);
+    };
+    let photo = telegram::OutgoingPhoto {
+        path: chart.path.clone(),
+        caption: Some(caption),
+        reply_to: None,
+        silent: false,
+    };
+    let message = secrets
+        .retry_policy
+        .run(|| {
+            secrets
+                .telegram
+                .send_photo(secrets.publish_channel_id(), &photo)
+        })
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    Ok(true)
+}
+
+/// Runs after the daily subscriber count: on the first of the month, sums up the
+/// month before and publishes it or asks for approval, depending on `mode`. Does
+/// nothing on other days or without counts for that month.
+pub async fn run(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    mode: Mode,
+    date: NaiveDate,
+) -> Result<(), Error> {
+    if date.day() != 1 {
+        return Ok(());
+    }
+    match mode {
+        Mode::Publish => {
+            if !publish(secrets, date).await? {
+                tracing::info!("Not enough subscriber counts for a growth recap");
+            }
+        }
+        Mode::Approve => {
+            let Some((caption, chart)) = recap(&secrets.db, date).await? else {
+                tracing::info!("Not enough subscriber counts for a growth recap");
+                return Ok(());
+            };
+            let keyboard = InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback(
+                    "Publish",
+                    format!("{}publish:{}", CALLBACK_PREFIX, date),
+                ),
+                InlineKeyboardButton::callback("Skip", format!("{}skip:{}", CALLBACK_PREFIX, date)),
+            ]]);
+            bot.send_photo(secrets.me_id, InputFile::file(chart.path.clone()))
+                .caption(caption)
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(keyboard)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles the publish/skip buttons of an approval request.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let (action, date) = data.split_once(':').ok_or("Malformed callback data")?;
+    let date: NaiveDate = date.parse()?;
+
+    let status = match action {
+        "publish" => {
+            if publish(secrets, date).await? {
+                "Published ✅"
+            } else {
+                "Nothing to publish"
+            }
+        }
+        "skip" => "Skipped",
+        _ => return Err("Unknown growth recap action".into()),
+    };
+
+    bot.answer_callback_query(query.id.clone())
+        .text(status)
+        .await?;
+    if let Some(message) = query.regular_message() {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+use crate::{ServerSecretsState, media};
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use std::collections::HashMap;
+use teloxide::{prelude::*, types::Audio};
+
+/// How many hashtags a caption gets when the rules do not say.
+const DEFAULT_MAX: usize = 5;
+/// What goes before a year, since Telegram does not link hashtags that are all digits.
+const DEFAULT_YEAR_PREFIX: &str = "y";
+
+/// Where a hashtag comes from.
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Source {
+    /// Each performer, "Daft Punk & Pharrell" being two.
+    Artist,
+    /// The year of the `date` tag, as `#y1997`.
+    Year,
+    /// Each genre in the `genre` tag.
+    Genre,
+}
+
+#[derive(Deserialize)]
+struct RawRules {
+    from: Vec<Source>,
+    #[serde(default)]
+    max: Option<usize>,
+    #[serde(default)]
+    blacklist: Vec<String>,
+    #[serde(default)]
+    year_prefix: Option<String>,
+}
+
+/// Hashtags made up from a track's metadata and added to its caption and catalog
+/// tags, configured with `AUTO_HASHTAGS` as JSON, e.g. `{"from": ["artist", "year",
+/// "genre"], "max": 4, "blacklist": ["various_artists", "other"]}`. Tags are
+/// lowercase, with underscores for spaces and anything but letters and digits
+/// dropped; the blacklist is written the same way.
+pub struct Rules {
+    from: Vec<Source>,
+    max: usize,
+    blacklist: Vec<String>,
+    year_prefix: String,
+}
+
+impl Rules {
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        let Some(raw) = raw.filter(|raw| !raw.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let raw: RawRules =
+            serde_json::from_str(raw).context("AUTO_HASHTAGS must be a JSON object of rules")?;
+        if raw.from.is_empty() {
+            bail!("AUTO_HASHTAGS needs at least one source in \"from\"");
+        }
+        Ok(Some(Rules {
+            from: raw.from,
+            max: raw.max.unwrap_or(DEFAULT_MAX),
+            blacklist: raw
+                .blacklist
+                .iter()
+                .filter_map(|tag| normalize(tag))
+                .collect(),
+            year_prefix: raw
+                .year_prefix
+                .unwrap_or_else(|| DEFAULT_YEAR_PREFIX.to_string()),
+        }))
+    }
+
+    fn needs_file(&self) -> bool {
+        self.from
+            .iter()
+            .any(|source| matches!(source, Source::Year | Source::Genre))
+    }
+
+    /// The hashtags for a track by `performer` whose file is tagged `tags`, in the
+    /// order of the sources, without the `#` and without any in `existing`.
+    fn synthesize(
+        &self,
+        performer: Option<&str>,
+        tags: &HashMap<String, String>,
+        existing: &[String],
+    ) -> Vec<String> {
+        let mut hashtags: Vec<String> = Vec::new();
+        for source in &self.from {
+            let candidates: Vec<String> = match source {
+                Source::Artist => performer.map(split_artists).unwrap_or_default(),
+                Source::Year => tags
+                    .get("date")
+                    .and_then(|date| date.get(..4))
+                    .filter(|year| year.chars().all(|c| c.is_ascii_digit()))
+                    .map(|year| vec![format!("{}{}", self.year_prefix, year)])
+                    .unwrap_or_default(),
+                Source::Genre => tags
+                    .get("genre")
+                    .map(|genres| genres.split([';', ',', '/']).map(str::to_string).collect())
+                    .unwrap_or_default(),
+            };
+            for tag in candidates.iter().filter_map(|tag| normalize(tag)) {
+                if !self.blacklist.contains(&tag)
+                    && !existing.contains(&tag)
+                    && !hashtags.contains(&tag)
+                {
+                    hashtags.push(tag);
+                }
+            }
+        }
+        hashtags.truncate(self.max);
+        hashtags
+    }
+}
+
+/// Separators between performers, as tagged by most libraries.
+const ARTIST_SEPARATORS: [&str; 9] = [
+    " feat. ",
+    " Feat. ",
+    " ft. ",
+    " Ft. ",
+    " featuring ",
+    " & ",
+    " x ",
+    ", ",
+    "; ",
+];
+
+/// "Daft Punk & Pharrell Williams feat. Nile Rodgers" → the three of them.
+fn split_artists(performer: &str) -> Vec<String> {
+    let mut artists = vec![performer.to_string()];
+    for separator in ARTIST_SEPARATORS {
+        artists = artists
+            .iter()
+            .flat_map(|artist| artist.split(separator).map(str::to_string))
+            .collect();
+    }
+    artists
+}
+
+/// "Sigur Rós" → "sigur_rós"; `None` when nothing usable is left.
+fn normalize(raw: &str) -> Option<String> {
+    let mut tag = String::new();
+    for c in raw.trim().trim_start_matches('#').chars() {
+        if c.is_alphanumeric() {
+            tag.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '_' || c == '-') && !tag.ends_with('_') {
+            tag.push('_');
+        }
+    }
+    let tag = tag.trim_matches('_').to_string();
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// The tags of a track's file that `AUTO_HASHTAGS` needs for the year and genre,
+/// empty when it needs none. Reading them takes the file: `processed` when there is
+/// one, otherwise a copy is fetched. Failures only cost those hashtags.
+pub async fn probe(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    audio: &Audio,
+    processed: Option<&media::LocalFile>,
+) -> HashMap<String, String> {
+    if !secrets
+        .auto_hashtags
+        .as_ref()
+        .is_some_and(Rules::needs_file)
+    {
+        return HashMap::new();
+    }
+    let probed = match processed {
+        Some(file) => media::probe_tags(&file.path).await,
+        None => match media::fetch(bot, secrets, &audio.file).await {
+            Ok(file) => media::probe_tags(&file.path).await,
+            Err(e) => Err(e),
+        },
+    };
+    probed.unwrap_or_else(|e| {
+        tracing::warn!("Hashtags without year and genre: {}", e);
+        HashMap::new()
+    })
+}
+
+/// The hashtags `AUTO_HASHTAGS` makes for a track about to be posted, from its
+/// performer and the `file_tags` [`probe`] read.
+pub fn for_upload(
+    secrets: &ServerSecretsState,
+    performer: Option<&str>,
+    existing: &[String],
+    file_tags: &HashMap<String, String>,
+) -> Vec<String> {
+    match &secrets.auto_hashtags {
+        Some(rules) => rules.synthesize(performer, file_tags, existing),
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{ServerSecretsState, maintenance, media, telegram::OutgoingText, web::HttpError};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use teloxide::prelude::*;
+use tokio::io::AsyncWriteExt;
+
+/// What an external system can ask for. The `event` field picks the variant, so a
+/// payload looks like `{"event": "enqueue", "url": "https://...", "tags": ["live"]}`.
+#[derive(Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum HookEvent {
+    /// Downloads an audio file and queues it like a forwarded track.
+    Enqueue {
+        url: String,
+        #[serde(flatten)]
+        metadata: media::Metadata,
+    },
+    /// Posts a plain text message to the channel.
+    Announce { text: String },
+}
+
+impl HookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            HookEvent::Enqueue { .. } => "enqueue",
+            HookEvent::Announce { .. } => "announce",
+        }
+    }
+}
+
+/// Which events each webhook source may send, configured with `INBOUND_HOOKS` as
+/// `source=event,event;source2=event`. A source's token is any API token with the
+/// `hook:<source>` scope, e.g. `API_TOKENS=s3cret:hook:nas`.
+pub struct HookSources {
+    events: HashMap<String, Vec<String>>,
+}
+
+impl HookSources {
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Self> {
+        let mut events = HashMap::new();
+        for entry in raw
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (source, allowed) = entry
+                .split_once('=')
+                .context("INBOUND_HOOKS entries must look like source=event,event")?;
+            let allowed = allowed
+                .split(',')
+                .map(|event| event.trim().to_string())
+                .collect::<Vec<_>>();
+            if let Some(event) = allowed
+                .iter()
+                .find(|event| !["enqueue", "announce"].contains(&event.as_str()))
+            {
+                bail!(
+                    "INBOUND_HOOKS events must be enqueue or announce, not {}",
+                    event
+                );
+            }
+            events.insert(source.trim().to_string(), allowed);
+        }
+        Ok(Self { events })
+    }
+
+    fn allows(&self, source: &str, event: &str) -> bool {
+        self.events
+            .get(source)
+            .is_some_and(|allowed| allowed.iter().any(|e| e == event))
+    }
+
+    fn knows(&self, source: &str) -> bool {
+        self.events.contains_key(source)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum HookResponse {
+    Enqueue {
+        position: usize,
+        queue_len: usize,
+        estimated_publish_time: DateTime<Utc>,
+    },
+    Announce {
+        message_id: i32,
+    },
+}
+
+fn failed(e: impl std::fmt::Display) -> HttpError {
+    HttpError::new(422, e.to_string())
+}
+
+/// The last path segment of `url`, without query string or fragment.
+fn url_file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Streams `url` to a scratch file, refusing anything the Bot API would not accept.
+async fn download(secrets: &ServerSecretsState, url: &str) -> Result<media::LocalFile, HttpError> {
+    let limit = secrets.bot_api_mode.upload_limit();
+    let mut response = reqwest::get(url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(failed)?;
+
+    let extension = url_file_name(url)
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or("mp3");
+    let local = media::LocalFile::scratch(&format!(
+        "hook-{}.{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        extension
+    ));
+    let mut file = tokio::fs::File::create(&local.path).await.map_err(failed)?;
+
+    let mut written = 0;
+    while let Some(chunk) = response.chunk().await.map_err(failed)? {
+        written += chunk.len() as u64;
+        if written > limit {
+            return Err(HttpError::new(
+                413,
+                format!("File is over the {} byte upload limit", limit),
+            ));
+        }
+        file.write_all(&chunk).await.map_err(failed)?;
+    }
+    file.flush().await.map_err(failed)?;
+
+    Ok(local)
+}
+
+/// Checks `token` against `source` and carries out `event`.
+async fn receive(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    source: &str,
+    token: Option<&str>,
+    event: HookEvent,
+) -> Result<HookResponse, HttpError> {
+    if !secrets.hook_sources.knows(source) {
+        return Err(HttpError::new(404, format!("Unknown source {}", source)));
+    }
+    let token = token.ok_or_else(|| HttpError::new(401, "Missing bearer token"))?;
+    if !secrets
+        .api_tokens
+        .authorize(token, &format!("hook:{}", source))
+    {
+        return Err(HttpError::new(403, "Token not valid for this source"));
+    }
+    if !secrets.hook_sources.allows(source, event.name()) {
+        return Err(HttpError::new(
+            403,
+            format!("{} may not send {} events", source, event.name()),
+        ));
+    }
+    tracing::info!(source, event = event.name(), "Inbound webhook");
+
+    match event {
+        HookEvent::Enqueue { .. } if maintenance::is_on(secrets) => {
+            Err(HttpError::new(503, "Not taking uploads during maintenance"))
+        }
+        HookEvent::Enqueue { url, metadata } => {
+            let local = download(secrets, &url).await?;
+            let name = url_file_name(&url);
+            let title = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+            let position =
+                media::queue_file(bot, secrets, &local.path, title.to_string(), metadata)
+                    .await
+                    .map_err(failed)?;
+            Ok(HookResponse::Enqueue {
+                position: position.position,
+                queue_len: position.queue_len,
+                estimated_publish_time: position.estimated_publish_time(),
+            })
+        }
+        HookEvent::Announce { text } => {
+            let channel_id = secrets.publish_channel_id();
+            let text = OutgoingText::plain(text);
+            let message = secrets
+                .retry_policy
+                .run(|| secrets.telegram.send_message(channel_id, &text))
+                .await
+                .map_err(failed)?;
+            secrets
+                .last_message_id
+                .store(message.id.0, Ordering::Relaxed);
+            Ok(HookResponse::Announce {
+                message_id: message.id.0,
+            })
+        }
+    }
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_routes::routes;
+
+#[cfg(feature = "rocket")]
+mod rocket_routes {
+    use super::*;
+    use crate::auth::BearerToken;
+    use rocket::{Route, State, post, routes, serde::json::Json};
+
+    pub fn routes() -> Vec<Route> {
+        routes![hook]
+    }
+
+    #[post("/hooks/<source>", data = "<event>")]
+    async fn hook(
+        source: &str,
+        token: BearerToken<'_>,
+        bot: &State<Arc<Bot>>,
+        secrets: &State<Arc<ServerSecretsState>>,
+        event: Json<HookEvent>,
+    ) -> Result<Json<HookResponse>, HttpError> {
+        Ok(Json(
+            receive(bot, secrets, source, Some(token.0), event.into_inner()).await?,
+        ))
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_routes::router;
+
+#[cfg(feature = "axum")]
+mod axum_routes {
+    use super::*;
+    use crate::{auth::bearer_token, web::AppState};
+    use axum::{
+        Json, Router,
+        extract::{Path, State},
+        http::{HeaderMap, header::AUTHORIZATION},
+        routing::post,
+    };
+
+    pub fn router() -> Router<AppState> {
+        Router::new().route("/hooks/{source}", post(hook))
+    }
+
+    async fn hook(
+        State(state): State<AppState>,
+        Path(source): Path<String>,
+        headers: HeaderMap,
+        Json(event): Json<HookEvent>,
+    ) -> Result<Json<HookResponse>, HttpError> {
+        let token = bearer_token(
+            headers
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok()),
+        );
+        Ok(Json(
+            receive(&state.bot, &state.secrets, &source, token, event).await?,
+        ))
+    }
+}
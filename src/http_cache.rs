@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+
+/// Caches may reuse a response for a minute, then have to revalidate it.
+const CACHE_CONTROL: &str = "public, max-age=60, must-revalidate";
+
+fn etag(version: DateTime<Utc>) -> String {
+    format!("W/\"{:x}\"", version.timestamp_micros())
+}
+
+fn http_date(version: DateTime<Utc>) -> String {
+    version.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// The `If-None-Match` and `If-Modified-Since` headers of a request.
+pub struct Conditional {
+    if_none_match: Option<String>,
+    if_modified_since: Option<DateTime<Utc>>,
+}
+
+impl Conditional {
+    pub fn from_headers(if_none_match: Option<&str>, if_modified_since: Option<&str>) -> Self {
+        Conditional {
+            if_none_match: if_none_match.map(str::to_string),
+            if_modified_since: if_modified_since
+                .and_then(|raw| DateTime::parse_from_rfc2822(raw).ok())
+                .map(|date| date.with_timezone(&Utc)),
+        }
+    }
+
+    /// Whether the client already has the response for catalog `version`. An
+    /// `If-None-Match` header takes precedence over `If-Modified-Since`.
+    pub fn is_fresh(&self, version: DateTime<Utc>) -> bool {
+        if let Some(tags) = &self.if_none_match {
+            let current = etag(version);
+            return tags
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag == current);
+        }
+        self.if_modified_since
+            .is_some_and(|since| since.timestamp() >= version.timestamp())
+    }
+}
+
+/// A catalog response tagged with the catalog version it was built from.
+pub enum Cached<R> {
+    Fresh(R, DateTime<Utc>),
+    NotModified(DateTime<Utc>),
+}
+
+impl<R> Cached<R> {
+    pub fn map<T>(self, f: impl FnOnce(R) -> T) -> Cached<T> {
+        match self {
+            Cached::Fresh(inner, version) => Cached::Fresh(f(inner), version),
+            Cached::NotModified(version) => Cached::NotModified(version),
+        }
+    }
+
+    fn version(&self) -> DateTime<Utc> {
+        match self {
+            Cached::Fresh(_, version) | Cached::NotModified(version) => *version,
+        }
+    }
+}
+
+/// The validators and caching policy sent with every cached response.
+fn headers(version: DateTime<Utc>) -> [(&'static str, String); 3] {
+    [
+        ("ETag", etag(version)),
+        ("Last-Modified", http_date(version)),
+        ("Cache-Control", CACHE_CONTROL.to_string()),
+    ]
+}
+
+#[cfg(feature = "rocket")]
+mod rocket_impls {
+    use super::*;
+    use rocket::{
+        Request, Response,
+        http::{Header, Status},
+        request::{FromRequest, Outcome},
+        response::{self, Responder},
+    };
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for Conditional {
+        type Error = ();
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            Outcome::Success(Conditional::from_headers(
+                req.headers().get_one("If-None-Match"),
+                req.headers().get_one("If-Modified-Since"),
+            ))
+        }
+    }
+
+    impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Cached<R> {
+        fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+            let version = self.version();
+            let mut response = match self {
+                Cached::Fresh(inner, _) => inner.respond_to(req)?,
+                Cached::NotModified(_) => Response::build().status(Status::NotModified).finalize(),
+            };
+            for (name, value) in headers(version) {
+                response.set_header(Header::new(name, value));
+            }
+            Ok(response)
+        }
+    }
+}
+
+#[cfg(feature = "axum")]
+mod axum_impls {
+    use super::*;
+    use axum::{
+        extract::FromRequestParts,
+        http::{HeaderMap, HeaderValue, StatusCode, request::Parts},
+        response::{IntoResponse, Response},
+    };
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+        headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    impl<S: Send + Sync> FromRequestParts<S> for Conditional {
+        type Rejection = std::convert::Infallible;
+
+        async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+            Ok(Conditional::from_headers(
+                header(&parts.headers, "If-None-Match"),
+                header(&parts.headers, "If-Modified-Since"),
+            ))
+        }
+    }
+
+    impl<R: IntoResponse> IntoResponse for Cached<R> {
+        fn into_response(self) -> Response {
+            let version = self.version();
+            let mut response = match self {
+                Cached::Fresh(inner, _) => inner.into_response(),
+                Cached::NotModified(_) => StatusCode::NOT_MODIFIED.into_response(),
+            };
+            for (name, value) in headers(version) {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    response.headers_mut().insert(name, value);
+                }
+            }
+            response
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{ServerSecretsState, catalog, cleanup, format::Format, media, reconcile};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use teloxide::{prelude::*, types::Document, utils::markdown};
+use tokio::time::sleep;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The parts of Telegram Desktop's `result.json` channel export the importer reads.
+#[derive(Deserialize)]
+struct Export {
+    id: Option<i64>,
+    messages: Vec<ExportMessage>,
+}
+
+#[derive(Deserialize)]
+struct ExportMessage {
+    id: i32,
+    #[serde(rename = "type")]
+    kind: String,
+    date: String,
+    date_unixtime: Option<String>,
+    media_type: Option<String>,
+    #[serde(default)]
+    text_entities: Vec<TextEntity>,
+}
+
+#[derive(Deserialize)]
+struct TextEntity {
+    #[serde(rename = "type")]
+    kind: String,
+    text: String,
+    href: Option<String>,
+}
+
+impl ExportMessage {
+    fn is_audio(&self) -> bool {
+        self.kind == "message" && self.media_type.as_deref() == Some("audio_file")
+    }
+
+    /// Exports write `date` in the exporting machine's local time; newer ones also
+    /// have `date_unixtime`, which is preferred.
+    fn posted_at(&self) -> Option<DateTime<Utc>> {
+        self.date_unixtime
+            .as_deref()
+            .and_then(|raw| raw.parse().ok())
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(&self.date, "%Y-%m-%dT%H:%M:%S")
+                    .ok()
+                    .map(|date| date.and_utc())
+            })
+    }
+
+    /// The caption as MarkdownV2, the format the catalog stores captions in.
+    fn caption(&self) -> String {
+        self.text_entities
+            .iter()
+            .map(|entity| match (entity.kind.as_str(), &entity.href) {
+                ("text_link", Some(href)) => markdown::link(
+                    &markdown::escape_link_url(href),
+                    &markdown::escape(&entity.text),
+                ),
+                ("bold", _) => markdown::bold(&markdown::escape(&entity.text)),
+                ("italic", _) => markdown::italic(&markdown::escape(&entity.text)),
+                _ => markdown::escape(&entity.text),
+            })
+            .collect()
+    }
+
+    fn tags(&self) -> Vec<String> {
+        let mut tags = Vec::new();
+        for entity in self.text_entities.iter().filter(|e| e.kind == "hashtag") {
+            let tag = entity.text.trim_start_matches('#').to_lowercase();
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+}
+
+pub fn is_export(document: &Document) -> bool {
+    document
+        .file_name
+        .as_ref()
+        .is_some_and(|name| name.to_lowercase().ends_with(".json"))
+}
+
+/// Reads a Telegram Desktop export sent to the bot and backfills the catalog with
+/// the channel's audio posts that predate it. The export has no Bot API file ids,
+/// so each post is probed to get one; that takes a while, so the import runs in
+/// the background and reports to the owner when done. Returns how many posts will
+/// be looked at.
+pub async fn ingest(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    document: &Document,
+) -> Result<usize, Error> {
+    let file = media::fetch(bot, secrets, &document.file).await?;
+    let export: Export = serde_json::from_slice(&tokio::fs::read(&file.path).await?)
+        .map_err(|e| format!("Not a Telegram Desktop export: {}", e))?;
+
+    let owner = secrets.me_id;
+    let channel_id = secrets.channel_id();
+    if let Some(id) = export.id
+        && format!("-100{}", id) != channel_id.0.to_string()
+    {
+        return Err("This export is from a different channel".into());
+    }
+
+    let known = catalog::live_posts(&secrets.db, channel_id.0)
+        .await?
+        .into_iter()
+        .map(|(_, message_id)| message_id)
+        .collect::<HashSet<_>>();
+    let pending = export
+        .messages
+        .into_iter()
+        .filter(|message| message.is_audio() && !known.contains(&message.id))
+        .collect::<Vec<_>>();
+    let count = pending.len();
+
+    let bot = bot.clone();
+    let secrets = secrets.clone();
+    tokio::spawn(async move {
+        let reply = match backfill(&bot, &secrets, owner, channel_id, pending).await {
+            Ok((imported, missing)) => format!(
+                "Imported {} post(s) from the export, {} no longer in the channel.",
+                imported, missing
+            ),
+            Err(e) => format!("Import stopped: {}", e),
+        };
+        if let Err(e) = cleanup::reply(&bot, &secrets, owner, reply).await {
+            tracing::warn!("Failed to report import: {}", e);
+        }
+    });
+    Ok(count)
+}
+
+async fn backfill(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    owner: ChatId,
+    channel_id: ChatId,
+    messages: Vec<ExportMessage>,
+) -> Result<(usize, usize), Error> {
+    let (mut imported, mut missing) = (0, 0);
+    for (i, message) in messages.iter().enumerate() {
+        if i > 0 {
+            sleep(reconcile::PROBE_INTERVAL).await;
+        }
+        let Some(copy) = reconcile::probe(bot, owner, channel_id, message.id).await? else {
+            missing += 1;
+            continue;
+        };
+        let Some(audio) = copy.audio() else {
+            continue;
+        };
+
+        let tags = message.tags();
+        let title_latin = secrets
+            .transliteration
+            .and_then(|scheme| scheme.latin(audio.title.as_deref()?));
+        let performer_latin = secrets
+            .transliteration
+            .and_then(|scheme| scheme.latin(audio.performer.as_deref()?));
+        catalog::record_track(
+            &secrets.db,
+            &catalog::NewTrack {
+                channel_id: channel_id.0,
+                message_id: message.id,
+                file_id: &audio.file.id.0,
+                file_unique_id: &audio.file.unique_id.0,
+                title: audio.title.as_deref(),
+                performer: audio.performer.as_deref(),
+                album: None,
+                release_id: None,
+                file_name: audio.file_name.as_deref(),
+                duration_secs: audio.duration.seconds() as i32,
+                file_size: Some(audio.file.size.into()),
+                series: catalog::SERIES,
+                number: None,
+                tags: &tags,
+                caption: &message.caption(),
+                caption_format: Format::MarkdownV2,
+                posted_at: message.posted_at(),
+                bpm: None,
+                musical_key: None,
+                title_latin: title_latin.as_deref(),
+                performer_latin: performer_latin.as_deref(),
+                part_number: None,
+                part_base: None,
+                first_part_id: None,
+                paid: false,
+                via: None,
+            },
+        )
+        .await?;
+        imported += 1;
+    }
+    tracing::info!("Imported {} posts from an export", imported);
+    Ok((imported, missing))
+}
@@ -0,0 +1,32 @@
+use crate::{ServerSecretsState, catalog};
+use teloxide::{
+    prelude::*,
+    types::{FileId, InlineQuery, InlineQueryResult, InlineQueryResultCachedAudio},
+};
+
+const INLINE_RESULTS: i64 = 10;
+
+/// Answers inline queries (`@bot [tag]`) with random tracks from the catalog, so
+/// anyone can surface a track in any chat. Requires inline mode to be enabled for
+/// the bot in BotFather.
+pub async fn handle_inline_query(
+    bot: &Bot,
+    query: &InlineQuery,
+    secrets: &ServerSecretsState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let tag = Some(query.query.trim()).filter(|tag| !tag.is_empty());
+    let tracks = catalog::random_tracks(&secrets.db, tag, INLINE_RESULTS).await?;
+
+    let results = tracks.into_iter().map(|track| {
+        InlineQueryResult::CachedAudio(
+            InlineQueryResultCachedAudio::new(track.id.to_string(), FileId(track.file_id))
+                .caption(catalog::permalink(track.message_id)),
+        )
+    });
+
+    bot.answer_inline_query(query.id.clone(), results)
+        .cache_time(0)
+        .is_personal(true)
+        .await?;
+    Ok(())
+}
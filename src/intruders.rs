@@ -0,0 +1,341 @@
+use crate::{ServerSecretsState, cleanup, submissions, welcome};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Utc};
+use shuttle_runtime::SecretStore;
+use sqlx::{FromRow, PgPool};
+use std::collections::HashSet;
+use std::sync::RwLock;
+use teloxide::prelude::*;
+
+const REPORT_SIZE: i64 = 20;
+/// Enough of the first message to tell what they wanted, short enough that a full
+/// report fits in one Telegram message.
+const MAX_TEXT_CHARS: usize = 120;
+
+const DEFAULT_REPLY: &str = "Sorry, this is a private bot.";
+
+/// What to do when someone other than the owner messages the bot, configured with
+/// `STRANGER_POLICY`. Every attempt is logged and the owner hears about the first
+/// one regardless.
+pub enum Policy {
+    /// `ignore`: no answer at all. The default.
+    Ignore,
+    /// `reply`: answer with `STRANGER_REPLY`, or a polite default. It can use the
+    /// same placeholders as the welcome text.
+    Reply(String),
+    /// `forward`: forward every message to the owner.
+    Forward,
+    /// `block:N`: block the sender on their Nth attempt.
+    AutoBlock(i32),
+    /// `suggest`: take audio they send as a track request; see [`listener_requests`].
+    ///
+    /// [`listener_requests`]: crate::listener_requests
+    Suggest,
+    /// `submit`: forward their first message to the owner as a submission to accept
+    /// or ignore, prompting with `STRANGER_REPLY` until there is one; see
+    /// [`submissions`]. Only in private chats; groups are left alone.
+    Submit(String),
+}
+
+impl Policy {
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Self> {
+        let raw = secrets.get("STRANGER_POLICY");
+        match raw.as_deref().map(str::trim) {
+            None | Some("") | Some("ignore") => Ok(Policy::Ignore),
+            Some("reply") => Ok(Policy::Reply(
+                secrets
+                    .get("STRANGER_REPLY")
+                    .unwrap_or_else(|| DEFAULT_REPLY.to_string()),
+            )),
+            Some("forward") => Ok(Policy::Forward),
+            Some("suggest") => Ok(Policy::Suggest),
+            Some("submit") => Ok(Policy::Submit(
+                secrets
+                    .get("STRANGER_REPLY")
+                    .unwrap_or_else(|| submissions::DEFAULT_PROMPT.to_string()),
+            )),
+            Some(other) => {
+                let Some(attempts) = other.strip_prefix("block:") else {
+                    bail!(
+                        "STRANGER_POLICY must be ignore, reply, forward, suggest, submit or block:N, not {}",
+                        other
+                    );
+                };
+                let attempts = attempts
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .context("STRANGER_POLICY block:N needs a positive number of attempts")?;
+                Ok(Policy::AutoBlock(attempts))
+            }
+        }
+    }
+}
+
+/// Someone other than the owner who messaged the bot, with the first thing they said.
+#[derive(FromRow)]
+pub struct Intruder {
+    pub user_id: i64,
+    pub username: Option<String>,
+    pub full_name: String,
+    pub first_text: Option<String>,
+    pub attempts: i32,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl Intruder {
+    pub fn label(&self) -> String {
+        match &self.username {
+            Some(username) => format!("{} (@{}, {})", self.full_name, username, self.user_id),
+            None => format!("{} ({})", self.full_name, self.user_id),
+        }
+    }
+}
+
+/// Counts an attempt, returning the stored record. `attempts` is 1 the first time.
+/// Messages without a sender, like ones posted as a channel, are not counted.
+async fn record(db: &PgPool, message: &Message) -> sqlx::Result<Option<Intruder>> {
+    let Some(user) = &message.from else {
+        return Ok(None);
+    };
+    let text = message
+        .text()
+        .or(message.caption())
+        .map(|text| text.chars().take(MAX_TEXT_CHARS).collect::<String>());
+    sqlx::query_as(
+        "INSERT INTO intruders (user_id, username, full_name, first_text)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (user_id) DO UPDATE SET
+             username = EXCLUDED.username,
+             full_name = EXCLUDED.full_name,
+             attempts = intruders.attempts + 1,
+             last_seen = now()
+         RETURNING *",
+    )
+    .bind(user.id.0 as i64)
+    .bind(&user.username)
+    .bind(user.full_name())
+    .bind(text)
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn recent(db: &PgPool, limit: i64) -> sqlx::Result<Vec<Intruder>> {
+    sqlx::query_as("SELECT * FROM intruders ORDER BY last_seen DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(db)
+        .await
+}
+
+/// Logs a message from someone who is not the owner, tells the owner about the
+/// first attempt from each person, then applies the stranger policy.
+pub async fn handle(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(intruder) = record(&secrets.db, message).await? else {
+        tracing::debug!("Ignored a message without a sender");
+        return Ok(());
+    };
+    tracing::warn!(
+        user_id = intruder.user_id,
+        attempts = intruder.attempts,
+        "Message from someone other than the owner"
+    );
+    // Submissions are only taken in private chats, and reach the owner anyway with
+    // more to them than this.
+    let submits = message.chat.is_private() && matches!(secrets.stranger_policy, Policy::Submit(_));
+    if intruder.attempts == 1 && !submits {
+        let mut text = format!("🚷 Someone tried to use this bot: {}", intruder.label());
+        if let Some(first_text) = &intruder.first_text {
+            text.push_str(&format!("\n\n{}", first_text));
+        }
+        text.push_str(&format!(
+            "\n\n/block {} to ignore them, /intruders for everyone so far.",
+            intruder.user_id
+        ));
+        cleanup::reply(bot, secrets, secrets.me_id, text).await?;
+    }
+
+    match &secrets.stranger_policy {
+        Policy::Ignore | Policy::Suggest => {}
+        Policy::Reply(text) => {
+            let text = welcome::render(text, secrets, message).await;
+            bot.send_message(message.chat.id, text).await?;
+        }
+        Policy::Submit(_) if !submits => {}
+        Policy::Submit(prompt) => {
+            submissions::handle(
+                bot,
+                message,
+                secrets,
+                intruder.user_id,
+                &intruder.label(),
+                prompt,
+            )
+            .await?;
+        }
+        Policy::Forward => {
+            bot.forward_message(secrets.me_id, message.chat.id, message.id)
+                .await?;
+        }
+        Policy::AutoBlock(limit) => {
+            if intruder.attempts >= *limit {
+                secrets.blocked.block(&secrets.db, intruder.user_id).await?;
+                tracing::info!(user_id = intruder.user_id, "User blocked automatically");
+                cleanup::reply(
+                    bot,
+                    secrets,
+                    secrets.me_id,
+                    format!(
+                        "🚫 Blocked {} after {} attempt(s). /unblock {} to undo.",
+                        intruder.label(),
+                        intruder.attempts,
+                        intruder.user_id
+                    ),
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The `/intruders` report, most recent first.
+pub async fn report(secrets: &ServerSecretsState) -> sqlx::Result<String> {
+    let intruders = recent(&secrets.db, REPORT_SIZE).await?;
+    if intruders.is_empty() {
+        return Ok("Nobody else has tried to use the bot.".to_string());
+    }
+    Ok(intruders
+        .iter()
+        .map(|intruder| {
+            let blocked = if secrets.blocked.contains(intruder.user_id) {
+                " 🚫"
+            } else {
+                ""
+            };
+            let mut line = format!(
+                "{}{}\n{} attempt(s), first {} UTC, last {} UTC",
+                intruder.label(),
+                blocked,
+                intruder.attempts,
+                intruder.first_seen.format("%Y-%m-%d %H:%M"),
+                intruder.last_seen.format("%Y-%m-%d %H:%M")
+            );
+            if let Some(first_text) = &intruder.first_text {
+                line.push_str(&format!("\n“{}”", first_text));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n"))
+}
+
+/// Users whose updates are dropped before any processing, kept in memory and
+/// persisted in `blocked_users`.
+pub struct BlockList(RwLock<HashSet<i64>>);
+
+impl BlockList {
+    pub async fn load(db: &PgPool) -> sqlx::Result<Self> {
+        let ids: Vec<i64> = sqlx::query_scalar("SELECT user_id FROM blocked_users")
+            .fetch_all(db)
+            .await?;
+        Ok(Self(RwLock::new(ids.into_iter().collect())))
+    }
+
+    pub fn contains(&self, user_id: i64) -> bool {
+        self.0
+            .read()
+            .expect("block list lock poisoned")
+            .contains(&user_id)
+    }
+
+    fn ids(&self) -> Vec<i64> {
+        let mut ids = self
+            .0
+            .read()
+            .expect("block list lock poisoned")
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    /// Whether the update comes from a blocked user.
+    pub fn blocks(&self, update: &Update) -> bool {
+        update
+            .from()
+            .is_some_and(|user| self.contains(user.id.0 as i64))
+    }
+
+    async fn block(&self, db: &PgPool, user_id: i64) -> sqlx::Result<()> {
+        sqlx::query("INSERT INTO blocked_users (user_id) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(user_id)
+            .execute(db)
+            .await?;
+        self.0
+            .write()
+            .expect("block list lock poisoned")
+            .insert(user_id);
+        Ok(())
+    }
+
+    async fn unblock(&self, db: &PgPool, user_id: i64) -> sqlx::Result<bool> {
+        sqlx::query("DELETE FROM blocked_users WHERE user_id = $1")
+            .bind(user_id)
+            .execute(db)
+            .await?;
+        Ok(self
+            .0
+            .write()
+            .expect("block list lock poisoned")
+            .remove(&user_id))
+    }
+}
+
+/// `/block <user_id>` and `/unblock <user_id>`; `/block` alone lists who is blocked.
+pub async fn handle_block_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    block: bool,
+    secrets: &ServerSecretsState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let args = args.trim();
+    let reply = if args.is_empty() && block {
+        let ids = secrets.blocked.ids();
+        if ids.is_empty() {
+            "Nobody is blocked.".to_string()
+        } else {
+            format!(
+                "Blocked: {}",
+                ids.iter()
+                    .map(i64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }
+    } else if let Ok(user_id) = args.parse::<i64>() {
+        if user_id == secrets.me_id.0 {
+            "You can't block yourself.".to_string()
+        } else if block {
+            secrets.blocked.block(&secrets.db, user_id).await?;
+            tracing::info!(user_id, "User blocked");
+            format!("Blocked {}.", user_id)
+        } else if secrets.blocked.unblock(&secrets.db, user_id).await? {
+            tracing::info!(user_id, "User unblocked");
+            format!("Unblocked {}.", user_id)
+        } else {
+            format!("{} wasn't blocked.", user_id)
+        }
+    } else {
+        "Usage: /block <user_id>, /unblock <user_id>, or /block alone to list".to_string()
+    };
+    cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+    Ok(())
+}
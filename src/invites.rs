@@ -0,0 +1,210 @@
+use crate::ServerSecretsState;
+use chrono::{DateTime, Days, Utc};
+use sqlx::FromRow;
+use teloxide::{prelude::*, types::ChatMemberUpdated};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Telegram cuts invite link names at this many characters.
+const MAX_NAME_CHARS: usize = 32;
+
+const USAGE: &str = "Usage: /invite [limit=<members>] [days=<days>] [name]";
+
+/// An invite link the bot made for the channel, with how many joined through it.
+#[derive(FromRow)]
+struct InviteLink {
+    invite_link: String,
+    name: Option<String>,
+    member_limit: Option<i32>,
+    expire_at: Option<DateTime<Utc>>,
+    joins: i64,
+}
+
+impl InviteLink {
+    fn render(&self) -> String {
+        let mut line = self.invite_link.clone();
+        if let Some(name) = &self.name {
+            line.push_str(&format!(" ({})", name));
+        }
+        line.push_str(&format!(": {} joined", self.joins));
+        if let Some(limit) = self.member_limit {
+            line.push_str(&format!(" of {}", limit));
+        }
+        match self.expire_at {
+            Some(expire_at) if expire_at <= Utc::now() => line.push_str(", expired"),
+            Some(expire_at) => line.push_str(&format!(
+                ", expires {}",
+                expire_at.format("%Y-%m-%d %H:%M UTC")
+            )),
+            None => {}
+        }
+        line
+    }
+}
+
+/// What `/invite` was asked for.
+#[derive(Default)]
+struct Request {
+    member_limit: Option<u32>,
+    days: Option<u64>,
+    name: Option<String>,
+}
+
+impl Request {
+    fn parse(args: &str) -> Option<Self> {
+        let mut request = Request::default();
+        let mut name = Vec::new();
+        for word in args.split_whitespace() {
+            if let Some(limit) = word.strip_prefix("limit=") {
+                request.member_limit =
+                    Some(limit.parse().ok().filter(|n| (1..=99_999).contains(n))?);
+            } else if let Some(days) = word.strip_prefix("days=") {
+                request.days = Some(days.parse().ok().filter(|days| *days > 0)?);
+            } else {
+                name.push(word);
+            }
+        }
+        if !name.is_empty() {
+            request.name = Some(name.join(" ").chars().take(MAX_NAME_CHARS).collect());
+        }
+        Some(request)
+    }
+}
+
+/// `/invite [limit=<members>] [days=<days>] [name]`: makes a new invite link for the
+/// channel. Joins through it are counted from then on.
+pub async fn handle_create(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let Some(request) = Request::parse(args) else {
+        bot.send_message(message.chat.id, USAGE).await?;
+        return Ok(());
+    };
+
+    let mut create = bot.create_chat_invite_link(secrets.channel_id());
+    if let Some(limit) = request.member_limit {
+        create = create.member_limit(limit);
+    }
+    if let Some(days) = request.days {
+        let expire_at = Utc::now()
+            .checked_add_days(Days::new(days))
+            .ok_or("Expiry is too far out")?;
+        create = create.expire_date(expire_at);
+    }
+    if let Some(name) = &request.name {
+        create = create.name(name.clone());
+    }
+    let link = create.await?;
+
+    sqlx::query(
+        "INSERT INTO invite_links (invite_link, name, member_limit, expire_at)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&link.invite_link)
+    .bind(&link.name)
+    .bind(link.member_limit.map(|limit| limit as i32))
+    .bind(link.expire_date)
+    .execute(&secrets.db)
+    .await?;
+    tracing::info!("Created invite link {}", link.invite_link);
+
+    bot.send_message(message.chat.id, format!("🔗 {}", link.invite_link))
+        .await?;
+    Ok(())
+}
+
+/// `/invites`: the links made with `/invite` that are not revoked, newest first.
+pub async fn handle_list(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let links: Vec<InviteLink> = sqlx::query_as(
+        "SELECT l.invite_link, l.name, l.member_limit, l.expire_at, COUNT(j.id) AS joins
+         FROM invite_links l LEFT JOIN channel_joins j ON j.invite_link = l.invite_link
+         WHERE l.revoked_at IS NULL
+         GROUP BY l.invite_link
+         ORDER BY l.created_at DESC",
+    )
+    .fetch_all(&secrets.db)
+    .await?;
+    let text = if links.is_empty() {
+        "No invite links. Make one with /invite.".to_string()
+    } else {
+        links
+            .iter()
+            .map(InviteLink::render)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    bot.send_message(message.chat.id, text).await?;
+    Ok(())
+}
+
+/// `/revoke <link or name>`: stops an invite link from working. People who joined
+/// through it stay.
+pub async fn handle_revoke(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let args = args.trim();
+    if args.is_empty() {
+        bot.send_message(
+            message.chat.id,
+            "Usage: /revoke <link or name>, see /invites",
+        )
+        .await?;
+        return Ok(());
+    }
+    let link: Option<String> = sqlx::query_scalar(
+        "SELECT invite_link FROM invite_links
+         WHERE revoked_at IS NULL AND (invite_link = $1 OR lower(name) = lower($1))
+         ORDER BY created_at DESC LIMIT 1",
+    )
+    .bind(args)
+    .fetch_optional(&secrets.db)
+    .await?;
+    let Some(link) = link else {
+        bot.send_message(message.chat.id, "No such invite link, see /invites")
+            .await?;
+        return Ok(());
+    };
+
+    bot.revoke_chat_invite_link(secrets.channel_id(), link.clone())
+        .await?;
+    sqlx::query("UPDATE invite_links SET revoked_at = now() WHERE invite_link = $1")
+        .bind(&link)
+        .execute(&secrets.db)
+        .await?;
+    bot.send_message(message.chat.id, format!("Revoked {}", link))
+        .await?;
+    Ok(())
+}
+
+/// Records someone joining the channel, with the invite link they used if any. The
+/// bot has to be an admin of the channel to be told about joins.
+pub async fn handle_member(
+    update: &ChatMemberUpdated,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    if update.chat.id != secrets.channel_id()
+        || update.old_chat_member.is_present()
+        || !update.new_chat_member.is_present()
+    {
+        return Ok(());
+    }
+    let link = update.invite_link.as_ref().map(|link| &link.invite_link);
+    sqlx::query("INSERT INTO channel_joins (user_id, invite_link, joined_at) VALUES ($1, $2, $3)")
+        .bind(update.new_chat_member.user.id.0 as i64)
+        .bind(link)
+        .bind(update.date)
+        .execute(&secrets.db)
+        .await?;
+    tracing::debug!("Channel join via {:?}", link);
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Utc, Weekday};
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::time::sleep;
+
+type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Daily jobs post straight to the channel, so a dry run does not schedule them.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// The jobs spawned so far, with their hour and, for weekly ones, their day.
+static SCHEDULED: Mutex<Vec<(&'static str, u32, Option<Weekday>)>> = Mutex::new(Vec::new());
+
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+/// Every run due after `from` up to `until`, soonest first, going by the clock alone;
+/// a job that already ran today is expected again on its next day.
+pub fn runs_between(
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Vec<(&'static str, DateTime<Utc>)> {
+    let jobs = SCHEDULED.lock().expect("job list poisoned").clone();
+    let mut runs = Vec::new();
+    let mut date = from.date_naive();
+    while date <= until.date_naive() {
+        for &(name, hour, weekday) in &jobs {
+            if weekday.is_some_and(|weekday| date.weekday() != weekday) {
+                continue;
+            }
+            if let Some(at) = date.and_hms_opt(hour, 0, 0).map(|at| at.and_utc())
+                && at > from
+                && at <= until
+            {
+                runs.push((name, at));
+            }
+        }
+        date = date + Days::new(1);
+    }
+    runs.sort_by_key(|&(_, at)| at);
+    runs
+}
+
+/// The job that runs next and when.
+pub fn next_run() -> Option<(&'static str, DateTime<Utc>)> {
+    let now = Utc::now();
+    runs_between(now, now + chrono::Duration::days(8))
+        .into_iter()
+        .next()
+}
+
+/// Records that `job` ran for `date`. Returns `false` when it already has, so a job
+/// runs at most once per day even across restarts.
+async fn claim(db: &PgPool, job: &str, date: NaiveDate) -> sqlx::Result<bool> {
+    let result =
+        sqlx::query("INSERT INTO job_runs (job, run_date) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(job)
+            .bind(date)
+            .execute(db)
+            .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Runs `job` once a day at `hour`:00 UTC. If the bot starts after that hour and the
+/// job has not run yet today, it runs immediately.
+pub fn spawn_daily<F, Fut>(name: &'static str, hour: u32, db: PgPool, job: F)
+where
+    F: Fn(NaiveDate) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), JobError>> + Send,
+{
+    spawn(name, hour, None, db, job);
+}
+
+/// Like [`spawn_daily`], but only on `weekday`.
+pub fn spawn_weekly<F, Fut>(name: &'static str, weekday: Weekday, hour: u32, db: PgPool, job: F)
+where
+    F: Fn(NaiveDate) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), JobError>> + Send,
+{
+    spawn(name, hour, Some(weekday), db, job);
+}
+
+fn spawn<F, Fut>(name: &'static str, hour: u32, weekday: Option<Weekday>, db: PgPool, job: F)
+where
+    F: Fn(NaiveDate) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), JobError>> + Send,
+{
+    let run_time = NaiveTime::from_hms_opt(hour, 0, 0).expect("hour must be below 24");
+    if DRY_RUN.load(Ordering::Relaxed) {
+        tracing::info!("Dry run: not scheduling job {}", name);
+        return;
+    }
+    SCHEDULED
+        .lock()
+        .expect("job list poisoned")
+        .push((name, hour, weekday));
+
+    tokio::spawn(async move {
+        loop {
+            let now = Utc::now();
+            let today = now.date_naive();
+            let due_today = today.and_time(run_time).and_utc();
+
+            if now >= due_today && weekday.is_none_or(|weekday| today.weekday() == weekday) {
+                match claim(&db, name, today).await {
+                    Ok(true) => {
+                        tracing::info!("Running job {}", name);
+                        if let Err(e) = job(today).await {
+                            tracing::error!("Job {} failed: {}", name, e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => tracing::error!("Failed to claim job {}: {}", name, e),
+                }
+            }
+
+            let next = if Utc::now() >= due_today {
+                (today + Days::new(1)).and_time(run_time).and_utc()
+            } else {
+                due_today
+            };
+            sleep((next - Utc::now()).to_std().unwrap_or_default()).await;
+        }
+    });
+}
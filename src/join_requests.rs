@@ -0,0 +1,206 @@
+use crate::ServerSecretsState;
+use anyhow::bail;
+use teloxide::{
+    prelude::*,
+    types::{ChatJoinRequest, InlineKeyboardButton, InlineKeyboardMarkup, User},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "join:";
+
+/// A reason to let someone in without asking the owner.
+enum Rule {
+    All,
+    /// They have a public @username.
+    Username,
+    Premium,
+    /// Their app is set to this language, e.g. `uk`.
+    Language(String),
+    /// They came through the invite link with this name.
+    Link(String),
+}
+
+impl Rule {
+    /// As written in `JOIN_AUTO_APPROVE`.
+    fn name(&self) -> String {
+        match self {
+            Rule::All => "all".to_string(),
+            Rule::Username => "username".to_string(),
+            Rule::Premium => "premium".to_string(),
+            Rule::Language(code) => format!("lang:{}", code),
+            Rule::Link(name) => format!("link:{}", name),
+        }
+    }
+}
+
+/// Auto-approval rules from `JOIN_AUTO_APPROVE`, comma-separated: `all`, `username`,
+/// `premium`, `lang:<code>` and `link:<invite link name>`. A request matching any of
+/// them is approved straight away; the rest are put to the owner.
+pub struct Rules(Vec<Rule>);
+
+impl Rules {
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Self> {
+        let mut rules = Vec::new();
+        for rule in raw.unwrap_or_default().split(',').map(str::trim) {
+            rules.push(match rule.split_once(':') {
+                _ if rule.is_empty() => continue,
+                None if rule == "all" => Rule::All,
+                None if rule == "username" => Rule::Username,
+                None if rule == "premium" => Rule::Premium,
+                Some(("lang", code)) => Rule::Language(code.trim().to_lowercase()),
+                Some(("link", name)) => Rule::Link(name.trim().to_string()),
+                _ => bail!(
+                    "JOIN_AUTO_APPROVE rules must be all, username, premium, lang:<code> or link:<name>, not {}",
+                    rule
+                ),
+            });
+        }
+        Ok(Self(rules))
+    }
+
+    /// The first rule `request` meets, named for the decision log.
+    fn approves(&self, request: &ChatJoinRequest) -> Option<String> {
+        let user = &request.from;
+        let link_name = request
+            .invite_link
+            .as_ref()
+            .and_then(|link| link.name.as_deref());
+        self.0.iter().find_map(|rule| {
+            let matches = match rule {
+                Rule::All => true,
+                Rule::Username => user.username.is_some(),
+                Rule::Premium => user.is_premium,
+                Rule::Language(code) => user
+                    .language_code
+                    .as_deref()
+                    .is_some_and(|language| language.eq_ignore_ascii_case(code)),
+                Rule::Link(name) => link_name.is_some_and(|link| link.eq_ignore_ascii_case(name)),
+            };
+            matches.then(|| format!("rule {}", rule.name()))
+        })
+    }
+}
+
+fn describe(user: &User) -> String {
+    let mut text = user.full_name();
+    if let Some(username) = &user.username {
+        text.push_str(&format!(" (@{})", username));
+    }
+    text.push_str(&format!(", id {}", user.id));
+    text
+}
+
+/// Stores a decision, whoever made it.
+async fn log(
+    secrets: &ServerSecretsState,
+    user_id: UserId,
+    invite_link: Option<&str>,
+    approved: bool,
+    decided_by: &str,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO join_decisions (user_id, invite_link, approved, decided_by)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user_id.0 as i64)
+    .bind(invite_link)
+    .bind(approved)
+    .bind(decided_by)
+    .execute(&secrets.db)
+    .await?;
+    tracing::info!(
+        "Join request from {} {} by {}",
+        user_id,
+        if approved { "approved" } else { "declined" },
+        decided_by
+    );
+    Ok(())
+}
+
+/// A request to join the channel: approved if a rule allows it, otherwise sent to
+/// the owner with buttons to approve or decline. The bot needs to be an admin that
+/// can invite users.
+pub async fn handle(
+    bot: &Bot,
+    request: &ChatJoinRequest,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    if request.chat.id != secrets.channel_id() {
+        return Ok(());
+    }
+    let user = &request.from;
+    let link = request.invite_link.as_ref();
+
+    if let Some(rule) = secrets.join_rules.approves(request) {
+        bot.approve_chat_join_request(request.chat.id, user.id)
+            .await?;
+        log(
+            secrets,
+            user.id,
+            link.map(|link| link.invite_link.as_str()),
+            true,
+            &rule,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut text = format!("🚪 {} asks to join the channel", describe(user));
+    if let Some(link) = link {
+        let via = link.name.as_deref().unwrap_or(&link.invite_link);
+        text.push_str(&format!(" via {}", via));
+    }
+    if let Some(bio) = &request.bio {
+        text.push_str(&format!("\n\n{}", bio));
+    }
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            "✅ Approve",
+            format!("{}approve:{}", CALLBACK_PREFIX, user.id),
+        ),
+        InlineKeyboardButton::callback(
+            "Decline",
+            format!("{}decline:{}", CALLBACK_PREFIX, user.id),
+        ),
+    ]]);
+    bot.send_message(secrets.me_id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+/// The owner's answer to a join request.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let (action, user_id) = data.split_once(':').ok_or("Malformed callback data")?;
+    let user_id = UserId(user_id.parse()?);
+    let channel_id = secrets.channel_id();
+    let result = match action {
+        "approve" => bot.approve_chat_join_request(channel_id, user_id).await,
+        "decline" => bot.decline_chat_join_request(channel_id, user_id).await,
+        _ => return Err("Unknown join request action".into()),
+    };
+    // Requests can be withdrawn, or answered by another admin in the meantime.
+    if let Err(e) = result {
+        bot.answer_callback_query(query.id.clone())
+            .text(format!("Couldn't: {}", e))
+            .await?;
+        return Ok(());
+    }
+
+    let approved = action == "approve";
+    log(secrets, user_id, None, approved, "owner").await?;
+    bot.answer_callback_query(query.id.clone()).await?;
+    if let Some(message) = query.regular_message() {
+        let status = if approved { "Approved ✅" } else { "Declined" };
+        let text = format!("{}\n\n{}", message.text().unwrap_or_default(), status);
+        bot.edit_message_text(message.chat.id, message.id, text)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,317 @@
+use crate::{QueuedMessage, ServerSecretsState, blurbs, catalog, cleanup, intruders::Policy};
+use anyhow::Context;
+use sqlx::{FromRow, PgPool, types::Json};
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{
+        Audio, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, MessageId, ReplyParameters,
+    },
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "req:";
+
+/// The hashtag that makes an audio posted in the requests group a request, so the
+/// group can still be used to talk.
+const TAG: &str = "#request";
+/// How many pending requests `/requests` sends at a time.
+const INBOX_SIZE: i64 = 10;
+/// What someone suggesting tracks in a DM is told to do.
+pub const HINT: &str =
+    "🎵 Send the track you'd like to hear as an audio file and it goes to the requests inbox.";
+
+/// Parses `REQUESTS_GROUP`, the id of a public group whose members can request tracks
+/// by posting them with `#request`. The bot must see every message there, so it has to
+/// be an admin or have privacy mode off.
+pub fn group_from_secret(raw: Option<&str>) -> anyhow::Result<Option<ChatId>> {
+    raw.map(str::trim)
+        .filter(|raw| !raw.is_empty())
+        .map(|raw| {
+            raw.parse()
+                .map(ChatId)
+                .context("REQUESTS_GROUP must be a chat id")
+        })
+        .transpose()
+}
+
+/// A track a listener asked for, waiting in the inbox or decided on.
+#[derive(FromRow)]
+struct TrackRequest {
+    id: i64,
+    audio: Json<Audio>,
+    requesters: Vec<i64>,
+    requester_name: String,
+    chat_id: i64,
+    message_id: i32,
+    status: String,
+}
+
+impl TrackRequest {
+    fn label(&self) -> String {
+        let audio = &self.audio.0;
+        match (&audio.performer, &audio.title) {
+            (Some(performer), Some(title)) => format!("{} – {}", performer, title),
+            (_, Some(title)) => title.clone(),
+            _ => audio
+                .file_name
+                .clone()
+                .unwrap_or_else(|| format!("Request #{}", self.id)),
+        }
+    }
+}
+
+/// What became of a submission.
+enum Submitted {
+    /// It is new to the inbox.
+    New(Box<TrackRequest>),
+    /// Someone asked for it before; the count is everyone who has now.
+    Seconded(usize),
+    /// This person asked for it already, or it was turned down.
+    Known,
+    /// It has been posted.
+    Posted(i32),
+}
+
+/// The same file, or the same performer and title.
+async fn posted(db: &PgPool, audio: &Audio) -> sqlx::Result<Option<i32>> {
+    sqlx::query_scalar(
+        "SELECT message_id FROM tracks
+         WHERE deleted_at IS NULL AND (file_unique_id = $1
+             OR (lower(performer) = lower($2) AND lower(title) = lower($3)))
+         ORDER BY posted_at LIMIT 1",
+    )
+    .bind(&audio.file.unique_id.0)
+    .bind(&audio.performer)
+    .bind(&audio.title)
+    .fetch_optional(db)
+    .await
+}
+
+async fn submit(db: &PgPool, message: &Message, audio: &Audio) -> sqlx::Result<Submitted> {
+    if let Some(message_id) = posted(db, audio).await? {
+        return Ok(Submitted::Posted(message_id));
+    }
+    let requester = message
+        .from
+        .as_ref()
+        .map_or(message.chat.id.0, |user| user.id.0 as i64);
+    let earlier: Option<TrackRequest> = sqlx::query_as(
+        "SELECT * FROM track_requests
+         WHERE status <> 'queued' AND (file_unique_id = $1
+             OR (lower(performer) = lower($2) AND lower(title) = lower($3)))
+         ORDER BY requested_at LIMIT 1",
+    )
+    .bind(&audio.file.unique_id.0)
+    .bind(&audio.performer)
+    .bind(&audio.title)
+    .fetch_optional(db)
+    .await?;
+    if let Some(earlier) = earlier {
+        if earlier.status != "pending" || earlier.requesters.contains(&requester) {
+            return Ok(Submitted::Known);
+        }
+        let requesters: Vec<i64> = sqlx::query_scalar(
+            "UPDATE track_requests SET requesters = array_append(requesters, $2)
+             WHERE id = $1 RETURNING requesters",
+        )
+        .bind(earlier.id)
+        .bind(requester)
+        .fetch_one(db)
+        .await?;
+        return Ok(Submitted::Seconded(requesters.len()));
+    }
+
+    let name = match &message.from {
+        Some(user) => match &user.username {
+            Some(username) => format!("{} (@{})", user.full_name(), username),
+            None => user.full_name(),
+        },
+        None => message.chat.title().unwrap_or_default().to_string(),
+    };
+    let request: TrackRequest = sqlx::query_as(
+        "INSERT INTO track_requests
+             (file_unique_id, performer, title, audio, requesters, requester_name, chat_id,
+              message_id)
+         VALUES ($1, $2, $3, $4, ARRAY[$5], $6, $7, $8)
+         RETURNING *",
+    )
+    .bind(&audio.file.unique_id.0)
+    .bind(&audio.performer)
+    .bind(&audio.title)
+    .bind(Json(audio))
+    .bind(requester)
+    .bind(name)
+    .bind(message.chat.id.0)
+    .bind(message.id.0)
+    .fetch_one(db)
+    .await?;
+    Ok(Submitted::New(Box::new(request)))
+}
+
+fn keyboard(id: i64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback("✅ Queue it", format!("{}queue:{}", CALLBACK_PREFIX, id)),
+        InlineKeyboardButton::callback("Reject", format!("{}reject:{}", CALLBACK_PREFIX, id)),
+    ]])
+}
+
+/// Sends the owner the requested track with buttons to queue or reject it.
+async fn send_card(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    request: &TrackRequest,
+) -> Result<(), Error> {
+    let mut caption = format!("🙋 Requested by {}", request.requester_name);
+    if request.requesters.len() > 1 {
+        caption.push_str(&format!(" and {} more", request.requesters.len() - 1));
+    }
+    bot.send_audio(
+        secrets.me_id,
+        InputFile::file_id(request.audio.file.id.clone()),
+    )
+    .caption(caption)
+    .reply_markup(keyboard(request.id))
+    .await?;
+    Ok(())
+}
+
+/// Takes a message from someone other than the owner as a track request when it comes
+/// from the requests group, or from a DM under `STRANGER_POLICY=suggest`. Returns
+/// whether it was handled; everything else in the group is left alone.
+pub async fn handle(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<bool, Error> {
+    let from_group = secrets.requests_group == Some(message.chat.id);
+    let suggesting =
+        message.chat.is_private() && matches!(secrets.stranger_policy, Policy::Suggest);
+    if !from_group && !suggesting {
+        return Ok(false);
+    }
+    let tagged = message
+        .caption()
+        .is_some_and(|caption| caption.to_lowercase().contains(TAG));
+    let Some(audio) = message.audio().filter(|_| suggesting || tagged) else {
+        if suggesting {
+            bot.send_message(message.chat.id, HINT).await?;
+        }
+        return Ok(true);
+    };
+
+    let reply = match submit(&secrets.db, message, audio).await? {
+        Submitted::New(request) => {
+            tracing::info!(request_id = request.id, "Track requested");
+            send_card(bot, secrets, &request).await?;
+            "🙏 Thanks! It's in the requests inbox.".to_string()
+        }
+        Submitted::Seconded(count) => {
+            format!("➕ Already requested, that's {} asking for it now.", count)
+        }
+        Submitted::Known => "👍 You asked for this one already.".to_string(),
+        Submitted::Posted(message_id) => {
+            format!("✅ Already posted: {}", catalog::permalink(message_id))
+        }
+    };
+    bot.send_message(message.chat.id, reply)
+        .reply_parameters(ReplyParameters::new(message.id))
+        .await?;
+    Ok(true)
+}
+
+/// `/requests`: sends the oldest pending requests again, with their buttons.
+pub async fn handle_list(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let pending: Vec<TrackRequest> = sqlx::query_as(
+        "SELECT * FROM track_requests WHERE status = 'pending'
+         ORDER BY requested_at LIMIT $1",
+    )
+    .bind(INBOX_SIZE)
+    .fetch_all(&secrets.db)
+    .await?;
+    if pending.is_empty() {
+        cleanup::reply(bot, secrets, message.chat.id, "No requests waiting.").await?;
+        return Ok(());
+    }
+    for request in &pending {
+        send_card(bot, secrets, request).await?;
+    }
+    Ok(())
+}
+
+/// Marks a pending request decided, returning it; `None` when it was already.
+async fn decide(db: &PgPool, id: i64, status: &str) -> sqlx::Result<Option<TrackRequest>> {
+    sqlx::query_as(
+        "UPDATE track_requests SET status = $2, decided_at = now()
+         WHERE id = $1 AND status = 'pending' RETURNING *",
+    )
+    .bind(id)
+    .bind(status)
+    .fetch_optional(db)
+    .await
+}
+
+/// Handles the queue/reject buttons of a request. A queued request goes the way of an
+/// upload from the owner, and whoever asked first hears about it.
+pub async fn handle_callback(
+    bot: &Arc<Bot>,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let (action, id) = data.split_once(':').ok_or("Malformed callback data")?;
+    let id: i64 = id.parse()?;
+    let card = query.regular_message().ok_or("Request card is gone")?;
+
+    let status = match action {
+        "queue" => match decide(&secrets.db, id, "queued").await? {
+            Some(request) => {
+                let queued = QueuedMessage {
+                    audio: request.audio.0.clone(),
+                    tags: Vec::new(),
+                    message_id: card.id.0,
+                    via: None,
+                    retag: None,
+                    description: None,
+                    blurb: None,
+                    queued_at: chrono::Utc::now(),
+                    theme: None,
+                    stars: None,
+                    part: None,
+                };
+                blurbs::queue(bot, secrets, card.chat.id, queued).await?;
+                if let Err(e) = bot
+                    .send_message(
+                        ChatId(request.chat_id),
+                        format!("🎶 {} is on its way to the channel!", request.label()),
+                    )
+                    .reply_parameters(
+                        ReplyParameters::new(MessageId(request.message_id))
+                            .allow_sending_without_reply(),
+                    )
+                    .await
+                {
+                    tracing::warn!("Failed to tell the requester: {}", e);
+                }
+                "Queued ✅"
+            }
+            None => "Already decided",
+        },
+        "reject" => match decide(&secrets.db, id, "rejected").await? {
+            Some(_) => "Rejected",
+            None => "Already decided",
+        },
+        _ => return Err("Unknown request action".into()),
+    };
+
+    bot.answer_callback_query(query.id.clone())
+        .text(status)
+        .await?;
+    bot.edit_message_reply_markup(card.chat.id, card.id).await?;
+    Ok(())
+}
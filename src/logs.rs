@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{Layer, layer::Context};
+
+#[derive(Clone, Serialize)]
+pub struct LogRecord {
+    pub timestamp: DateTime<Utc>,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// Keeps the most recent log records in memory so they can be inspected without
+/// going through the hosting provider's log viewer.
+#[derive(Clone)]
+pub struct LogBuffer {
+    records: Arc<Mutex<VecDeque<LogRecord>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Returns up to `limit` of the newest records, oldest first.
+    pub fn tail(&self, limit: usize) -> Vec<LogRecord> {
+        let records = self.records.lock().expect("log buffer poisoned");
+        let skip = records.len().saturating_sub(limit);
+        records.iter().skip(skip).cloned().collect()
+    }
+
+    fn push(&self, record: LogRecord) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut records = self.records.lock().expect("log buffer poisoned");
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogBuffer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        self.push(LogRecord {
+            timestamp: Utc::now(),
+            level: event.metadata().level().to_string(),
+            target: event.metadata().target().to_string(),
+            message: redact(&visitor.message).into_owned(),
+        });
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        // Metric events carry no message and are not worth keeping.
+        if field.name().starts_with("monotonic_counter.") || field.name().starts_with("histogram.")
+        {
+            return;
+        }
+
+        if !self.message.is_empty() {
+            self.message.push(' ');
+        }
+
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.message, "{}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Renders the tail of the buffer as plain text lines for a Telegram reply,
+/// keeping only as many of the newest lines as fit into `max_len` bytes.
+pub fn format_tail(records: &[LogRecord], max_len: usize) -> String {
+    let mut lines = Vec::new();
+    let mut len = 0;
+
+    for r in records.iter().rev() {
+        let mut line = format!(
+            "{} {} {}: {}",
+            r.timestamp.format("%H:%M:%S"),
+            r.level,
+            r.target,
+            r.message
+        );
+        if len + line.len() + 1 > max_len {
+            if !lines.is_empty() {
+                break;
+            }
+            let mut cut = max_len.min(line.len());
+            while !line.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            line.truncate(cut);
+        }
+        len += line.len() + 1;
+        lines.push(line);
+    }
+
+    lines.reverse();
+    lines.join("\n")
+}
+
+/// What a redacted token reads as.
+const REDACTED: &str = "[redacted]";
+
+/// Masks the value of every `token=` parameter in `line`. The web server logs the
+/// URLs it is asked for, and calendar apps fetch `/schedule.ics` with their token in
+/// the query string.
+pub fn redact(line: &str) -> Cow<'_, str> {
+    if !line.contains("token=") {
+        return Cow::Borrowed(line);
+    }
+    let mut redacted = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(at) = rest.find("token=") {
+        let (before, after) = rest.split_at(at + "token=".len());
+        redacted.push_str(before);
+        let end = after
+            .find(|c: char| c == '&' || c == '#' || c == '"' || c == '\'' || c.is_whitespace())
+            .unwrap_or(after.len());
+        if end > 0 {
+            redacted.push_str(REDACTED);
+        }
+        rest = &after[end..];
+    }
+    redacted.push_str(rest);
+    Cow::Owned(redacted)
+}
+
+/// Passes formatted log lines on to `W` with [`redact`] applied.
+pub struct Redacting<W>(pub W);
+
+impl<W: io::Write> io::Write for Redacting<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(line) => self.0.write_all(redact(line).as_bytes())?,
+            Err(_) => self.0.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_in_urls_are_masked() {
+        assert_eq!(
+            redact("GET /schedule.ics?token=s3cret text/calendar:"),
+            "GET /schedule.ics?token=[redacted] text/calendar:"
+        );
+        assert_eq!(
+            redact("GET /schedule.ics?a=1&token=s3cret&b=2"),
+            "GET /schedule.ics?a=1&token=[redacted]&b=2"
+        );
+        assert_eq!(redact("?token=&b=2"), "?token=&b=2");
+    }
+
+    #[test]
+    fn lines_without_tokens_are_left_alone() {
+        assert!(matches!(redact("Posted #42"), Cow::Borrowed("Posted #42")));
+    }
+
+    #[test]
+    fn the_writer_reports_the_whole_line_written() {
+        let mut out = Redacting(Vec::new());
+        let line = b"GET /schedule.ics?token=s3cret\n";
+        assert_eq!(io::Write::write(&mut out, line).unwrap(), line.len());
+        assert_eq!(out.0, b"GET /schedule.ics?token=[redacted]\n");
+    }
+}
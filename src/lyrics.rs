@@ -0,0 +1,167 @@
+use crate::{ServerSecretsState, dry_run};
+use anyhow::{Context, bail};
+use reqwest::StatusCode;
+use serde_json::Value;
+use shuttle_runtime::SecretStore;
+use teloxide::{
+    prelude::*,
+    types::{MessageOrigin, ReplyParameters},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Telegram messages top out at 4096 characters; longer lyrics are cut.
+const MAX_CHARS: usize = 4000;
+
+/// Where lyrics come from, configured with `LYRICS`: `lrclib` posts the text itself,
+/// `genius` (which needs `GENIUS_TOKEN`) a link to the song's page, as its API does
+/// not hand out lyrics.
+pub enum Provider {
+    Lrclib,
+    Genius { token: String },
+}
+
+pub enum Lyrics {
+    Text(String),
+    Link(String),
+}
+
+impl Provider {
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Option<Self>> {
+        let Some(kind) = secrets.get("LYRICS") else {
+            return Ok(None);
+        };
+        match kind.trim() {
+            "lrclib" => Ok(Some(Provider::Lrclib)),
+            "genius" => Ok(Some(Provider::Genius {
+                token: secrets
+                    .get("GENIUS_TOKEN")
+                    .context("GENIUS_TOKEN must be set for Genius lyrics")?,
+            })),
+            other => bail!("LYRICS must be lrclib or genius, not {}", other),
+        }
+    }
+
+    /// Looks the song up; `None` when the provider does not know it or it is an
+    /// instrumental.
+    pub async fn lookup(
+        &self,
+        title: &str,
+        artist: &str,
+        duration_secs: u32,
+    ) -> Result<Option<Lyrics>, Error> {
+        let client = reqwest::Client::new();
+        match self {
+            Provider::Lrclib => {
+                let response = client
+                    .get("https://lrclib.net/api/get")
+                    .header("User-Agent", "ankh")
+                    .query(&[
+                        ("track_name", title),
+                        ("artist_name", artist),
+                        ("duration", &duration_secs.to_string()),
+                    ])
+                    .send()
+                    .await?;
+                if response.status() == StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                let song: Value = response.error_for_status()?.json().await?;
+                if song["instrumental"] == true {
+                    return Ok(None);
+                }
+                Ok(song["plainLyrics"]
+                    .as_str()
+                    .map(str::trim)
+                    .filter(|text| !text.is_empty())
+                    .map(|text| Lyrics::Text(text.to_string())))
+            }
+            Provider::Genius { token } => {
+                let found: Value = client
+                    .get("https://api.genius.com/search")
+                    .bearer_auth(token)
+                    .query(&[("q", format!("{} {}", artist, title))])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(found["response"]["hits"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|hit| &hit["result"])
+                    .find(|song| {
+                        song["primary_artist"]["name"]
+                            .as_str()
+                            .is_some_and(|name| name.eq_ignore_ascii_case(artist))
+                    })
+                    .and_then(|song| song["url"].as_str())
+                    .map(|url| Lyrics::Link(url.to_string())))
+            }
+        }
+    }
+}
+
+fn render(lyrics: Lyrics) -> String {
+    match lyrics {
+        Lyrics::Text(text) => {
+            let mut text = format!("📝 Lyrics\n\n{}", text);
+            if text.chars().count() > MAX_CHARS {
+                text = text.chars().take(MAX_CHARS).collect();
+                text.push('…');
+            }
+            text
+        }
+        Lyrics::Link(url) => format!("📝 Lyrics: {}", url),
+    }
+}
+
+/// Whether `message` is the copy of one of our channel posts that Telegram drops into
+/// the linked discussion group, which is what comments are replies to.
+fn is_channel_copy(message: &Message, secrets: &ServerSecretsState) -> bool {
+    if !message.is_automatic_forward() {
+        return false;
+    }
+    let Some(MessageOrigin::Channel { chat, .. }) = message.forward_origin() else {
+        return false;
+    };
+    chat.id == secrets.channel_id() || Some(chat.id) == secrets.staging_channel_id
+}
+
+/// Answers the discussion group copy of a newly posted track with its lyrics, making
+/// them the post's first comment. The bot has to be an admin of the group to see the
+/// copies. Returns `false` when `message` is not such a copy.
+pub async fn handle_channel_copy(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<bool, Error> {
+    if !is_channel_copy(message, secrets) {
+        return Ok(false);
+    }
+    let (Some(provider), Some(audio)) = (&secrets.lyrics, message.audio()) else {
+        return Ok(true);
+    };
+    let (Some(title), Some(artist)) = (&audio.title, &audio.performer) else {
+        return Ok(true);
+    };
+    if dry_run::is_active(secrets) {
+        return Ok(true);
+    }
+
+    match provider
+        .lookup(title, artist, audio.duration.seconds())
+        .await?
+    {
+        Some(lyrics) => {
+            bot.send_message(message.chat.id, render(lyrics))
+                .reply_parameters(ReplyParameters::new(message.id))
+                .disable_notification(true)
+                .await?;
+            tracing::info!("Posted lyrics for {} – {}", artist, title);
+        }
+        None => tracing::debug!("No lyrics for {} – {}", artist, title),
+    }
+    Ok(true)
+}
@@ -1,27 +1,264 @@
+mod acoustid;
+mod analysis;
+mod announce;
+mod api;
+mod archive;
+mod artist_index;
+mod audio_cache;
+mod audit;
+mod auth;
+mod blurbs;
+mod cadence;
+mod calendar;
+mod caption;
+mod catalog;
+mod cleanup;
+mod commands;
+mod cors;
+mod cover;
+mod dashboard;
+mod dead_letter;
+mod deep_link;
+mod dialogue;
+mod digest;
+mod dry_run;
+mod duplicates;
+mod expiry;
+mod favorites;
+mod fix_metadata;
+mod flags;
+mod format;
+mod graphql;
+mod growth;
+mod hashtags;
+mod hooks;
+mod http_cache;
+mod import;
+mod inline;
+mod intruders;
+mod invites;
+mod jobs;
+mod join_requests;
+mod listener_requests;
+mod logs;
+mod lyrics;
+mod maintenance;
+mod media;
+mod menus;
+mod migrate;
+mod mirror;
+mod mirror_set;
+mod notify;
+mod now_playing;
+mod on_this_day;
+mod paid;
+mod parts;
+mod pinned;
+mod prepare;
+mod preview;
+mod quality;
+mod queue_export;
+mod quiz;
+mod rate_limit;
+mod reactions;
+mod receipts;
+mod reconcile;
+mod releases;
+mod replay;
+mod replaygain;
+mod retry;
+mod scheduled;
+mod settings;
+mod status;
+mod stream;
+mod submissions;
+mod subscribers;
+mod telegram;
+mod telemetry;
+mod test_mode;
+mod themes;
+mod topics;
+mod translate;
+mod translit;
+mod vacation;
+mod watch_folder;
+mod waveform;
+mod web;
+mod webhook;
+mod welcome;
+mod wrapped;
+
 use anyhow::Context;
-use rocket::{State, get, post, routes, serde::json::Json};
-use shuttle_rocket::ShuttleRocket;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicI32, Ordering};
+use commands::Command;
+use format::{Format, Template};
+use logs::LogBuffer;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
 use teloxide::{
     Bot,
     prelude::*,
-    types::{ChatId, FileId, InputFile, ParseMode, Update},
+    types::{Audio, ChatId, MessageEntityKind, Update},
+    utils::{command::BotCommands, render::Renderer},
 };
 use tokio::sync::Mutex;
 use tokio::time::{Duration, Instant, sleep};
 use url::Url;
 
-#[derive(Clone)]
+/// How long the queue has to be quiet before a batch is published, so a burst of
+/// forwarded files is posted together and in order.
+const QUIET_PERIOD: Duration = Duration::from_secs(3);
+/// Delay between consecutive posts within a batch.
+const SEND_INTERVAL: Duration = Duration::from_millis(1000);
+
+#[derive(Clone, Serialize, Deserialize)]
 struct QueuedMessage {
-    audio_file_id: FileId,
+    audio: Audio,
+    tags: Vec<String>,
     message_id: i32,
+    /// Set for tracks republished from a mirrored channel.
+    via: Option<mirror::Attribution>,
+    /// Tags the owner accepted from AcoustID, replacing the file's own.
+    #[serde(default)]
+    retag: Option<acoustid::Retag>,
+    /// The text of the caption the audio was sent with, without its hashtags.
+    #[serde(default)]
+    description: Option<String>,
+    /// A one-sentence description the owner accepted; see [`blurbs`].
+    #[serde(default)]
+    blurb: Option<String>,
+    /// When it joined the queue, or was last confirmed; see [`expiry`].
+    #[serde(default = "chrono::Utc::now")]
+    queued_at: chrono::DateTime<chrono::Utc>,
+    /// The weekday theme it was held for, whose caption it gets; see [`themes`].
+    #[serde(default)]
+    theme: Option<String>,
+    /// The Stars it costs to unlock, for uploads tagged `#paid`; see [`paid`].
+    #[serde(default)]
+    stars: Option<u32>,
+    /// The part of a longer mix it is, for uploads tagged `#part2`; see [`parts`].
+    #[serde(default)]
+    part: Option<i32>,
+}
+
+/// Where a newly added message ended up in the queue (1-based).
+struct QueuePosition {
+    position: usize,
+    queue_len: usize,
+}
+
+impl QueuePosition {
+    /// Estimated publish time assuming no further messages arrive: the batch starts
+    /// once the quiet period has passed and earlier messages go out first.
+    fn estimated_publish_time(&self) -> chrono::DateTime<chrono::Utc> {
+        let delay = QUIET_PERIOD + SEND_INTERVAL * (self.position as u32 - 1);
+        chrono::Utc::now() + chrono::Duration::from_std(delay).unwrap_or_default()
+    }
+}
+
+/// A message in the owner's chat that is edited while a batch is being published.
+struct BatchStatus {
+    message: Option<telegram::Sent>,
+    total: usize,
+    failed: usize,
+}
+
+impl BatchStatus {
+    async fn start(
+        telegram: &dyn telegram::TelegramClient,
+        owner: Option<ChatId>,
+        total: usize,
+    ) -> Self {
+        let message = match owner {
+            Some(owner) => telegram
+                .send_message(
+                    owner,
+                    &telegram::OutgoingText::plain(format!("Posting 1/{}…", total)),
+                )
+                .await
+                .inspect_err(|e| tracing::warn!("Failed to send batch status: {}", e))
+                .ok(),
+            None => None,
+        };
+        Self {
+            message,
+            total,
+            failed: 0,
+        }
+    }
+
+    async fn edit(&self, telegram: &dyn telegram::TelegramClient, text: String) {
+        if let Some(message) = &self.message
+            && let Err(e) = telegram
+                .edit_text(
+                    message.chat_id,
+                    message.id,
+                    &telegram::OutgoingText::plain(text),
+                )
+                .await
+        {
+            tracing::warn!("Failed to update batch status: {}", e);
+        }
+    }
+
+    async fn progress(&self, telegram: &dyn telegram::TelegramClient, done: usize) {
+        let mut text = format!(
+            "Posting {}/{}… next in {}s",
+            done + 1,
+            self.total,
+            SEND_INTERVAL.as_secs_f32()
+        );
+        if self.failed > 0 {
+            text.push_str(&format!(" ({} failed)", self.failed));
+        }
+        self.edit(telegram, text).await;
+    }
+
+    /// Reports progress and waits before the next send, unless that was the last one.
+    async fn pace(&self, telegram: &dyn telegram::TelegramClient, done: usize) {
+        if done < self.total {
+            self.progress(telegram, done).await;
+            sleep(SEND_INTERVAL).await;
+        }
+    }
+
+    async fn finish(&self, telegram: &dyn telegram::TelegramClient) {
+        let posted = self.total - self.failed;
+        let mut text = format!("Posted {}/{}", posted, self.total);
+        if self.failed > 0 {
+            text.push_str(&format!(", {} failed — see /logs", self.failed));
+        } else {
+            text.push_str(" ✅");
+        }
+        self.edit(telegram, text).await;
+    }
+}
+
+/// Marks a batch as being sent for as long as it is held, so the flag comes down
+/// however the batch ends, a panic included.
+struct Sending(Arc<AtomicBool>);
+
+impl Sending {
+    fn start(flag: &Arc<AtomicBool>) -> Self {
+        flag.store(true, Ordering::Release);
+        Self(flag.clone())
+    }
+}
+
+impl Drop for Sending {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
 }
 
 struct MessageQueue {
     messages: Arc<Mutex<Vec<QueuedMessage>>>,
     last_received: Arc<Mutex<Instant>>,
     processing: Arc<Mutex<bool>>,
+    /// Set while a batch is being posted.
+    sending: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
 }
 
 impl MessageQueue {
@@ -30,32 +267,62 @@ impl MessageQueue {
             messages: Arc::new(Mutex::new(Vec::new())),
             last_received: Arc::new(Mutex::new(Instant::now())),
             processing: Arc::new(Mutex::new(false)),
+            sending: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    async fn snapshot(&self) -> Vec<QueuedMessage> {
+        self.messages.lock().await.clone()
+    }
+
+    /// While paused, messages keep queueing up but nothing is published.
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    fn is_sending(&self) -> bool {
+        self.sending.load(Ordering::Acquire)
+    }
+
+    /// How long until the queued messages start going out, if any are waiting and
+    /// nothing holds them back.
+    async fn next_send(&self) -> Option<Duration> {
+        if self.messages.lock().await.is_empty() {
+            return None;
+        }
+        Some(QUIET_PERIOD.saturating_sub(self.last_received.lock().await.elapsed()))
+    }
+
     async fn add_message(
         &self,
-        audio_file_id: FileId,
-        message_id: i32,
+        new_message: QueuedMessage,
         bot: Arc<Bot>,
         secrets: Arc<ServerSecretsState>,
-    ) {
-        {
+    ) -> QueuePosition {
+        let queue_position = {
             let mut messages = self.messages.lock().await;
-            let new_message = QueuedMessage {
-                audio_file_id,
-                message_id,
-            };
+            let message_id = new_message.message_id;
 
-            match messages.binary_search_by_key(&message_id, |m| m.message_id) {
+            let pos = match messages.binary_search_by_key(&message_id, |m| m.message_id) {
                 Ok(pos) => {
                     messages[pos] = new_message;
+                    pos
                 }
                 Err(pos) => {
                     messages.insert(pos, new_message);
+                    pos
                 }
+            };
+            QueuePosition {
+                position: pos + 1,
+                queue_len: messages.len(),
             }
-        }
+        };
 
         *self.last_received.lock().await = Instant::now();
 
@@ -67,19 +334,26 @@ impl MessageQueue {
                 self.start_processing_task(bot, secrets).await;
             }
         }
+
+        queue_position
     }
 
     async fn start_processing_task(&self, bot: Arc<Bot>, secrets: Arc<ServerSecretsState>) {
         let messages = self.messages.clone();
         let last_received = self.last_received.clone();
         let processing_flag = self.processing.clone();
+        let sending = self.sending.clone();
+        let paused = self.paused.clone();
 
         tokio::spawn(async move {
             loop {
-                sleep(Duration::from_secs(3)).await;
+                sleep(QUIET_PERIOD).await;
 
                 let time_since_last = last_received.lock().await.elapsed();
-                if time_since_last < Duration::from_secs(3) {
+                if time_since_last < QUIET_PERIOD
+                    || paused.load(Ordering::Relaxed)
+                    || vacation::is_active(&secrets)
+                {
                     continue;
                 }
 
@@ -89,20 +363,88 @@ impl MessageQueue {
                 }
 
                 let to_process = msgs.drain(..).collect::<Vec<_>>();
+                let _sending = Sending::start(&sending);
                 drop(msgs);
 
-                println!("Processing {} queued messages", to_process.len());
+                let to_process = expiry::hold_stale(&secrets, to_process).await;
+                let to_process = themes::hold(&secrets, to_process).await;
+                if to_process.is_empty() {
+                    break;
+                }
 
+                tracing::info!(
+                    histogram.batch_size = to_process.len() as u64,
+                    "Processing {} queued messages",
+                    to_process.len()
+                );
+
+                let prepared = prepare::Batch::start(&bot, &secrets, &to_process);
                 let total_count = to_process.len();
-                for (i, msg) in to_process.into_iter().enumerate() {
-                    if let Err(e) = Self::send_audio_message(&bot, &secrets, &msg).await {
-                        eprintln!("Error sending queued message: {}", e);
-                    }
+                let telegram = &*secrets.telegram;
+                let mut status =
+                    BatchStatus::start(telegram, Some(secrets.me_id), total_count).await;
+                let mut done = 0;
+                for entry in releases::group(&bot, &secrets, to_process).await {
+                    match entry {
+                        releases::Entry::Single(msg) => {
+                            Self::publish(
+                                &bot,
+                                telegram,
+                                &secrets,
+                                &mut status,
+                                &prepared,
+                                &msg,
+                                None,
+                            )
+                            .await;
+                            done += 1;
+                            status.pace(telegram, done).await;
+                        }
+                        releases::Entry::Release(release) => {
+                            let lead = releases::post_lead(&bot, &secrets, &release)
+                                .await
+                                .inspect_err(|e| tracing::error!("Failed to post release: {}", e))
+                                .ok();
+                            if let Some((lead, _)) = &lead {
+                                secrets
+                                    .last_message_id
+                                    .store(lead.message.id.0, Ordering::Relaxed);
+                            }
 
-                    if i < total_count - 1 {
-                        sleep(Duration::from_millis(1000)).await;
+                            let mut posted = Vec::new();
+                            for msg in &release.tracks {
+                                let record = lead.as_ref().and_then(|(_, record)| record.as_ref());
+                                posted.push(
+                                    Self::publish(
+                                        &bot,
+                                        telegram,
+                                        &secrets,
+                                        &mut status,
+                                        &prepared,
+                                        msg,
+                                        record,
+                                    )
+                                    .await,
+                                );
+                                done += 1;
+                                status.pace(telegram, done).await;
+                            }
+                            if let Some((lead, _)) = &lead {
+                                releases::link_tracklist(&secrets, &release, lead, &posted).await;
+                            }
+                        }
                     }
                 }
+                status.finish(telegram).await;
+                if status.failed > 0 {
+                    dead_letter::alert(&secrets).await;
+                }
+                if let Some(mode) = secrets.pinned_post
+                    && let Err(e) = pinned::refresh(&secrets, mode).await
+                {
+                    tracing::warn!("Failed to update pinned post: {}", e);
+                }
+                dry_run::report(&bot, &secrets).await;
 
                 break;
             }
@@ -111,42 +453,285 @@ impl MessageQueue {
         });
     }
 
+    /// Sends one queued message, dead-lettering it on failure, and returns the id of
+    /// the channel post.
+    async fn publish(
+        bot: &Bot,
+        telegram: &dyn telegram::TelegramClient,
+        secrets: &ServerSecretsState,
+        status: &mut BatchStatus,
+        prepared: &prepare::Batch,
+        msg: &QueuedMessage,
+        release: Option<&catalog::Release>,
+    ) -> Option<i32> {
+        let prepared = prepared.take(msg.message_id).await;
+        match Self::send_audio_message(bot, telegram, secrets, msg, release, prepared).await {
+            Ok(message_id) => {
+                tracing::info!(monotonic_counter.tracks_published = 1u64);
+                Some(message_id)
+            }
+            Err(e) => {
+                status.failed += 1;
+                tracing::error!(
+                    monotonic_counter.send_failures = 1u64,
+                    "Error sending queued message: {}",
+                    e
+                );
+                if let Err(e) = dead_letter::push(&secrets.db, msg, &e.to_string()).await {
+                    tracing::error!("Failed to store failed item: {}", e);
+                }
+                None
+            }
+        }
+    }
+
+    #[tracing::instrument(skip_all, fields(message_id = queued_msg.message_id))]
     async fn send_audio_message(
         bot: &Bot,
+        telegram: &dyn telegram::TelegramClient,
         secrets: &ServerSecretsState,
         queued_msg: &QueuedMessage,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let predicted_id = secrets.last_message_id.load(Ordering::Relaxed) + 1;
+        release: Option<&catalog::Release>,
+        prepared: prepare::Prepared,
+    ) -> Result<i32, Box<dyn std::error::Error + Send + Sync>> {
+        let channel_id = secrets.publish_channel_id();
+        let processed = match prepared.processed {
+            Some(processed) => processed,
+            None => {
+                media::process(
+                    bot,
+                    secrets,
+                    &queued_msg.audio.file,
+                    queued_msg.audio.file_name.as_deref(),
+                )
+                .await?
+            }
+        };
 
-        let sent_message = bot
-            .send_audio(
-                ChatId(secrets.channel_id.parse()?),
-                InputFile::file_id(queued_msg.audio_file_id.clone()),
+        let clip = match secrets.preview_channel_id {
+            Some(_) if !test_mode::is_active(secrets) && !dry_run::is_active(secrets) => {
+                match preview::make_clip(bot, secrets, &queued_msg.audio).await {
+                    Ok(clip) => Some(clip),
+                    Err(e) => {
+                        tracing::warn!("Failed to make preview clip: {}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        // Previews for the main channel go out right before the track they tease,
+        // pointing at the message id the track is expected to get.
+        let mut teaser = None;
+        if let Some(clip) = &clip
+            && secrets.preview_channel_id == Some(channel_id)
+        {
+            let expected_id = secrets.last_message_id.load(Ordering::Relaxed) + 2;
+            match preview::post(
+                bot,
+                secrets,
+                channel_id,
+                clip,
+                &queued_msg.audio,
+                expected_id,
             )
-            .caption(format!(
-                "[Music: Reborn](https://t.me/the_ankh_music/{})",
-                predicted_id
-            ))
-            .parse_mode(ParseMode::MarkdownV2)
-            .await?;
+            .await
+            {
+                Ok(message) => {
+                    secrets
+                        .last_message_id
+                        .store(message.id.0, Ordering::Relaxed);
+                    teaser = Some(message);
+                }
+                Err(e) => tracing::warn!("Failed to post preview: {}", e),
+            }
+        }
+
+        // Thumbnails are ignored when a file is resent by id, and so are new tags, so a
+        // track that gets either has to be uploaded again.
+        let thumbnail = cover::thumbnail(bot, secrets, &queued_msg.audio).await;
+        let processed = match processed {
+            None if thumbnail.is_some() || queued_msg.retag.is_some() => {
+                match media::fetch(bot, secrets, &queued_msg.audio.file).await {
+                    Ok(file) => Some(file),
+                    Err(e) => {
+                        tracing::warn!("Posting without thumbnail or new tags: {}", e);
+                        None
+                    }
+                }
+            }
+            processed => processed,
+        };
+        let mut facts = caption::Facts::of_audio(&queued_msg.audio);
+        if let Some(retag) = &queued_msg.retag {
+            facts.title = Some(retag.title.clone());
+            facts.performer = Some(retag.performer.clone());
+        }
+        if let Some(scheme) = secrets.transliteration {
+            facts.transliterate(scheme);
+        }
+        facts.theme = queued_msg.theme.clone();
+        let part = parts::of(queued_msg.part, facts.title.as_deref());
+        let first_part = match &part {
+            Some(part) if part.number > 1 => {
+                match parts::first(&secrets.db, facts.performer.as_deref(), part).await {
+                    Ok(first) => first,
+                    Err(e) => {
+                        tracing::warn!("Failed to look up part 1: {}", e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        facts.part = part.as_ref().map(|part| part.number);
+        facts.first_part = first_part.as_ref().map(|track| track.message_id);
+        if secrets.artist_index.is_some()
+            && let Some(performer) = &facts.performer
+        {
+            facts.artist_index =
+                artist_index::message_id(&secrets.db, secrets.publish_channel_id(), performer)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("Failed to look up the artist index post: {}", e);
+                        None
+                    });
+        }
+        let template = queued_msg
+            .theme
+            .as_deref()
+            .and_then(|theme| secrets.themes.template(theme))
+            .unwrap_or(&secrets.caption_template);
+        if let Some(file) = &processed {
+            facts.measure(&file.path).await;
+        }
+        if secrets.audio_analysis {
+            // Analysis needs the audio but not an upload: unless it is being uploaded
+            // anyway, a temporary copy is fetched and the post still goes out by id.
+            let analyzed = match (prepared.analyzed, &processed) {
+                (Some(analyzed), _) => Some(analyzed),
+                (None, Some(file)) => Some(analysis::analyze(file).await),
+                (None, None) => match media::fetch(bot, secrets, &queued_msg.audio.file).await {
+                    Ok(file) => Some(analysis::analyze(&file).await),
+                    Err(e) => {
+                        tracing::warn!("Posting without BPM and key: {}", e);
+                        None
+                    }
+                },
+            };
+            if let Some(analyzed) = analyzed {
+                facts.bpm = analyzed.bpm;
+                facts.key = analyzed.key;
+            }
+        }
+        facts.description = queued_msg.description.clone();
+        facts.blurb = queued_msg.blurb.clone();
+        if let (Some(description), Some(translator)) = (&facts.description, &secrets.translator) {
+            match translator.translate(description).await {
+                Ok(translation) if translation != *description => {
+                    facts.translation = Some(translation)
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Posting without translation: {}", e),
+            }
+        }
+
+        let file_tags = match prepared.file_tags {
+            Some(file_tags) => file_tags,
+            None => hashtags::probe(bot, secrets, &queued_msg.audio, processed.as_ref()).await,
+        };
+        facts.hashtags = hashtags::for_upload(
+            secrets,
+            facts.performer.as_deref(),
+            &queued_msg.tags,
+            &file_tags,
+        );
+        let tags: Vec<String> = queued_msg
+            .tags
+            .iter()
+            .chain(&facts.hashtags)
+            .cloned()
+            .collect();
+
+        // Later parts stay in the series of the first, wherever that was moved to.
+        let series = first_part
+            .as_ref()
+            .map_or(catalog::SERIES, |track| track.series.as_str());
+        let number = catalog::next_number(&secrets.db, series).await?;
+        let predicted_id = secrets.last_message_id.load(Ordering::Relaxed) + 1;
+        let outgoing = telegram::OutgoingAudio {
+            source: match &processed {
+                Some(file) => telegram::AudioSource::Upload {
+                    path: file.path.clone(),
+                    file_name: queued_msg.audio.file_name.clone(),
+                },
+                None => telegram::AudioSource::FileId(queued_msg.audio.file.id.clone()),
+            },
+            caption: caption(
+                template,
+                series,
+                predicted_id,
+                Some(number),
+                queued_msg.via.as_ref(),
+                &facts,
+            ),
+            caption_format: template.format,
+            thumbnail,
+            title: facts.title.clone(),
+            performer: facts.performer.clone(),
+            thread: secrets.topics.pick(series, &tags),
+        };
+        let sent_message = match queued_msg.stars {
+            Some(stars) => {
+                let video =
+                    paid::video(bot, secrets, &queued_msg.audio, processed.as_ref()).await?;
+                let outgoing = telegram::OutgoingPaidVideo {
+                    path: video.path.clone(),
+                    caption: outgoing.caption.clone(),
+                    caption_format: outgoing.caption_format,
+                    duration: queued_msg.audio.duration.seconds(),
+                    stars,
+                };
+                secrets
+                    .retry_policy
+                    .run(|| telegram.send_paid_video(channel_id, &outgoing))
+                    .await?
+            }
+            None => {
+                secrets
+                    .retry_policy
+                    .run(|| telegram.send_audio(channel_id, &outgoing))
+                    .await?
+            }
+        };
 
         if sent_message.id.0 == predicted_id {
-            println!("Message ID prediction correct: {}", predicted_id);
+            tracing::debug!("Message ID prediction correct: {}", predicted_id);
             secrets
                 .last_message_id
                 .store(predicted_id, Ordering::Relaxed);
         } else {
-            println!(
+            tracing::warn!(
                 "Message ID mismatch! Predicted: {}, Actual: {}",
-                predicted_id, sent_message.id.0
+                predicted_id,
+                sent_message.id.0
             );
 
-            bot.edit_message_caption(sent_message.chat.id, sent_message.id)
-                .caption(format!(
-                    "[Music: Reborn](https://t.me/the_ankh_music/{})",
-                    sent_message.id.0
-                ))
-                .parse_mode(ParseMode::MarkdownV2)
+            telegram
+                .edit_caption(
+                    sent_message.chat_id,
+                    sent_message.id,
+                    &caption(
+                        template,
+                        series,
+                        sent_message.id.0,
+                        Some(number),
+                        queued_msg.via.as_ref(),
+                        &facts,
+                    ),
+                    template.format,
+                )
                 .await?;
 
             secrets
@@ -154,124 +739,1024 @@ impl MessageQueue {
                 .store(sent_message.id.0, Ordering::Relaxed);
         }
 
-        Ok(())
+        match waveform::post_photo(bot, secrets, &queued_msg.audio, &sent_message).await {
+            Ok(Some(photo)) => secrets.last_message_id.store(photo.id.0, Ordering::Relaxed),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to post waveform: {}", e),
+        }
+
+        if let Some(teaser) = &teaser
+            && teaser.id.0 + 1 != sent_message.id.0
+        {
+            telegram
+                .edit_reply_markup(
+                    teaser.chat.id,
+                    teaser.id,
+                    Some(preview::keyboard(sent_message.id.0)),
+                )
+                .await?;
+        }
+        if let Some(clip) = &clip
+            && let Some(preview_channel_id) = secrets.preview_channel_id
+            && preview_channel_id != channel_id
+            && secrets.flags.is_enabled(flags::Flag::CrossPosting)
+            && let Err(e) = preview::post(
+                bot,
+                secrets,
+                preview_channel_id,
+                clip,
+                &queued_msg.audio,
+                sent_message.id.0,
+            )
+            .await
+        {
+            tracing::warn!("Failed to post preview: {}", e);
+        }
+
+        if test_mode::is_active(secrets) || dry_run::is_active(secrets) {
+            return Ok(sent_message.id.0);
+        }
+        let audio = sent_message.audio.as_ref().unwrap_or(&queued_msg.audio);
+        let new_track = catalog::NewTrack {
+            channel_id: sent_message.chat_id.0,
+            message_id: sent_message.id.0,
+            file_id: &audio.file.id.0,
+            file_unique_id: &audio.file.unique_id.0,
+            title: facts.title.as_deref(),
+            performer: facts.performer.as_deref(),
+            album: release.map(|release| release.title.as_str()),
+            release_id: release.map(|release| release.id),
+            file_name: audio.file_name.as_deref(),
+            duration_secs: audio.duration.seconds() as i32,
+            file_size: Some(audio.file.size.into()),
+            series,
+            number: Some(number),
+            tags: &tags,
+            caption: &caption(
+                template,
+                series,
+                sent_message.id.0,
+                Some(number),
+                queued_msg.via.as_ref(),
+                &facts,
+            ),
+            caption_format: template.format,
+            posted_at: None,
+            bpm: facts.bpm,
+            musical_key: facts.key.as_deref(),
+            title_latin: facts.title_latin.as_deref(),
+            performer_latin: facts.performer_latin.as_deref(),
+            part_number: part.as_ref().map(|part| part.number),
+            part_base: part.as_ref().map(|part| part.base.as_str()),
+            first_part_id: first_part.as_ref().map(|track| track.id),
+            paid: queued_msg.stars.is_some(),
+            via: queued_msg.via.as_ref(),
+        };
+        match catalog::record_track(&secrets.db, &new_track).await {
+            Ok(track) => {
+                secrets.notifier.track_published(&track);
+                receipts::send_receipt(secrets, &track).await;
+                if let (Some(min_tracks), Some(performer)) =
+                    (secrets.artist_index, &track.performer)
+                    && let Err(e) =
+                        artist_index::refresh(secrets, min_tracks, sent_message.chat_id, performer)
+                            .await
+                {
+                    tracing::warn!("Failed to update the index post of {}: {}", performer, e);
+                }
+            }
+            Err(e) => tracing::error!("Failed to record track in catalog: {}", e),
+        }
+        mirror_set::fan_out(secrets, sent_message.chat_id, sent_message.id).await;
+
+        Ok(sent_message.id.0)
+    }
+}
+
+/// `template`, usually `CAPTION_TEMPLATE`, filled in, its `{series}` being
+/// "Music: Reborn № 42" linking to the post, with a "via" line for mirrored tracks. In
+/// the template's own format.
+fn caption(
+    template: &Template,
+    series: &str,
+    message_id: i32,
+    number: Option<i32>,
+    via: Option<&mirror::Attribution>,
+    facts: &caption::Facts,
+) -> String {
+    let series = match number {
+        Some(number) => format!("{} № {}", series, number),
+        None => series.to_string(),
+    };
+    let format = template.format;
+    let series = format.link(&catalog::permalink(message_id), &format.escape(&series));
+    let caption = caption::render(format, &template.text, &series, facts);
+    match via {
+        Some(via) => format!("{}\n{}", caption, via.render(format)),
+        None => caption,
     }
 }
 
+/// "Queued 2/5, publishing around 12:00:05 UTC"
+fn queued_text(position: &QueuePosition) -> String {
+    format!(
+        "Queued {}/{}, publishing around {} UTC",
+        position.position,
+        position.queue_len,
+        position.estimated_publish_time().format("%H:%M:%S")
+    )
+}
+
+/// Hashtags from the caption the audio was sent to the bot with, normalized to
+/// lowercase without the leading `#`.
+fn caption_tags(message: &Message) -> Vec<String> {
+    let mut tags = Vec::new();
+    for entity in message.parse_caption_entities().unwrap_or_default() {
+        let tag = entity.text().trim_start_matches('#').to_lowercase();
+        if *entity.kind() == MessageEntityKind::Hashtag && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// The caption the audio was sent to the bot with, hashtags taken out.
+fn caption_description(message: &Message) -> Option<String> {
+    let mut text = message.caption()?.to_string();
+    for entity in message.parse_caption_entities().unwrap_or_default() {
+        if *entity.kind() == MessageEntityKind::Hashtag {
+            text = text.replace(entity.text(), "");
+        }
+    }
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Mirrors a caption edited by hand in the channel into the catalog, which also
+/// refreshes its search index.
+async fn sync_edited_post(
+    post: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if post.chat.id != secrets.channel_id() || post.audio().is_none() {
+        return Ok(());
+    }
+    let format = secrets.caption_template.format;
+    let caption = match post.caption() {
+        Some(text) => {
+            let renderer = Renderer::new(text, post.caption_entities().unwrap_or_default());
+            match format {
+                Format::MarkdownV2 => renderer.as_markdown(),
+                Format::Html => renderer.as_html(),
+            }
+        }
+        None => String::new(),
+    };
+    catalog::update_caption(&secrets.db, post.chat.id.0, post.id.0, &caption, format).await?;
+    tracing::info!("Synced edited caption of post {}", post.id.0);
+    Ok(())
+}
+
 struct ServerSecretsState {
     bot_token: String,
-    me_id: String,
-    channel_id: String,
+    /// `PUBLIC_URL` followed by the token, where Telegram delivers updates.
+    webhook_url: Url,
+    /// `ME_ID`, the owner's private chat.
+    me_id: ChatId,
+    /// The channel tracks are published to; see [`ServerSecretsState::channel_id`].
+    channel: AtomicI64,
+    api_tokens: auth::ApiTokens,
+    bot_username: String,
+    dashboard_admin_ids: Vec<i64>,
     last_message_id: AtomicI32,
     message_queue: MessageQueue,
+    logs: LogBuffer,
+    db: PgPool,
+    retry_policy: retry::RetryPolicy,
+    bot_api_mode: media::BotApiMode,
+    /// `AUDIO_CACHE_MB`: downloaded files kept for the other stages that need them.
+    audio_cache: Option<audio_cache::AudioCache>,
+    process_command: Option<String>,
+    /// `REPLAYGAIN`: tag processed files with their track gain and peak.
+    replaygain: bool,
+    /// See [`caption::Facts`] for the placeholders. Its format, `CAPTION_PARSE_MODE`,
+    /// is also what templates without one of their own are written in.
+    caption_template: Template,
+    /// `TRANSLITERATE`: how Cyrillic titles are spelled in Latin letters for captions
+    /// and search, if at all.
+    transliteration: Option<translit::Scheme>,
+    preview_channel_id: Option<ChatId>,
+    waveform: Option<waveform::Mode>,
+    cover_art: bool,
+    /// `AUDIO_ANALYSIS`: estimate BPM and key of each track before posting it.
+    audio_analysis: bool,
+    album_releases: bool,
+    hook_sources: hooks::HookSources,
+    media_server: Option<now_playing::MediaServer>,
+    lyrics: Option<lyrics::Provider>,
+    /// `ACOUSTID_KEY`, the application key that turns on recognizing untagged tracks.
+    acoustid_key: Option<String>,
+    acoustid_pending: acoustid::Pending,
+    translator: Option<translate::Translator>,
+    blurb_writer: Option<blurbs::Writer>,
+    blurbs_pending: blurbs::Pending,
+    /// `MIN_BITRATE_KBPS` and `MIN_SAMPLE_RATE_HZ`, checked before an upload is queued.
+    quality_gate: Option<quality::Gate>,
+    quality_pending: quality::Pending,
+    now_playing_shared: now_playing::LastShared,
+    vacation: RwLock<Option<vacation::Vacation>>,
+    reply_ttl: Option<Duration>,
+    blocked: intruders::BlockList,
+    stranger_policy: intruders::Policy,
+    /// `REQUESTS_GROUP`: where listeners can post `#request` tracks.
+    requests_group: Option<ChatId>,
+    /// The channel's linked discussion group, where public commands work.
+    discussion_group: Option<ChatId>,
+    /// `FAVORITES_USERS`: who may star tracks besides the owner.
+    favorites_users: Vec<UserId>,
+    welcome_text: String,
+    notifier: notify::Notifier,
+    reconciling: reconcile::Running,
+    maintenance: maintenance::Maintenance,
+    mirror_sources: mirror::Sources,
+    mirror_set: mirror_set::MirrorSet,
+    join_rules: join_requests::Rules,
+    topics: topics::Topics,
+    /// `QUIZ_REVEAL_HOURS`: how long quizzes stay open before the answer is posted.
+    quiz_reveal_after: chrono::Duration,
+    /// `QUEUE_TTL_HOURS`: queued items older than this wait for /retry instead of
+    /// going out.
+    queue_ttl: Option<chrono::Duration>,
+    /// `WEEKDAY_THEMES`.
+    themes: themes::Themes,
+    /// `PAID_MEDIA_STARS`: the price of uploads tagged just `#paid`.
+    paid_stars: Option<u32>,
+    /// `AUTO_HASHTAGS`: hashtags made up from each track's metadata.
+    auto_hashtags: Option<hashtags::Rules>,
+    /// `PREPARE_CONCURRENCY`: how many tracks of a batch are prepared at once.
+    prepare_concurrency: usize,
+    /// `ARTIST_INDEX`: how many tracks a performer needs to get an index post.
+    artist_index: Option<usize>,
+    pinned_post: Option<pinned::Mode>,
+    staging_channel_id: Option<ChatId>,
+    test_mode: AtomicBool,
+    flags: flags::Flags,
+    /// Outgoing calls of the publishing path, normally the [`Bot`] itself.
+    telegram: Arc<dyn telegram::TelegramClient>,
+    /// Where those calls end up instead while `DRY_RUN` is on.
+    dry_run: Option<Arc<telegram::MemoryClient>>,
+    /// How many days incoming updates are kept for replaying.
+    record_updates: Option<u32>,
+    started_at: Instant,
+}
+
+impl ServerSecretsState {
+    /// The channel tracks are published to: `CHANNEL_ID`, unless a migration has
+    /// switched to another one since.
+    fn channel_id(&self) -> ChatId {
+        ChatId(self.channel.load(Ordering::Relaxed))
+    }
+
+    /// Where new posts go: the staging channel while `/testmode` is on, otherwise
+    /// [`Self::channel_id`]. Anything reading or editing existing posts uses the latter.
+    fn publish_channel_id(&self) -> ChatId {
+        match self.staging_channel_id {
+            Some(staging) if test_mode::is_active(self) => staging,
+            _ => self.channel_id(),
+        }
+    }
 }
 
+/// Handles one update. Channel posts it edits or deletes itself go through
+/// `telegram`, so a replay can hand it a client that only records them.
+#[tracing::instrument(skip_all, fields(update_id = update.id.0))]
 async fn handle_update(
     bot: Arc<Bot>,
+    telegram: &dyn telegram::TelegramClient,
     update: Update,
     secrets: Arc<ServerSecretsState>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    tracing::info!(monotonic_counter.updates_handled = 1u64);
+
+    if secrets.blocked.blocks(&update) {
+        tracing::debug!("Dropped update from a blocked user");
+        return Ok(());
+    }
+
+    if let teloxide::types::UpdateKind::InlineQuery(query) = &update.kind {
+        return inline::handle_inline_query(&bot, query, &secrets).await;
+    }
+
+    if let teloxide::types::UpdateKind::CallbackQuery(query) = &update.kind {
+        return handle_callback_query(&bot, telegram, query, &secrets).await;
+    }
+
+    if let teloxide::types::UpdateKind::MessageReactionCount(update) = &update.kind {
+        return reactions::handle_count(update, &secrets).await;
+    }
+
+    if let teloxide::types::UpdateKind::Poll(poll) = &update.kind {
+        return quiz::handle_poll(poll, &secrets).await;
+    }
+
+    if let teloxide::types::UpdateKind::ChatJoinRequest(request) = &update.kind {
+        return join_requests::handle(&bot, request, &secrets).await;
+    }
+
+    if let teloxide::types::UpdateKind::ChatMember(member) = &update.kind {
+        return invites::handle_member(member, &secrets).await;
+    }
+
+    if let teloxide::types::UpdateKind::ChannelPost(post) = &update.kind {
+        return mirror::handle_post(&bot, post, &secrets).await;
+    }
+
+    if let teloxide::types::UpdateKind::EditedChannelPost(post) = &update.kind {
+        return sync_edited_post(post, &secrets).await;
+    }
+
     if let teloxide::types::UpdateKind::Message(message) = update.kind {
-        if message.chat.id != ChatId(secrets.me_id.parse()?) {
-            bot.send_message(
-                secrets.me_id.clone(),
-                format!(
-                    "Someone tried to use this bot {}",
-                    message
-                        .chat
-                        .username()
-                        .unwrap_or(&message.chat.id.to_string())
-                ),
-            )
-            .await?;
-            bot.send_message(message.chat.id, "Welcome! What can do you for?")
-                .await?;
+        if deep_link::handle_start(&bot, &message, &secrets).await? {
             return Ok(());
         }
 
-        if let Some(text) = message.text() {
-            if text == "/start" {
-                bot.send_message(message.chat.id, "Welcome! Up and running.")
-                    .await?;
+        if lyrics::handle_channel_copy(&bot, &message, &secrets).await? {
+            return Ok(());
+        }
+
+        if menus::handle_shared(&bot, &message, &secrets).await? {
+            return Ok(());
+        }
+
+        if message.chat.id != secrets.me_id {
+            if listener_requests::handle(&bot, &message, &secrets).await? {
                 return Ok(());
             }
+            return intruders::handle(&bot, &message, &secrets).await;
+        }
+
+        if receipts::handle_reply(&bot, telegram, &message, &secrets).await? {
+            return Ok(());
+        }
+
+        if dialogue::handle_message(&bot, &message, &secrets).await? {
+            return Ok(());
+        }
+
+        // Commands that act on a file or photo, like /importqueue or /schedule, come as
+        // its caption.
+        if let Some(text) = message.text().or_else(|| {
+            message
+                .caption()
+                .filter(|_| message.document().is_some() || message.photo().is_some())
+        }) && let Ok(command) = Command::parse(text, &secrets.bot_username)
+        {
+            commands::handle_command(&bot, &message, command, &secrets).await?;
+            return Ok(());
+        }
+
+        if maintenance::is_on(&secrets)
+            && (message.audio().is_some() || message.document().is_some())
+        {
+            cleanup::reply(
+                &bot,
+                &secrets,
+                message.chat.id,
+                "🛠 Maintenance is on, not taking uploads until /maintenance off.",
+            )
+            .await?;
+            return Ok(());
         }
 
         if let Some(audio) = message.audio() {
-            secrets
-                .message_queue
-                .add_message(
-                    audio.file.id.clone(),
-                    message.id.0,
-                    bot.clone(),
-                    secrets.clone(),
+            let mut tags = caption_tags(&message);
+            let stars = paid::take(&mut tags, secrets.paid_stars);
+            let part = parts::take(&mut tags);
+            let queued = QueuedMessage {
+                audio: audio.clone(),
+                tags,
+                message_id: message.id.0,
+                via: None,
+                retag: None,
+                description: caption_description(&message),
+                blurb: None,
+                queued_at: chrono::Utc::now(),
+                theme: None,
+                stars,
+                part,
+            };
+            if secrets.acoustid_key.is_some() && acoustid::is_untagged(audio) {
+                acoustid::spawn(bot.clone(), secrets.clone(), message.chat.id, queued);
+                cleanup::reply(
+                    &bot,
+                    &secrets,
+                    message.chat.id,
+                    "🔎 No usable tags, trying to recognize it first…",
                 )
-                .await;
+                .await?;
+                return Ok(());
+            }
 
-            println!("Added audio to queue (ID: {})", message.id.0);
+            blurbs::queue(&bot, &secrets, message.chat.id, queued).await?;
+        } else if let Some(document) = message.document()
+            && archive::is_zip(document)
+        {
+            let reply =
+                match archive::ingest(&bot, &secrets, document, &caption_tags(&message)).await {
+                    Ok(count) => format!("Queued {} track(s) from the archive", count),
+                    Err(e) => {
+                        tracing::error!("Failed to ingest archive: {}", e);
+                        format!("Couldn't unpack the archive: {}", e)
+                    }
+                };
+            cleanup::reply(&bot, &secrets, message.chat.id, reply).await?;
+        } else if let Some(document) = message.document()
+            && import::is_export(document)
+        {
+            let reply = match import::ingest(&bot, &secrets, document).await {
+                Ok(count) => format!(
+                    "Importing {} audio post(s) from the export, this takes a while…",
+                    count
+                ),
+                Err(e) => {
+                    tracing::error!("Failed to read export: {}", e);
+                    format!("Couldn't import the export: {}", e)
+                }
+            };
+            cleanup::reply(&bot, &secrets, message.chat.id, reply).await?;
         }
 
-        bot.delete_message(message.chat.id, message.id).await?;
+        telegram.delete_message(message.chat.id, message.id).await?;
     }
     Ok(())
 }
 
-#[get("/")]
-fn index_handler() -> &'static str {
-    "hi!"
+async fn handle_callback_query(
+    bot: &Arc<Bot>,
+    telegram: &dyn telegram::TelegramClient,
+    query: &CallbackQuery,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Stars are not only the owner's.
+    if let Some(data) = query
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(favorites::CALLBACK_PREFIX))
+    {
+        return favorites::handle_callback(bot, query, data, secrets).await;
+    }
+    if ChatId::from(query.from.id) != secrets.me_id {
+        bot.answer_callback_query(query.id.clone())
+            .text("Not allowed")
+            .await?;
+        return Ok(());
+    }
+
+    let data = query.data.as_deref().unwrap_or_default();
+    if let Some(data) = data.strip_prefix(on_this_day::CALLBACK_PREFIX) {
+        return on_this_day::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(dialogue::CALLBACK_PREFIX) {
+        return dialogue::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(receipts::CALLBACK_PREFIX) {
+        return receipts::handle_callback(bot, telegram, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(subscribers::CALLBACK_PREFIX) {
+        return subscribers::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(acoustid::CALLBACK_PREFIX) {
+        return acoustid::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(blurbs::CALLBACK_PREFIX) {
+        return blurbs::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(quality::CALLBACK_PREFIX) {
+        return quality::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(announce::CALLBACK_PREFIX) {
+        return announce::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(join_requests::CALLBACK_PREFIX) {
+        return join_requests::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(listener_requests::CALLBACK_PREFIX) {
+        return listener_requests::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(submissions::CALLBACK_PREFIX) {
+        return submissions::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(wrapped::CALLBACK_PREFIX) {
+        return wrapped::handle_callback(bot, query, data, secrets).await;
+    }
+    if let Some(data) = data.strip_prefix(growth::CALLBACK_PREFIX) {
+        return growth::handle_callback(bot, query, data, secrets).await;
+    }
+
+    bot.answer_callback_query(query.id.clone()).await?;
+    Ok(())
 }
 
-#[post("/<_bot_token>", data = "<update>")]
-async fn webhook_handler(
-    bot: &State<Arc<Bot>>,
-    update: Json<Update>,
-    _bot_token: &str,
-    secrets: &State<Arc<ServerSecretsState>>,
-) -> &'static str {
-    let bot = bot.inner().clone();
-    let secrets = secrets.inner().clone();
+/// Handles an update from the webhook in the background, so Telegram gets its
+/// answer straight away.
+fn dispatch_update(bot: Arc<Bot>, update: Update, secrets: Arc<ServerSecretsState>) {
     tokio::spawn(async move {
-        if let Err(e) = handle_update(bot, update.into_inner(), secrets).await {
-            eprintln!("Error handling update: {}", e);
+        if secrets.record_updates.is_some()
+            && let Err(e) = replay::record(&secrets.db, &update).await
+        {
+            tracing::warn!("Failed to record update: {}", e);
+        }
+        let telegram = secrets.telegram.clone();
+        let result = handle_update(bot, &*telegram, update.clone(), secrets.clone()).await;
+        if let Err(e) = &result {
+            tracing::error!("Error handling update: {}", e);
+        }
+        let error = result.err().map(|e| e.to_string());
+        if let Err(e) = audit::record(&secrets.db, &update, error.as_deref()).await {
+            tracing::warn!("Failed to audit update: {}", e);
         }
     });
-    "OK"
 }
 
 #[shuttle_runtime::main]
-async fn main(#[shuttle_runtime::Secrets] secrets: shuttle_runtime::SecretStore) -> ShuttleRocket {
+async fn main(
+    #[shuttle_runtime::Secrets] secrets: shuttle_runtime::SecretStore,
+    #[shuttle_shared_db::Postgres] db: PgPool,
+) -> web::Server {
+    let log_buffer_size = match secrets.get("LOG_BUFFER_SIZE") {
+        Some(size) => size.parse().context("LOG_BUFFER_SIZE must be a number")?,
+        None => 1000,
+    };
+    let logs = LogBuffer::new(log_buffer_size);
+    let telemetry = telemetry::init(&secrets, logs.clone())?;
+
     let bot_token = secrets
         .get("BOT_TOKEN")
         .context("BOT_TOKEN environment variable must be set")?;
     let me_id = secrets
         .get("ME_ID")
         .context("ME_ID environment variable must be set")?;
-    let channel_id = secrets
+    let channel_id: i64 = secrets
         .get("CHANNEL_ID")
-        .context("CHANNEL_ID environment variable must be set")?;
+        .context("CHANNEL_ID environment variable must be set")?
+        .parse()
+        .context("CHANNEL_ID must be a chat id")?;
     let public_url = secrets
         .get("PUBLIC_URL")
         .context("PUBLIC_URL must be set")?;
+    let webhook_url = Url::parse(&format!("{}/{}", public_url, bot_token))
+        .context("Failed to parse webhook URL")?;
+    let api_tokens = auth::ApiTokens::new(
+        secrets.get("ADMIN_TOKEN").as_deref(),
+        secrets.get("API_TOKENS").as_deref(),
+    )?;
+
+    let retry_policy = retry::RetryPolicy::from_secrets(&secrets)?;
+    let reply_ttl = match secrets.get("REPLY_TTL_SECS") {
+        Some(secs) => match secs
+            .parse()
+            .context("REPLY_TTL_SECS must be a number of seconds")?
+        {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        },
+        None => Some(cleanup::DEFAULT_TTL),
+    };
+    let preview_channel_id = secrets
+        .get("PREVIEW_CHANNEL_ID")
+        .map(|id| id.parse().map(ChatId))
+        .transpose()
+        .context("PREVIEW_CHANNEL_ID must be a chat id")?;
+    let flags = flags::Flags::from_secret(secrets.get("FEATURE_FLAGS").as_deref())?;
+    flags
+        .load(&db)
+        .await
+        .context("Failed to load feature flags")?;
+    let staging_channel_id = secrets
+        .get("STAGING_CHANNEL_ID")
+        .map(|id| id.parse().map(ChatId))
+        .transpose()
+        .context("STAGING_CHANNEL_ID must be a chat id")?;
+    let caption_format = Format::from_secret(
+        "CAPTION_PARSE_MODE",
+        secrets.get("CAPTION_PARSE_MODE").as_deref(),
+    )?;
+
+    let me_id = ChatId(me_id.parse().context("ME_ID must be a number")?);
+    let mut dashboard_admin_ids = vec![me_id.0];
+    for id in secrets
+        .get("DASHBOARD_ADMIN_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+    {
+        dashboard_admin_ids.push(id.parse().context("DASHBOARD_ADMIN_IDS must be numbers")?);
+    }
+
+    sqlx::migrate!()
+        .run(&db)
+        .await
+        .context("Failed to run database migrations")?;
+
+    let mut bot = Bot::new(bot_token.clone());
+    let mut bot_api_mode = media::BotApiMode::Cloud;
+    if let Some(api_url) = secrets.get("BOT_API_URL") {
+        bot = bot.set_api_url(Url::parse(&api_url).context("Failed to parse BOT_API_URL")?);
+        bot_api_mode = media::BotApiMode::Local;
+        tracing::info!("Using Bot API server at {}", api_url);
+    }
+    let bot = Arc::new(bot);
+    let dry_run = dry_run::from_secret(secrets.get("DRY_RUN").as_deref());
+    jobs::set_dry_run(dry_run.is_some());
+    let me = bot.get_me().await.context("Failed to fetch bot info")?;
 
     let server_secrets_state = Arc::new(ServerSecretsState {
         bot_token,
+        webhook_url,
         me_id,
-        channel_id,
+        channel: AtomicI64::new(channel_id),
+        api_tokens,
+        bot_username: me.username().to_string(),
+        dashboard_admin_ids,
         last_message_id: AtomicI32::new(0),
         message_queue: MessageQueue::new(),
+        logs,
+        db: db.clone(),
+        retry_policy,
+        bot_api_mode,
+        audio_cache: audio_cache::AudioCache::from_secret(
+            secrets.get("AUDIO_CACHE_MB").as_deref(),
+        )?,
+        process_command: secrets.get("PROCESS_COMMAND"),
+        replaygain: secrets.get("REPLAYGAIN").is_some_and(|v| v == "true"),
+        caption_template: Template {
+            text: secrets
+                .get("CAPTION_TEMPLATE")
+                .unwrap_or_else(|| caption::DEFAULT_TEMPLATE.to_string()),
+            format: caption_format,
+        },
+        transliteration: translit::Scheme::from_secret(secrets.get("TRANSLITERATE").as_deref())?,
+        preview_channel_id,
+        waveform: waveform::Mode::from_secret(secrets.get("WAVEFORM").as_deref())?,
+        cover_art: secrets.get("COVER_ART").is_some_and(|v| v == "true"),
+        audio_analysis: secrets.get("AUDIO_ANALYSIS").is_some_and(|v| v == "true"),
+        album_releases: secrets.get("ALBUM_RELEASES").is_some_and(|v| v == "true"),
+        hook_sources: hooks::HookSources::from_secret(secrets.get("INBOUND_HOOKS").as_deref())?,
+        media_server: now_playing::MediaServer::from_secrets(&secrets)?,
+        lyrics: lyrics::Provider::from_secrets(&secrets)?,
+        acoustid_key: secrets.get("ACOUSTID_KEY"),
+        acoustid_pending: acoustid::Pending::default(),
+        translator: translate::Translator::from_secrets(&secrets)?,
+        blurb_writer: blurbs::Writer::from_secrets(&secrets),
+        blurbs_pending: blurbs::Pending::default(),
+        quality_gate: quality::Gate::from_secrets(&secrets)?,
+        quality_pending: quality::Pending::default(),
+        now_playing_shared: Default::default(),
+        reply_ttl,
+        stranger_policy: intruders::Policy::from_secrets(&secrets)?,
+        requests_group: listener_requests::group_from_secret(
+            secrets.get("REQUESTS_GROUP").as_deref(),
+        )?,
+        discussion_group: menus::discussion_group(&bot, ChatId(channel_id)).await,
+        favorites_users: favorites::users_from_secret(secrets.get("FAVORITES_USERS").as_deref())?,
+        notifier: notify::Notifier::from_secrets(&secrets)?,
+        reconciling: reconcile::Running::default(),
+        maintenance: maintenance::Maintenance::default(),
+        staging_channel_id,
+        flags,
+        telegram: match &dry_run {
+            Some(client) => client.clone(),
+            None => bot.clone(),
+        },
+        dry_run,
+        record_updates: replay::retention_from_secret(secrets.get("RECORD_UPDATES").as_deref())?,
+        started_at: Instant::now(),
+        test_mode: AtomicBool::new(
+            test_mode::load(&db)
+                .await
+                .context("Failed to load test mode")?,
+        ),
+        pinned_post: pinned::Mode::from_secret(secrets.get("PINNED_POST").as_deref())?,
+        mirror_sources: mirror::Sources::from_secret(secrets.get("MIRROR_SOURCES").as_deref())?,
+        mirror_set: mirror_set::MirrorSet::from_secrets(&secrets, caption_format)?,
+        join_rules: join_requests::Rules::from_secret(secrets.get("JOIN_AUTO_APPROVE").as_deref())?,
+        topics: topics::Topics::from_secret(secrets.get("FORUM_TOPICS").as_deref())?,
+        quiz_reveal_after: quiz::reveal_after_from_secret(
+            secrets.get("QUIZ_REVEAL_HOURS").as_deref(),
+        )?,
+        queue_ttl: expiry::ttl_from_secret(secrets.get("QUEUE_TTL_HOURS").as_deref())?,
+        themes: themes::Themes::from_secret(
+            secrets.get("WEEKDAY_THEMES").as_deref(),
+            caption_format,
+        )?,
+        paid_stars: paid::stars_from_secret(secrets.get("PAID_MEDIA_STARS").as_deref())?,
+        auto_hashtags: hashtags::Rules::from_secret(secrets.get("AUTO_HASHTAGS").as_deref())?,
+        prepare_concurrency: prepare::concurrency_from_secret(
+            secrets.get("PREPARE_CONCURRENCY").as_deref(),
+        )?,
+        artist_index: artist_index::min_tracks_from_secret(secrets.get("ARTIST_INDEX").as_deref())?,
+        welcome_text: secrets
+            .get("WELCOME_TEXT")
+            .unwrap_or_else(|| welcome::DEFAULT_TEXT.to_string()),
+        blocked: intruders::BlockList::load(&db)
+            .await
+            .context("Failed to load block list")?,
+        vacation: RwLock::new(
+            vacation::load(&db)
+                .await
+                .context("Failed to load vacation")?,
+        ),
     });
 
-    let bot = Arc::new(Bot::new(server_secrets_state.bot_token.clone()));
-    let webhook_url = format!("{}/{}", public_url, server_secrets_state.bot_token);
+    if let Some(channel) = migrate::load(&db)
+        .await
+        .context("Failed to load migrated channel")?
+    {
+        tracing::info!("Publishing to migrated channel {}", channel.id);
+        migrate::activate(&server_secrets_state, &channel);
+    }
+
+    if let Some(mode) = on_this_day::Mode::from_secret(secrets.get("ON_THIS_DAY").as_deref())? {
+        let hour = match secrets.get("ON_THIS_DAY_HOUR") {
+            Some(hour) => hour
+                .parse()
+                .ok()
+                .filter(|hour| *hour < 24)
+                .context("ON_THIS_DAY_HOUR must be an hour between 0 and 23")?,
+            None => 12,
+        };
+        let bot = bot.clone();
+        let state = server_secrets_state.clone();
+        jobs::spawn_daily("on_this_day", hour, db.clone(), move |date| {
+            let bot = bot.clone();
+            let state = state.clone();
+            async move {
+                if vacation::is_active(&state) {
+                    return Ok(());
+                }
+                on_this_day::run(&bot, &state, mode, date).await
+            }
+        });
+    }
+
+    if let Some(mode) = wrapped::Mode::from_secret(secrets.get("YEAR_IN_REVIEW").as_deref())? {
+        let hour = match secrets.get("YEAR_IN_REVIEW_HOUR") {
+            Some(hour) => hour
+                .parse()
+                .ok()
+                .filter(|hour| *hour < 24)
+                .context("YEAR_IN_REVIEW_HOUR must be an hour between 0 and 23")?,
+            None => 18,
+        };
+        let bot = bot.clone();
+        let state = server_secrets_state.clone();
+        jobs::spawn_daily("year_in_review", hour, db.clone(), move |date| {
+            let bot = bot.clone();
+            let state = state.clone();
+            async move {
+                if vacation::is_active(&state) {
+                    return Ok(());
+                }
+                wrapped::run(&bot, &state, mode, date).await
+            }
+        });
+    }
+
+    if server_secrets_state.media_server.is_some()
+        && let Some(hour) = secrets.get("NOW_PLAYING_HOUR")
+    {
+        let hour = hour
+            .parse()
+            .ok()
+            .filter(|hour| *hour < 24)
+            .context("NOW_PLAYING_HOUR must be an hour between 0 and 23")?;
+        let state = server_secrets_state.clone();
+        jobs::spawn_daily("now_playing", hour, db.clone(), move |_| {
+            let state = state.clone();
+            async move {
+                if !vacation::is_active(&state) {
+                    now_playing::share(&state).await?;
+                }
+                Ok(())
+            }
+        });
+    }
+
+    {
+        let state = server_secrets_state.clone();
+        jobs::spawn_daily("vacation_notice", 0, db.clone(), move |date| {
+            let state = state.clone();
+            async move { vacation::run(&state, date).await }
+        });
+    }
+
+    {
+        let hour = match secrets.get("DIGEST_HOUR") {
+            Some(hour) => hour
+                .parse()
+                .ok()
+                .filter(|hour| *hour < 24)
+                .context("DIGEST_HOUR must be an hour between 0 and 23")?,
+            None => 9,
+        };
+        let bot = bot.clone();
+        let state = server_secrets_state.clone();
+        jobs::spawn_weekly(
+            "digest",
+            chrono::Weekday::Mon,
+            hour,
+            db.clone(),
+            move |date| {
+                let bot = bot.clone();
+                let state = state.clone();
+                async move { digest::run(&bot, &state, date).await }
+            },
+        );
+    }
+
+    themes::spawn(bot.clone(), server_secrets_state.clone());
+
+    if let Some(hour) = secrets.get("RECONCILE_HOUR") {
+        let hour = hour
+            .parse()
+            .ok()
+            .filter(|hour| *hour < 24)
+            .context("RECONCILE_HOUR must be an hour between 0 and 23")?;
+        let bot = bot.clone();
+        let state = server_secrets_state.clone();
+        jobs::spawn_daily("reconcile", hour, db.clone(), move |_| {
+            let bot = bot.clone();
+            let state = state.clone();
+            async move {
+                if let Some((checked, deleted)) = reconcile::run(&bot, &state).await? {
+                    tracing::info!("Reconciled {} posts, {} deleted", checked, deleted);
+                }
+                Ok(())
+            }
+        });
+    }
+
+    {
+        let mode = subscribers::Mode::from_secret(secrets.get("SUBSCRIBER_MILESTONES").as_deref())?;
+        let recap = growth::Mode::from_secret(secrets.get("GROWTH_RECAP").as_deref())?;
+        let hour = match secrets.get("SUBSCRIBERS_HOUR") {
+            Some(hour) => hour
+                .parse()
+                .ok()
+                .filter(|hour| *hour < 24)
+                .context("SUBSCRIBERS_HOUR must be an hour between 0 and 23")?,
+            None => 6,
+        };
+        let bot = bot.clone();
+        let state = server_secrets_state.clone();
+        jobs::spawn_daily("subscribers", hour, db.clone(), move |date| {
+            let bot = bot.clone();
+            let state = state.clone();
+            async move {
+                subscribers::run(&bot, &state, mode, date).await?;
+                match recap {
+                    Some(recap) if !vacation::is_active(&state) => {
+                        growth::run(&bot, &state, recap, date).await
+                    }
+                    _ => Ok(()),
+                }
+            }
+        });
+    }
 
-    bot.set_webhook(Url::parse(&webhook_url).context("Failed to parse webhook URL")?)
+    {
+        let days = match secrets.get("AUDIT_RETENTION_DAYS") {
+            Some(days) => days
+                .parse()
+                .context("AUDIT_RETENTION_DAYS must be a number of days")?,
+            None => audit::DEFAULT_RETENTION_DAYS,
+        };
+        let db = db.clone();
+        jobs::spawn_daily("prune_audit", 3, db.clone(), move |_| {
+            let db = db.clone();
+            async move {
+                let pruned = audit::prune(&db, days).await?;
+                tracing::info!("Pruned {} audit entries", pruned);
+                Ok(())
+            }
+        });
+    }
+
+    if let Some(days) = server_secrets_state.record_updates {
+        let db = db.clone();
+        jobs::spawn_daily("prune_updates", 3, db.clone(), move |_| {
+            let db = db.clone();
+            async move {
+                let pruned = replay::prune(&db, days).await?;
+                tracing::info!("Pruned {} recorded updates", pruned);
+                Ok(())
+            }
+        });
+    }
+
+    dead_letter::alert(&server_secrets_state).await;
+    match maintenance::restore(&bot, &server_secrets_state).await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Requeued {} item(s) saved by the last drain", count),
+        Err(e) => tracing::error!("Failed to requeue the drained queue: {}", e),
+    }
+    cleanup::resume(&bot, &db)
+        .await
+        .context("Failed to resume pending reply deletions")?;
+    scheduled::resume(&bot, &server_secrets_state)
+        .await
+        .context("Failed to resume scheduled posts")?;
+    quiz::resume(&bot, &server_secrets_state)
+        .await
+        .context("Failed to resume quiz reveals")?;
+    mirror_set::spawn_retries(server_secrets_state.clone());
+    if let Some(scheme) = server_secrets_state.transliteration
+        && let Err(e) = translit::backfill(&db, scheme).await
+    {
+        tracing::warn!("Failed to transliterate cataloged tracks: {}", e);
+    }
+
+    if let Err(e) = menus::register(&bot, &server_secrets_state).await {
+        tracing::warn!("Failed to set the command menus: {}", e);
+    }
+
+    let webhook_url = server_secrets_state.webhook_url.clone();
+    let allowed_updates = webhook::allowed_updates(&db)
+        .await
+        .context("Failed to load allowed updates")?;
+    webhook::register(&bot, webhook_url.clone(), &allowed_updates)
         .await
         .context("Failed to set webhook")?;
-    println!("Webhook set successfully");
+    tracing::info!("Webhook set successfully");
+    if let Some(watchdog) = webhook::Watchdog::from_secrets(
+        secrets.get("WEBHOOK_CHECK_MINUTES").as_deref(),
+        secrets.get("WEBHOOK_PENDING_LIMIT").as_deref(),
+    )? {
+        watchdog.spawn(
+            (*bot).clone(),
+            db.clone(),
+            Some(server_secrets_state.me_id),
+            webhook_url,
+        );
+    }
+
+    if let Some(watch_folder) = watch_folder::WatchFolder::from_secrets(&secrets)? {
+        watch_folder.spawn(bot.clone(), server_secrets_state.clone());
+    }
+
+    let rate_limiter =
+        rate_limit::RateLimiter::from_secrets(&secrets, &server_secrets_state.bot_token)?;
 
-    let rocket = rocket::build()
-        .manage(bot)
-        .mount("/", routes![index_handler, webhook_handler])
-        .manage(server_secrets_state);
-    Ok(rocket.into())
+    web::serve(web::App {
+        bot,
+        schema: graphql::schema(db),
+        secrets: server_secrets_state,
+        cors: cors::Cors::from_secret(secrets.get("CORS_ORIGINS").as_deref()),
+        rate_limiter,
+        telemetry,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telegram::{Call, MemoryClient};
+    use teloxide::types::MessageId;
+
+    #[tokio::test]
+    async fn batch_status_reports_to_the_owner_through_the_client() {
+        let client = MemoryClient::new(10);
+        let owner = ChatId(42);
+        let mut status = BatchStatus::start(&client, Some(owner), 3).await;
+        status.progress(&client, 1).await;
+        status.failed = 1;
+        status.finish(&client).await;
+
+        let calls = client.take_calls();
+        assert_eq!(calls.len(), 3);
+        assert!(matches!(&calls[0], Call::SendMessage(chat, text)
+            if *chat == owner && text.text == "Posting 1/3…"));
+        assert!(matches!(&calls[1], Call::EditText(chat, id, text)
+            if *chat == owner && *id == MessageId(10) && text.text.starts_with("Posting 2/3…")));
+        assert!(matches!(&calls[2], Call::EditText(_, _, text)
+            if text.text == "Posted 2/3, 1 failed — see /logs"));
+    }
+
+    #[test]
+    fn sending_comes_down_when_dropped() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let sending = Sending::start(&flag);
+        assert!(flag.load(Ordering::Acquire));
+        drop(sending);
+        assert!(!flag.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn batch_status_stays_quiet_without_an_owner() {
+        let client = MemoryClient::new(1);
+        let status = BatchStatus::start(&client, None, 2).await;
+        status.finish(&client).await;
+        assert!(client.take_calls().is_empty());
+    }
 }
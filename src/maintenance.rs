@@ -0,0 +1,195 @@
+use crate::{QueuedMessage, ServerSecretsState, cleanup, settings};
+use serde::Serialize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use teloxide::prelude::*;
+use tokio::time::{Duration, sleep};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The queue as saved by a drain, requeued by the next deployment.
+const SETTING: &str = "drained_queue";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Maintenance mode: set before a planned redeploy so the queue worker is not cut off
+/// halfway through a batch.
+#[derive(Default)]
+pub struct Maintenance {
+    on: AtomicBool,
+    /// Whether the queue was paused before maintenance began, to leave it that way.
+    was_paused: AtomicBool,
+    /// Set once in-flight work is done and the queue is saved.
+    drained: AtomicBool,
+}
+
+pub fn is_on(secrets: &ServerSecretsState) -> bool {
+    secrets.maintenance.on.load(Ordering::Relaxed)
+}
+
+/// How far a drain has got.
+#[derive(Serialize)]
+pub struct DrainState {
+    pub maintenance: bool,
+    /// Whether a batch is still being posted.
+    pub sending: bool,
+    pub reconciling: bool,
+    pub queued: usize,
+    pub safe_to_redeploy: bool,
+}
+
+pub async fn state(secrets: &ServerSecretsState) -> DrainState {
+    let maintenance = is_on(secrets);
+    DrainState {
+        maintenance,
+        sending: secrets.message_queue.is_sending(),
+        reconciling: secrets.reconciling.is_running(),
+        queued: secrets.message_queue.snapshot().await.len(),
+        safe_to_redeploy: maintenance && secrets.maintenance.drained.load(Ordering::Relaxed),
+    }
+}
+
+fn busy(secrets: &ServerSecretsState) -> bool {
+    secrets.message_queue.is_sending() || secrets.reconciling.is_running()
+}
+
+/// Enters maintenance: new uploads are refused, the queue is paused, and once the
+/// batch being posted has gone out the rest of the queue is saved for the next
+/// deployment and the owner is told it is safe to redeploy. Until maintenance ends
+/// the saved queue is kept in step with the one in memory. Returns `false` if
+/// maintenance was already on.
+pub fn start(bot: &Arc<Bot>, secrets: &Arc<ServerSecretsState>) -> bool {
+    let maintenance = &secrets.maintenance;
+    if maintenance.on.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    maintenance
+        .was_paused
+        .store(secrets.message_queue.is_paused(), Ordering::Relaxed);
+    maintenance.drained.store(false, Ordering::Relaxed);
+    secrets.message_queue.set_paused(true);
+    tracing::warn!("Maintenance on, draining");
+
+    let bot = bot.clone();
+    let secrets = secrets.clone();
+    tokio::spawn(async move {
+        let mut saved: Option<Vec<i32>> = None;
+        while is_on(&secrets) {
+            if busy(&secrets) {
+                sleep(POLL_INTERVAL).await;
+                continue;
+            }
+            let queue = secrets.message_queue.snapshot().await;
+            let ids = queue.iter().map(|msg| msg.message_id).collect::<Vec<_>>();
+            if saved.as_ref() != Some(&ids) {
+                if let Err(e) = settings::set(&secrets.db, SETTING, &queue).await {
+                    tracing::error!("Failed to save the queue for redeploying: {}", e);
+                    sleep(POLL_INTERVAL).await;
+                    continue;
+                }
+                saved = Some(ids);
+            }
+            if !secrets.maintenance.drained.swap(true, Ordering::AcqRel) {
+                tracing::info!("Drained, {} queued item(s) saved", queue.len());
+                notify(
+                    &bot,
+                    &secrets,
+                    format!(
+                        "🛠 Safe to redeploy. {} queued item(s) saved; they go out after the restart.",
+                        queue.len()
+                    ),
+                )
+                .await;
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    });
+    true
+}
+
+/// Leaves maintenance without a redeploy: uploads are taken again, the saved queue is
+/// dropped (the one in memory is still there) and the queue resumes unless it was
+/// paused before.
+pub async fn stop(secrets: &ServerSecretsState) -> Result<bool, Error> {
+    let maintenance = &secrets.maintenance;
+    if !maintenance.on.swap(false, Ordering::AcqRel) {
+        return Ok(false);
+    }
+    settings::clear(&secrets.db, SETTING).await?;
+    maintenance.drained.store(false, Ordering::Relaxed);
+    secrets
+        .message_queue
+        .set_paused(maintenance.was_paused.load(Ordering::Relaxed));
+    tracing::info!("Maintenance off");
+    Ok(true)
+}
+
+/// Requeues what the previous deployment saved while draining. Called on startup.
+pub async fn restore(bot: &Arc<Bot>, secrets: &Arc<ServerSecretsState>) -> Result<usize, Error> {
+    let Some(queue) = settings::get::<Vec<QueuedMessage>>(&secrets.db, SETTING).await? else {
+        return Ok(0);
+    };
+    let count = queue.len();
+    for message in queue {
+        secrets
+            .message_queue
+            .add_message(message, bot.clone(), secrets.clone())
+            .await;
+    }
+    settings::clear(&secrets.db, SETTING).await?;
+    Ok(count)
+}
+
+async fn notify(bot: &Bot, secrets: &ServerSecretsState, text: String) {
+    if let Err(e) = bot.send_message(secrets.me_id, text).await {
+        tracing::warn!("Failed to send maintenance notice: {}", e);
+    }
+}
+
+fn describe(state: &DrainState) -> String {
+    if !state.maintenance {
+        return "Maintenance is off.".to_string();
+    }
+    if state.safe_to_redeploy {
+        return format!(
+            "Maintenance is on and drained: safe to redeploy, {} queued item(s) saved.",
+            state.queued
+        );
+    }
+    let mut waiting = Vec::new();
+    if state.sending {
+        waiting.push("the current batch");
+    }
+    if state.reconciling {
+        waiting.push("the reconciliation");
+    }
+    if waiting.is_empty() {
+        waiting.push("the queue to be saved");
+    }
+    format!(
+        "Maintenance is on, waiting for {}. I'll tell you when it is safe to redeploy.",
+        waiting.join(" and ")
+    )
+}
+
+/// `/maintenance [on|off]`.
+pub async fn handle_command(
+    bot: &Arc<Bot>,
+    message: &Message,
+    args: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let reply = match args.trim() {
+        "" => describe(&state(secrets).await),
+        "on" => {
+            start(bot, secrets);
+            describe(&state(secrets).await)
+        }
+        "off" => match stop(secrets).await? {
+            true => "Maintenance off, taking uploads again.".to_string(),
+            false => "Maintenance is off.".to_string(),
+        },
+        _ => "Usage: /maintenance [on|off]".to_string(),
+    };
+    cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+    Ok(())
+}
@@ -0,0 +1,427 @@
+use crate::{
+    QueuePosition, QueuedMessage, ServerSecretsState, audio_cache, flags::Flag, replaygain,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use teloxide::{
+    net::Download,
+    prelude::*,
+    types::{FileMeta, InputFile},
+};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The cloud Bot API refuses to hand out files bigger than this.
+const CLOUD_DOWNLOAD_LIMIT: u64 = 20 * 1024 * 1024;
+/// ...and to accept uploads bigger than this.
+const CLOUD_UPLOAD_LIMIT: u64 = 50 * 1024 * 1024;
+const LOCAL_UPLOAD_LIMIT: u64 = 2000 * 1024 * 1024;
+
+/// Whether the bot talks to api.telegram.org or to a self-hosted Bot API server
+/// (`BOT_API_URL`) running with `--local`, which lifts the size limits and serves
+/// files straight from its own disk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BotApiMode {
+    Cloud,
+    Local,
+}
+
+impl BotApiMode {
+    pub fn can_download(self, size: u64) -> bool {
+        self == BotApiMode::Local || size <= CLOUD_DOWNLOAD_LIMIT
+    }
+
+    fn can_upload(self, size: u64) -> bool {
+        size <= self.upload_limit()
+    }
+
+    /// The largest file the Bot API will take from us.
+    pub fn upload_limit(self) -> u64 {
+        match self {
+            BotApiMode::Cloud => CLOUD_UPLOAD_LIMIT,
+            BotApiMode::Local => LOCAL_UPLOAD_LIMIT,
+        }
+    }
+}
+
+/// A file on local disk. Files we downloaded ourselves are removed on drop; files
+/// served by a local Bot API server belong to it and are left alone, and ones in the
+/// audio cache are kept from eviction while in use.
+pub struct LocalFile {
+    pub path: PathBuf,
+    temporary: bool,
+    pin: Option<audio_cache::Pin>,
+}
+
+impl Drop for LocalFile {
+    fn drop(&mut self) {
+        if !self.temporary {
+            return;
+        }
+        let result = if self.path.is_dir() {
+            std::fs::remove_dir_all(&self.path)
+        } else {
+            std::fs::remove_file(&self.path)
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to remove {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+impl LocalFile {
+    /// A temporary file (or directory) named after `name` that is cleaned up on drop.
+    pub fn scratch(name: &str) -> Self {
+        LocalFile {
+            path: std::env::temp_dir().join(format!("ankh-{}", name)),
+            temporary: true,
+            pin: None,
+        }
+    }
+
+    /// Moves the file to `to` and keeps it there. The move is a rename, so whoever
+    /// looks at `to` sees either nothing or the whole file.
+    pub async fn persist(mut self, to: &Path) -> std::io::Result<()> {
+        tokio::fs::rename(&self.path, to).await?;
+        self.temporary = false;
+        Ok(())
+    }
+}
+
+/// Runs an external tool such as ffmpeg, turning a non-zero exit into an error that
+/// carries its stderr.
+pub async fn run(command: &mut Command) -> Result<(), Error> {
+    let result = command.output().await?;
+    if !result.status.success() {
+        return Err(format!(
+            "{:?} failed with {}: {}",
+            command.as_std().get_program(),
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Reads the container-level tags (album, date, track, ...) of an audio file with
+/// ffprobe, keyed by lowercase tag name.
+pub async fn probe_tags(path: &Path) -> Result<HashMap<String, String>, Error> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format_tags"])
+        .args(["-of", "default=noprint_wrappers=1"])
+        .arg(path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("TAG:")?.split_once('='))
+        .map(|(key, value)| (key.to_lowercase(), value.trim().to_string()))
+        .collect())
+}
+
+/// The overall bitrate of an audio file in kbps, as ffprobe reports it.
+pub async fn probe_bitrate(path: &Path) -> Result<u32, Error> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=bit_rate"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    let bits: u64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|_| "ffprobe reported no bitrate")?;
+    Ok((bits / 1000) as u32)
+}
+
+/// The sample rate of the first audio stream in Hz, as ffprobe reports it.
+pub async fn probe_sample_rate(path: &Path) -> Result<u32, Error> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "a:0"])
+        .args(["-show_entries", "stream=sample_rate"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .map_err(|_| "ffprobe reported no sample rate")?)
+}
+
+/// "3/12" and "3" both mean 3.
+fn leading_number(raw: Option<&String>) -> Option<u32> {
+    raw?.split('/').next()?.trim().parse().ok()
+}
+
+/// Sort key putting tracks in tracklist order by their disc and track tags, with
+/// untagged tracks last.
+pub fn tracklist_position(tags: &HashMap<String, String>) -> (u32, u32) {
+    (
+        leading_number(tags.get("disc")).unwrap_or(1),
+        leading_number(tags.get("track")).unwrap_or(u32::MAX),
+    )
+}
+
+/// Makes `file` available on local disk, reading it in place from a local Bot API
+/// server, from the [`AudioCache`](crate::audio_cache::AudioCache), or downloading it
+/// from the cloud one.
+pub async fn fetch(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    file: &FileMeta,
+) -> Result<LocalFile, Error> {
+    let size = u64::from(file.size);
+    if !secrets.bot_api_mode.can_download(size) {
+        return Err(format!(
+            "{} MB is over the cloud Bot API download limit; set BOT_API_URL to a local server",
+            size / (1024 * 1024)
+        )
+        .into());
+    }
+
+    let cache = secrets
+        .audio_cache
+        .as_ref()
+        .filter(|cache| cache.fits(size));
+    if let Some((path, pin)) = cache.and_then(|cache| cache.get(&file.unique_id.0)) {
+        return Ok(LocalFile {
+            path,
+            temporary: false,
+            pin: Some(pin),
+        });
+    }
+
+    let remote = secrets
+        .retry_policy
+        .run(|| bot.get_file(file.id.clone()).send())
+        .await?;
+
+    if secrets.bot_api_mode == BotApiMode::Local && Path::new(&remote.path).is_absolute() {
+        return Ok(LocalFile {
+            path: PathBuf::from(remote.path),
+            temporary: false,
+            pin: None,
+        });
+    }
+
+    // The same file can be fetched twice at once when it was queued twice.
+    let mut local = LocalFile::scratch(&format!(
+        "{}-{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        file.unique_id.0
+    ));
+    let mut dst = tokio::fs::File::create(&local.path).await?;
+    bot.download_file(&remote.path, &mut dst).await?;
+    let Some(cache) = cache else {
+        return Ok(local);
+    };
+    dst.flush().await?;
+    drop(dst);
+    match cache.insert(&file.unique_id.0, &local.path).await {
+        Ok((path, pin)) => {
+            // The cache decides when it goes from now on.
+            local.path = path;
+            local.temporary = false;
+            local.pin = Some(pin);
+        }
+        Err(e) => tracing::warn!("Failed to cache {}: {}", file.unique_id.0, e),
+    }
+    Ok(local)
+}
+
+/// Runs `PROCESS_COMMAND` on the queued audio before it is published, returning the
+/// processed file, or `None` when processing is off or impossible for this file, in
+/// which case the original is reposted by file id.
+///
+/// The command is split on whitespace and `{input}` / `{output}` are replaced with
+/// file paths, e.g. `ffmpeg -y -i {input} -af loudnorm {output}`. With `REPLAYGAIN`
+/// on, the result is then measured and tagged with its track gain and peak.
+pub async fn process(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    file: &FileMeta,
+    file_name: Option<&str>,
+) -> Result<Option<LocalFile>, Error> {
+    let Some(command) = &secrets.process_command else {
+        return Ok(None);
+    };
+    if !secrets.flags.is_enabled(Flag::Transcoding) {
+        return Ok(None);
+    }
+
+    if !secrets.bot_api_mode.can_download(file.size.into()) {
+        tracing::warn!(
+            "Skipping processing of {} byte file, too large for the cloud Bot API",
+            file.size
+        );
+        return Ok(None);
+    }
+
+    let input = fetch(bot, secrets, file).await?;
+    let extension = file_name
+        .and_then(|name| Path::new(name).extension())
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("mp3");
+    let stamp = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+    let output = LocalFile::scratch(&format!(
+        "{}-{}-processed.{}",
+        stamp, file.unique_id.0, extension
+    ));
+
+    let mut args = command.split_whitespace().map(|arg| {
+        arg.replace("{input}", &input.path.to_string_lossy())
+            .replace("{output}", &output.path.to_string_lossy())
+    });
+    let program = args.next().ok_or("PROCESS_COMMAND is empty")?;
+    run(Command::new(program).args(args)).await?;
+    let output = if secrets.replaygain {
+        let name = format!("{}-{}-replaygain.{}", stamp, file.unique_id.0, extension);
+        match replaygain::tag(&output, &name).await {
+            Ok(tagged) => tagged,
+            Err(e) => {
+                tracing::warn!("Posting without ReplayGain tags: {}", e);
+                output
+            }
+        }
+    } else {
+        output
+    };
+
+    let size = tokio::fs::metadata(&output.path).await?.len();
+    if !secrets.bot_api_mode.can_upload(size) {
+        return Err(format!(
+            "Processed file is {} MB, over the cloud Bot API upload limit; set BOT_API_URL to a local server",
+            size / (1024 * 1024)
+        )
+        .into());
+    }
+
+    Ok(Some(output))
+}
+
+/// Uploads a local audio file to the owner's chat to get a file id the queue can
+/// repost, taking title and artist from `tags`. Files Telegram does not treat as
+/// audio are rejected.
+pub async fn stage_audio(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    path: &Path,
+    tags: &HashMap<String, String>,
+) -> Result<Message, Error> {
+    let owner = secrets.me_id;
+    let message = secrets
+        .retry_policy
+        .run(|| {
+            let mut request = bot
+                .send_audio(owner, InputFile::file(path.to_path_buf()))
+                .disable_notification(true);
+            if let Some(title) = tags.get("title") {
+                request = request.title(title.clone());
+            }
+            if let Some(artist) = tags.get("artist") {
+                request = request.performer(artist.clone());
+            }
+            request.send()
+        })
+        .await?;
+
+    if message.audio().is_none() {
+        bot.delete_message(message.chat.id, message.id).await?;
+        return Err("Telegram did not recognise it as audio".into());
+    }
+    Ok(message)
+}
+
+/// Optional overrides for what a file's own tags say, as sent by HTTP clients.
+#[derive(Deserialize, Default)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Queues a local audio file that did not come through Telegram: its tags are read,
+/// overridden by `metadata`, and it is staged in the owner's chat for a file id.
+/// `fallback_title` is used when neither has a title.
+pub async fn queue_file(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    path: &Path,
+    fallback_title: String,
+    metadata: Metadata,
+) -> Result<QueuePosition, Error> {
+    let mut tags = probe_tags(path).await.unwrap_or_default();
+    tags.entry("title".to_string()).or_insert(fallback_title);
+    if let Some(title) = metadata.title {
+        tags.insert("title".to_string(), title);
+    }
+    if let Some(performer) = metadata.performer {
+        tags.insert("artist".to_string(), performer);
+    }
+
+    let staged = stage_audio(bot, secrets, path, &tags).await?;
+    let audio = staged.audio().cloned().ok_or("Not an audio file")?;
+
+    let position = secrets
+        .message_queue
+        .add_message(
+            QueuedMessage {
+                audio,
+                tags: metadata
+                    .tags
+                    .iter()
+                    .map(|tag| tag.trim_start_matches('#').to_lowercase())
+                    .collect(),
+                message_id: staged.id.0,
+                via: None,
+                retag: None,
+                description: None,
+                blurb: None,
+                queued_at: chrono::Utc::now(),
+                theme: None,
+                stars: None,
+                part: None,
+            },
+            bot.clone(),
+            secrets.clone(),
+        )
+        .await;
+    if let Err(e) = bot.delete_message(staged.chat.id, staged.id).await {
+        tracing::warn!("Failed to delete staging message: {}", e);
+    }
+
+    Ok(position)
+}
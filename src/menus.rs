@@ -0,0 +1,119 @@
+use crate::{
+    ServerSecretsState,
+    commands::{self, Audience, Command},
+    intruders::Policy,
+};
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{BotCommand, BotCommandScope, Recipient},
+    utils::command::BotCommands,
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The channel's linked discussion group, where public commands work. Looked up
+/// once at startup, so after `/migrate` it takes a restart to follow the new channel.
+pub async fn discussion_group(bot: &Bot, channel_id: ChatId) -> Option<ChatId> {
+    match bot.get_chat(channel_id).await {
+        Ok(chat) => chat.linked_chat_id().map(ChatId),
+        Err(e) => {
+            tracing::warn!("Failed to look up the discussion group: {}", e);
+            None
+        }
+    }
+}
+
+async fn set(bot: &Bot, scope: BotCommandScope, commands: Vec<BotCommand>) -> Result<(), Error> {
+    if commands.is_empty() {
+        bot.delete_my_commands().scope(scope).await?;
+    } else {
+        bot.set_my_commands(commands).scope(scope).await?;
+    }
+    Ok(())
+}
+
+/// Sets the command menus from the one list in [`Command`]: all of it in the owner's
+/// DM, the starring commands in the DMs of `FAVORITES_USERS`, the submission commands
+/// in everyone else's when strangers may suggest or submit tracks, and the public
+/// ones in the discussion group. Menus left over from an earlier configuration are
+/// cleared. A curator whose menu cannot be set, say because they never started the
+/// bot, is logged and skipped.
+pub async fn register(bot: &Bot, secrets: &ServerSecretsState) -> Result<(), Error> {
+    let owner = Recipient::Id(secrets.me_id);
+    set(
+        bot,
+        BotCommandScope::Chat { chat_id: owner },
+        Command::bot_commands(),
+    )
+    .await?;
+
+    let contributors = match secrets.stranger_policy {
+        Policy::Suggest | Policy::Submit(_) => Command::menu(Audience::Contributors),
+        _ => Vec::new(),
+    };
+    set(bot, BotCommandScope::AllPrivateChats, contributors).await?;
+
+    for user in &secrets.favorites_users {
+        let chat_id = Recipient::Id(ChatId(user.0 as i64));
+        if let Err(e) = set(
+            bot,
+            BotCommandScope::Chat { chat_id },
+            Command::menu(Audience::Curators),
+        )
+        .await
+        {
+            tracing::warn!("Failed to set the command menu of {}: {}", user, e);
+        }
+    }
+
+    if let Some(group) = secrets.discussion_group {
+        set(
+            bot,
+            BotCommandScope::Chat {
+                chat_id: Recipient::Id(group),
+            },
+            Command::menu(Audience::Public),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Runs a command someone other than the owner may use: a public one like
+/// `/random` in the discussion group, `/fav` and `/favorites` in a DM from someone
+/// in `FAVORITES_USERS`, or `/start` and `/submit` in any other DM when strangers may
+/// send in tracks. Returns whether `message` was one; anything else goes the
+/// way it would without menus.
+pub async fn handle_shared(
+    bot: &Arc<Bot>,
+    message: &Message,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<bool, Error> {
+    let audience = if secrets.discussion_group == Some(message.chat.id) {
+        Audience::Public
+    } else if message.chat.is_private()
+        && message
+            .from
+            .as_ref()
+            .is_some_and(|user| secrets.favorites_users.contains(&user.id))
+    {
+        Audience::Curators
+    } else if message.chat.is_private()
+        && message.chat.id != secrets.me_id
+        && matches!(secrets.stranger_policy, Policy::Suggest | Policy::Submit(_))
+    {
+        Audience::Contributors
+    } else {
+        return Ok(false);
+    };
+    let Some(command) = message
+        .text()
+        .and_then(|text| Command::parse(text, &secrets.bot_username).ok())
+        .filter(|command| command.audience() == audience)
+    else {
+        return Ok(false);
+    };
+    commands::handle_command(bot, message, command, secrets).await?;
+    Ok(true)
+}
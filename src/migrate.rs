@@ -0,0 +1,263 @@
+use crate::{
+    ServerSecretsState, catalog, cleanup, commands::no_link_preview, dry_run, releases, settings,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    ApiError, RequestError,
+    prelude::*,
+    types::{MessageId, ParseMode},
+};
+use tokio::time::{Duration, sleep};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const SETTING: &str = "channel";
+
+/// Stays under the Bot API's limit of about 20 messages a minute to one channel.
+const COPY_INTERVAL: Duration = Duration::from_secs(3);
+
+/// The channel a migration switched to, overriding `CHANNEL_ID` from then on.
+#[derive(Serialize, Deserialize)]
+pub struct ActiveChannel {
+    pub id: i64,
+    pub link: String,
+}
+
+pub async fn load(db: &sqlx::PgPool) -> sqlx::Result<Option<ActiveChannel>> {
+    settings::get(db, SETTING).await
+}
+
+pub fn activate(secrets: &ServerSecretsState, channel: &ActiveChannel) {
+    secrets.channel.store(channel.id, Ordering::Relaxed);
+    catalog::set_channel_link(channel.link.clone());
+}
+
+enum Post {
+    Track(i64),
+    Release(catalog::Release),
+}
+
+/// Replaces links to `old_link/<id>` with links to the copies under `new_link`.
+fn rewrite_links(text: &str, old_link: &str, new_link: &str, ids: &HashMap<i32, i32>) -> String {
+    let prefix = format!("{}/", old_link);
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(&prefix) {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + prefix.len()..];
+        let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        match after[..digits].parse().ok().and_then(|id| ids.get(&id)) {
+            Some(new_id) => out.push_str(&format!("{}/{}", new_link, new_id)),
+            None => out.push_str(&rest[start..start + prefix.len() + digits]),
+        }
+        rest = &after[digits..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Copies every cataloged post, oldest first, from the active channel to `to`, moves
+/// the catalog over, fixes up links, and makes `to` the active channel. The queue is
+/// held while this runs. Returns how many posts were copied.
+async fn migrate(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    to: ChatId,
+    new_link: String,
+) -> Result<usize, Error> {
+    let from = secrets.channel_id();
+    let old_link = catalog::channel_link();
+
+    let mut posts = catalog::live_posts(&secrets.db, from.0)
+        .await?
+        .into_iter()
+        .map(|(id, message_id)| (message_id, Post::Track(id)))
+        .collect::<Vec<_>>();
+    for release in catalog::channel_releases(&secrets.db, from.0).await? {
+        posts.push((release.message_id, Post::Release(release)));
+    }
+    posts.sort_by_key(|(message_id, _)| *message_id);
+
+    let mut ids = HashMap::new();
+    for (i, (message_id, post)) in posts.iter().enumerate() {
+        if i > 0 {
+            sleep(COPY_INTERVAL).await;
+        }
+        let copy = match secrets
+            .retry_policy
+            .run(|| {
+                bot.copy_message(to, from, MessageId(*message_id))
+                    .disable_notification(true)
+                    .send()
+            })
+            .await
+        {
+            Ok(copy) => copy,
+            Err(RequestError::Api(ApiError::MessageToCopyNotFound)) => {
+                if let Post::Track(id) = post {
+                    catalog::mark_deleted(&secrets.db, *id).await?;
+                }
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        ids.insert(*message_id, copy.0);
+    }
+
+    for (message_id, post) in &posts {
+        let Some(&new_id) = ids.get(message_id) else {
+            continue;
+        };
+        match post {
+            Post::Track(id) => {
+                let Some(track) = catalog::get_track(&secrets.db, *id).await? else {
+                    continue;
+                };
+                let caption = rewrite_links(&track.caption, &old_link, &new_link, &ids);
+                catalog::move_track(&secrets.db, *id, to.0, new_id, &caption).await?;
+            }
+            Post::Release(release) => {
+                catalog::move_release(&secrets.db, release.id, to.0, new_id).await?;
+            }
+        }
+    }
+
+    let channel = ActiveChannel {
+        id: to.0,
+        link: new_link,
+    };
+    settings::set(&secrets.db, SETTING, &channel).await?;
+    activate(secrets, &channel);
+    if let Some(last) = ids.values().max() {
+        secrets.last_message_id.store(*last, Ordering::Relaxed);
+    }
+
+    // Captions carry links to the posts themselves and release leads link to their
+    // tracks, so both are rewritten now that the new ids are in the catalog.
+    for (message_id, post) in &posts {
+        let Some(&new_id) = ids.get(message_id) else {
+            continue;
+        };
+        sleep(COPY_INTERVAL).await;
+        let result = match post {
+            Post::Track(id) => {
+                let Some(track) = catalog::get_track(&secrets.db, *id).await? else {
+                    continue;
+                };
+                bot.edit_message_caption(to, MessageId(new_id))
+                    .parse_mode(track.caption_format().parse_mode())
+                    .caption(track.caption)
+                    .await
+                    .map(|_| ())
+            }
+            Post::Release(release) => {
+                let tracks = catalog::release_tracks(&secrets.db, release.id).await?;
+                let text = releases::render_recorded(release, &tracks);
+                // The lead is a photo when the album had cover art, text otherwise.
+                match bot
+                    .edit_message_text(to, MessageId(new_id), text.clone())
+                    .parse_mode(ParseMode::MarkdownV2)
+                    .link_preview_options(no_link_preview())
+                    .await
+                {
+                    Ok(_) => Ok(()),
+                    Err(_) => bot
+                        .edit_message_caption(to, MessageId(new_id))
+                        .caption(text)
+                        .parse_mode(ParseMode::MarkdownV2)
+                        .await
+                        .map(|_| ()),
+                }
+            }
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to rewrite links in post {}: {}", new_id, e);
+        }
+    }
+
+    Ok(ids.len())
+}
+
+/// `/migrate <chat id> <https://t.me/name>`: moves the whole catalog to a new channel
+/// the bot can post in, then publishes there from now on.
+pub async fn handle_command(
+    bot: &Arc<Bot>,
+    message: &Message,
+    args: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let (Some(to), Some(link)) = (
+        args.first().and_then(|id| id.parse().ok()).map(ChatId),
+        args.get(1).filter(|link| link.starts_with("https://t.me/")),
+    ) else {
+        cleanup::reply(
+            bot,
+            secrets,
+            message.chat.id,
+            "Usage: /migrate <new channel id> <https://t.me/newchannel>",
+        )
+        .await?;
+        return Ok(());
+    };
+    if to == secrets.channel_id() {
+        cleanup::reply(
+            bot,
+            secrets,
+            message.chat.id,
+            "That is already the channel.",
+        )
+        .await?;
+        return Ok(());
+    }
+    if dry_run::skips(secrets, format!("migrate the channel to {}", to)) {
+        cleanup::reply(bot, secrets, message.chat.id, "Dry run: not migrating.").await?;
+        return Ok(());
+    }
+    let me = bot.get_me().await?;
+    if !bot.get_chat_member(to, me.id).await?.can_post_messages() {
+        cleanup::reply(
+            bot,
+            secrets,
+            message.chat.id,
+            "Make the bot an admin who can post in the new channel first.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    cleanup::reply(
+        bot,
+        secrets,
+        message.chat.id,
+        "Migrating, the queue is held until it is done…",
+    )
+    .await?;
+
+    let bot = bot.clone();
+    let secrets = secrets.clone();
+    let chat_id = message.chat.id;
+    let link = link.trim_end_matches('/').to_string();
+    tokio::spawn(async move {
+        let was_paused = secrets.message_queue.is_paused();
+        secrets.message_queue.set_paused(true);
+        let reply = match migrate(&bot, &secrets, to, link.clone()).await {
+            Ok(count) => format!("Copied {} post(s), now publishing to {}.", count, link),
+            Err(e) => {
+                tracing::error!("Channel migration failed: {}", e);
+                format!(
+                    "Migration stopped, still publishing to the old channel: {}",
+                    e
+                )
+            }
+        };
+        secrets.message_queue.set_paused(was_paused);
+        if let Err(e) = bot.send_message(chat_id, reply).await {
+            tracing::warn!("Failed to report migration: {}", e);
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::{QueuedMessage, ServerSecretsState, cleanup, flags::Flag, format::Format, maintenance};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::prelude::*;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Credit for a track mirrored from a partner channel, added under the caption.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Attribution {
+    pub title: String,
+    /// The original post, when the source channel is public.
+    pub link: Option<String>,
+}
+
+impl Attribution {
+    fn of(post: &Message) -> Self {
+        Self {
+            title: post.chat.title().unwrap_or("a partner channel").to_string(),
+            link: post
+                .chat
+                .username()
+                .map(|username| format!("https://t.me/{}/{}", username, post.id.0)),
+        }
+    }
+
+    /// "via Source", linking to the original post.
+    pub fn render(&self, format: Format) -> String {
+        let title = format.escape(&self.title);
+        match &self.link {
+            Some(link) => format!("via {}", format.link(link, &title)),
+            None => format!("via {}", title),
+        }
+    }
+}
+
+/// Channels whose audio posts are republished, configured with `MIRROR_SOURCES` as
+/// comma-separated chat ids. The bot has to be an admin there to see the posts.
+pub struct Sources(Vec<i64>);
+
+impl Sources {
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Self> {
+        let ids = raw
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                id.parse()
+                    .map_err(|_| anyhow::anyhow!("MIRROR_SOURCES must be chat ids, not {}", id))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(ids))
+    }
+
+    fn contains(&self, chat_id: ChatId) -> bool {
+        self.0.contains(&chat_id.0)
+    }
+}
+
+/// Queues an audio post from a mirrored channel like one forwarded by the owner,
+/// keeping its hashtags and crediting the source. The post is staged in the owner's
+/// chat first so it gets a place in the queue's message order.
+pub async fn handle_post(
+    bot: &Arc<Bot>,
+    post: &Message,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    if !secrets.mirror_sources.contains(post.chat.id)
+        || post.audio().is_none()
+        || !secrets.flags.is_enabled(Flag::CrossPosting)
+    {
+        return Ok(());
+    }
+    if maintenance::is_on(secrets) {
+        tracing::warn!(
+            source = post.chat.id.0,
+            "Maintenance on, not mirroring post {}",
+            post.id.0
+        );
+        return Ok(());
+    }
+    let owner = secrets.me_id;
+    let staged = bot
+        .forward_message(owner, post.chat.id, post.id)
+        .disable_notification(true)
+        .await?;
+    let Some(audio) = staged.audio().cloned() else {
+        return Ok(());
+    };
+
+    let via = Attribution::of(post);
+    tracing::info!(source = post.chat.id.0, "Mirroring post {}", post.id.0);
+    let position = secrets
+        .message_queue
+        .add_message(
+            QueuedMessage {
+                audio,
+                tags: crate::caption_tags(post),
+                message_id: staged.id.0,
+                via: Some(via.clone()),
+                retag: None,
+                description: None,
+                blurb: None,
+                queued_at: chrono::Utc::now(),
+                theme: None,
+                stars: None,
+                part: None,
+            },
+            bot.clone(),
+            secrets.clone(),
+        )
+        .await;
+    if let Err(e) = bot.delete_message(owner, staged.id).await {
+        tracing::warn!("Failed to delete staging message: {}", e);
+    }
+
+    cleanup::reply(
+        bot,
+        secrets,
+        owner,
+        format!(
+            "Mirrored a track from {}, queued {}/{}",
+            via.title, position.position, position.queue_len
+        ),
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,274 @@
+use crate::{
+    ServerSecretsState, caption,
+    catalog::Track,
+    flags::Flag,
+    format::{Format, Template},
+    telegram,
+};
+use shuttle_runtime::SecretStore;
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use teloxide::{prelude::*, types::MessageId};
+use tokio::time::{Duration, sleep};
+
+/// How often copies that failed are tried again.
+const RETRY_EVERY: Duration = Duration::from_secs(10 * 60);
+/// Copies still failing after this many attempts are given up on.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// What the copies in the mirrors say, configured with `MIRROR_CAPTION`.
+enum Caption {
+    /// Unset or `keep`: the main channel's caption.
+    Keep,
+    /// `none`: no caption at all.
+    Strip,
+    /// Anything else: a caption template like `CAPTION_TEMPLATE`, its `{series}`
+    /// linking to the post in the main channel, written in `MIRROR_PARSE_MODE` or
+    /// else `CAPTION_PARSE_MODE`.
+    Template(Template),
+}
+
+/// Channels every published track is copied to after it goes out in the main one,
+/// configured with `MIRROR_CHANNELS` as comma-separated chat ids. The bot has to be
+/// an admin there. Not to be confused with `MIRROR_SOURCES`, which goes the other way.
+pub struct MirrorSet {
+    channels: Vec<ChatId>,
+    caption: Caption,
+}
+
+impl MirrorSet {
+    /// `format` is the `CAPTION_PARSE_MODE` a template without its own is taken to be in.
+    pub fn from_secrets(secrets: &SecretStore, format: Format) -> anyhow::Result<Self> {
+        let channels = secrets
+            .get("MIRROR_CHANNELS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                id.parse()
+                    .map(ChatId)
+                    .map_err(|_| anyhow::anyhow!("MIRROR_CHANNELS must be chat ids, not {}", id))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let caption = match secrets.get("MIRROR_CAPTION") {
+            None => Caption::Keep,
+            Some(raw) => match raw.trim() {
+                "" | "keep" => Caption::Keep,
+                "none" => Caption::Strip,
+                _ => Caption::Template(Template {
+                    text: raw,
+                    format: match secrets.get("MIRROR_PARSE_MODE") {
+                        Some(mode) => Format::from_secret("MIRROR_PARSE_MODE", Some(&mode))?,
+                        None => format,
+                    },
+                }),
+            },
+        };
+        Ok(Self { channels, caption })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+}
+
+/// The caption replacing the main channel's in a copy of `message_id`, and its
+/// format, if any. Posts that are not in the catalog keep theirs.
+async fn caption_override(
+    secrets: &ServerSecretsState,
+    channel_id: ChatId,
+    message_id: MessageId,
+) -> sqlx::Result<Option<(String, Format)>> {
+    let template = match &secrets.mirror_set.caption {
+        Caption::Keep => return Ok(None),
+        Caption::Strip => return Ok(Some((String::new(), secrets.caption_template.format))),
+        Caption::Template(template) => template,
+    };
+    let track: Option<Track> = sqlx::query_as(
+        "SELECT * FROM tracks
+         WHERE channel_id = $1 AND message_id = $2 AND deleted_at IS NULL",
+    )
+    .bind(channel_id.0)
+    .bind(message_id.0)
+    .fetch_optional(&secrets.db)
+    .await?;
+    Ok(track.map(|track| {
+        let caption = crate::caption(
+            template,
+            &track.series,
+            track.message_id,
+            track.number,
+            None,
+            &caption::Facts::of_track(&track),
+        );
+        (caption, template.format)
+    }))
+}
+
+/// One post's copy in one mirror, as tracked in `mirror_copies`.
+#[derive(FromRow)]
+struct Copy {
+    channel_id: i64,
+    message_id: i32,
+    mirror_id: i64,
+    attempts: i32,
+}
+
+/// Copies the post, with `MIRROR_CAPTION` applied, from the channel it was recorded
+/// in, which for posts from before a `/migrate` is the old one. Records how it went,
+/// so failures are picked up by [`spawn_retries`].
+async fn copy(secrets: &ServerSecretsState, copy: &Copy) -> sqlx::Result<bool> {
+    let caption =
+        caption_override(secrets, ChatId(copy.channel_id), MessageId(copy.message_id)).await?;
+    let outgoing = telegram::OutgoingCopy {
+        caption,
+        ..Default::default()
+    };
+    let result = secrets
+        .retry_policy
+        .run(|| {
+            secrets.telegram.copy_message(
+                ChatId(copy.mirror_id),
+                ChatId(copy.channel_id),
+                MessageId(copy.message_id),
+                &outgoing,
+            )
+        })
+        .await;
+    let (mirror_message_id, error) = match result {
+        Ok(sent) => (Some(sent.id.0), None),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to copy post {} to mirror {} (attempt {}): {}",
+                copy.message_id,
+                copy.mirror_id,
+                copy.attempts + 1,
+                e
+            );
+            (None, Some(e.to_string()))
+        }
+    };
+    sqlx::query(
+        "INSERT INTO mirror_copies
+             (channel_id, message_id, mirror_id, mirror_message_id, attempts, last_error)
+         VALUES ($1, $2, $3, $4, 1, $5)
+         ON CONFLICT (channel_id, message_id, mirror_id) DO UPDATE SET
+             mirror_message_id = EXCLUDED.mirror_message_id,
+             attempts = mirror_copies.attempts + 1,
+             last_error = EXCLUDED.last_error,
+             updated_at = now()",
+    )
+    .bind(copy.channel_id)
+    .bind(copy.message_id)
+    .bind(copy.mirror_id)
+    .bind(mirror_message_id)
+    .bind(error)
+    .execute(&secrets.db)
+    .await?;
+    Ok(mirror_message_id.is_some())
+}
+
+/// Copies a post that just went out in the main channel to every mirror, right
+/// away so the mirrors keep its order. Skipped while cross-posting is switched off.
+pub async fn fan_out(secrets: &ServerSecretsState, channel_id: ChatId, message_id: MessageId) {
+    if !secrets.flags.is_enabled(Flag::CrossPosting) {
+        return;
+    }
+    for &mirror in &secrets.mirror_set.channels {
+        let pending = Copy {
+            channel_id: channel_id.0,
+            message_id: message_id.0,
+            mirror_id: mirror.0,
+            attempts: 0,
+        };
+        if let Err(e) = copy(secrets, &pending).await {
+            tracing::error!("Failed to record mirror copy: {}", e);
+        }
+    }
+}
+
+async fn failed(db: &PgPool) -> sqlx::Result<Vec<Copy>> {
+    sqlx::query_as(
+        "SELECT channel_id, message_id, mirror_id, attempts FROM mirror_copies
+         WHERE mirror_message_id IS NULL AND attempts < $1
+         ORDER BY channel_id, message_id",
+    )
+    .bind(MAX_ATTEMPTS)
+    .fetch_all(db)
+    .await
+}
+
+/// Tries failed copies again every few minutes, oldest post first so mirrors keep
+/// the main channel's order as far as possible.
+pub fn spawn_retries(secrets: Arc<ServerSecretsState>) {
+    if secrets.mirror_set.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            sleep(RETRY_EVERY).await;
+            if !secrets.flags.is_enabled(Flag::CrossPosting) {
+                continue;
+            }
+            let copies = match failed(&secrets.db).await {
+                Ok(copies) => copies,
+                Err(e) => {
+                    tracing::warn!("Failed to list failed mirror copies: {}", e);
+                    continue;
+                }
+            };
+            for pending in copies {
+                match copy(&secrets, &pending).await {
+                    Ok(true) => tracing::info!(
+                        "Copied post {} to mirror {} on retry",
+                        pending.message_id,
+                        pending.mirror_id
+                    ),
+                    Ok(false) => {}
+                    Err(e) => tracing::error!("Failed to record mirror copy: {}", e),
+                }
+            }
+        }
+    });
+}
+
+/// For `/status`: how far behind each mirror is, or `None` without mirrors.
+pub async fn status_line(secrets: &ServerSecretsState) -> Option<String> {
+    if secrets.mirror_set.is_empty() {
+        return None;
+    }
+    let counts: Vec<(i64, i64, i64)> = match sqlx::query_as(
+        "SELECT mirror_id,
+             COUNT(*) FILTER (WHERE mirror_message_id IS NULL AND attempts < $1),
+             COUNT(*) FILTER (WHERE mirror_message_id IS NULL AND attempts >= $1)
+         FROM mirror_copies GROUP BY mirror_id",
+    )
+    .bind(MAX_ATTEMPTS)
+    .fetch_all(&secrets.db)
+    .await
+    {
+        Ok(counts) => counts,
+        Err(e) => return Some(format!("⚠️ Mirrors: could not check ({})", e)),
+    };
+    let mirrors = secrets
+        .mirror_set
+        .channels
+        .iter()
+        .map(|mirror| {
+            let (retrying, given_up) = counts
+                .iter()
+                .find(|(id, _, _)| *id == mirror.0)
+                .map_or((0, 0), |&(_, retrying, given_up)| (retrying, given_up));
+            match (retrying, given_up) {
+                (0, 0) => format!("{} ok", mirror),
+                (retrying, 0) => format!("{} {} retrying", mirror, retrying),
+                (retrying, given_up) => {
+                    format!("{} {} retrying, {} given up", mirror, retrying, given_up)
+                }
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    Some(format!("🪞 Mirrors: {}", mirrors))
+}
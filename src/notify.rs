@@ -0,0 +1,146 @@
+use crate::catalog::{self, Track};
+use anyhow::{Context, bail};
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use shuttle_runtime::SecretStore;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const IFTTT_URL: &str = "https://maker.ifttt.com/trigger";
+
+enum Format {
+    /// `{"event": ..., "track": {...}}`, signed in `X-Ankh-Signature` when a key is set.
+    Json,
+    /// A single level of plain fields, with the key as a `key` field, which is what
+    /// Zapier catch hooks and similar no-code tools handle best.
+    Flat,
+    /// IFTTT Webhooks: the target is the IFTTT key and the event name becomes the
+    /// applet trigger, with `value1`..`value3` filled in.
+    Ifttt,
+}
+
+struct Target {
+    format: Format,
+    url: String,
+}
+
+/// Tells other services about new posts, configured with `OUTGOING_HOOKS` as
+/// `format=target;format=target` where format is `json`, `flat` or `ifttt`, and
+/// `OUTGOING_HOOKS_KEY` as the shared key.
+pub struct Notifier {
+    targets: Vec<Target>,
+    key: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Self> {
+        let mut targets = Vec::new();
+        for entry in secrets
+            .get("OUTGOING_HOOKS")
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (format, url) = entry
+                .split_once('=')
+                .context("OUTGOING_HOOKS entries must look like format=url")?;
+            let format = match format.trim() {
+                "json" => Format::Json,
+                "flat" => Format::Flat,
+                "ifttt" => Format::Ifttt,
+                other => bail!(
+                    "OUTGOING_HOOKS formats must be json, flat or ifttt, not {}",
+                    other
+                ),
+            };
+            targets.push(Target {
+                format,
+                url: url.trim().to_string(),
+            });
+        }
+        Ok(Self {
+            targets,
+            key: secrets.get("OUTGOING_HOOKS_KEY"),
+            client: reqwest::Client::builder().timeout(TIMEOUT).build()?,
+        })
+    }
+
+    fn signature(&self, body: &[u8]) -> Option<String> {
+        let key = self.key.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(body);
+        Some(format!(
+            "sha256={}",
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+
+    fn flat(&self, event: &str, track: &Track) -> Value {
+        let mut payload = json!({
+            "event": event,
+            "label": track.label(),
+            "title": track.title.clone().unwrap_or_default(),
+            "performer": track.performer.clone().unwrap_or_default(),
+            "album": track.album.clone().unwrap_or_default(),
+            "link": catalog::permalink(track.message_id),
+            "tags": hashtags(track),
+            "length": track.size_label(),
+            "posted_at": track.posted_at.to_rfc3339(),
+        });
+        if let Some(key) = &self.key {
+            payload["key"] = json!(key);
+        }
+        payload
+    }
+
+    /// Sends `event` for `track` to every target in the background, so a slow
+    /// receiver does not hold up the queue.
+    fn send(&self, event: &'static str, track: &Track) {
+        for target in &self.targets {
+            let request = match target.format {
+                Format::Json => {
+                    let body = json!({ "event": event, "track": track }).to_string();
+                    let mut request = self
+                        .client
+                        .post(&target.url)
+                        .header("Content-Type", "application/json");
+                    if let Some(signature) = self.signature(body.as_bytes()) {
+                        request = request.header("X-Ankh-Signature", signature);
+                    }
+                    request.body(body)
+                }
+                Format::Flat => self.client.post(&target.url).json(&self.flat(event, track)),
+                Format::Ifttt => self
+                    .client
+                    .post(format!("{}/{}/with/key/{}", IFTTT_URL, event, target.url))
+                    .json(&json!({
+                        "value1": track.label(),
+                        "value2": catalog::permalink(track.message_id),
+                        "value3": hashtags(track),
+                    })),
+            };
+            tokio::spawn(async move {
+                if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+                    tracing::warn!(event, "Outgoing hook failed: {}", e);
+                }
+            });
+        }
+    }
+
+    pub fn track_published(&self, track: &Track) {
+        self.send("track_published", track);
+    }
+}
+
+fn hashtags(track: &Track) -> String {
+    track
+        .tags
+        .iter()
+        .map(|tag| format!("#{}", tag))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,201 @@
+use crate::{ServerSecretsState, catalog, telegram::OutgoingText};
+use anyhow::{Context, bail};
+use serde_json::Value;
+use shuttle_runtime::SecretStore;
+use std::sync::atomic::Ordering;
+use teloxide::utils::markdown;
+use tokio::sync::Mutex;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The media server whose playback is shared, configured with `MEDIA_SERVER`
+/// (`jellyfin` or `navidrome`) and `MEDIA_SERVER_URL`. Jellyfin takes an API key in
+/// `MEDIA_SERVER_TOKEN`; Navidrome, speaking the Subsonic API, takes
+/// `MEDIA_SERVER_USER` and `MEDIA_SERVER_PASSWORD`.
+pub enum MediaServer {
+    Jellyfin {
+        url: String,
+        api_key: String,
+    },
+    Navidrome {
+        url: String,
+        user: String,
+        password: String,
+    },
+}
+
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+impl NowPlaying {
+    fn label(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} – {}", artist, self.title),
+            None => self.title.clone(),
+        }
+    }
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+impl MediaServer {
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Option<Self>> {
+        let Some(kind) = secrets.get("MEDIA_SERVER") else {
+            return Ok(None);
+        };
+        let url = secrets
+            .get("MEDIA_SERVER_URL")
+            .context("MEDIA_SERVER_URL must be set with MEDIA_SERVER")?
+            .trim_end_matches('/')
+            .to_string();
+        match kind.trim() {
+            "jellyfin" => Ok(Some(MediaServer::Jellyfin {
+                url,
+                api_key: secrets
+                    .get("MEDIA_SERVER_TOKEN")
+                    .context("MEDIA_SERVER_TOKEN must be set for Jellyfin")?,
+            })),
+            "navidrome" => Ok(Some(MediaServer::Navidrome {
+                url,
+                user: secrets
+                    .get("MEDIA_SERVER_USER")
+                    .context("MEDIA_SERVER_USER must be set for Navidrome")?,
+                password: secrets
+                    .get("MEDIA_SERVER_PASSWORD")
+                    .context("MEDIA_SERVER_PASSWORD must be set for Navidrome")?,
+            })),
+            other => bail!("MEDIA_SERVER must be jellyfin or navidrome, not {}", other),
+        }
+    }
+
+    /// Asks the server what is playing right now, taking the first audio session.
+    pub async fn now_playing(&self) -> Result<Option<NowPlaying>, Error> {
+        let client = reqwest::Client::new();
+        match self {
+            MediaServer::Jellyfin { url, api_key } => {
+                let sessions: Value = client
+                    .get(format!("{}/Sessions", url))
+                    .query(&[("ActiveWithinSeconds", "600")])
+                    .header("X-Emby-Token", api_key)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                Ok(sessions
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|session| &session["NowPlayingItem"])
+                    .find(|item| item["MediaType"] == "Audio")
+                    .and_then(|item| {
+                        Some(NowPlaying {
+                            title: string(&item["Name"])?,
+                            artist: item["Artists"]
+                                .as_array()
+                                .and_then(|artists| artists.first())
+                                .and_then(string)
+                                .or_else(|| string(&item["AlbumArtist"])),
+                            album: string(&item["Album"]),
+                        })
+                    }))
+            }
+            MediaServer::Navidrome {
+                url,
+                user,
+                password,
+            } => {
+                let response: Value = client
+                    .get(format!("{}/rest/getNowPlaying", url))
+                    .query(&[
+                        ("u", user.as_str()),
+                        ("p", &format!("enc:{}", hex::encode(password))),
+                        ("v", "1.16.1"),
+                        ("c", "ankh"),
+                        ("f", "json"),
+                    ])
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                let response = &response["subsonic-response"];
+                if response["status"] != "ok" {
+                    return Err(format!("Navidrome said: {}", response["error"]["message"]).into());
+                }
+                Ok(response["nowPlaying"]["entry"]
+                    .as_array()
+                    .and_then(|entries| entries.first())
+                    .and_then(|entry| {
+                        Some(NowPlaying {
+                            title: string(&entry["title"])?,
+                            artist: string(&entry["artist"]),
+                            album: string(&entry["album"]),
+                        })
+                    }))
+            }
+        }
+    }
+}
+
+/// Remembers the last thing shared so a stalled player does not get posted twice.
+#[derive(Default)]
+pub struct LastShared(Mutex<Option<String>>);
+
+/// "🎧 Currently spinning: Artist – Title", linking to the channel post when the
+/// track is in the catalog.
+async fn render(secrets: &ServerSecretsState, playing: &NowPlaying) -> String {
+    let label = playing.label();
+    let in_catalog = catalog::search(&secrets.db, &label, 1)
+        .await
+        .inspect_err(|e| tracing::warn!("Failed to look up now playing track: {}", e))
+        .ok()
+        .and_then(|tracks| tracks.into_iter().next());
+
+    let mut text = format!("🎧 Currently spinning: *{}*", markdown::escape(&label));
+    if let Some(album) = &playing.album {
+        text.push_str(&format!("\n💿 {}", markdown::escape(album)));
+    }
+    if let Some(track) = in_catalog {
+        text.push_str(&format!(
+            "\n\n{}",
+            markdown::link(&catalog::permalink(track.message_id), "Listen here")
+        ));
+    }
+    text
+}
+
+/// Posts what the media server is playing to the channel. Returns what was posted,
+/// or `None` when nothing is playing or it was already shared.
+pub async fn share(secrets: &ServerSecretsState) -> Result<Option<String>, Error> {
+    let Some(server) = &secrets.media_server else {
+        return Err("No media server configured".into());
+    };
+    let Some(playing) = server.now_playing().await? else {
+        return Ok(None);
+    };
+
+    let label = playing.label();
+    let mut last = secrets.now_playing_shared.0.lock().await;
+    if last.as_deref() == Some(label.as_str()) {
+        return Ok(None);
+    }
+
+    let channel_id = secrets.publish_channel_id();
+    let text = OutgoingText::markdown(render(secrets, &playing).await).without_link_preview();
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_message(channel_id, &text))
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+
+    *last = Some(label.clone());
+    Ok(Some(label))
+}
@@ -0,0 +1,129 @@
+use crate::{ServerSecretsState, catalog, telegram::OutgoingText};
+use anyhow::bail;
+use chrono::{Datelike, NaiveDate};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+    utils::markdown,
+};
+
+pub const CALLBACK_PREFIX: &str = "otd:";
+
+#[derive(Clone, Copy)]
+pub enum Mode {
+    /// Post the throwback to the channel straight away.
+    Publish,
+    /// DM the owner a draft with publish/skip buttons.
+    Approve,
+}
+
+impl Mode {
+    /// Parses the `ON_THIS_DAY` secret; the job is opt-in, so a missing value or
+    /// `off` disables it.
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match raw.map(str::trim) {
+            None | Some("") | Some("off") => Ok(None),
+            Some("publish") => Ok(Some(Mode::Publish)),
+            Some("approve") => Ok(Some(Mode::Approve)),
+            Some(other) => bail!("ON_THIS_DAY must be off, publish or approve, not {}", other),
+        }
+    }
+}
+
+fn render(date: NaiveDate, tracks: &[catalog::Track]) -> String {
+    let mut text = format!(
+        "🕰 *{}*\n",
+        markdown::escape(&format!("On this day, {}", date.format("%B %-d")))
+    );
+    let mut year = None;
+    for track in tracks {
+        if year != Some(track.posted_at.year()) {
+            year = Some(track.posted_at.year());
+            text.push_str(&format!("\n*{}*\n", track.posted_at.year()));
+        }
+        text.push_str(&markdown::link(
+            &markdown::escape_link_url(&catalog::permalink(track.message_id)),
+            &markdown::escape(&track.label()),
+        ));
+        text.push('\n');
+    }
+    text
+}
+
+async fn publish(
+    secrets: &ServerSecretsState,
+    text: String,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    secrets
+        .telegram
+        .send_message(secrets.publish_channel_id(), &OutgoingText::markdown(text))
+        .await?;
+    Ok(())
+}
+
+/// Builds the throwback post for `date` and publishes it or asks for approval,
+/// depending on `mode`. Does nothing when no earlier track was posted on that day.
+pub async fn run(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    mode: Mode,
+    date: NaiveDate,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let tracks = catalog::posted_on_this_day(&secrets.db, date).await?;
+    if tracks.is_empty() {
+        tracing::info!(
+            "No tracks posted on {} in earlier years",
+            date.format("%m-%d")
+        );
+        return Ok(());
+    }
+
+    let text = render(date, &tracks);
+    match mode {
+        Mode::Publish => publish(secrets, text).await?,
+        Mode::Approve => {
+            let keyboard = InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback(
+                    "Publish",
+                    format!("{}publish:{}", CALLBACK_PREFIX, date),
+                ),
+                InlineKeyboardButton::callback("Skip", format!("{}skip:{}", CALLBACK_PREFIX, date)),
+            ]]);
+            bot.send_message(secrets.me_id, text)
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_markup(keyboard)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles the publish/skip buttons of an approval request.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (action, date) = data.split_once(':').ok_or("Malformed callback data")?;
+    let date: NaiveDate = date.parse()?;
+
+    let status = match action {
+        "publish" => {
+            let tracks = catalog::posted_on_this_day(&secrets.db, date).await?;
+            publish(secrets, render(date, &tracks)).await?;
+            "Published ✅"
+        }
+        "skip" => "Skipped",
+        _ => return Err("Unknown on-this-day action".into()),
+    };
+
+    bot.answer_callback_query(query.id.clone())
+        .text(status)
+        .await?;
+    if let Some(message) = query.regular_message() {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use crate::{ServerSecretsState, cover, media, waveform};
+use anyhow::Context;
+use teloxide::{prelude::*, types::Audio};
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The most Stars the Bot API lets a post ask for.
+const MAX_STARS: u32 = 10_000;
+/// The hashtag that marks an upload as paid, as `#paid` or with its price, `#paid250`.
+const TAG: &str = "paid";
+
+/// Parses `PAID_MEDIA_STARS`, the price of uploads tagged just `#paid`. Unset means
+/// they have to name their price.
+pub fn stars_from_secret(raw: Option<&str>) -> anyhow::Result<Option<u32>> {
+    raw.map(|raw| {
+        raw.trim()
+            .parse()
+            .ok()
+            .filter(|stars| (1..=MAX_STARS).contains(stars))
+            .with_context(|| format!("PAID_MEDIA_STARS must be between 1 and {}", MAX_STARS))
+    })
+    .transpose()
+}
+
+/// Takes the `#paid` hashtag out of an upload's tags and returns the price it asks
+/// for, falling back to `PAID_MEDIA_STARS`.
+pub fn take(tags: &mut Vec<String>, default: Option<u32>) -> Option<u32> {
+    let index = tags.iter().position(|tag| {
+        tag.strip_prefix(TAG)
+            .is_some_and(|price| price.chars().all(|c| c.is_ascii_digit()))
+    })?;
+    let tag = tags.remove(index);
+    let stars = match &tag[TAG.len()..] {
+        "" => default,
+        price => price.parse().ok(),
+    };
+    let stars = stars.filter(|stars| (1..=MAX_STARS).contains(stars));
+    if stars.is_none() {
+        tracing::warn!("#{} names no usable price; posting for free", tag);
+    }
+    stars
+}
+
+/// Paid posts can only be photos or videos, so the track is posted as a video of
+/// its cover art, or its waveform for want of one, with the audio as the soundtrack.
+/// `processed` is the file to use when it has been through `PROCESS_COMMAND`.
+pub async fn video(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    audio: &Audio,
+    processed: Option<&media::LocalFile>,
+) -> Result<media::LocalFile, Error> {
+    let fetched;
+    let input = match processed {
+        Some(file) => file,
+        None => {
+            fetched = media::fetch(bot, secrets, &audio.file).await?;
+            &fetched
+        }
+    };
+    let picture = match cover::extract(bot, secrets, audio).await {
+        Ok(Some(path)) => Some(path),
+        Ok(None) => None,
+        Err(e) => {
+            tracing::warn!("Paid video without cover art: {}", e);
+            None
+        }
+    };
+    let picture = match picture {
+        Some(path) => Some(path),
+        None => waveform::render(bot, secrets, audio, waveform::Mode::Photo)
+            .await
+            .inspect_err(|e| tracing::warn!("Paid video without a waveform: {}", e))
+            .ok(),
+    };
+
+    let output = media::LocalFile::scratch(&format!("{}-paid.mp4", audio.file.unique_id.0));
+    let mut command = Command::new("ffmpeg");
+    command.args(["-y", "-v", "error"]);
+    match &picture {
+        Some(path) => command
+            .args(["-loop", "1", "-framerate", "1", "-i"])
+            .arg(path),
+        None => command.args(["-f", "lavfi", "-i", "color=c=black:s=640x640:r=1"]),
+    };
+    media::run(
+        command
+            .arg("-i")
+            .arg(&input.path)
+            .args(["-map", "0:v", "-map", "1:a", "-shortest"])
+            // H.264 wants even dimensions.
+            .args(["-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2,format=yuv420p"])
+            .args(["-c:v", "libx264", "-tune", "stillimage"])
+            .args(["-c:a", "aac", "-b:a", "192k", "-movflags", "+faststart"])
+            .arg(&output.path),
+    )
+    .await?;
+
+    let size = tokio::fs::metadata(&output.path).await?.len();
+    if size > secrets.bot_api_mode.upload_limit() {
+        return Err(format!(
+            "The paid video is {} MB, over the Bot API upload limit",
+            size / (1024 * 1024)
+        )
+        .into());
+    }
+    Ok(output)
+}
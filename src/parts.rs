@@ -0,0 +1,123 @@
+use crate::catalog::Track;
+use sqlx::PgPool;
+
+/// The hashtag that marks an upload as a part of a longer mix, `#part2`, for titles
+/// that do not say so themselves.
+const TAG: &str = "part";
+
+/// Where a track sits in a mix split into parts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    /// The title without its part marker, shared by all the parts.
+    pub base: String,
+    pub number: i32,
+}
+
+const ROMAN: [&str; 10] = ["i", "ii", "iii", "iv", "v", "vi", "vii", "viii", "ix", "x"];
+
+/// The number in "Part 2", "Pt. II", "pt2", "Part 2 of 4" or "2/4". A bare number
+/// does not count, or every "Symphony No. 5" would be a part.
+fn marker_number(marker: &str) -> Option<i32> {
+    let marker = marker.trim().to_lowercase();
+    let (named, rest) = match ["part", "pt.", "pt"]
+        .iter()
+        .find_map(|prefix| marker.strip_prefix(prefix))
+    {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, marker.as_str()),
+    };
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    let after = rest[digits.len()..].trim();
+    let number = if !digits.is_empty() {
+        let total = after
+            .strip_prefix('/')
+            .or_else(|| after.strip_prefix("of"))
+            .map(str::trim);
+        match total {
+            Some(total) if total.chars().all(|c| c.is_ascii_digit()) && !total.is_empty() => {}
+            None if named && after.is_empty() => {}
+            _ => return None,
+        }
+        digits.parse().ok()?
+    } else if named {
+        ROMAN.iter().position(|roman| *roman == rest)? as i32 + 1
+    } else {
+        return None;
+    };
+    (number > 0).then_some(number)
+}
+
+/// "Mix" and "Part 2" from "Mix (Part 2)" or "Mix [Part 2]".
+fn bracketed(title: &str) -> Option<(&str, &str)> {
+    let (rest, open) = match title.strip_suffix(')') {
+        Some(rest) => (rest, '('),
+        None => (title.strip_suffix(']')?, '['),
+    };
+    let at = rest.rfind(open)?;
+    Some((&rest[..at], &rest[at + 1..]))
+}
+
+/// Reads a part marker off the end of `title`: "Mix (Part 2)", "Mix [2/4]",
+/// "Mix - Pt. 2", "Mix, part II".
+pub fn detect(title: &str) -> Option<Part> {
+    let title = title.trim();
+    let (base, number) = match bracketed(title) {
+        Some((base, marker)) => (base, marker_number(marker)?),
+        None => {
+            // ASCII lowercasing keeps byte offsets, so they still point into `title`.
+            let lower = title.to_ascii_lowercase();
+            let at = [" part", " pt"]
+                .iter()
+                .filter_map(|marker| lower.rfind(marker))
+                .max()?;
+            (&title[..at], marker_number(&title[at..])?)
+        }
+    };
+    let base = base
+        .trim_end_matches(|c: char| c.is_whitespace() || "-–—,:|".contains(c))
+        .to_string();
+    (!base.is_empty()).then_some(Part { base, number })
+}
+
+/// Takes a `#part2` hashtag out of an upload's tags and returns its number.
+pub fn take(tags: &mut Vec<String>) -> Option<i32> {
+    let index = tags.iter().position(|tag| {
+        tag.strip_prefix(TAG)
+            .is_some_and(|number| number.parse::<i32>().is_ok_and(|number| number > 0))
+    })?;
+    tags.remove(index)[TAG.len()..].parse().ok()
+}
+
+/// The part a track titled `title` is: the number marked by hand if there is one,
+/// otherwise what the title says.
+pub fn of(marked: Option<i32>, title: Option<&str>) -> Option<Part> {
+    let title = title?;
+    let detected = detect(title);
+    match marked {
+        Some(number) => Some(Part {
+            base: detected.map_or_else(|| title.trim().to_string(), |part| part.base),
+            number,
+        }),
+        None => detected,
+    }
+}
+
+/// The first part of the mix `part` belongs to, by the same performer: a track marked
+/// part 1, or one titled just like the others without a marker.
+pub async fn first(
+    db: &PgPool,
+    performer: Option<&str>,
+    part: &Part,
+) -> sqlx::Result<Option<Track>> {
+    sqlx::query_as(
+        "SELECT * FROM tracks
+         WHERE deleted_at IS NULL AND performer IS NOT DISTINCT FROM $2
+             AND ((part_number = 1 AND lower(part_base) = lower($1))
+                 OR (part_number IS NULL AND lower(title) = lower($1)))
+         ORDER BY part_number NULLS LAST, posted_at DESC LIMIT 1",
+    )
+    .bind(&part.base)
+    .bind(performer)
+    .fetch_optional(db)
+    .await
+}
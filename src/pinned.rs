@@ -0,0 +1,114 @@
+use crate::{ServerSecretsState, catalog, dry_run, settings, telegram::OutgoingText};
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use teloxide::{ApiError, RequestError, types::MessageId, utils::markdown};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const SETTING: &str = "pinned_post";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Show the latest track.
+    Latest,
+    /// Also show the first track waiting in the queue.
+    WithNext,
+}
+
+impl Mode {
+    /// Parses the `PINNED_POST` secret; a missing value or `off` leaves pins alone.
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match raw.map(str::trim) {
+            None | Some("") | Some("off") => Ok(None),
+            Some("latest") => Ok(Some(Mode::Latest)),
+            Some("next") => Ok(Some(Mode::WithNext)),
+            Some(other) => bail!("PINNED_POST must be off, latest or next, not {}", other),
+        }
+    }
+}
+
+/// The one message the bot keeps pinned in the channel.
+#[derive(Serialize, Deserialize)]
+struct PinnedPost {
+    channel_id: i64,
+    message_id: i32,
+}
+
+async fn render(secrets: &ServerSecretsState, mode: Mode) -> Result<Option<String>, Error> {
+    let Some(latest) = catalog::list_tracks(&secrets.db, &Default::default(), 1, 0)
+        .await?
+        .pop()
+    else {
+        return Ok(None);
+    };
+    let mut text = format!(
+        "📌 Latest: {}",
+        markdown::link(
+            &markdown::escape_link_url(&catalog::permalink(latest.message_id)),
+            &markdown::escape(&latest.label()),
+        )
+    );
+    if mode == Mode::WithNext
+        && let Some(next) = secrets.message_queue.snapshot().await.first()
+    {
+        let label = match (&next.audio.performer, &next.audio.title) {
+            (Some(performer), Some(title)) => format!("{} – {}", performer, title),
+            (_, Some(title)) => title.clone(),
+            _ => next.audio.file_name.clone().unwrap_or_default(),
+        };
+        if !label.is_empty() {
+            text.push_str(&format!("\n⏭ Up next: {}", markdown::escape(&label)));
+        }
+    }
+    Ok(Some(text))
+}
+
+/// Edits the pinned post to show the latest track, posting and pinning a new one
+/// when there is none in the current channel or it can no longer be edited.
+pub async fn refresh(secrets: &ServerSecretsState, mode: Mode) -> Result<(), Error> {
+    let Some(text) = render(secrets, mode).await? else {
+        return Ok(());
+    };
+    let channel_id = secrets.publish_channel_id();
+
+    let text = OutgoingText::markdown(text).without_link_preview();
+    let pinned: Option<PinnedPost> = settings::get(&secrets.db, SETTING).await?;
+    if let Some(pinned) = pinned.filter(|pinned| pinned.channel_id == channel_id.0) {
+        match secrets
+            .telegram
+            .edit_text(channel_id, MessageId(pinned.message_id), &text)
+            .await
+        {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => return Ok(()),
+            Err(RequestError::Api(
+                ApiError::MessageToEditNotFound | ApiError::MessageCantBeEdited,
+            )) => tracing::info!("Pinned post is gone, posting a new one"),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let text = text.silent();
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_message(channel_id, &text))
+        .await?;
+    secrets.telegram.pin_message(channel_id, message.id).await?;
+    if dry_run::is_active(secrets) {
+        return Ok(());
+    }
+    // Pinning posts a service message right after the pinned one.
+    secrets
+        .last_message_id
+        .store(message.id.0 + 1, Ordering::Relaxed);
+    settings::set(
+        &secrets.db,
+        SETTING,
+        &PinnedPost {
+            channel_id: channel_id.0,
+            message_id: message.id.0,
+        },
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use crate::{QueuedMessage, ServerSecretsState, analysis, cover, hashtags, media, waveform};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use teloxide::prelude::*;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore, oneshot},
+    task::JoinHandle,
+};
+
+/// How many tracks of a batch are prepared at once unless `PREPARE_CONCURRENCY` says
+/// otherwise.
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Parses `PREPARE_CONCURRENCY`; 1 prepares one track at a time, still ahead of
+/// publishing.
+pub fn concurrency_from_secret(raw: Option<&str>) -> anyhow::Result<usize> {
+    match raw.map(str::trim).filter(|raw| !raw.is_empty()) {
+        Some(raw) => raw
+            .parse()
+            .ok()
+            .filter(|concurrency| *concurrency >= 1)
+            .context("PREPARE_CONCURRENCY must be a number of tracks, at least 1"),
+        None => Ok(DEFAULT_CONCURRENCY),
+    }
+}
+
+/// The slow part of posting a track, done ahead of time. Anything missing is done
+/// when the track is published, as it would be without preparing.
+#[derive(Default)]
+pub struct Prepared {
+    /// What `PROCESS_COMMAND` made of the file. `None` when it failed, so publishing
+    /// runs it again and fails the track the usual way.
+    pub processed: Option<Option<media::LocalFile>>,
+    pub analyzed: Option<analysis::Analysis>,
+    /// The file's own tags, for `AUTO_HASHTAGS`.
+    pub file_tags: Option<HashMap<String, String>>,
+}
+
+async fn prepare(bot: &Bot, secrets: &ServerSecretsState, message: &QueuedMessage) -> Prepared {
+    let audio = &message.audio;
+    let processed = media::process(bot, secrets, &audio.file, audio.file_name.as_deref())
+        .await
+        .inspect_err(|e| tracing::warn!("Failed to process ahead of publishing: {}", e))
+        .ok();
+
+    // Covers and waveforms are cached on disk, so rendering them now is enough.
+    cover::thumbnail(bot, secrets, audio).await;
+    if secrets.waveform == Some(waveform::Mode::Photo)
+        && let Err(e) = waveform::render(bot, secrets, audio, waveform::Mode::Photo).await
+    {
+        tracing::warn!("Failed to render waveform ahead of publishing: {}", e);
+    }
+
+    let processed_file = processed.as_ref().and_then(Option::as_ref);
+    let analyzed = if secrets.audio_analysis {
+        match processed_file {
+            Some(file) => Some(analysis::analyze(file).await),
+            None => match media::fetch(bot, secrets, &audio.file).await {
+                Ok(file) => Some(analysis::analyze(&file).await),
+                Err(e) => {
+                    tracing::warn!("Failed to fetch for analysis ahead of publishing: {}", e);
+                    None
+                }
+            },
+        }
+    } else {
+        None
+    };
+    let file_tags = Some(hashtags::probe(bot, secrets, audio, processed_file).await);
+
+    Prepared {
+        processed,
+        analyzed,
+        file_tags,
+    }
+}
+
+/// The tracks of a batch being prepared, by queued message id. Preparing runs
+/// alongside publishing, in queue order and at most `PREPARE_CONCURRENCY` tracks
+/// ahead of it: a track holds its permit until it is taken, so prepared files do not
+/// pile up in the temp dir while publishing is paced. The tracks themselves still go
+/// out one by one.
+pub struct Batch {
+    prepared: Mutex<HashMap<i32, oneshot::Receiver<(Prepared, OwnedSemaphorePermit)>>>,
+    /// Hands out the permits, so no track starts before the ones ahead of it.
+    driver: JoinHandle<()>,
+}
+
+impl Batch {
+    pub fn start(
+        bot: &Arc<Bot>,
+        secrets: &Arc<ServerSecretsState>,
+        messages: &[QueuedMessage],
+    ) -> Self {
+        let mut prepared = HashMap::new();
+        let mut jobs = Vec::new();
+        for message in messages {
+            let (tx, rx) = oneshot::channel();
+            prepared.insert(message.message_id, rx);
+            jobs.push((message.clone(), tx));
+        }
+
+        let permits = Arc::new(Semaphore::new(secrets.prepare_concurrency));
+        let bot = bot.clone();
+        let secrets = secrets.clone();
+        let driver = tokio::spawn(async move {
+            for (message, tx) in jobs {
+                let Ok(permit) = permits.clone().acquire_owned().await else {
+                    return;
+                };
+                let bot = bot.clone();
+                let secrets = secrets.clone();
+                tokio::spawn(async move {
+                    let prepared = prepare(&bot, &secrets, &message).await;
+                    // Nobody is waiting when the batch is over already, and then the
+                    // permit goes with the unsent value.
+                    let _ = tx.send((prepared, permit));
+                });
+            }
+        });
+        Batch {
+            prepared: Mutex::new(prepared),
+            driver,
+        }
+    }
+
+    /// Waits for the track queued as `message_id` to be prepared, which lets the
+    /// next one start.
+    pub async fn take(&self, message_id: i32) -> Prepared {
+        let rx = self
+            .prepared
+            .lock()
+            .expect("prepared batch poisoned")
+            .remove(&message_id);
+        match rx {
+            Some(rx) => match rx.await {
+                Ok((prepared, _permit)) => prepared,
+                Err(_) => {
+                    tracing::warn!("Preparing track {} failed", message_id);
+                    Prepared::default()
+                }
+            },
+            None => Prepared::default(),
+        }
+    }
+}
+
+impl Drop for Batch {
+    /// Tracks that were not published, say because the batch was cut short, are not
+    /// started on; the ones under way finish on their own.
+    fn drop(&mut self) {
+        self.driver.abort();
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{ServerSecretsState, catalog, media};
+use teloxide::{
+    prelude::*,
+    types::{Audio, InlineKeyboardButton, InlineKeyboardMarkup, InputFile},
+};
+use tokio::process::Command;
+
+const CLIP_SECONDS: u32 = 30;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub fn keyboard(track_message_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[InlineKeyboardButton::url(
+        "▶️ Full track",
+        catalog::permalink(track_message_id)
+            .parse()
+            .expect("permalinks are valid URLs"),
+    )]])
+}
+
+/// Cuts a 30-second clip out of the track with ffmpeg. Tracks long enough to have
+/// an intro are clipped from a third of the way in, where the hook usually is.
+pub async fn make_clip(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    audio: &Audio,
+) -> Result<media::LocalFile, Error> {
+    let input = media::fetch(bot, secrets, &audio.file).await?;
+    let clip = media::LocalFile::scratch(&format!("{}-preview.mp3", audio.file.unique_id.0));
+
+    let duration = audio.duration.seconds();
+    let start = if duration > CLIP_SECONDS * 2 {
+        duration / 3
+    } else {
+        0
+    };
+
+    media::run(
+        Command::new("ffmpeg")
+            .args(["-y", "-v", "error", "-ss", &start.to_string()])
+            .args(["-t", &CLIP_SECONDS.to_string(), "-i"])
+            .arg(&input.path)
+            .args(["-vn", "-c:a", "libmp3lame", "-b:a", "128k"])
+            .arg(&clip.path),
+    )
+    .await?;
+
+    Ok(clip)
+}
+
+/// Posts `clip` to `chat` with a button pointing at the full track.
+pub async fn post(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    chat: ChatId,
+    clip: &media::LocalFile,
+    audio: &Audio,
+    track_message_id: i32,
+) -> Result<Message, Error> {
+    let title = format!("{} (preview)", audio.title.as_deref().unwrap_or("Untitled"));
+    Ok(secrets
+        .retry_policy
+        .run(|| {
+            let mut request = bot
+                .send_audio(
+                    chat,
+                    InputFile::file(clip.path.clone()).file_name("preview.mp3"),
+                )
+                .title(title.clone())
+                .duration(CLIP_SECONDS)
+                .reply_markup(keyboard(track_message_id));
+            if let Some(performer) = &audio.performer {
+                request = request.performer(performer.clone());
+            }
+            request.send()
+        })
+        .await?)
+}
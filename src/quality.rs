@@ -0,0 +1,220 @@
+use crate::{QueuedMessage, ServerSecretsState, blurbs, cleanup, media};
+use anyhow::{Context, bail};
+use shuttle_runtime::SecretStore;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "qual:";
+
+/// What happens to an upload under the thresholds, configured with `QUALITY_GATE`.
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    /// `reject`: it is not queued; the owner is told why.
+    Reject,
+    /// `confirm`: it is held until the owner queues it anyway or drops it. The default.
+    Confirm,
+}
+
+/// The least an upload has to be to go out, measured on the downloaded file:
+/// `MIN_BITRATE_KBPS` and `MIN_SAMPLE_RATE_HZ`. Files too big for the Bot API to hand
+/// over, or that cannot be probed, are let through.
+pub struct Gate {
+    min_kbps: Option<u32>,
+    min_hz: Option<u32>,
+    action: Action,
+}
+
+impl Gate {
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Option<Self>> {
+        let min_kbps = secrets
+            .get("MIN_BITRATE_KBPS")
+            .map(|raw| raw.trim().parse())
+            .transpose()
+            .context("MIN_BITRATE_KBPS must be a number of kbps")?;
+        let min_hz = secrets
+            .get("MIN_SAMPLE_RATE_HZ")
+            .map(|raw| raw.trim().parse())
+            .transpose()
+            .context("MIN_SAMPLE_RATE_HZ must be a number of Hz")?;
+        let action = match secrets.get("QUALITY_GATE").as_deref().map(str::trim) {
+            None | Some("") | Some("confirm") => Action::Confirm,
+            Some("reject") => Action::Reject,
+            Some(other) => bail!("QUALITY_GATE must be reject or confirm, not {}", other),
+        };
+        if min_kbps.is_none() && min_hz.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Gate {
+            min_kbps,
+            min_hz,
+            action,
+        }))
+    }
+
+    /// Why a file of `kbps` and `hz` falls short, if it does.
+    fn shortfall(&self, kbps: Option<u32>, hz: Option<u32>) -> Option<String> {
+        let mut reasons = Vec::new();
+        if let (Some(min), Some(kbps)) = (self.min_kbps, kbps)
+            && kbps < min
+        {
+            reasons.push(format!("{} kbps, under the {} kbps minimum", kbps, min));
+        }
+        if let (Some(min), Some(hz)) = (self.min_hz, hz)
+            && hz < min
+        {
+            reasons.push(format!("{} Hz, under the {} Hz minimum", hz, min));
+        }
+        (!reasons.is_empty()).then(|| reasons.join("; "))
+    }
+}
+
+/// Uploads held for the owner to confirm, by the id of the owner's message. Kept in
+/// memory only: after a restart the file just has to be sent again.
+#[derive(Default)]
+pub struct Pending(Mutex<HashMap<i32, QueuedMessage>>);
+
+async fn measure(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    message: &QueuedMessage,
+) -> Result<(Option<u32>, Option<u32>), Error> {
+    let local = media::fetch(bot, secrets, &message.audio.file).await?;
+    let kbps = media::probe_bitrate(&local.path)
+        .await
+        .inspect_err(|e| tracing::warn!("Failed to probe bitrate: {}", e))
+        .ok();
+    let hz = media::probe_sample_rate(&local.path)
+        .await
+        .inspect_err(|e| tracing::warn!("Failed to probe sample rate: {}", e))
+        .ok();
+    Ok((kbps, hz))
+}
+
+fn keyboard(message_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            "Queue it anyway",
+            format!("{}queue:{}", CALLBACK_PREFIX, message_id),
+        ),
+        InlineKeyboardButton::callback(
+            "Drop it",
+            format!("{}drop:{}", CALLBACK_PREFIX, message_id),
+        ),
+    ]])
+}
+
+/// Checks an upload against `QUALITY_GATE`, returning it when it may be queued. One
+/// that falls short is rejected with a reply saying why, or held with buttons to
+/// queue it anyway.
+pub async fn check(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    chat_id: ChatId,
+    message: QueuedMessage,
+) -> Result<Option<QueuedMessage>, Error> {
+    let Some(gate) = &secrets.quality_gate else {
+        return Ok(Some(message));
+    };
+    if !secrets
+        .bot_api_mode
+        .can_download(message.audio.file.size.into())
+    {
+        return Ok(Some(message));
+    }
+    let (kbps, hz) = match measure(bot, secrets, &message).await {
+        Ok(measured) => measured,
+        Err(e) => {
+            tracing::warn!("Letting an unmeasured upload through: {}", e);
+            return Ok(Some(message));
+        }
+    };
+    let Some(shortfall) = gate.shortfall(kbps, hz) else {
+        return Ok(Some(message));
+    };
+
+    let name = message
+        .audio
+        .title
+        .clone()
+        .or_else(|| message.audio.file_name.clone())
+        .unwrap_or_else(|| "This upload".to_string());
+    tracing::info!("{} is below the quality gate: {}", name, shortfall);
+    match gate.action {
+        Action::Reject => {
+            cleanup::reply(
+                bot,
+                secrets,
+                chat_id,
+                format!("🎚 {} was not queued: {}.", name, shortfall),
+            )
+            .await?;
+        }
+        Action::Confirm => {
+            let message_id = message.message_id;
+            secrets
+                .quality_pending
+                .0
+                .lock()
+                .expect("pending quality checks poisoned")
+                .insert(message_id, message);
+            bot.send_message(
+                chat_id,
+                format!("🎚 {} is {}. Queue it anyway?", name, shortfall),
+            )
+            .reply_markup(keyboard(message_id))
+            .await?;
+        }
+    }
+    Ok(None)
+}
+
+/// The owner's answer about a held upload: queues it past the gate, or drops it.
+pub async fn handle_callback(
+    bot: &Arc<Bot>,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let (action, message_id) = data.split_once(':').ok_or("Malformed callback data")?;
+    let message_id: i32 = message_id.parse()?;
+    if !matches!(action, "queue" | "drop") {
+        return Err("Unknown quality gate action".into());
+    }
+    let pending = secrets
+        .quality_pending
+        .0
+        .lock()
+        .expect("pending quality checks poisoned")
+        .remove(&message_id);
+    let Some(message) = pending else {
+        bot.answer_callback_query(query.id.clone())
+            .text("Already answered, or the bot restarted since; send the file again")
+            .await?;
+        return Ok(());
+    };
+
+    let status = if action == "queue" {
+        "Queuing it anyway."
+    } else {
+        "Dropped."
+    };
+    bot.answer_callback_query(query.id.clone()).await?;
+    let chat_id = match query.regular_message() {
+        Some(question) => {
+            bot.edit_message_text(question.chat.id, question.id, status)
+                .await?;
+            question.chat.id
+        }
+        None => secrets.me_id,
+    };
+    if action == "queue" {
+        blurbs::queue_checked(bot, secrets, chat_id, message).await?;
+    }
+    Ok(())
+}
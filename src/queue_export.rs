@@ -0,0 +1,118 @@
+use crate::{QueuePosition, QueuedMessage, ServerSecretsState, cleanup, media, vacation};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use teloxide::{prelude::*, types::InputFile};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The queue as sent by `/exportqueue`. File ids only work for the bot that saw the
+/// files, so a snapshot can move between deployments of one bot but not between bots
+/// with different tokens.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    exported_at: DateTime<Utc>,
+    /// Whether the queue was paused or on vacation, making the times meaningless.
+    held: bool,
+    items: Vec<Item>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Item {
+    #[serde(flatten)]
+    message: QueuedMessage,
+    /// When the item would have gone out; informational, ignored on import.
+    #[serde(default)]
+    estimated_publish_time: Option<DateTime<Utc>>,
+}
+
+/// `/exportqueue`: sends the queue as a JSON document.
+pub async fn export(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let messages = secrets.message_queue.snapshot().await;
+    if messages.is_empty() {
+        cleanup::reply(bot, secrets, message.chat.id, "The queue is empty.").await?;
+        return Ok(());
+    }
+
+    let queue_len = messages.len();
+    let snapshot = Snapshot {
+        exported_at: Utc::now(),
+        held: secrets.message_queue.is_paused() || vacation::is_active(secrets),
+        items: messages
+            .into_iter()
+            .enumerate()
+            .map(|(i, message)| Item {
+                message,
+                estimated_publish_time: Some(
+                    QueuePosition {
+                        position: i + 1,
+                        queue_len,
+                    }
+                    .estimated_publish_time(),
+                ),
+            })
+            .collect(),
+    };
+    let file_name = format!(
+        "queue-{}.json",
+        snapshot.exported_at.format("%Y%m%d-%H%M%S")
+    );
+    bot.send_document(
+        message.chat.id,
+        InputFile::memory(serde_json::to_vec_pretty(&snapshot)?).file_name(file_name),
+    )
+    .caption(format!(
+        "{} queued item(s). Send the file back with /importqueue as its caption to restore them.",
+        queue_len
+    ))
+    .await?;
+    Ok(())
+}
+
+/// `/importqueue`, as the caption of a file from `/exportqueue`: queues its items
+/// again. Items already in the queue are replaced rather than duplicated.
+pub async fn import(
+    bot: &Arc<Bot>,
+    message: &Message,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let Some(document) = message.document() else {
+        cleanup::reply(
+            bot,
+            secrets,
+            message.chat.id,
+            "Send a file from /exportqueue with /importqueue as its caption.",
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let file = media::fetch(bot, secrets, &document.file).await?;
+    let snapshot: Snapshot = serde_json::from_slice(&tokio::fs::read(&file.path).await?)
+        .map_err(|e| format!("Not a queue export: {}", e))?;
+
+    let count = snapshot.items.len();
+    for item in snapshot.items {
+        secrets
+            .message_queue
+            .add_message(item.message, bot.clone(), secrets.clone())
+            .await;
+    }
+    tracing::info!("Imported {} queued item(s)", count);
+    cleanup::reply(
+        bot,
+        secrets,
+        message.chat.id,
+        format!(
+            "Queued {} item(s) exported at {} UTC.",
+            count,
+            snapshot.exported_at.format("%Y-%m-%d %H:%M")
+        ),
+    )
+    .await?;
+    Ok(())
+}
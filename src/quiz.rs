@@ -0,0 +1,453 @@
+use crate::{ServerSecretsState, catalog, dry_run};
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Utc};
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    prelude::*,
+    types::{InputPollOption, MessageId, ParseMode, Poll, PollType, ReplyParameters},
+    utils::markdown,
+};
+use tokio::time::sleep;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long a quiz stays open before the answer is posted, unless
+/// `QUIZ_REVEAL_HOURS` says otherwise.
+const DEFAULT_REVEAL_HOURS: i64 = 24;
+const OPTIONS: usize = 4;
+/// Telegram's limits on poll questions and options.
+const MAX_QUESTION_CHARS: usize = 300;
+const MAX_OPTION_CHARS: usize = 100;
+
+/// Parses `QUIZ_REVEAL_HOURS`.
+pub fn reveal_after_from_secret(raw: Option<&str>) -> anyhow::Result<chrono::Duration> {
+    let hours = match raw {
+        Some(hours) => hours
+            .trim()
+            .parse()
+            .ok()
+            .filter(|hours| *hours > 0)
+            .context("QUIZ_REVEAL_HOURS must be a positive number of hours")?,
+        None => DEFAULT_REVEAL_HOURS,
+    };
+    Ok(chrono::Duration::hours(hours))
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    /// Who performed this track?
+    Artist,
+    /// Which year did its release come out?
+    Year,
+}
+
+impl Kind {
+    fn parse(raw: &str) -> Option<Option<Self>> {
+        match raw.trim() {
+            "" => Some(None),
+            "artist" => Some(Some(Kind::Artist)),
+            "year" => Some(Some(Kind::Year)),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Artist => "artist",
+            Kind::Year => "year",
+        }
+    }
+}
+
+/// A question made from a catalog entry, its options already shuffled.
+struct Question {
+    kind: Kind,
+    track: catalog::Track,
+    text: String,
+    options: Vec<String>,
+    correct: usize,
+}
+
+fn clip(text: &str, max: usize) -> String {
+    let mut clipped: String = text.chars().take(max).collect();
+    if clipped.len() < text.len() {
+        clipped.pop();
+        clipped.push('…');
+    }
+    clipped
+}
+
+/// `options` in random order; Postgres is the only source of randomness around.
+async fn shuffle(db: &PgPool, options: Vec<String>) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar("SELECT o FROM unnest($1::TEXT[]) o ORDER BY random()")
+        .bind(options)
+        .fetch_all(db)
+        .await
+}
+
+async fn artist_question(db: &PgPool) -> Result<Option<Question>, Error> {
+    let track: Option<catalog::Track> = sqlx::query_as(
+        "SELECT * FROM tracks
+         WHERE performer IS NOT NULL AND title IS NOT NULL AND deleted_at IS NULL
+         ORDER BY random() LIMIT 1",
+    )
+    .fetch_optional(db)
+    .await?;
+    let Some(track) = track else {
+        return Ok(None);
+    };
+    let (Some(title), Some(performer)) = (&track.title, &track.performer) else {
+        return Ok(None);
+    };
+
+    let mut options: Vec<String> = sqlx::query_scalar(
+        "SELECT performer FROM (
+             SELECT DISTINCT performer FROM tracks
+             WHERE performer IS NOT NULL AND lower(performer) <> lower($1)
+                 AND deleted_at IS NULL
+         ) p ORDER BY random() LIMIT $2",
+    )
+    .bind(performer)
+    .bind(OPTIONS as i64 - 1)
+    .fetch_all(db)
+    .await?;
+    if options.len() < OPTIONS - 1 {
+        return Ok(None);
+    }
+    options.push(performer.clone());
+    let options: Vec<String> = shuffle(db, options)
+        .await?
+        .iter()
+        .map(|option| clip(option, MAX_OPTION_CHARS))
+        .collect();
+    let answer = clip(performer, MAX_OPTION_CHARS);
+    let correct = options
+        .iter()
+        .position(|option| *option == answer)
+        .ok_or("The answer got lost in the shuffle")?;
+    Ok(Some(Question {
+        kind: Kind::Artist,
+        text: clip(
+            &format!("🎤 Who performed “{}”?", title),
+            MAX_QUESTION_CHARS,
+        ),
+        track,
+        options,
+        correct,
+    }))
+}
+
+async fn year_question(db: &PgPool) -> Result<Option<Question>, Error> {
+    let track: Option<catalog::Track> = sqlx::query_as(
+        "SELECT t.* FROM tracks t JOIN releases r ON r.id = t.release_id
+         WHERE r.year IS NOT NULL AND t.deleted_at IS NULL
+         ORDER BY random() LIMIT 1",
+    )
+    .fetch_optional(db)
+    .await?;
+    let Some(track) = track else {
+        return Ok(None);
+    };
+    let Some(release) = catalog::get_release(db, track.release_id.unwrap_or_default()).await?
+    else {
+        return Ok(None);
+    };
+    let Some(year) = release.year else {
+        return Ok(None);
+    };
+
+    let this_year = Utc::now().year();
+    let decoys = (-4..=4)
+        .map(|offset| year + offset)
+        .filter(|&decoy| decoy != year && decoy <= this_year)
+        .map(|decoy| decoy.to_string())
+        .collect();
+    let mut options: Vec<String> = shuffle(db, decoys).await?;
+    options.truncate(OPTIONS - 1);
+    options.push(year.to_string());
+    let options = shuffle(db, options).await?;
+    let correct = options
+        .iter()
+        .position(|option| *option == year.to_string())
+        .ok_or("The answer got lost in the shuffle")?;
+    Ok(Some(Question {
+        kind: Kind::Year,
+        text: clip(
+            &format!("📅 What year did “{}” come out?", track.label()),
+            MAX_QUESTION_CHARS,
+        ),
+        track,
+        options,
+        correct,
+    }))
+}
+
+/// A quiz the bot posted, as tracked in `quizzes`.
+#[derive(FromRow)]
+struct Quiz {
+    id: i64,
+    chat_id: i64,
+    message_id: i32,
+    track_id: i64,
+    kind: String,
+    answer: String,
+    total_votes: i32,
+    correct_votes: i32,
+}
+
+/// "🎯 Answer: 1997. 42% of 57 got it right. Listen", as MarkdownV2.
+async fn render_reveal(db: &PgPool, quiz: &Quiz) -> sqlx::Result<String> {
+    let mut text = format!("🎯 Answer: *{}*\\.", markdown::escape(&quiz.answer));
+    if quiz.total_votes > 0 {
+        text.push_str(&markdown::escape(&format!(
+            " {}% of {} got it right.",
+            quiz.correct_votes * 100 / quiz.total_votes,
+            quiz.total_votes
+        )));
+    }
+    if let Some(track) = catalog::get_track(db, quiz.track_id).await? {
+        text.push_str(&format!(
+            "\n\n{}",
+            markdown::link(
+                &catalog::permalink(track.message_id),
+                &markdown::escape(&track.label())
+            )
+        ));
+    }
+    Ok(text)
+}
+
+/// Closes the quiz, takes its final counts and posts the answer under it.
+async fn reveal(bot: &Bot, secrets: &ServerSecretsState, id: i64) -> Result<(), Error> {
+    let quiz: Option<Quiz> = sqlx::query_as(
+        "UPDATE quizzes SET revealed_at = now() WHERE id = $1 AND revealed_at IS NULL
+         RETURNING *",
+    )
+    .bind(id)
+    .fetch_optional(&secrets.db)
+    .await?;
+    let Some(mut quiz) = quiz else {
+        return Ok(());
+    };
+
+    let chat_id = ChatId(quiz.chat_id);
+    match bot.stop_poll(chat_id, MessageId(quiz.message_id)).await {
+        Ok(poll) => {
+            record_poll(&secrets.db, &poll).await?;
+            let (total, correct) = counts(&poll);
+            quiz.total_votes = total;
+            quiz.correct_votes = correct;
+        }
+        // Already closed by hand; the counts from the last update stand.
+        Err(e) => tracing::debug!("Failed to stop quiz #{}: {}", quiz.id, e),
+    }
+
+    let text = render_reveal(&secrets.db, &quiz).await?;
+    let message = secrets
+        .retry_policy
+        .run(|| {
+            bot.send_message(chat_id, text.clone())
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_parameters(ReplyParameters::new(MessageId(quiz.message_id)))
+                .send()
+        })
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    tracing::info!("Revealed {} quiz #{}", quiz.kind, quiz.id);
+    Ok(())
+}
+
+fn spawn_reveal(bot: Bot, secrets: Arc<ServerSecretsState>, id: i64, reveal_at: DateTime<Utc>) {
+    tokio::spawn(async move {
+        sleep((reveal_at - Utc::now()).to_std().unwrap_or_default()).await;
+        if dry_run::is_active(&secrets) {
+            tracing::info!("Dry run: not revealing quiz #{}", id);
+            return;
+        }
+        if let Err(e) = reveal(&bot, &secrets, id).await {
+            tracing::error!("Failed to reveal quiz #{}: {}", id, e);
+        }
+    });
+}
+
+/// Posts a quiz made from a random catalog entry to the channel and schedules the
+/// answer for `QUIZ_REVEAL_HOURS` later.
+async fn post(
+    bot: &Bot,
+    secrets: &Arc<ServerSecretsState>,
+    kind: Option<Kind>,
+) -> Result<Option<i64>, Error> {
+    // Without a kind asked for, any will do: they are tried in random order until
+    // the catalog has enough for one.
+    let kinds = match kind {
+        Some(kind) => vec![kind],
+        None => shuffle(&secrets.db, vec!["artist".to_string(), "year".to_string()])
+            .await?
+            .iter()
+            .filter_map(|name| Kind::parse(name).flatten())
+            .collect(),
+    };
+    let mut question = None;
+    for kind in kinds {
+        question = match kind {
+            Kind::Artist => artist_question(&secrets.db).await?,
+            Kind::Year => year_question(&secrets.db).await?,
+        };
+        if question.is_some() {
+            break;
+        }
+    }
+    let Some(question) = question else {
+        return Ok(None);
+    };
+
+    let channel_id = secrets.publish_channel_id();
+    let message = secrets
+        .retry_policy
+        .run(|| {
+            bot.send_poll(
+                channel_id,
+                question.text.clone(),
+                question.options.iter().cloned().map(InputPollOption::new),
+            )
+            .type_(PollType::Quiz)
+            .correct_option_id(question.correct as u8)
+            .send()
+        })
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    let poll = message.poll().ok_or("Telegram sent back no poll")?;
+
+    let reveal_at = Utc::now() + secrets.quiz_reveal_after;
+    let id: i64 = sqlx::query_scalar(
+        "INSERT INTO quizzes
+             (poll_id, chat_id, message_id, track_id, kind, answer, reveal_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING id",
+    )
+    .bind(&poll.id.0)
+    .bind(message.chat.id.0)
+    .bind(message.id.0)
+    .bind(question.track.id)
+    .bind(question.kind.name())
+    .bind(&question.options[question.correct])
+    .bind(reveal_at)
+    .fetch_one(&secrets.db)
+    .await?;
+    spawn_reveal(bot.clone(), secrets.clone(), id, reveal_at);
+    Ok(Some(id))
+}
+
+/// Voters in total, and those who picked the right option.
+fn counts(poll: &Poll) -> (i32, i32) {
+    let correct = poll
+        .correct_option_id
+        .and_then(|option| poll.options.get(option as usize))
+        .map_or(0, |option| option.voter_count);
+    (poll.total_voter_count as i32, correct as i32)
+}
+
+async fn record_poll(db: &PgPool, poll: &Poll) -> sqlx::Result<()> {
+    let (total, correct) = counts(poll);
+    sqlx::query("UPDATE quizzes SET total_votes = $2, correct_votes = $3 WHERE poll_id = $1")
+        .bind(&poll.id.0)
+        .bind(total)
+        .bind(correct)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Telegram's updates on our quizzes' vote counts.
+pub async fn handle_poll(poll: &Poll, secrets: &ServerSecretsState) -> Result<(), Error> {
+    record_poll(&secrets.db, poll).await?;
+    Ok(())
+}
+
+async fn stats(db: &PgPool) -> sqlx::Result<String> {
+    let (quizzes, votes, correct): (i64, Option<i64>, Option<i64>) =
+        sqlx::query_as("SELECT COUNT(*), SUM(total_votes), SUM(correct_votes) FROM quizzes")
+            .fetch_one(db)
+            .await?;
+    let votes = votes.unwrap_or_default();
+    if quizzes == 0 {
+        return Ok("No quizzes yet. Post one with /quiz.".to_string());
+    }
+    let mut text = format!("🧠 {} quiz(zes), {} vote(s)", quizzes, votes);
+    if votes > 0 {
+        text.push_str(&format!(
+            ", {}% right",
+            correct.unwrap_or_default() * 100 / votes
+        ));
+    }
+    let kinds: Vec<(String, i64, i64, i64)> = sqlx::query_as(
+        "SELECT kind, COUNT(*), SUM(total_votes)::BIGINT, SUM(correct_votes)::BIGINT
+         FROM quizzes GROUP BY kind ORDER BY kind",
+    )
+    .fetch_all(db)
+    .await?;
+    for (kind, count, votes, correct) in kinds {
+        text.push_str(&format!(
+            "\n• {}: {} quiz(zes), {} vote(s)",
+            kind, count, votes
+        ));
+        if votes > 0 {
+            text.push_str(&format!(", {}% right", correct * 100 / votes));
+        }
+    }
+    Ok(text)
+}
+
+/// `/quiz [artist|year]` posts a quiz to the channel; `/quiz stats` shows how the
+/// channel has done so far.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    if args.trim() == "stats" {
+        bot.send_message(message.chat.id, stats(&secrets.db).await?)
+            .await?;
+        return Ok(());
+    }
+    let Some(kind) = Kind::parse(args) else {
+        bot.send_message(message.chat.id, "Usage: /quiz [artist|year|stats]")
+            .await?;
+        return Ok(());
+    };
+    if dry_run::is_active(secrets) {
+        bot.send_message(message.chat.id, "Dry run: not posting a quiz.")
+            .await?;
+        return Ok(());
+    }
+
+    let text = match post(bot, secrets, kind).await? {
+        Some(id) => format!(
+            "🧠 Posted quiz #{}, the answer follows in {} hour(s).",
+            id,
+            secrets.quiz_reveal_after.num_hours()
+        ),
+        None => "Not enough in the catalog for that quiz yet.".to_string(),
+    };
+    bot.send_message(message.chat.id, text).await?;
+    Ok(())
+}
+
+/// Picks up reveals scheduled before a restart.
+pub async fn resume(bot: &Bot, secrets: &Arc<ServerSecretsState>) -> sqlx::Result<()> {
+    let pending: Vec<(i64, DateTime<Utc>)> =
+        sqlx::query_as("SELECT id, reveal_at FROM quizzes WHERE revealed_at IS NULL")
+            .fetch_all(&secrets.db)
+            .await?;
+    for (id, reveal_at) in pending {
+        spawn_reveal(bot.clone(), secrets.clone(), id, reveal_at);
+    }
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use anyhow::Context;
+use shuttle_runtime::SecretStore;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Buckets are only swept once the store grows past this, dropping the full ones.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Per-IP token buckets for every HTTP route except the Telegram webhook. Each client
+/// may make `burst` requests at once, refilled at `per_minute`. Configured with
+/// `RATE_LIMIT_PER_MINUTE` (default 60, 0 disables) and `RATE_LIMIT_BURST`
+/// (default the per-minute rate).
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    webhook_path: String,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn from_secrets(secrets: &SecretStore, bot_token: &str) -> anyhow::Result<Option<Self>> {
+        let per_minute: u32 = match secrets.get("RATE_LIMIT_PER_MINUTE") {
+            Some(raw) => raw
+                .parse()
+                .context("RATE_LIMIT_PER_MINUTE must be a number of requests")?,
+            None => 60,
+        };
+        if per_minute == 0 {
+            return Ok(None);
+        }
+        let burst: u32 = match secrets.get("RATE_LIMIT_BURST") {
+            Some(raw) => raw
+                .parse()
+                .ok()
+                .filter(|burst| *burst > 0)
+                .context("RATE_LIMIT_BURST must be a positive number of requests")?,
+            None => per_minute,
+        };
+        Ok(Some(Self {
+            per_second: per_minute as f64 / 60.0,
+            burst: burst as f64,
+            webhook_path: format!("/{}", bot_token),
+            buckets: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Takes a token for `ip`, or returns how many seconds until one is available.
+    fn take(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+        if buckets.len() > SWEEP_THRESHOLD {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * self.per_second
+                    < self.burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.updated).as_secs_f64() * self.per_second)
+            .min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / self.per_second).ceil() as u64)
+        }
+    }
+
+    /// Admits a request from `ip` to `path`, or returns how many seconds the client
+    /// has to wait.
+    pub fn check(&self, path: &str, ip: IpAddr) -> Result<(), u64> {
+        if path == self.webhook_path {
+            return Ok(());
+        }
+        self.take(ip)
+            .inspect_err(|_| tracing::warn!(%ip, path, "Rate limited"))
+    }
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_fairing::routes;
+
+#[cfg(feature = "rocket")]
+mod rocket_fairing {
+    use super::RateLimiter;
+    use rocket::{
+        Data, Request, Route,
+        fairing::{Fairing, Info, Kind},
+        get,
+        http::{Header, Method, Status, uri::Origin},
+        request::{FromRequest, Outcome},
+        routes,
+    };
+
+    const LIMITED_PATH: &str = "/rate-limited";
+
+    pub fn routes() -> Vec<Route> {
+        routes![rate_limited]
+    }
+
+    /// Seconds until the client may try again, stashed by the fairing.
+    struct RetryAfter(u64);
+
+    #[rocket::async_trait]
+    impl Fairing for RateLimiter {
+        fn info(&self) -> Info {
+            Info {
+                name: "Rate limit",
+                kind: Kind::Request,
+            }
+        }
+
+        /// Fairings cannot answer a request themselves, so one over the limit is
+        /// rerouted to a handler that only says 429.
+        async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+            let Some(ip) = req.client_ip() else {
+                return;
+            };
+            if let Err(retry_after) = self.check(req.uri().path().as_str(), ip) {
+                req.local_cache(|| Some(RetryAfter(retry_after)));
+                req.set_method(Method::Get);
+                req.set_uri(Origin::parse(LIMITED_PATH).expect("valid path"));
+            }
+        }
+    }
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for &'r RetryAfter {
+        type Error = ();
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            match req.local_cache(|| None::<RetryAfter>) {
+                Some(retry_after) => Outcome::Success(retry_after),
+                None => Outcome::Error((Status::NotFound, ())),
+            }
+        }
+    }
+
+    #[derive(rocket::Responder)]
+    #[response(status = 429)]
+    struct TooManyRequests {
+        body: &'static str,
+        retry_after: Header<'static>,
+    }
+
+    #[get("/rate-limited")]
+    fn rate_limited(retry_after: &RetryAfter) -> TooManyRequests {
+        TooManyRequests {
+            body: "Too many requests",
+            retry_after: Header::new("Retry-After", retry_after.0.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_middleware::limit;
+
+#[cfg(feature = "axum")]
+mod axum_middleware {
+    use super::RateLimiter;
+    use axum::{
+        extract::{ConnectInfo, Request, State},
+        http::{StatusCode, header},
+        middleware::Next,
+        response::{IntoResponse, Response},
+    };
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::Arc;
+
+    /// Middleware answering 429 to clients over the limit. Like Rocket, it trusts
+    /// `X-Real-IP` from the proxy in front and falls back to the peer address.
+    pub async fn limit(
+        State(limiter): State<Arc<RateLimiter>>,
+        ConnectInfo(peer): ConnectInfo<SocketAddr>,
+        req: Request,
+        next: Next,
+    ) -> Response {
+        let ip = req
+            .headers()
+            .get("X-Real-IP")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<IpAddr>().ok())
+            .unwrap_or(peer.ip());
+        match limiter.check(req.uri().path(), ip) {
+            Ok(()) => next.run(req).await,
+            Err(retry_after) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "Too many requests",
+            )
+                .into_response(),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{ServerSecretsState, catalog};
+use sqlx::PgPool;
+use teloxide::types::{MessageReactionCountUpdated, ReactionType};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+fn key(reaction: &ReactionType) -> String {
+    match reaction {
+        ReactionType::Emoji { emoji } => emoji.clone(),
+        ReactionType::CustomEmoji { custom_emoji_id } => format!("custom:{}", custom_emoji_id),
+        ReactionType::Paid => "paid".to_string(),
+    }
+}
+
+/// Replaces the stored counts of a channel post with the ones Telegram reports and
+/// sums them onto the track, if the post is one.
+pub async fn handle_count(
+    update: &MessageReactionCountUpdated,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    if update.chat.id != secrets.channel_id() {
+        return Ok(());
+    }
+    let (reactions, counts): (Vec<String>, Vec<i32>) = update
+        .reactions
+        .iter()
+        .map(|reaction| (key(&reaction.r#type), reaction.total_count as i32))
+        .unzip();
+
+    let mut tx = secrets.db.begin().await?;
+    sqlx::query("DELETE FROM post_reactions WHERE channel_id = $1 AND message_id = $2")
+        .bind(update.chat.id.0)
+        .bind(update.message_id.0)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "INSERT INTO post_reactions (channel_id, message_id, reaction, count)
+         SELECT $1, $2, * FROM UNNEST($3::TEXT[], $4::INTEGER[])",
+    )
+    .bind(update.chat.id.0)
+    .bind(update.message_id.0)
+    .bind(&reactions)
+    .bind(&counts)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "UPDATE tracks SET reaction_count = $3
+         WHERE channel_id = $1 AND message_id = $2 AND reaction_count <> $3",
+    )
+    .bind(update.chat.id.0)
+    .bind(update.message_id.0)
+    .bind(counts.iter().sum::<i32>())
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The tracks with the most reactions, for `/stats`.
+pub async fn most_reacted(pool: &PgPool, limit: i64) -> sqlx::Result<Vec<catalog::Track>> {
+    sqlx::query_as(
+        "SELECT * FROM tracks WHERE deleted_at IS NULL AND reaction_count > 0
+         ORDER BY reaction_count DESC, posted_at DESC LIMIT $1",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// "Performer – Title ❤ 12", or just the label when nobody has reacted.
+pub fn label(track: &catalog::Track) -> String {
+    match track.reaction_count {
+        0 => track.label(),
+        count => format!("{} ❤ {}", track.label(), count),
+    }
+}
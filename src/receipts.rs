@@ -0,0 +1,214 @@
+use crate::{
+    ServerSecretsState, caption, catalog, dialogue, favorites, fix_metadata,
+    telegram::{self, OutgoingText},
+};
+use teloxide::{
+    prelude::*,
+    types::{ForceReply, InlineKeyboardButton, InlineKeyboardMarkup, MessageId},
+};
+
+pub const CALLBACK_PREFIX: &str = "rcpt:";
+
+const CAPTION_PROMPT: &str = "New caption for post ";
+const TAGS_PROMPT: &str = "New tags for post ";
+
+type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+fn keyboard(message_id: i32) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([
+        vec![
+            InlineKeyboardButton::callback(
+                "✏️ Caption",
+                format!("{}caption:{}", CALLBACK_PREFIX, message_id),
+            ),
+            InlineKeyboardButton::callback(
+                "🏷 Tag",
+                format!("{}tag:{}", CALLBACK_PREFIX, message_id),
+            ),
+            InlineKeyboardButton::callback(
+                "🗑 Delete",
+                format!("{}delete:{}", CALLBACK_PREFIX, message_id),
+            ),
+        ],
+        vec![
+            InlineKeyboardButton::callback(
+                "📝 Title, artist, series",
+                format!("{}fix:{}", CALLBACK_PREFIX, message_id),
+            ),
+            favorites::button(message_id),
+        ],
+    ])
+}
+
+fn receipt_text(track: &catalog::Track) -> String {
+    let mut text = format!("✅ {}\n{}", track.label(), track.size_label());
+    if !track.tags.is_empty() {
+        let tags = track.tags.iter().map(|tag| format!("#{}", tag));
+        text.push_str(&format!("\n{}", tags.collect::<Vec<_>>().join(" ")));
+    }
+    text.push_str(&format!("\n{}", catalog::permalink(track.message_id)));
+    text
+}
+
+/// DMs the owner a confirmation of a channel post with quick actions for it.
+pub async fn send_receipt(secrets: &ServerSecretsState, track: &catalog::Track) {
+    let text = OutgoingText::plain(receipt_text(track))
+        .without_link_preview()
+        .reply_markup(keyboard(track.message_id));
+    if let Err(e) = secrets.telegram.send_message(secrets.me_id, &text).await {
+        tracing::warn!("Failed to send delivery receipt: {}", e);
+    }
+}
+
+/// Handles the receipt buttons.
+pub async fn handle_callback(
+    bot: &Bot,
+    telegram: &dyn telegram::TelegramClient,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> HandlerResult {
+    let (action, message_id) = data.split_once(':').ok_or("Malformed callback data")?;
+    let message_id: i32 = message_id.parse()?;
+    let channel_id = secrets.channel_id();
+    let receipt = query.regular_message();
+
+    match action {
+        "caption" | "tag" => {
+            let prompt = if action == "caption" {
+                CAPTION_PROMPT
+            } else {
+                TAGS_PROMPT
+            };
+            let placeholder = if action == "caption" {
+                "Caption text"
+            } else {
+                "#tag #another"
+            };
+            bot.send_message(secrets.me_id, format!("{}{}:", prompt, message_id))
+                .reply_markup(ForceReply::new().input_field_placeholder(Some(placeholder.into())))
+                .await?;
+            bot.answer_callback_query(query.id.clone()).await?;
+        }
+        "fix" => match fix_metadata::start(secrets, message_id).await? {
+            Some(flow) => {
+                bot.answer_callback_query(query.id.clone()).await?;
+                dialogue::start(bot, secrets, secrets.me_id, flow).await?;
+            }
+            None => {
+                bot.answer_callback_query(query.id.clone())
+                    .text("That post is not in the catalog")
+                    .await?;
+            }
+        },
+        "delete" => {
+            if let Some(receipt) = receipt {
+                let confirm = InlineKeyboardMarkup::new([[
+                    InlineKeyboardButton::callback(
+                        "Yes, delete",
+                        format!("{}confirm_delete:{}", CALLBACK_PREFIX, message_id),
+                    ),
+                    InlineKeyboardButton::callback(
+                        "Cancel",
+                        format!("{}cancel:{}", CALLBACK_PREFIX, message_id),
+                    ),
+                ]]);
+                bot.edit_message_reply_markup(receipt.chat.id, receipt.id)
+                    .reply_markup(confirm)
+                    .await?;
+            }
+            bot.answer_callback_query(query.id.clone()).await?;
+        }
+        "confirm_delete" => {
+            telegram
+                .delete_message(channel_id, MessageId(message_id))
+                .await?;
+            catalog::delete_track(&secrets.db, channel_id.0, message_id).await?;
+            if let Some(receipt) = receipt {
+                bot.edit_message_text(
+                    receipt.chat.id,
+                    receipt.id,
+                    format!("🗑 Deleted post {}", message_id),
+                )
+                .await?;
+            }
+            bot.answer_callback_query(query.id.clone())
+                .text("Deleted")
+                .await?;
+        }
+        "cancel" => {
+            if let Some(receipt) = receipt {
+                bot.edit_message_reply_markup(receipt.chat.id, receipt.id)
+                    .reply_markup(keyboard(message_id))
+                    .await?;
+            }
+            bot.answer_callback_query(query.id.clone()).await?;
+        }
+        _ => return Err("Unknown receipt action".into()),
+    }
+    Ok(())
+}
+
+fn prompted_message_id(prompt: &Message, prefix: &str) -> Option<i32> {
+    prompt
+        .text()?
+        .strip_prefix(prefix)?
+        .trim_end_matches(':')
+        .parse()
+        .ok()
+}
+
+/// Handles the owner's answer to a caption or tag prompt. Returns `false` when
+/// `message` is not such an answer.
+pub async fn handle_reply(
+    bot: &Bot,
+    telegram: &dyn telegram::TelegramClient,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let (Some(prompt), Some(text)) = (message.reply_to_message(), message.text()) else {
+        return Ok(false);
+    };
+    let channel_id = secrets.channel_id();
+
+    if let Some(message_id) = prompted_message_id(prompt, CAPTION_PROMPT) {
+        let track = catalog::track_at(&secrets.db, channel_id.0, message_id)
+            .await?
+            .ok_or("That post is not in the catalog")?;
+        let format = secrets.caption_template.format;
+        let caption = format!(
+            "{}\n\n{}",
+            format.escape(text),
+            crate::caption(
+                &secrets.caption_template,
+                &track.series,
+                message_id,
+                track.number,
+                track.via.as_ref().map(|via| &via.0),
+                &caption::Facts::of_track(&track)
+            )
+        );
+        telegram
+            .edit_caption(channel_id, MessageId(message_id), &caption, format)
+            .await?;
+        catalog::update_caption(&secrets.db, channel_id.0, message_id, &caption, format).await?;
+        bot.send_message(message.chat.id, "Caption updated ✅")
+            .await?;
+        return Ok(true);
+    }
+
+    if let Some(message_id) = prompted_message_id(prompt, TAGS_PROMPT) {
+        let mut tags = Vec::new();
+        for tag in text.split_whitespace() {
+            let tag = tag.trim_start_matches('#').to_lowercase();
+            if !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        catalog::update_tags(&secrets.db, channel_id.0, message_id, &tags).await?;
+        bot.send_message(message.chat.id, "Tags updated ✅").await?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
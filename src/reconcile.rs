@@ -0,0 +1,128 @@
+use crate::{ServerSecretsState, catalog, cleanup};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use teloxide::{ApiError, RequestError, prelude::*, types::MessageId};
+use tokio::time::{Duration, sleep};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Each probe is two requests, so keep well under the Bot API's per-chat limit.
+pub const PROBE_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// Set while a reconciliation is running, so `/reconcile` and the job never overlap.
+#[derive(Default)]
+pub struct Running(AtomicBool);
+
+impl Running {
+    pub fn is_running(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Claims the flag, or `None` if a run holds it already. It is let go when the
+    /// guard is dropped, however the run ends.
+    fn claim(&self) -> Option<Claim<'_>> {
+        (!self.0.swap(true, Ordering::AcqRel)).then_some(Claim(self))
+    }
+}
+
+struct Claim<'a>(&'a Running);
+
+impl Drop for Claim<'_> {
+    fn drop(&mut self) {
+        self.0.0.store(false, Ordering::Release);
+    }
+}
+
+/// Reads a channel post by forwarding it silently to the owner and deleting the copy
+/// straight away, returning the copy, or `None` if the post is gone. The Bot API has
+/// no way to read a channel message by id, so this is the cheapest reliable test.
+pub async fn probe(
+    bot: &Bot,
+    owner: ChatId,
+    channel_id: ChatId,
+    message_id: i32,
+) -> Result<Option<Message>, RequestError> {
+    match bot
+        .forward_message(owner, channel_id, MessageId(message_id))
+        .disable_notification(true)
+        .await
+    {
+        Ok(copy) => {
+            bot.delete_message(owner, copy.id).await?;
+            Ok(Some(copy))
+        }
+        Err(RequestError::Api(ApiError::MessageToForwardNotFound | ApiError::MessageIdInvalid)) => {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Probes every cataloged post and marks the ones gone from the channel as
+/// deleted. Returns how many were checked and how many were marked, or `None` if
+/// a run was already in progress.
+pub async fn run(bot: &Bot, secrets: &ServerSecretsState) -> Result<Option<(usize, usize)>, Error> {
+    let Some(_claim) = secrets.reconciling.claim() else {
+        return Ok(None);
+    };
+    reconcile(bot, secrets).await.map(Some)
+}
+
+async fn reconcile(bot: &Bot, secrets: &ServerSecretsState) -> Result<(usize, usize), Error> {
+    let owner = secrets.me_id;
+    let channel_id = secrets.channel_id();
+    let posts = catalog::live_posts(&secrets.db, channel_id.0).await?;
+
+    let mut deleted = 0;
+    for (i, (id, message_id)) in posts.iter().enumerate() {
+        if i > 0 {
+            sleep(PROBE_INTERVAL).await;
+        }
+        if probe(bot, owner, channel_id, *message_id).await?.is_none() {
+            tracing::info!(
+                "Post {} is gone from the channel, marking deleted",
+                message_id
+            );
+            catalog::mark_deleted(&secrets.db, *id).await?;
+            deleted += 1;
+        }
+    }
+    Ok((posts.len(), deleted))
+}
+
+/// `/reconcile`: runs in the background, since probing a large catalog takes a while.
+pub fn handle_command(bot: &Arc<Bot>, message: &Message, secrets: &Arc<ServerSecretsState>) {
+    let bot = bot.clone();
+    let secrets = secrets.clone();
+    let chat_id = message.chat.id;
+    tokio::spawn(async move {
+        let reply = match run(&bot, &secrets).await {
+            Ok(Some((checked, deleted))) => format!(
+                "Checked {} post(s), {} gone from the channel and marked deleted.",
+                checked, deleted
+            ),
+            Ok(None) => "A reconciliation is already running.".to_string(),
+            Err(e) => format!("Reconciliation failed: {}", e),
+        };
+        if let Err(e) = cleanup::reply(&bot, &secrets, chat_id, reply).await {
+            tracing::warn!("Failed to report reconciliation: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claim_is_exclusive_and_let_go_on_drop() {
+        let running = Running::default();
+        let claim = running.claim();
+        assert!(claim.is_some());
+        assert!(running.is_running());
+        assert!(running.claim().is_none());
+        drop(claim);
+        assert!(!running.is_running());
+        assert!(running.claim().is_some());
+    }
+}
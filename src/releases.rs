@@ -0,0 +1,277 @@
+use crate::{
+    QueuedMessage, ServerSecretsState, catalog, cover, dry_run,
+    format::Format,
+    media,
+    telegram::{OutgoingPhoto, OutgoingText, Sent},
+    test_mode,
+};
+use teloxide::{prelude::*, types::Audio, utils::markdown};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// A batch item: either a track posted on its own or an album posted as a release.
+pub enum Entry {
+    Single(Box<QueuedMessage>),
+    Release(PendingRelease),
+}
+
+/// The posted lead post of a release.
+pub struct Lead {
+    pub message: Sent,
+    /// Whether it went out as a photo with the tracklist as its caption.
+    with_cover: bool,
+}
+
+/// Consecutive tracks of a batch that share album and performer, in tracklist order.
+pub struct PendingRelease {
+    pub title: String,
+    pub performer: Option<String>,
+    pub year: Option<i32>,
+    pub tracks: Vec<QueuedMessage>,
+}
+
+struct AlbumTags {
+    album: String,
+    year: Option<i32>,
+    position: (u32, u32),
+}
+
+async fn album_tags(bot: &Bot, secrets: &ServerSecretsState, audio: &Audio) -> Option<AlbumTags> {
+    let tags = match media::fetch(bot, secrets, &audio.file).await {
+        Ok(file) => media::probe_tags(&file.path).await,
+        Err(e) => Err(e),
+    }
+    .inspect_err(|e| tracing::warn!("Failed to read album tags: {}", e))
+    .ok()?;
+
+    Some(AlbumTags {
+        album: tags.get("album").filter(|album| !album.is_empty())?.clone(),
+        year: tags
+            .get("date")
+            .or(tags.get("year"))
+            .and_then(|date| date.get(..4)?.parse().ok()),
+        position: media::tracklist_position(&tags),
+    })
+}
+
+/// Splits a batch into single tracks and releases. Only runs of two or more tracks
+/// with the same album tag and performer become a release; everything else is
+/// posted as before. Off unless `ALBUM_RELEASES` is on, since it has to download
+/// every file to read its tags.
+pub async fn group(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    batch: Vec<QueuedMessage>,
+) -> Vec<Entry> {
+    if !secrets.album_releases {
+        return batch
+            .into_iter()
+            .map(|msg| Entry::Single(Box::new(msg)))
+            .collect();
+    }
+
+    let mut tagged = Vec::with_capacity(batch.len());
+    for msg in batch {
+        let tags = album_tags(bot, secrets, &msg.audio).await;
+        tagged.push((msg, tags));
+    }
+
+    let mut entries = Vec::new();
+    let mut items = tagged.into_iter().peekable();
+    while let Some((msg, tags)) = items.next() {
+        let Some(tags) = tags else {
+            entries.push(Entry::Single(Box::new(msg)));
+            continue;
+        };
+
+        let performer = msg.audio.performer.clone();
+        let mut run = vec![(msg, tags)];
+        while let Some((next, Some(next_tags))) = items.next_if(|(next, next_tags)| {
+            next_tags
+                .as_ref()
+                .is_some_and(|t| t.album == run[0].1.album)
+                && next.audio.performer == performer
+        }) {
+            run.push((next, next_tags));
+        }
+
+        if run.len() < 2 {
+            entries.extend(run.into_iter().map(|(msg, _)| Entry::Single(Box::new(msg))));
+            continue;
+        }
+
+        run.sort_by_key(|(_, tags)| tags.position);
+        entries.push(Entry::Release(PendingRelease {
+            title: run[0].1.album.clone(),
+            performer,
+            year: run.iter().find_map(|(_, tags)| tags.year),
+            tracks: run.into_iter().map(|(msg, _)| msg).collect(),
+        }));
+    }
+    entries
+}
+
+fn track_title(audio: &Audio) -> String {
+    audio
+        .title
+        .clone()
+        .or_else(|| audio.file_name.clone())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// The lead post: title, performer and year, then the tracklist, which links to
+/// each track once `message_ids` are known.
+fn render(release: &PendingRelease, message_ids: Option<&[Option<i32>]>) -> String {
+    let tracklist = release
+        .tracks
+        .iter()
+        .enumerate()
+        .map(|(i, track)| {
+            (
+                track_title(&track.audio),
+                message_ids.and_then(|ids| ids[i]),
+            )
+        })
+        .collect::<Vec<_>>();
+    render_lead(
+        &release.title,
+        release.performer.as_deref(),
+        release.year,
+        &tracklist,
+    )
+}
+
+/// The lead post of a release already in the catalog, linking to its tracks.
+pub fn render_recorded(release: &catalog::Release, tracks: &[catalog::Track]) -> String {
+    let tracklist = tracks
+        .iter()
+        .map(|track| {
+            (
+                track
+                    .title
+                    .clone()
+                    .or_else(|| track.file_name.clone())
+                    .unwrap_or_else(|| "Untitled".to_string()),
+                Some(track.message_id),
+            )
+        })
+        .collect::<Vec<_>>();
+    render_lead(
+        &release.title,
+        release.performer.as_deref(),
+        release.year,
+        &tracklist,
+    )
+}
+
+fn render_lead(
+    title: &str,
+    performer: Option<&str>,
+    year: Option<i32>,
+    tracklist: &[(String, Option<i32>)],
+) -> String {
+    let mut text = format!("💿 *{}*", markdown::escape(title));
+    let byline = [
+        performer.map(str::to_string),
+        year.map(|year| year.to_string()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    if !byline.is_empty() {
+        text.push_str(&format!("\n{}", markdown::escape(&byline.join(" · "))));
+    }
+    text.push('\n');
+
+    for (i, (title, message_id)) in tracklist.iter().enumerate() {
+        let title = markdown::escape(title);
+        let line = match message_id {
+            Some(id) => markdown::link(&catalog::permalink(*id), &title),
+            None => title,
+        };
+        text.push_str(&format!("\n{}\\. {}", i + 1, line));
+    }
+    text
+}
+
+/// Posts the lead post of a release, with the album's cover art when the first track
+/// has any, and records the release in the catalog.
+pub async fn post_lead(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    release: &PendingRelease,
+) -> Result<(Lead, Option<catalog::Release>), Error> {
+    let channel_id = secrets.publish_channel_id();
+    let cover = cover::extract(bot, secrets, &release.tracks[0].audio)
+        .await
+        .inspect_err(|e| tracing::warn!("Failed to extract release cover: {}", e))
+        .ok()
+        .flatten();
+
+    let text = render(release, None);
+    let message = match &cover {
+        Some(cover) => {
+            let photo = OutgoingPhoto {
+                path: cover.clone(),
+                caption: Some(text),
+                reply_to: None,
+                silent: false,
+            };
+            secrets
+                .retry_policy
+                .run(|| secrets.telegram.send_photo(channel_id, &photo))
+                .await?
+        }
+        None => {
+            let text = OutgoingText::markdown(text).without_link_preview();
+            secrets
+                .retry_policy
+                .run(|| secrets.telegram.send_message(channel_id, &text))
+                .await?
+        }
+    };
+    let lead = Lead {
+        message,
+        with_cover: cover.is_some(),
+    };
+
+    if test_mode::is_active(secrets) || dry_run::is_active(secrets) {
+        return Ok((lead, None));
+    }
+    let record = catalog::record_release(
+        &secrets.db,
+        &catalog::NewRelease {
+            channel_id: channel_id.0,
+            message_id: lead.message.id.0,
+            title: &release.title,
+            performer: release.performer.as_deref(),
+            year: release.year,
+        },
+    )
+    .await?;
+
+    Ok((lead, Some(record)))
+}
+
+/// Rewrites the lead post's tracklist to link to the tracks as they were posted.
+pub async fn link_tracklist(
+    secrets: &ServerSecretsState,
+    release: &PendingRelease,
+    lead: &Lead,
+    message_ids: &[Option<i32>],
+) {
+    let text = render(release, Some(message_ids));
+    let Sent { chat_id, id, .. } = lead.message;
+    let result = if lead.with_cover {
+        secrets
+            .telegram
+            .edit_caption(chat_id, id, &text, Format::MarkdownV2)
+            .await
+    } else {
+        let text = OutgoingText::markdown(text).without_link_preview();
+        secrets.telegram.edit_text(chat_id, id, &text).await
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to link release tracklist: {}", e);
+    }
+}
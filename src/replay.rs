@@ -0,0 +1,172 @@
+use crate::{ServerSecretsState, audit, dialogue};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool, types::Json};
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{Update, UpdateKind},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Parses `RECORD_UPDATES`, the number of days incoming updates are kept for; a
+/// missing value or 0 records nothing.
+pub fn retention_from_secret(raw: Option<&str>) -> anyhow::Result<Option<u32>> {
+    match raw {
+        Some(days) => match days
+            .trim()
+            .parse()
+            .context("RECORD_UPDATES must be a number of days")?
+        {
+            0 => Ok(None),
+            days => Ok(Some(days)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// A stored update, without its payload.
+#[derive(FromRow, Serialize)]
+pub struct RecordedUpdate {
+    pub id: i64,
+    pub update_id: i64,
+    pub kind: String,
+    pub received_at: DateTime<Utc>,
+}
+
+pub async fn record(db: &PgPool, update: &Update) -> Result<(), Error> {
+    let payload = serde_json::to_value(update)?;
+    sqlx::query("INSERT INTO recorded_updates (update_id, kind, payload) VALUES ($1, $2, $3)")
+        .bind(i64::from(update.id.0))
+        .bind(audit::kind(update))
+        .bind(Json(&payload))
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Updates received between `from` and `to`, oldest first.
+pub async fn list(
+    db: &PgPool,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: i64,
+) -> sqlx::Result<Vec<RecordedUpdate>> {
+    sqlx::query_as(
+        "SELECT id, update_id, kind, received_at FROM recorded_updates
+         WHERE ($1::timestamptz IS NULL OR received_at >= $1)
+           AND ($2::timestamptz IS NULL OR received_at < $2)
+         ORDER BY id
+         LIMIT $3",
+    )
+    .bind(from)
+    .bind(to)
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
+
+/// Forgets updates older than `days`.
+pub async fn prune(db: &PgPool, days: u32) -> sqlx::Result<u64> {
+    let result = sqlx::query(
+        "DELETE FROM recorded_updates WHERE received_at < now() - make_interval(days => $1)",
+    )
+    .bind(days as i32)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// What replaying one update did.
+#[derive(Serialize)]
+pub struct Replayed {
+    pub id: i64,
+    pub update_id: i64,
+    pub kind: String,
+    pub error: Option<String>,
+    /// The Telegram calls it would have made, as logged by the dry run.
+    pub calls: Vec<String>,
+}
+
+/// Why `update` is not replayed, if it isn't. Only uploads the owner sent the bot
+/// are: everything else, from join requests to strangers' messages and answers in a
+/// conversation, is handled with the live bot or writes the catalog in places the
+/// dry run does not reach, so replaying it could act for real.
+async fn refusal(
+    secrets: &ServerSecretsState,
+    update: &Update,
+) -> sqlx::Result<Option<&'static str>> {
+    let UpdateKind::Message(message) = &update.kind else {
+        return Ok(Some("only messages are replayed"));
+    };
+    if message.chat.id != secrets.me_id {
+        return Ok(Some("only the owner's messages are replayed"));
+    }
+    if message
+        .text()
+        .or_else(|| message.caption())
+        .is_some_and(|text| text.starts_with('/'))
+    {
+        return Ok(Some("commands are not replayed"));
+    }
+    if message.audio().is_some() || message.document().is_some() {
+        return Ok(None);
+    }
+    if dialogue::is_open(&secrets.db, message.chat.id).await? {
+        return Ok(Some(
+            "a conversation is open, which would take the text as an answer",
+        ));
+    }
+    Ok(Some("only audio and documents are replayed"))
+}
+
+/// Feeds the stored updates `ids` through `handle_update` again, one after another,
+/// with the dry-run client. Only allowed in a dry run, so nothing reaches the channel
+/// twice; tracks it queues are reported the usual dry-run way once their batch has
+/// gone through. Updates [`refusal`] turns down come back with the reason as error.
+pub async fn run(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    ids: &[i64],
+) -> Result<Vec<Replayed>, Error> {
+    let Some(client) = secrets.dry_run.clone() else {
+        return Err("Updates can only be replayed with DRY_RUN on".into());
+    };
+
+    let mut replayed = Vec::with_capacity(ids.len());
+    for &id in ids {
+        let row: Option<(i64, String, Json<Update>)> =
+            sqlx::query_as("SELECT update_id, kind, payload FROM recorded_updates WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&secrets.db)
+                .await?;
+        let Some((update_id, kind, Json(update))) = row else {
+            return Err(format!("No recorded update {}", id).into());
+        };
+
+        let error = match refusal(secrets, &update).await? {
+            Some(reason) => Some(format!("Not replayed: {}", reason)),
+            None => {
+                tracing::info!(id, update_id, "Replaying {} update", kind);
+                crate::handle_update(bot.clone(), &*client, update, secrets.clone())
+                    .await
+                    .err()
+                    .map(|e| e.to_string())
+            }
+        };
+        replayed.push(Replayed {
+            id,
+            update_id,
+            kind,
+            error,
+            calls: client
+                .take_calls()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        });
+    }
+    Ok(replayed)
+}
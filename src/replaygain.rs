@@ -0,0 +1,61 @@
+use crate::media::{self, LocalFile};
+use std::path::Path;
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// ReplayGain values of a track, as written to its `REPLAYGAIN_TRACK_*` tags.
+struct TrackGain {
+    /// e.g. "-6.52 dB"
+    gain: String,
+    /// e.g. "0.988553"
+    peak: String,
+}
+
+/// Measures the track with ffmpeg's `replaygain` filter, which prints its result
+/// to stderr as `track_gain = -6.52 dB` and `track_peak = 0.988553`.
+async fn measure(path: &Path) -> Result<TrackGain, Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .args(["-vn", "-af", "replaygain", "-f", "null", "-"])
+        .output()
+        .await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("ffmpeg failed with {}: {}", output.status, stderr.trim()).into());
+    }
+
+    let value = |name: &str| {
+        stderr
+            .lines()
+            .find_map(|line| line.split_once(&format!("{} = ", name)))
+            .map(|(_, value)| value.trim().to_string())
+    };
+    Ok(TrackGain {
+        gain: value("track_gain").ok_or("ffmpeg reported no track gain")?,
+        peak: value("track_peak").ok_or("ffmpeg reported no track peak")?,
+    })
+}
+
+/// Measures `file` and copies it into a new file carrying the result in its tags,
+/// leaving the audio itself untouched. Players that honour ReplayGain then level it
+/// with everything else downloaded from the channel.
+pub async fn tag(file: &LocalFile, name: &str) -> Result<LocalFile, Error> {
+    let gain = measure(&file.path).await?;
+    let output = LocalFile::scratch(name);
+    media::run(
+        Command::new("ffmpeg")
+            .args(["-y", "-v", "error", "-i"])
+            .arg(&file.path)
+            .args(["-map", "0", "-c", "copy"])
+            .arg("-metadata")
+            .arg(format!("REPLAYGAIN_TRACK_GAIN={}", gain.gain))
+            .arg("-metadata")
+            .arg(format!("REPLAYGAIN_TRACK_PEAK={}", gain.peak))
+            .arg(&output.path),
+    )
+    .await?;
+    tracing::debug!("Tagged track with ReplayGain {}", gain.gain);
+    Ok(output)
+}
@@ -0,0 +1,165 @@
+use anyhow::{Context, bail};
+use shuttle_runtime::SecretStore;
+use std::future::Future;
+use teloxide::RequestError;
+use tokio::time::{Duration, sleep};
+
+/// The backoff stops doubling after this many retries, however many attempts are
+/// allowed; by then it is over an hour even from a one-second base.
+const MAX_DOUBLINGS: u32 = 12;
+
+/// Kinds of failure a [`RetryPolicy`] can choose to retry, named as in `RETRY_ON`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorClass {
+    /// Connection problems and I/O errors.
+    Network,
+    /// Telegram flood control (`retry_after`).
+    RateLimit,
+    /// Requests Telegram rejected, e.g. "Bad Request: ...".
+    BadRequest,
+    /// Responses that could not be parsed.
+    InvalidResponse,
+}
+
+impl ErrorClass {
+    fn of(error: &RequestError) -> Self {
+        match error {
+            RequestError::Network(_) | RequestError::Io(_) => ErrorClass::Network,
+            RequestError::RetryAfter(_) => ErrorClass::RateLimit,
+            RequestError::Api(_) | RequestError::MigrateToChatId(_) => ErrorClass::BadRequest,
+            RequestError::InvalidJson { .. } => ErrorClass::InvalidResponse,
+        }
+    }
+
+    fn parse(raw: &str) -> anyhow::Result<Self> {
+        match raw {
+            "network" => Ok(ErrorClass::Network),
+            "rate_limit" => Ok(ErrorClass::RateLimit),
+            "bad_request" => Ok(ErrorClass::BadRequest),
+            "invalid_response" => Ok(ErrorClass::InvalidResponse),
+            other => bail!(
+                "RETRY_ON entries must be network, rate_limit, bad_request or invalid_response, not {}",
+                other
+            ),
+        }
+    }
+}
+
+/// How outgoing Telegram requests are retried. Configured with `RETRY_MAX_ATTEMPTS`,
+/// `RETRY_BASE_BACKOFF_SECS` and `RETRY_ON`, a comma-separated list of error classes.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_backoff: Duration,
+    pub retry_on: Vec<ErrorClass>,
+}
+
+impl Default for RetryPolicy {
+    /// Network hiccups and flood control are worth another try; anything Telegram
+    /// rejected outright will fail the same way again.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_backoff: Duration::from_secs(2),
+            retry_on: vec![ErrorClass::Network, ErrorClass::RateLimit],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Self> {
+        let mut policy = Self::default();
+
+        if let Some(attempts) = secrets.get("RETRY_MAX_ATTEMPTS") {
+            policy.max_attempts = attempts
+                .parse()
+                .ok()
+                .filter(|attempts| *attempts >= 1)
+                .context("RETRY_MAX_ATTEMPTS must be a positive number")?;
+        }
+        if let Some(secs) = secrets.get("RETRY_BASE_BACKOFF_SECS") {
+            policy.base_backoff = Duration::from_secs(
+                secs.parse()
+                    .context("RETRY_BASE_BACKOFF_SECS must be a number of seconds")?,
+            );
+        }
+        if let Some(classes) = secrets.get("RETRY_ON") {
+            policy.retry_on = classes
+                .split(',')
+                .map(str::trim)
+                .filter(|class| !class.is_empty())
+                .map(ErrorClass::parse)
+                .collect::<anyhow::Result<_>>()?;
+        }
+
+        Ok(policy)
+    }
+
+    fn is_retryable(&self, error: &RequestError) -> bool {
+        self.retry_on.contains(&ErrorClass::of(error))
+    }
+
+    /// How long to wait after failed attempt number `attempt`, counting from 1.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(MAX_DOUBLINGS);
+        self.base_backoff.saturating_mul(2u32.pow(doublings))
+    }
+
+    /// Runs `request` up to `max_attempts` times with exponential backoff, honouring
+    /// Telegram's `retry_after` when flood control kicks in.
+    pub async fn run<T, F, Fut>(&self, mut request: F) -> Result<T, RequestError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, RequestError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.max_attempts && self.is_retryable(&e) => {
+                    let delay = match &e {
+                        RequestError::RetryAfter(seconds) => seconds.duration(),
+                        _ => self.backoff(attempt),
+                    };
+                    tracing::warn!(
+                        "Attempt {}/{} failed: {}; retrying in {:?}",
+                        attempt,
+                        self.max_attempts,
+                        e,
+                        delay
+                    );
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_then_levels_off() {
+        let policy = RetryPolicy {
+            max_attempts: u32::MAX,
+            base_backoff: Duration::from_secs(1),
+            retry_on: Vec::new(),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(40), Duration::from_secs(1 << MAX_DOUBLINGS));
+        assert_eq!(policy.backoff(u32::MAX), policy.backoff(40));
+    }
+
+    #[test]
+    fn backoff_saturates_instead_of_overflowing() {
+        let policy = RetryPolicy {
+            base_backoff: Duration::MAX,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(5), Duration::MAX);
+    }
+}
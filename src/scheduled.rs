@@ -0,0 +1,289 @@
+use crate::{ServerSecretsState, announce, dry_run};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    prelude::*,
+    types::{FileId, InputFile, ParseMode},
+};
+use tokio::time::sleep;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+/// How much of each post `/scheduled` shows.
+const LIST_CHARS: usize = 60;
+
+/// A text or photo post waiting for its time, kept in the database so it survives
+/// restarts.
+#[derive(FromRow)]
+struct ScheduledPost {
+    id: i64,
+    post_at: DateTime<Utc>,
+    /// MarkdownV2, or the photo's caption.
+    text: String,
+    photo_file_id: Option<String>,
+}
+
+impl ScheduledPost {
+    /// The start of the text, good enough to recognise a post by; the escapes are
+    /// only noise here.
+    fn preview(&self) -> String {
+        let text = self.text.replace('\\', "");
+        let mut line: String = text.chars().take(LIST_CHARS).collect();
+        if line.len() < text.len() {
+            line.push('…');
+        }
+        line
+    }
+
+    fn summary(&self) -> String {
+        let icon = if self.photo_file_id.is_some() {
+            "🖼"
+        } else {
+            "📝"
+        };
+        format!(
+            "#{} {} UTC {} {}",
+            self.id,
+            self.post_at.format(TIME_FORMAT),
+            icon,
+            self.preview()
+        )
+    }
+}
+
+/// "2026-11-01 18:00 Doors open at eight" → the time, in UTC like the daily jobs.
+fn parse_time(args: &str) -> Option<DateTime<Utc>> {
+    let mut words = args.split_whitespace();
+    let raw = format!("{} {}", words.next()?, words.next()?);
+    NaiveDateTime::parse_from_str(&raw, TIME_FORMAT)
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// `text` without its first `n` words.
+fn skip_words(mut text: &str, n: usize) -> &str {
+    for _ in 0..n {
+        text = text
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest);
+    }
+    text.trim()
+}
+
+async fn publish(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    post: &ScheduledPost,
+) -> Result<(), Error> {
+    let channel_id = secrets.publish_channel_id();
+    let message = match &post.photo_file_id {
+        Some(file_id) => {
+            let photo = InputFile::file_id(FileId(file_id.clone()));
+            secrets
+                .retry_policy
+                .run(|| {
+                    let request = bot.send_photo(channel_id, photo.clone());
+                    if post.text.is_empty() {
+                        request.send()
+                    } else {
+                        request
+                            .caption(post.text.clone())
+                            .parse_mode(ParseMode::MarkdownV2)
+                            .send()
+                    }
+                })
+                .await?
+        }
+        None => {
+            secrets
+                .retry_policy
+                .run(|| {
+                    bot.send_message(channel_id, post.text.clone())
+                        .parse_mode(ParseMode::MarkdownV2)
+                        .send()
+                })
+                .await?
+        }
+    };
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    tracing::info!("Published scheduled post #{}", post.id);
+    Ok(())
+}
+
+/// Sleeps until the post is due, then takes it off the schedule and publishes it. A
+/// post cancelled in the meantime is simply gone by then.
+fn spawn(bot: Bot, secrets: Arc<ServerSecretsState>, id: i64, post_at: DateTime<Utc>) {
+    tokio::spawn(async move {
+        sleep((post_at - Utc::now()).to_std().unwrap_or_default()).await;
+        if dry_run::is_active(&secrets) {
+            tracing::info!("Dry run: not publishing scheduled post #{}", id);
+            return;
+        }
+
+        let post: Option<ScheduledPost> =
+            match sqlx::query_as("DELETE FROM scheduled_posts WHERE id = $1 RETURNING *")
+                .bind(id)
+                .fetch_optional(&secrets.db)
+                .await
+            {
+                Ok(post) => post,
+                Err(e) => {
+                    tracing::error!("Failed to take scheduled post #{}: {}", id, e);
+                    return;
+                }
+            };
+        let Some(post) = post else {
+            return;
+        };
+        if let Err(e) = publish(&bot, &secrets, &post).await {
+            tracing::error!("Failed to publish scheduled post #{}: {}", id, e);
+            let text = format!("⚠️ Scheduled post #{} failed: {}", id, e);
+            if let Err(e) = bot.send_message(secrets.me_id, text).await {
+                tracing::warn!("Failed to report scheduled post failure: {}", e);
+            }
+        }
+    });
+}
+
+async fn list(db: &PgPool) -> sqlx::Result<Vec<ScheduledPost>> {
+    sqlx::query_as("SELECT * FROM scheduled_posts ORDER BY post_at, id")
+        .fetch_all(db)
+        .await
+}
+
+/// Posts due by `until`, soonest first, as their time and a short description.
+pub async fn upcoming(
+    db: &PgPool,
+    until: DateTime<Utc>,
+) -> sqlx::Result<Vec<(DateTime<Utc>, String)>> {
+    let posts = list(db).await?;
+    Ok(posts
+        .iter()
+        .filter(|post| post.post_at <= until)
+        .map(|post| {
+            let label = format!("Scheduled post #{}: {}", post.id, post.preview());
+            (post.post_at, label)
+        })
+        .collect())
+}
+
+/// `/schedule <YYYY-MM-DD HH:MM> <text>`, sent as text or as the caption of a photo:
+/// the post goes to the channel at that time (UTC), keeping the formatting it was
+/// written with.
+pub async fn handle_schedule(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let usage = "Usage: /schedule YYYY-MM-DD HH:MM <text>, as text or as the caption of a photo (times are UTC)";
+    let Some(post_at) = parse_time(args) else {
+        bot.send_message(message.chat.id, usage).await?;
+        return Ok(());
+    };
+    if post_at <= Utc::now() {
+        bot.send_message(message.chat.id, "That time has already passed.")
+            .await?;
+        return Ok(());
+    }
+    let photo_file_id = message
+        .photo()
+        .and_then(|sizes| sizes.last())
+        .map(|photo| photo.file.id.0.clone());
+    // The command and the two words of the time come first.
+    let text = announce::markdown_text(message)
+        .map(|text| skip_words(&text, 3).to_string())
+        .unwrap_or_default();
+    if text.is_empty() && photo_file_id.is_none() {
+        bot.send_message(message.chat.id, usage).await?;
+        return Ok(());
+    }
+
+    let id: i64 = sqlx::query_scalar(
+        "INSERT INTO scheduled_posts (post_at, text, photo_file_id) VALUES ($1, $2, $3)
+         RETURNING id",
+    )
+    .bind(post_at)
+    .bind(&text)
+    .bind(&photo_file_id)
+    .fetch_one(&secrets.db)
+    .await?;
+    spawn(bot.clone(), secrets.clone(), id, post_at);
+
+    bot.send_message(
+        message.chat.id,
+        format!(
+            "🗓 Scheduled #{} for {} UTC. /unschedule {} to cancel it.",
+            id,
+            post_at.format(TIME_FORMAT),
+            id
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// `/scheduled`: the posts still to go out, soonest first.
+pub async fn handle_list(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let posts = list(&secrets.db).await?;
+    let text = if posts.is_empty() {
+        "Nothing scheduled.".to_string()
+    } else {
+        posts
+            .iter()
+            .map(ScheduledPost::summary)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    bot.send_message(message.chat.id, text).await?;
+    Ok(())
+}
+
+/// `/unschedule <id>`.
+pub async fn handle_unschedule(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let Ok(id) = args.trim().trim_start_matches('#').parse::<i64>() else {
+        bot.send_message(message.chat.id, "Usage: /unschedule <id>, see /scheduled")
+            .await?;
+        return Ok(());
+    };
+    let deleted = sqlx::query("DELETE FROM scheduled_posts WHERE id = $1")
+        .bind(id)
+        .execute(&secrets.db)
+        .await?
+        .rows_affected();
+    let text = if deleted == 0 {
+        format!("No scheduled post #{}.", id)
+    } else {
+        format!("Cancelled scheduled post #{}.", id)
+    };
+    bot.send_message(message.chat.id, text).await?;
+    Ok(())
+}
+
+/// Picks up posts scheduled before a restart; any that came due while the bot was
+/// down go out straight away.
+pub async fn resume(bot: &Bot, secrets: &Arc<ServerSecretsState>) -> sqlx::Result<()> {
+    let posts = list(&secrets.db).await?;
+    if !posts.is_empty() {
+        tracing::info!("Resuming {} scheduled post(s)", posts.len());
+    }
+    for post in posts {
+        spawn(bot.clone(), secrets.clone(), post.id, post.post_at);
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use serde::{Serialize, de::DeserializeOwned};
+use sqlx::{PgPool, types::Json};
+
+/// Reads a setting changed at runtime through a bot command.
+pub async fn get<T: DeserializeOwned + Send + Unpin + 'static>(
+    db: &PgPool,
+    key: &str,
+) -> sqlx::Result<Option<T>> {
+    let value: Option<Json<T>> = sqlx::query_scalar("SELECT value FROM settings WHERE key = $1")
+        .bind(key)
+        .fetch_optional(db)
+        .await?;
+    Ok(value.map(|value| value.0))
+}
+
+pub async fn set<T: Serialize + Sync>(db: &PgPool, key: &str, value: &T) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO settings (key, value) VALUES ($1, $2)
+         ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, updated_at = now()",
+    )
+    .bind(key)
+    .bind(Json(value))
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn clear(db: &PgPool, key: &str) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM settings WHERE key = $1")
+        .bind(key)
+        .execute(db)
+        .await?;
+    Ok(())
+}
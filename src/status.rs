@@ -0,0 +1,163 @@
+use crate::{
+    ServerSecretsState, catalog, dead_letter, dry_run, jobs, maintenance, media::BotApiMode,
+    mirror_set, test_mode, vacation, webhook,
+};
+use chrono::{DateTime, Utc};
+use std::time::{Duration, Instant};
+use teloxide::prelude::*;
+
+/// Telegram keeps a webhook error around after deliveries recover; older ones are
+/// shown without the warning sign.
+const RECENT_WEBHOOK_ERROR: chrono::Duration = chrono::Duration::hours(1);
+
+/// "3d 4h", "4h 12m" or "12m 5s".
+fn span(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes) = (secs / 86400, secs % 86400 / 3600, secs % 3600 / 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m {}s", minutes, secs % 60),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+fn at(time: DateTime<Utc>) -> String {
+    let ago = (Utc::now() - time).to_std().unwrap_or_default();
+    format!("{} UTC ({} ago)", time.format("%Y-%m-%d %H:%M"), span(ago))
+}
+
+async fn queue_line(secrets: &ServerSecretsState) -> String {
+    let queue = &secrets.message_queue;
+    let waiting = queue.snapshot().await.len();
+    let state = if queue.is_paused() {
+        "paused".to_string()
+    } else if vacation::is_active(secrets) {
+        "held for the vacation".to_string()
+    } else {
+        match queue.next_send().await {
+            Some(delay) => format!("posting in {}", span(delay)),
+            None => "idle".to_string(),
+        }
+    };
+    format!("📥 Queue: {} waiting, {}", waiting, state)
+}
+
+fn mode_line(secrets: &ServerSecretsState) -> String {
+    let mode = if maintenance::is_on(secrets) {
+        "maintenance, not taking uploads".to_string()
+    } else if dry_run::is_active(secrets) {
+        "dry run, nothing is sent".to_string()
+    } else if test_mode::is_active(secrets) {
+        format!("test mode, publishing to {}", secrets.publish_channel_id())
+    } else {
+        format!("live, publishing to {}", secrets.publish_channel_id())
+    };
+    format!("🎛 Mode: {}", mode)
+}
+
+fn next_job_line() -> String {
+    match jobs::next_run() {
+        Some((name, next)) => {
+            let until = (next - Utc::now()).to_std().unwrap_or_default();
+            format!(
+                "⏰ Next job: {} at {} UTC (in {})",
+                name,
+                next.format("%H:%M"),
+                span(until)
+            )
+        }
+        None => "⏰ Next job: none scheduled".to_string(),
+    }
+}
+
+async fn last_post_line(secrets: &ServerSecretsState) -> String {
+    match catalog::stats(&secrets.db).await {
+        Ok(stats) => match stats.last_posted_at {
+            Some(posted_at) => format!("🎵 Last post: {}", at(posted_at)),
+            None => "🎵 Last post: none yet".to_string(),
+        },
+        Err(e) => format!("🎵 Last post: unknown ({})", e),
+    }
+}
+
+async fn webhook_line(bot: &Bot, secrets: &ServerSecretsState) -> String {
+    let info = match bot.get_webhook_info().await {
+        Ok(info) => info,
+        Err(e) => return format!("⚠️ Webhook: could not check ({})", e),
+    };
+    let Some(url) = info.url else {
+        return "⚠️ Webhook: not set".to_string();
+    };
+    let recent_error = info
+        .last_error_date
+        .is_some_and(|error_at| Utc::now() - error_at < RECENT_WEBHOOK_ERROR);
+    let mut line = format!(
+        "{} Webhook: set on {}, {} update(s) pending",
+        if recent_error { "⚠️" } else { "🪝" },
+        url.host_str().unwrap_or_default(),
+        info.pending_update_count
+    );
+    // Recorded by the watchdog, so only there when it runs.
+    if let Ok(Some(before)) = webhook::pending_since(&secrets.db, chrono::Duration::hours(1)).await
+        && before as u32 != info.pending_update_count
+    {
+        line.push_str(&format!(" ({} an hour ago)", before));
+    }
+    if let Some(error_at) = info.last_error_date {
+        line.push_str(&format!(
+            "\nLast error {}: {}",
+            at(error_at),
+            info.last_error_message.as_deref().unwrap_or("unknown")
+        ));
+    }
+    line
+}
+
+async fn storage_lines(secrets: &ServerSecretsState) -> String {
+    let started = Instant::now();
+    let database = match sqlx::query("SELECT 1").execute(&secrets.db).await {
+        Ok(_) => format!("🗄 Database: up, {} ms", started.elapsed().as_millis()),
+        Err(e) => format!("⚠️ Database: {}", e),
+    };
+    let bot_api = match secrets.bot_api_mode {
+        BotApiMode::Cloud => "cloud",
+        BotApiMode::Local => "local server",
+    };
+    let failed = match dead_letter::count(&secrets.db).await {
+        Ok(0) => String::new(),
+        Ok(count) => format!("\n📭 {} failed item(s), see /failed", count),
+        Err(_) => String::new(),
+    };
+    format!(
+        "{}\n📦 Bot API: {}, uploads up to {} MB{}",
+        database,
+        bot_api,
+        secrets.bot_api_mode.upload_limit() / 1024 / 1024,
+        failed
+    )
+}
+
+/// `/status`: one message with everything worth checking when the bot seems off.
+/// Each line reports its own failure, so one broken dependency does not hide the rest.
+pub async fn report(bot: &Bot, secrets: &ServerSecretsState) -> String {
+    let (queue, last_post, webhook, storage, mirrors) = tokio::join!(
+        queue_line(secrets),
+        last_post_line(secrets),
+        webhook_line(bot, secrets),
+        storage_lines(secrets),
+        mirror_set::status_line(secrets),
+    );
+    [
+        format!("🟢 Up {}", span(secrets.started_at.elapsed())),
+        queue,
+        mode_line(secrets),
+        next_job_line(),
+        last_post,
+        webhook,
+        storage,
+    ]
+    .into_iter()
+    .chain(mirrors)
+    .collect::<Vec<_>>()
+    .join("\n")
+}
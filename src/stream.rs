@@ -0,0 +1,307 @@
+use crate::{ServerSecretsState, catalog::Track, media::BotApiMode, web::HttpError};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::io::SeekFrom;
+use std::path::Path;
+use teloxide::{prelude::*, types::FileId};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// How much of a file on local disk is read at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The audio of a cataloged track on its way to an HTTP client, proxied from
+/// Telegram so it never has to be stored twice.
+pub struct AudioStream {
+    /// 200 for the whole file, 206 for the range asked for.
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: BoxStream<'static, Vec<u8>>,
+}
+
+/// What browsers and podcast apps should take the file for, by its extension.
+fn content_type(file_name: Option<&str>) -> &'static str {
+    let extension = file_name
+        .and_then(|name| Path::new(name).extension())
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    match extension.as_deref() {
+        Some("m4a" | "mp4" | "aac") => "audio/mp4",
+        Some("flac") => "audio/flac",
+        Some("ogg" | "oga" | "opus") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        _ => "audio/mpeg",
+    }
+}
+
+/// The first and last byte of a `Range: bytes=...` header. Several ranges, or units
+/// other than bytes, get the whole file, which HTTP allows; a range outside the file
+/// is an error.
+fn parse_range(raw: &str, size: u64) -> Result<Option<(u64, u64)>, HttpError> {
+    let unsatisfiable = || HttpError::new(416, format!("The file is {} bytes", size));
+    let Some(spec) = raw.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    if size == 0 {
+        return Err(unsatisfiable());
+    }
+    let (start, end) = spec.split_once('-').ok_or_else(unsatisfiable)?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().map_err(|_| unsatisfiable())?;
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        (start, "") => (start.parse().map_err(|_| unsatisfiable())?, size - 1),
+        (start, end) => (
+            start.parse().map_err(|_| unsatisfiable())?,
+            end.parse::<u64>()
+                .map_err(|_| unsatisfiable())?
+                .min(size - 1),
+        ),
+    };
+    if start > end {
+        return Err(unsatisfiable());
+    }
+    Ok(Some((start, end)))
+}
+
+/// Serves a file the local Bot API server keeps on our disk, reading only the range
+/// asked for.
+async fn from_disk(path: &Path, range: Option<&str>) -> Result<AudioStream, HttpError> {
+    let failed = |e| HttpError::internal("Failed to read the audio file", e);
+    let mut file = tokio::fs::File::open(path).await.map_err(failed)?;
+    let size = file.metadata().await.map_err(failed)?.len();
+    let range = range
+        .map(|raw| parse_range(raw, size))
+        .transpose()?
+        .flatten();
+    let (start, end) = range.unwrap_or((0, size.saturating_sub(1)));
+    let length = if size == 0 { 0 } else { end - start + 1 };
+    file.seek(SeekFrom::Start(start)).await.map_err(failed)?;
+
+    let mut headers = vec![("Content-Length", length.to_string())];
+    if range.is_some() {
+        headers.push(("Content-Range", format!("bytes {}-{}/{}", start, end, size)));
+    }
+    let body = stream::unfold((file, length), |(mut file, left)| async move {
+        if left == 0 {
+            return None;
+        }
+        let mut chunk = vec![0; CHUNK_SIZE.min(left as usize)];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((chunk, (file, left - read as u64)))
+            }
+            Err(e) => {
+                tracing::warn!("Stopped streaming audio: {}", e);
+                None
+            }
+        }
+    });
+    Ok(AudioStream {
+        status: if range.is_some() { 206 } else { 200 },
+        headers,
+        body: body.boxed(),
+    })
+}
+
+/// Relays Telegram's file download, passing the `Range` header through so it is
+/// Telegram that seeks.
+async fn from_telegram(
+    bot: &Bot,
+    path: &str,
+    range: Option<&str>,
+) -> Result<AudioStream, HttpError> {
+    fn failed(e: impl std::fmt::Display) -> HttpError {
+        HttpError::internal("Failed to download the audio file", e)
+    }
+    let url = bot
+        .api_url()
+        .join(&format!("file/bot{}/{}", bot.token(), path))
+        .map_err(failed)?;
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(range) = range {
+        request = request.header("Range", range);
+    }
+    let response = request.send().await.map_err(|e| failed(e.without_url()))?;
+    let status = response.status().as_u16();
+    if status == 416 {
+        return Err(HttpError::new(416, "Range not satisfiable"));
+    }
+    if !response.status().is_success() {
+        return Err(HttpError::new(
+            502,
+            format!("Telegram answered {} for the file", status),
+        ));
+    }
+
+    let headers = ["Content-Length", "Content-Range"]
+        .into_iter()
+        .filter_map(|name| {
+            let value = response.headers().get(name)?.to_str().ok()?;
+            Some((name, value.to_string()))
+        })
+        .collect();
+    let body = stream::unfold(response, |mut response| async move {
+        match response.chunk().await {
+            Ok(Some(chunk)) => Some((chunk.to_vec(), response)),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("Stopped streaming audio: {}", e.without_url());
+                None
+            }
+        }
+    });
+    Ok(AudioStream {
+        status,
+        headers,
+        body: body.boxed(),
+    })
+}
+
+/// The audio of `track`, or the part of it `range` asks for. Paid tracks are refused.
+pub async fn audio(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    track: &Track,
+    range: Option<&str>,
+) -> Result<AudioStream, HttpError> {
+    if track.paid {
+        return Err(HttpError::new(
+            403,
+            "Paid tracks can only be played in the channel",
+        ));
+    }
+    let size = track.file_size.unwrap_or_default().max(0) as u64;
+    if !secrets.bot_api_mode.can_download(size) {
+        return Err(HttpError::new(
+            413,
+            "The file is over the cloud Bot API download limit",
+        ));
+    }
+    let file = secrets
+        .retry_policy
+        .run(|| bot.get_file(FileId(track.file_id.clone())).send())
+        .await
+        .map_err(|e| HttpError::internal("Failed to look up the audio file", e))?;
+
+    let mut stream =
+        if secrets.bot_api_mode == BotApiMode::Local && Path::new(&file.path).is_absolute() {
+            from_disk(Path::new(&file.path), range).await?
+        } else {
+            from_telegram(bot, &file.path, range).await?
+        };
+    stream.headers.extend([
+        (
+            "Content-Type",
+            content_type(track.file_name.as_deref()).to_string(),
+        ),
+        ("Accept-Ranges", "bytes".to_string()),
+    ]);
+    Ok(stream)
+}
+
+#[cfg(feature = "rocket")]
+mod rocket_impls {
+    use super::*;
+    use rocket::{
+        Request,
+        http::{Header, Status},
+        request::{FromRequest, Outcome},
+        response::{self, Responder, stream::ByteStream},
+    };
+
+    /// The `Range` header of a request, if it has one.
+    pub struct Range(pub Option<String>);
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for Range {
+        type Error = ();
+
+        async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+            Outcome::Success(Range(req.headers().get_one("Range").map(str::to_string)))
+        }
+    }
+
+    impl<'r> Responder<'r, 'r> for AudioStream {
+        fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+            let mut response = ByteStream(self.body).respond_to(req)?;
+            response.set_status(Status::new(self.status));
+            for (name, value) in self.headers {
+                response.set_header(Header::new(name, value));
+            }
+            Ok(response)
+        }
+    }
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_impls::Range;
+
+#[cfg(feature = "axum")]
+mod axum_impls {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{HeaderValue, StatusCode},
+        response::{IntoResponse, Response},
+    };
+
+    impl IntoResponse for AudioStream {
+        fn into_response(self) -> Response {
+            let body = self.body.map(Ok::<_, std::convert::Infallible>);
+            let mut response = Body::from_stream(body).into_response();
+            *response.status_mut() = StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK);
+            for (name, value) in self.headers {
+                if let Ok(value) = HeaderValue::from_str(&value) {
+                    response.headers_mut().insert(name, value);
+                }
+            }
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(raw: &str, size: u64) -> u16 {
+        parse_range(raw, size).err().map_or(200, |e| e.status)
+    }
+
+    #[test]
+    fn open_ranges_run_to_the_end() {
+        assert_eq!(parse_range("bytes=0-", 100).ok(), Some(Some((0, 99))));
+        assert_eq!(parse_range("bytes=40-", 100).ok(), Some(Some((40, 99))));
+    }
+
+    #[test]
+    fn suffix_ranges_take_the_last_bytes() {
+        assert_eq!(parse_range("bytes=-10", 100).ok(), Some(Some((90, 99))));
+        assert_eq!(parse_range("bytes=-500", 100).ok(), Some(Some((0, 99))));
+        assert_eq!(status("bytes=-0", 100), 416);
+    }
+
+    #[test]
+    fn ranges_past_the_end() {
+        assert_eq!(status("bytes=100-", 100), 416);
+        assert_eq!(status("bytes=150-200", 100), 416);
+        assert_eq!(parse_range("bytes=90-500", 100).ok(), Some(Some((90, 99))));
+        assert_eq!(status("bytes=0-", 0), 416);
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert_eq!(status("bytes=abc-", 100), 416);
+        assert_eq!(status("bytes=5", 100), 416);
+        assert_eq!(status("bytes=10-5", 100), 416);
+        assert_eq!(status("bytes=-x", 100), 416);
+        // Other units and several ranges get the whole file.
+        assert_eq!(parse_range("items=0-5", 100).ok(), Some(None));
+        assert_eq!(parse_range("bytes=0-5,10-20", 100).ok(), Some(None));
+    }
+}
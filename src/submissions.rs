@@ -0,0 +1,133 @@
+use crate::{QueuedMessage, ServerSecretsState, blurbs, cleanup, welcome};
+use std::sync::Arc;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ReplyParameters},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "sub:";
+
+/// What strangers are told when they have not sent their submission yet, unless
+/// `STRANGER_REPLY` says otherwise.
+pub const DEFAULT_PROMPT: &str = "📨 Send us your demo, as an audio file or a link with a few words, and it goes straight to the label.";
+const ALREADY_SUBMITTED: &str = "📨 Thanks, we have your submission and will get back to you.";
+
+/// Marks `user_id`'s submission as sent. `false` when they have sent one before.
+async fn claim(secrets: &ServerSecretsState, user_id: i64) -> sqlx::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE intruders SET submitted_at = now() WHERE user_id = $1 AND submitted_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(&secrets.db)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+fn keyboard(user_id: i64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(
+            "✅ Accept",
+            format!("{}accept:{}", CALLBACK_PREFIX, user_id),
+        ),
+        InlineKeyboardButton::callback("Ignore", format!("{}ignore:{}", CALLBACK_PREFIX, user_id)),
+    ]])
+}
+
+/// `STRANGER_POLICY=submit`: forwards the first real message of someone who is not
+/// the owner, audio or text, with buttons to accept or ignore it. Commands like a bare
+/// `/start` get `prompt`; anything after the submission a thank-you.
+pub async fn handle(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+    user_id: i64,
+    label: &str,
+    prompt: &str,
+) -> Result<(), Error> {
+    if message.text().is_some_and(|text| text.starts_with('/')) {
+        let text = welcome::render(prompt, secrets, message).await;
+        bot.send_message(message.chat.id, text).await?;
+        return Ok(());
+    }
+    if !claim(secrets, user_id).await? {
+        bot.send_message(message.chat.id, ALREADY_SUBMITTED).await?;
+        return Ok(());
+    }
+
+    let owner = secrets.me_id;
+    let forwarded = bot
+        .forward_message(owner, message.chat.id, message.id)
+        .await?;
+    bot.send_message(owner, format!("📥 Submission from {}", label))
+        .reply_parameters(ReplyParameters::new(forwarded.id))
+        .reply_markup(keyboard(user_id))
+        .await?;
+    tracing::info!(user_id, "Submission forwarded");
+    bot.send_message(message.chat.id, "📨 Thanks! It's with the label now.")
+        .await?;
+    Ok(())
+}
+
+/// Handles the accept/ignore buttons under a submission. An accepted audio goes the
+/// way of an upload from the owner; either way accepting lets the sender know.
+pub async fn handle_callback(
+    bot: &Arc<Bot>,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &Arc<ServerSecretsState>,
+) -> Result<(), Error> {
+    let (action, user_id) = data.split_once(':').ok_or("Malformed callback data")?;
+    let user_id: i64 = user_id.parse()?;
+    let card = query.regular_message().ok_or("Submission card is gone")?;
+
+    let status = match action {
+        "accept" => {
+            let audio = card
+                .reply_to_message()
+                .and_then(|forwarded| Some((forwarded.id, forwarded.audio()?)));
+            if let Some((forwarded_id, audio)) = audio {
+                let queued = QueuedMessage {
+                    audio: audio.clone(),
+                    tags: Vec::new(),
+                    message_id: forwarded_id.0,
+                    via: None,
+                    retag: None,
+                    description: None,
+                    blurb: None,
+                    queued_at: chrono::Utc::now(),
+                    theme: None,
+                    stars: None,
+                    part: None,
+                };
+                blurbs::queue(bot, secrets, card.chat.id, queued).await?;
+            }
+            if let Err(e) = bot
+                .send_message(
+                    ChatId(user_id),
+                    "🎉 Your submission was accepted, thank you!",
+                )
+                .await
+            {
+                tracing::warn!("Failed to tell the submitter: {}", e);
+                cleanup::reply(
+                    bot,
+                    secrets,
+                    card.chat.id,
+                    format!("Couldn't tell {} it was accepted: {}", user_id, e),
+                )
+                .await?;
+            }
+            "Accepted ✅"
+        }
+        "ignore" => "Ignored",
+        _ => return Err("Unknown submission action".into()),
+    };
+
+    bot.answer_callback_query(query.id.clone())
+        .text(status)
+        .await?;
+    bot.edit_message_reply_markup(card.chat.id, card.id).await?;
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use crate::{ServerSecretsState, telegram::OutgoingText, vacation};
+use anyhow::bail;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "subs:";
+
+#[derive(Clone, Copy)]
+pub enum Mode {
+    /// Post milestones to the channel straight away.
+    Publish,
+    /// DM the owner a draft with publish/skip buttons.
+    Approve,
+}
+
+impl Mode {
+    /// Parses the `SUBSCRIBER_MILESTONES` secret. Counts are recorded either way; a
+    /// missing value or `off` only keeps milestones from being announced.
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match raw.map(str::trim) {
+            None | Some("") | Some("off") => Ok(None),
+            Some("publish") => Ok(Some(Mode::Publish)),
+            Some("approve") => Ok(Some(Mode::Approve)),
+            Some(other) => bail!(
+                "SUBSCRIBER_MILESTONES must be off, publish or approve, not {}",
+                other
+            ),
+        }
+    }
+}
+
+/// 100, 250, 500, 1000, 2500, 5000, … up to `count`.
+fn milestones_up_to(count: i32) -> Vec<i32> {
+    let mut milestones = Vec::new();
+    let mut base = 100;
+    while base <= count {
+        for milestone in [base, base * 5 / 2, base * 5] {
+            if milestone <= count {
+                milestones.push(milestone);
+            }
+        }
+        base = match base.checked_mul(10) {
+            Some(base) => base,
+            None => break,
+        };
+    }
+    milestones
+}
+
+/// "10,000".
+pub fn thousands(n: i32) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+fn render(milestone: i32) -> String {
+    format!(
+        "{} subscribers 🎉\nThank you for listening!",
+        thousands(milestone)
+    )
+}
+
+async fn record(db: &PgPool, date: NaiveDate, count: i32) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO subscriber_counts (counted_on, count) VALUES ($1, $2)
+         ON CONFLICT (counted_on) DO UPDATE SET count = EXCLUDED.count, counted_at = now()",
+    )
+    .bind(date)
+    .bind(count)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Marks the milestones up to `count` as reached and returns the ones that were not
+/// before, highest first.
+async fn reach(db: &PgPool, count: i32) -> sqlx::Result<Vec<i32>> {
+    sqlx::query_scalar(
+        "INSERT INTO subscriber_milestones (milestone) SELECT unnest($1::INTEGER[])
+         ON CONFLICT DO NOTHING RETURNING milestone",
+    )
+    .bind(milestones_up_to(count))
+    .fetch_all(db)
+    .await
+    .map(|mut reached: Vec<i32>| {
+        reached.sort_unstable_by(|a, b| b.cmp(a));
+        reached
+    })
+}
+
+async fn publish(secrets: &ServerSecretsState, milestone: i32) -> Result<(), Error> {
+    let channel_id = secrets.publish_channel_id();
+    let text = OutgoingText::plain(render(milestone));
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_message(channel_id, &text))
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Daily job storing the channel's subscriber count and announcing the highest
+/// milestone crossed since the last count. The first count only marks milestones
+/// already behind the channel, so they are not announced late. During a vacation
+/// milestones are left for the first count after it.
+pub async fn run(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    mode: Option<Mode>,
+    date: NaiveDate,
+) -> Result<(), Error> {
+    let count = bot.get_chat_member_count(secrets.channel_id()).await? as i32;
+    let first_count: bool =
+        sqlx::query_scalar("SELECT NOT EXISTS (SELECT 1 FROM subscriber_counts)")
+            .fetch_one(&secrets.db)
+            .await?;
+    record(&secrets.db, date, count).await?;
+    tracing::info!("Channel has {} subscribers", count);
+    if vacation::is_active(secrets) {
+        return Ok(());
+    }
+
+    let reached = reach(&secrets.db, count).await?;
+    let (Some(mode), Some(&milestone), false) = (mode, reached.first(), first_count) else {
+        return Ok(());
+    };
+    match mode {
+        Mode::Publish => publish(secrets, milestone).await?,
+        Mode::Approve => {
+            let keyboard = InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback(
+                    "Publish",
+                    format!("{}publish:{}", CALLBACK_PREFIX, milestone),
+                ),
+                InlineKeyboardButton::callback(
+                    "Skip",
+                    format!("{}skip:{}", CALLBACK_PREFIX, milestone),
+                ),
+            ]]);
+            bot.send_message(secrets.me_id, render(milestone))
+                .reply_markup(keyboard)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles the publish/skip buttons of a milestone approval request.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let (action, milestone) = data.split_once(':').ok_or("Malformed callback data")?;
+    let milestone: i32 = milestone.parse()?;
+
+    let status = match action {
+        "publish" => {
+            publish(secrets, milestone).await?;
+            "Published ✅"
+        }
+        "skip" => "Skipped",
+        _ => return Err("Unknown milestone action".into()),
+    };
+
+    bot.answer_callback_query(query.id.clone())
+        .text(status)
+        .await?;
+    if let Some(message) = query.regular_message() {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .await?;
+    }
+    Ok(())
+}
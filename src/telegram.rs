@@ -0,0 +1,608 @@
+//! The Telegram calls the publishing path makes, behind a trait so the queue can run
+//! against something other than the Bot API. Features that move files around
+//! (previews, waveforms, cover art, releases) still talk to [`Bot`] directly.
+
+use crate::format::Format;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use teloxide::{
+    RequestError,
+    prelude::*,
+    types::{
+        Audio, FileId, InlineKeyboardMarkup, InputFile, InputPaidMedia, InputPaidMediaVideo,
+        MessageId, ParseMode, ReplyParameters, Seconds, ThreadId,
+    },
+};
+
+/// What callers need from a message the client sent.
+#[derive(Clone, Debug)]
+pub struct Sent {
+    pub chat_id: ChatId,
+    pub id: MessageId,
+    /// The audio as Telegram stored it, when the message has one.
+    pub audio: Option<Audio>,
+}
+
+impl From<Message> for Sent {
+    fn from(message: Message) -> Self {
+        Sent {
+            chat_id: message.chat.id,
+            id: message.id,
+            audio: message.audio().cloned(),
+        }
+    }
+}
+
+/// A text message; plain text with link previews unless told otherwise.
+#[derive(Clone, Debug)]
+pub struct OutgoingText {
+    pub text: String,
+    pub markdown: bool,
+    pub link_preview: bool,
+    pub silent: bool,
+    pub reply_markup: Option<InlineKeyboardMarkup>,
+}
+
+impl OutgoingText {
+    pub fn plain(text: impl Into<String>) -> Self {
+        OutgoingText {
+            text: text.into(),
+            markdown: false,
+            link_preview: true,
+            silent: false,
+            reply_markup: None,
+        }
+    }
+
+    /// Text in MarkdownV2, already escaped.
+    pub fn markdown(text: impl Into<String>) -> Self {
+        OutgoingText {
+            markdown: true,
+            ..Self::plain(text)
+        }
+    }
+
+    pub fn without_link_preview(self) -> Self {
+        OutgoingText {
+            link_preview: false,
+            ..self
+        }
+    }
+
+    pub fn silent(self) -> Self {
+        OutgoingText {
+            silent: true,
+            ..self
+        }
+    }
+
+    pub fn reply_markup(self, markup: InlineKeyboardMarkup) -> Self {
+        OutgoingText {
+            reply_markup: Some(markup),
+            ..self
+        }
+    }
+}
+
+/// An audio post: a file Telegram already has, or one uploaded from disk.
+#[derive(Clone, Debug)]
+pub enum AudioSource {
+    FileId(FileId),
+    Upload {
+        path: PathBuf,
+        file_name: Option<String>,
+    },
+}
+
+/// A photo uploaded from disk.
+#[derive(Clone, Debug)]
+pub struct OutgoingPhoto {
+    pub path: PathBuf,
+    /// MarkdownV2.
+    pub caption: Option<String>,
+    pub reply_to: Option<MessageId>,
+    pub silent: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct OutgoingAudio {
+    pub source: AudioSource,
+    pub caption: String,
+    pub caption_format: Format,
+    /// Only used for uploads; Telegram ignores thumbnails of files resent by id.
+    pub thumbnail: Option<PathBuf>,
+    /// Only used for uploads, like the thumbnail.
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// The forum topic to post in, when the destination is a forum supergroup.
+    pub thread: Option<ThreadId>,
+}
+
+/// A copy of a message that is already somewhere, sent without uploading anything
+/// again. Works for any kind of message, not only the ones modelled here.
+#[derive(Clone, Debug, Default)]
+pub struct OutgoingCopy {
+    /// Replaces the caption, in the given format; the original one is kept otherwise.
+    pub caption: Option<(String, Format)>,
+    pub thread: Option<ThreadId>,
+    pub silent: bool,
+}
+
+/// A video uploaded from disk that subscribers unlock with Stars.
+#[derive(Clone, Debug)]
+pub struct OutgoingPaidVideo {
+    pub path: PathBuf,
+    pub caption: String,
+    pub caption_format: Format,
+    pub duration: u32,
+    pub stars: u32,
+}
+
+#[async_trait]
+pub trait TelegramClient: Send + Sync {
+    async fn send_message(
+        &self,
+        chat_id: ChatId,
+        text: &OutgoingText,
+    ) -> Result<Sent, RequestError>;
+
+    async fn send_audio(
+        &self,
+        chat_id: ChatId,
+        audio: &OutgoingAudio,
+    ) -> Result<Sent, RequestError>;
+
+    async fn send_photo(
+        &self,
+        chat_id: ChatId,
+        photo: &OutgoingPhoto,
+    ) -> Result<Sent, RequestError>;
+
+    async fn send_paid_video(
+        &self,
+        chat_id: ChatId,
+        video: &OutgoingPaidVideo,
+    ) -> Result<Sent, RequestError>;
+
+    /// Copies `message_id` of `from_chat_id` to `chat_id`. The copy has no link back to
+    /// the original and no audio in the returned [`Sent`].
+    async fn copy_message(
+        &self,
+        chat_id: ChatId,
+        from_chat_id: ChatId,
+        message_id: MessageId,
+        copy: &OutgoingCopy,
+    ) -> Result<Sent, RequestError>;
+
+    async fn edit_text(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &OutgoingText,
+    ) -> Result<(), RequestError>;
+
+    /// Replaces the caption with `caption`, written in `format`.
+    async fn edit_caption(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        caption: &str,
+        format: Format,
+    ) -> Result<(), RequestError>;
+
+    /// Replaces the inline keyboard, or removes it when `markup` is `None`.
+    async fn edit_reply_markup(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        markup: Option<InlineKeyboardMarkup>,
+    ) -> Result<(), RequestError>;
+
+    async fn delete_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), RequestError>;
+
+    /// Pins without notifying anyone.
+    async fn pin_message(&self, chat_id: ChatId, message_id: MessageId)
+    -> Result<(), RequestError>;
+}
+
+#[async_trait]
+impl TelegramClient for Bot {
+    async fn send_message(
+        &self,
+        chat_id: ChatId,
+        text: &OutgoingText,
+    ) -> Result<Sent, RequestError> {
+        let mut request = Requester::send_message(self, chat_id, text.text.clone())
+            .disable_notification(text.silent);
+        if text.markdown {
+            request = request.parse_mode(ParseMode::MarkdownV2);
+        }
+        if !text.link_preview {
+            request = request.link_preview_options(crate::commands::no_link_preview());
+        }
+        if let Some(markup) = &text.reply_markup {
+            request = request.reply_markup(markup.clone());
+        }
+        Ok(request.await?.into())
+    }
+
+    async fn send_audio(
+        &self,
+        chat_id: ChatId,
+        audio: &OutgoingAudio,
+    ) -> Result<Sent, RequestError> {
+        let input = match &audio.source {
+            AudioSource::FileId(id) => InputFile::file_id(id.clone()),
+            AudioSource::Upload { path, file_name } => {
+                let input = InputFile::file(path.clone());
+                match file_name {
+                    Some(name) => input.file_name(name.clone()),
+                    None => input,
+                }
+            }
+        };
+        let mut request = Requester::send_audio(self, chat_id, input)
+            .caption(audio.caption.clone())
+            .parse_mode(audio.caption_format.parse_mode());
+        if let Some(thread) = audio.thread {
+            request = request.message_thread_id(thread);
+        }
+        if let AudioSource::Upload { .. } = audio.source {
+            if let Some(thumbnail) = &audio.thumbnail {
+                request = request.thumbnail(InputFile::file(thumbnail.clone()));
+            }
+            if let Some(title) = &audio.title {
+                request = request.title(title.clone());
+            }
+            if let Some(performer) = &audio.performer {
+                request = request.performer(performer.clone());
+            }
+        }
+        Ok(request.await?.into())
+    }
+
+    async fn send_photo(
+        &self,
+        chat_id: ChatId,
+        photo: &OutgoingPhoto,
+    ) -> Result<Sent, RequestError> {
+        let mut request = Requester::send_photo(self, chat_id, InputFile::file(photo.path.clone()))
+            .disable_notification(photo.silent);
+        if let Some(caption) = &photo.caption {
+            request = request
+                .caption(caption.clone())
+                .parse_mode(ParseMode::MarkdownV2);
+        }
+        if let Some(reply_to) = photo.reply_to {
+            request = request.reply_parameters(ReplyParameters::new(reply_to));
+        }
+        Ok(request.await?.into())
+    }
+
+    async fn send_paid_video(
+        &self,
+        chat_id: ChatId,
+        video: &OutgoingPaidVideo,
+    ) -> Result<Sent, RequestError> {
+        let media = InputPaidMediaVideo::new(InputFile::file(video.path.clone()))
+            .duration(Seconds::from_seconds(video.duration))
+            .supports_streaming(true);
+        let request = Requester::send_paid_media(
+            self,
+            chat_id,
+            video.stars,
+            [InputPaidMedia::Video(Box::new(media))],
+        )
+        .caption(video.caption.clone())
+        .parse_mode(video.caption_format.parse_mode());
+        Ok(request.await?.into())
+    }
+
+    async fn copy_message(
+        &self,
+        chat_id: ChatId,
+        from_chat_id: ChatId,
+        message_id: MessageId,
+        copy: &OutgoingCopy,
+    ) -> Result<Sent, RequestError> {
+        let mut request = Requester::copy_message(self, chat_id, from_chat_id, message_id)
+            .disable_notification(copy.silent);
+        if let Some((caption, format)) = &copy.caption {
+            request = request
+                .caption(caption.clone())
+                .parse_mode(format.parse_mode());
+        }
+        if let Some(thread) = copy.thread {
+            request = request.message_thread_id(thread);
+        }
+        Ok(Sent {
+            chat_id,
+            id: request.await?,
+            audio: None,
+        })
+    }
+
+    async fn edit_text(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &OutgoingText,
+    ) -> Result<(), RequestError> {
+        let mut request = self.edit_message_text(chat_id, message_id, text.text.clone());
+        if text.markdown {
+            request = request.parse_mode(ParseMode::MarkdownV2);
+        }
+        if !text.link_preview {
+            request = request.link_preview_options(crate::commands::no_link_preview());
+        }
+        if let Some(markup) = &text.reply_markup {
+            request = request.reply_markup(markup.clone());
+        }
+        request.await?;
+        Ok(())
+    }
+
+    async fn edit_caption(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        caption: &str,
+        format: Format,
+    ) -> Result<(), RequestError> {
+        self.edit_message_caption(chat_id, message_id)
+            .caption(caption)
+            .parse_mode(format.parse_mode())
+            .await?;
+        Ok(())
+    }
+
+    async fn edit_reply_markup(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        markup: Option<InlineKeyboardMarkup>,
+    ) -> Result<(), RequestError> {
+        let mut request = self.edit_message_reply_markup(chat_id, message_id);
+        if let Some(markup) = markup {
+            request = request.reply_markup(markup);
+        }
+        request.await?;
+        Ok(())
+    }
+
+    async fn delete_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), RequestError> {
+        Requester::delete_message(self, chat_id, message_id).await?;
+        Ok(())
+    }
+
+    async fn pin_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), RequestError> {
+        self.pin_chat_message(chat_id, message_id)
+            .disable_notification(true)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A call made to a [`MemoryClient`].
+#[derive(Clone, Debug)]
+pub enum Call {
+    SendMessage(ChatId, OutgoingText),
+    SendAudio(ChatId, OutgoingAudio),
+    SendPhoto(ChatId, OutgoingPhoto),
+    SendPaidVideo(ChatId, OutgoingPaidVideo),
+    Copy(ChatId, ChatId, MessageId, OutgoingCopy),
+    EditText(ChatId, MessageId, OutgoingText),
+    EditCaption(ChatId, MessageId, String),
+    EditReplyMarkup(ChatId, MessageId, Option<InlineKeyboardMarkup>),
+    Delete(ChatId, MessageId),
+    Pin(ChatId, MessageId),
+    /// A call the trait does not model, like a poll, left out by its caller.
+    Skipped(String),
+}
+
+impl std::fmt::Display for Call {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Call::SendMessage(chat, text) => write!(f, "send message to {}: {}", chat, text.text),
+            Call::SendAudio(chat, audio) => {
+                let file = match &audio.source {
+                    AudioSource::FileId(id) => format!("file {}", id),
+                    AudioSource::Upload { path, .. } => path.display().to_string(),
+                };
+                write!(f, "send audio {} to {}: {}", file, chat, audio.caption)
+            }
+            Call::SendPhoto(chat, photo) => {
+                write!(f, "send photo {} to {}", photo.path.display(), chat)?;
+                match &photo.caption {
+                    Some(caption) => write!(f, ": {}", caption),
+                    None => Ok(()),
+                }
+            }
+            Call::SendPaidVideo(chat, video) => write!(
+                f,
+                "send video {} to {} for {} Stars: {}",
+                video.path.display(),
+                chat,
+                video.stars,
+                video.caption
+            ),
+            Call::Copy(chat, from, id, copy) => {
+                write!(f, "copy {} of {} to {}", id.0, from, chat)?;
+                match &copy.caption {
+                    Some((caption, _)) => write!(f, ": {}", caption),
+                    None => Ok(()),
+                }
+            }
+            Call::EditText(chat, id, text) => {
+                write!(f, "edit text of {} in {}: {}", id.0, chat, text.text)
+            }
+            Call::EditCaption(chat, id, caption) => {
+                write!(f, "edit caption of {} in {}: {}", id.0, chat, caption)
+            }
+            Call::EditReplyMarkup(chat, id, markup) => write!(
+                f,
+                "{} keyboard of {} in {}",
+                if markup.is_some() {
+                    "replace"
+                } else {
+                    "remove"
+                },
+                id.0,
+                chat
+            ),
+            Call::Delete(chat, id) => write!(f, "delete {} in {}", id.0, chat),
+            Call::Pin(chat, id) => write!(f, "pin {} in {}", id.0, chat),
+            Call::Skipped(what) => write!(f, "{}", what),
+        }
+    }
+}
+
+/// A client that never touches the network: it logs and records every call and
+/// answers with consecutive message ids, as a channel would hand them out.
+pub struct MemoryClient {
+    calls: Mutex<Vec<Call>>,
+    next_id: Mutex<i32>,
+}
+
+impl MemoryClient {
+    /// The first message sent gets `first_id`.
+    pub fn new(first_id: i32) -> Self {
+        MemoryClient {
+            calls: Mutex::new(Vec::new()),
+            next_id: Mutex::new(first_id),
+        }
+    }
+
+    /// Everything recorded since the last time, oldest first.
+    pub fn take_calls(&self) -> Vec<Call> {
+        std::mem::take(&mut *self.calls.lock().expect("recorded calls poisoned"))
+    }
+
+    pub fn record(&self, call: Call) {
+        tracing::info!("Not sent: {}", call);
+        self.calls
+            .lock()
+            .expect("recorded calls poisoned")
+            .push(call);
+    }
+
+    fn sent(&self, chat_id: ChatId, call: Call) -> Sent {
+        self.record(call);
+        let mut next_id = self.next_id.lock().expect("message ids poisoned");
+        let id = MessageId(*next_id);
+        *next_id += 1;
+        Sent {
+            chat_id,
+            id,
+            audio: None,
+        }
+    }
+}
+
+#[async_trait]
+impl TelegramClient for MemoryClient {
+    async fn send_message(
+        &self,
+        chat_id: ChatId,
+        text: &OutgoingText,
+    ) -> Result<Sent, RequestError> {
+        Ok(self.sent(chat_id, Call::SendMessage(chat_id, text.clone())))
+    }
+
+    async fn send_audio(
+        &self,
+        chat_id: ChatId,
+        audio: &OutgoingAudio,
+    ) -> Result<Sent, RequestError> {
+        Ok(self.sent(chat_id, Call::SendAudio(chat_id, audio.clone())))
+    }
+
+    async fn send_photo(
+        &self,
+        chat_id: ChatId,
+        photo: &OutgoingPhoto,
+    ) -> Result<Sent, RequestError> {
+        Ok(self.sent(chat_id, Call::SendPhoto(chat_id, photo.clone())))
+    }
+
+    async fn send_paid_video(
+        &self,
+        chat_id: ChatId,
+        video: &OutgoingPaidVideo,
+    ) -> Result<Sent, RequestError> {
+        Ok(self.sent(chat_id, Call::SendPaidVideo(chat_id, video.clone())))
+    }
+
+    async fn copy_message(
+        &self,
+        chat_id: ChatId,
+        from_chat_id: ChatId,
+        message_id: MessageId,
+        copy: &OutgoingCopy,
+    ) -> Result<Sent, RequestError> {
+        let call = Call::Copy(chat_id, from_chat_id, message_id, copy.clone());
+        Ok(self.sent(chat_id, call))
+    }
+
+    async fn edit_text(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        text: &OutgoingText,
+    ) -> Result<(), RequestError> {
+        self.record(Call::EditText(chat_id, message_id, text.clone()));
+        Ok(())
+    }
+
+    async fn edit_caption(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        caption: &str,
+        _format: Format,
+    ) -> Result<(), RequestError> {
+        self.record(Call::EditCaption(chat_id, message_id, caption.to_string()));
+        Ok(())
+    }
+
+    async fn edit_reply_markup(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+        markup: Option<InlineKeyboardMarkup>,
+    ) -> Result<(), RequestError> {
+        self.record(Call::EditReplyMarkup(chat_id, message_id, markup));
+        Ok(())
+    }
+
+    async fn delete_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), RequestError> {
+        self.record(Call::Delete(chat_id, message_id));
+        Ok(())
+    }
+
+    async fn pin_message(
+        &self,
+        chat_id: ChatId,
+        message_id: MessageId,
+    ) -> Result<(), RequestError> {
+        self.record(Call::Pin(chat_id, message_id));
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use crate::logs::{LogBuffer, Redacting};
+use anyhow::Context;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::{Resource, metrics::SdkMeterProvider, trace::SdkTracerProvider};
+use shuttle_runtime::SecretStore;
+use std::collections::HashMap;
+use tracing_opentelemetry::MetricsLayer;
+use tracing_subscriber::{EnvFilter, fmt, prelude::*};
+
+/// Keeps the OTLP providers around so pending batches can be flushed on shutdown.
+pub struct Telemetry {
+    providers: Option<(SdkTracerProvider, SdkMeterProvider)>,
+}
+
+impl Telemetry {
+    pub fn shutdown(&self) {
+        if let Some((tracer_provider, meter_provider)) = &self.providers {
+            if let Err(e) = tracer_provider.shutdown() {
+                eprintln!("Failed to shut down tracer provider: {}", e);
+            }
+            if let Err(e) = meter_provider.shutdown() {
+                eprintln!("Failed to shut down meter provider: {}", e);
+            }
+        }
+    }
+}
+
+/// Installs the global tracing subscriber. When `OTLP_ENDPOINT` is set, traces and
+/// metrics are additionally exported over OTLP/HTTP, with `OTLP_HEADERS` given in the
+/// same `key=value,key2=value2` form as `OTEL_EXPORTER_OTLP_HEADERS`. Every event is
+/// also recorded into `logs`. Tokens in URLs are masked in both.
+pub fn init(secrets: &SecretStore, logs: LogBuffer) -> anyhow::Result<Telemetry> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "info,ankh=debug".into());
+
+    let providers = match secrets.get("OTLP_ENDPOINT") {
+        Some(endpoint) => {
+            let headers = parse_headers(&secrets.get("OTLP_HEADERS").unwrap_or_default())?;
+            Some(build_providers(endpoint.trim_end_matches('/'), headers)?)
+        }
+        None => None,
+    };
+
+    let otel_layers = providers.as_ref().map(|(tracer_provider, meter_provider)| {
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer_provider.tracer("ankh"))
+            .and_then(MetricsLayer::new(meter_provider.clone()))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            fmt::layer()
+                .without_time()
+                .with_writer(|| Redacting(std::io::stdout())),
+        )
+        .with(logs)
+        .with(otel_layers)
+        .try_init()
+        .context("Failed to install tracing subscriber")?;
+
+    if providers.is_some() {
+        tracing::info!("OTLP export enabled");
+    }
+
+    Ok(Telemetry { providers })
+}
+
+fn build_providers(
+    endpoint: &str,
+    headers: HashMap<String, String>,
+) -> anyhow::Result<(SdkTracerProvider, SdkMeterProvider)> {
+    let resource = Resource::builder().with_service_name("ankh").build();
+
+    let span_exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint))
+        .with_headers(headers.clone())
+        .build()
+        .context("Failed to build OTLP span exporter")?;
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_resource(resource.clone())
+        .with_batch_exporter(span_exporter)
+        .build();
+
+    let metric_exporter = MetricExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/metrics", endpoint))
+        .with_headers(headers)
+        .build()
+        .context("Failed to build OTLP metric exporter")?;
+    let meter_provider = SdkMeterProvider::builder()
+        .with_resource(resource)
+        .with_periodic_exporter(metric_exporter)
+        .build();
+
+    opentelemetry::global::set_tracer_provider(tracer_provider.clone());
+    opentelemetry::global::set_meter_provider(meter_provider.clone());
+
+    Ok((tracer_provider, meter_provider))
+}
+
+fn parse_headers(raw: &str) -> anyhow::Result<HashMap<String, String>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair
+                .split_once('=')
+                .with_context(|| format!("Malformed OTLP header: {}", pair))?;
+            let value = percent_encoding::percent_decode_str(value.trim())
+                .decode_utf8()
+                .with_context(|| format!("OTLP header {} is not valid UTF-8", key))?;
+            Ok((key.trim().to_string(), value.into_owned()))
+        })
+        .collect()
+}
@@ -0,0 +1,50 @@
+use crate::{ServerSecretsState, cleanup, settings};
+use std::sync::atomic::Ordering;
+use teloxide::prelude::*;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const SETTING: &str = "test_mode";
+
+pub async fn load(db: &sqlx::PgPool) -> sqlx::Result<bool> {
+    Ok(settings::get(db, SETTING).await?.unwrap_or(false))
+}
+
+pub fn is_active(secrets: &ServerSecretsState) -> bool {
+    secrets.test_mode.load(Ordering::Relaxed)
+}
+
+/// `/testmode [on|off]`: sends everything that would be published to the channel to
+/// `STAGING_CHANNEL_ID` instead. Posts made in test mode are not recorded in the
+/// catalog, and previews and outgoing hooks are skipped.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let reply = match (args.trim(), secrets.staging_channel_id) {
+        (_, None) => "Set STAGING_CHANNEL_ID to use test mode.".to_string(),
+        ("", Some(staging)) => match is_active(secrets) {
+            true => format!("Test mode is on, publishing to {}.", staging),
+            false => "Test mode is off.".to_string(),
+        },
+        (arg @ ("on" | "off"), Some(staging)) => {
+            let on = arg == "on";
+            settings::set(&secrets.db, SETTING, &on).await?;
+            secrets.test_mode.store(on, Ordering::Relaxed);
+            tracing::info!(on, "Test mode switched");
+            match on {
+                true => format!(
+                    "Test mode on: publishing to {} until /testmode off. \
+                     Links in captions still point at the real channel.",
+                    staging
+                ),
+                false => "Test mode off, publishing to the channel again.".to_string(),
+            }
+        }
+        _ => "Usage: /testmode on|off".to_string(),
+    };
+    cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+    Ok(())
+}
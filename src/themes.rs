@@ -0,0 +1,415 @@
+use crate::{
+    QueuedMessage, ServerSecretsState, caption, catalog,
+    format::{Format, Template},
+    jobs, telegram, vacation,
+};
+use anyhow::{Context, bail};
+use chrono::{DateTime, Datelike, Days, Utc, Weekday};
+use serde::Deserialize;
+use sqlx::types::Json;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    ApiError, RequestError,
+    prelude::*,
+    types::{FileId, MessageId},
+};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The hour a theme posts at when it does not say.
+const DEFAULT_HOUR: u32 = 18;
+/// A track reposted by a catalog theme is left alone for this long.
+const REPOST_COOLDOWN_DAYS: i32 = 90;
+/// Job names for [`jobs::spawn_daily`], one per weekday.
+const JOB_NAMES: [&str; 7] = [
+    "theme_mon",
+    "theme_tue",
+    "theme_wed",
+    "theme_thu",
+    "theme_fri",
+    "theme_sat",
+    "theme_sun",
+];
+
+#[derive(Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Source {
+    /// Uploads with one of the theme's tags wait for its slot instead of going out
+    /// right away; one is posted per slot, oldest first.
+    Queue,
+    /// A track from the catalog with one of the theme's tags, or any track without
+    /// tags, is posted again.
+    Catalog,
+}
+
+/// One weekday's slot, as written in `WEEKDAY_THEMES`.
+#[derive(Deserialize)]
+struct RawTheme {
+    day: String,
+    name: String,
+    #[serde(default)]
+    hour: Option<u32>,
+    source: Source,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    template: Option<String>,
+    /// `markdown` or `html`; `CAPTION_PARSE_MODE` when left out.
+    #[serde(default)]
+    parse_mode: Option<String>,
+}
+
+struct Theme {
+    day: Weekday,
+    name: String,
+    hour: u32,
+    source: Source,
+    /// Lowercase, without the `#`.
+    tags: Vec<String>,
+    /// Used instead of `CAPTION_TEMPLATE`; see [`caption::Facts`].
+    template: Option<Template>,
+}
+
+impl Theme {
+    fn matches(&self, tags: &[String]) -> bool {
+        tags.iter().any(|tag| self.tags.contains(tag))
+    }
+}
+
+/// Themed slots, at most one per weekday, configured with `WEEKDAY_THEMES` as a JSON
+/// list, e.g. `[{"day": "thu", "name": "Throwback Thursday", "hour": 18,
+/// "source": "catalog", "tags": ["90s"], "template": "🕰 {theme}\n\n{series}"}]`.
+/// A template can have its own `parse_mode`. Times are UTC, like the other daily jobs.
+#[derive(Default)]
+pub struct Themes(Vec<Theme>);
+
+impl Themes {
+    /// `format` is what templates without a `parse_mode` are written in.
+    pub fn from_secret(raw: Option<&str>, format: Format) -> anyhow::Result<Self> {
+        let Some(raw) = raw.filter(|raw| !raw.trim().is_empty()) else {
+            return Ok(Self::default());
+        };
+        let raw: Vec<RawTheme> =
+            serde_json::from_str(raw).context("WEEKDAY_THEMES must be a JSON list of themes")?;
+        let mut themes: Vec<Theme> = Vec::new();
+        for theme in raw {
+            let day: Weekday = theme
+                .day
+                .parse()
+                .ok()
+                .with_context(|| format!("Unknown weekday {} in WEEKDAY_THEMES", theme.day))?;
+            if themes.iter().any(|other| other.day == day) {
+                bail!("WEEKDAY_THEMES has more than one theme for {}", day);
+            }
+            let hour = theme.hour.unwrap_or(DEFAULT_HOUR);
+            if hour >= 24 {
+                bail!("The hour of theme {} must be between 0 and 23", theme.name);
+            }
+            let tags: Vec<String> = theme
+                .tags
+                .iter()
+                .map(|tag| tag.trim().trim_start_matches('#').to_lowercase())
+                .filter(|tag| !tag.is_empty())
+                .collect();
+            if theme.source == Source::Queue && tags.is_empty() {
+                bail!(
+                    "Theme {} takes from the queue, so it needs tags",
+                    theme.name
+                );
+            }
+            let format = match &theme.parse_mode {
+                Some(raw) => Format::from_secret(
+                    &format!("The parse_mode of theme {}", theme.name),
+                    Some(raw),
+                )?,
+                None => format,
+            };
+            themes.push(Theme {
+                day,
+                name: theme.name,
+                hour,
+                source: theme.source,
+                tags,
+                template: theme.template.map(|text| Template { text, format }),
+            });
+        }
+        Ok(Self(themes))
+    }
+
+    /// The caption template of the theme called `name`, if it has its own.
+    pub fn template(&self, name: &str) -> Option<&Template> {
+        self.0
+            .iter()
+            .find(|theme| theme.name == name)
+            .and_then(|theme| theme.template.as_ref())
+    }
+
+    /// Every theme slot after `from` up to `until`, soonest first, with the name of
+    /// its theme.
+    pub fn slots_between(
+        &self,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, &str)> {
+        let mut slots = Vec::new();
+        let mut date = from.date_naive();
+        while date <= until.date_naive() {
+            if let Some(theme) = self.0.iter().find(|theme| theme.day == date.weekday())
+                && let Some(at) = date.and_hms_opt(theme.hour, 0, 0).map(|at| at.and_utc())
+                && at > from
+                && at <= until
+            {
+                slots.push((at, theme.name.as_str()));
+            }
+            date = date + Days::new(1);
+        }
+        slots
+    }
+
+    fn for_upload(&self, message: &QueuedMessage) -> Option<&Theme> {
+        if message.theme.is_some() {
+            return None;
+        }
+        self.0
+            .iter()
+            .find(|theme| theme.source == Source::Queue && theme.matches(&message.tags))
+    }
+}
+
+/// "Thu 18:00 UTC"
+fn slot(theme: &Theme) -> String {
+    format!("{} {:02}:00 UTC", theme.day, theme.hour)
+}
+
+async fn tell_owner(secrets: &ServerSecretsState, text: String) {
+    if let Err(e) = secrets
+        .telegram
+        .send_message(secrets.me_id, &telegram::OutgoingText::plain(text))
+        .await
+    {
+        tracing::warn!("Failed to message the owner: {}", e);
+    }
+}
+
+/// Takes uploads tagged for a queue theme out of a batch, to wait for its slot.
+/// Returns the rest.
+pub async fn hold(secrets: &ServerSecretsState, batch: Vec<QueuedMessage>) -> Vec<QueuedMessage> {
+    let mut rest = Vec::with_capacity(batch.len());
+    for message in batch {
+        let Some(theme) = secrets.themes.for_upload(&message) else {
+            rest.push(message);
+            continue;
+        };
+        let held = sqlx::query("INSERT INTO themed_items (theme, item) VALUES ($1, $2)")
+            .bind(&theme.name)
+            .bind(Json(&message))
+            .execute(&secrets.db)
+            .await;
+        match held {
+            Ok(_) => {
+                tracing::info!("Holding message {} for {}", message.message_id, theme.name);
+                let label = message.audio.title.as_deref().unwrap_or("A track");
+                tell_owner(
+                    secrets,
+                    format!("🎨 {} waits for {} ({}).", label, theme.name, slot(theme)),
+                )
+                .await;
+            }
+            Err(e) => {
+                // Better posted off-theme than lost.
+                tracing::error!("Failed to hold message for {}: {}", theme.name, e);
+                rest.push(message);
+            }
+        }
+    }
+    rest
+}
+
+/// Puts the oldest upload held for `theme` back in the queue, marked so it goes out
+/// with the theme's caption.
+async fn release(
+    bot: &Arc<Bot>,
+    secrets: &Arc<ServerSecretsState>,
+    theme: &Theme,
+) -> Result<(), Error> {
+    let item: Option<Json<QueuedMessage>> = sqlx::query_scalar(
+        "DELETE FROM themed_items WHERE id = (
+             SELECT id FROM themed_items WHERE theme = $1 ORDER BY id LIMIT 1
+         ) RETURNING item",
+    )
+    .bind(&theme.name)
+    .fetch_optional(&secrets.db)
+    .await?;
+    let Some(Json(mut message)) = item else {
+        tracing::info!("Nothing held for {}", theme.name);
+        return Ok(());
+    };
+    message.theme = Some(theme.name.clone());
+    message.queued_at = chrono::Utc::now();
+    secrets
+        .message_queue
+        .add_message(message, bot.clone(), secrets.clone())
+        .await;
+    Ok(())
+}
+
+/// Posts a cataloged track again with the theme's caption, skipping ones the theme
+/// reposted recently.
+async fn repost(secrets: &ServerSecretsState, theme: &Theme) -> Result<(), Error> {
+    let track: Option<catalog::Track> = sqlx::query_as(
+        "SELECT * FROM tracks t
+         WHERE t.deleted_at IS NULL AND t.channel_id = $1
+             AND (cardinality($2::text[]) = 0 OR t.tags && $2)
+             AND NOT EXISTS (
+                 SELECT 1 FROM theme_reposts r
+                 WHERE r.track_id = t.id
+                     AND r.reposted_at > now() - make_interval(days => $3)
+             )
+         ORDER BY random() LIMIT 1",
+    )
+    .bind(secrets.channel_id().0)
+    .bind(&theme.tags)
+    .bind(REPOST_COOLDOWN_DAYS)
+    .fetch_optional(&secrets.db)
+    .await?;
+    let Some(track) = track else {
+        tracing::info!("No track to repost for {}", theme.name);
+        return Ok(());
+    };
+
+    let mut facts = caption::Facts::of_track(&track);
+    facts.theme = Some(theme.name.clone());
+    let template = theme.template.as_ref().unwrap_or(&secrets.caption_template);
+    // `{series}` links to the original post.
+    let caption = crate::caption(
+        template,
+        &track.series,
+        track.message_id,
+        track.number,
+        None,
+        &facts,
+    );
+    let thread = secrets.topics.pick(&track.series, &track.tags);
+    let copy = telegram::OutgoingCopy {
+        caption: Some((caption.clone(), template.format)),
+        thread,
+        silent: false,
+    };
+    // Copying spares the upload; the file id is the way back when the original post
+    // is gone from the channel, unless it is the free upload of a paid track.
+    let copied = secrets
+        .retry_policy
+        .run(|| {
+            secrets.telegram.copy_message(
+                secrets.publish_channel_id(),
+                ChatId(track.channel_id),
+                MessageId(track.message_id),
+                &copy,
+            )
+        })
+        .await;
+    let sent = match copied {
+        Ok(sent) => sent,
+        Err(RequestError::Api(ApiError::MessageToCopyNotFound | ApiError::MessageIdInvalid))
+            if !track.paid =>
+        {
+            let outgoing = telegram::OutgoingAudio {
+                source: telegram::AudioSource::FileId(FileId(track.file_id.clone())),
+                caption,
+                caption_format: template.format,
+                thumbnail: None,
+                title: None,
+                performer: None,
+                thread,
+            };
+            secrets
+                .retry_policy
+                .run(|| {
+                    secrets
+                        .telegram
+                        .send_audio(secrets.publish_channel_id(), &outgoing)
+                })
+                .await?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    secrets.last_message_id.store(sent.id.0, Ordering::Relaxed);
+    sqlx::query("INSERT INTO theme_reposts (track_id, theme) VALUES ($1, $2)")
+        .bind(track.id)
+        .bind(&theme.name)
+        .execute(&secrets.db)
+        .await?;
+    tracing::info!("Reposted {} for {}", track.label(), theme.name);
+    Ok(())
+}
+
+/// Schedules each theme's slot with the other jobs.
+pub fn spawn(bot: Arc<Bot>, secrets: Arc<ServerSecretsState>) {
+    for (index, theme) in secrets.themes.0.iter().enumerate() {
+        let name = JOB_NAMES[theme.day.num_days_from_monday() as usize];
+        let bot = bot.clone();
+        let state = secrets.clone();
+        jobs::spawn_weekly(name, theme.day, theme.hour, secrets.db.clone(), move |_| {
+            let bot = bot.clone();
+            let state = state.clone();
+            async move {
+                let theme = &state.themes.0[index];
+                if vacation::is_active(&state) {
+                    return Ok(());
+                }
+                match theme.source {
+                    Source::Queue => release(&bot, &state, theme).await,
+                    Source::Catalog => repost(&state, theme).await,
+                }
+            }
+        });
+    }
+}
+
+/// `/themes`: the weekly slots, with how many uploads wait for each.
+pub async fn handle_list(
+    bot: &Bot,
+    message: &Message,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    if secrets.themes.0.is_empty() {
+        bot.send_message(
+            message.chat.id,
+            "No themes. Set them up with WEEKDAY_THEMES.",
+        )
+        .await?;
+        return Ok(());
+    }
+    let held: Vec<(String, i64)> =
+        sqlx::query_as("SELECT theme, COUNT(*) FROM themed_items GROUP BY theme")
+            .fetch_all(&secrets.db)
+            .await?;
+    let mut themes: Vec<&Theme> = secrets.themes.0.iter().collect();
+    themes.sort_by_key(|theme| theme.day.num_days_from_monday());
+    let lines = themes
+        .iter()
+        .map(|theme| {
+            let tags = theme
+                .tags
+                .iter()
+                .map(|tag| format!("#{}", tag))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let source = match theme.source {
+                Source::Queue => {
+                    let waiting = held
+                        .iter()
+                        .find(|(name, _)| *name == theme.name)
+                        .map_or(0, |(_, count)| *count);
+                    format!("uploads tagged {}, {} waiting", tags, waiting)
+                }
+                Source::Catalog if tags.is_empty() => "reposts from the catalog".to_string(),
+                Source::Catalog => format!("reposts tagged {}", tags),
+            };
+            format!("🎨 {} {}: {}", slot(theme), theme.name, source)
+        })
+        .collect::<Vec<_>>();
+    bot.send_message(message.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
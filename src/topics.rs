@@ -0,0 +1,60 @@
+use anyhow::Context;
+use teloxide::types::{MessageId, ThreadId};
+
+/// Which forum topic a track goes to when the destination is a forum supergroup
+/// rather than a channel, configured with `FORUM_TOPICS` as comma-separated
+/// `key=topic id` pairs. A key is a hashtag (`#ambient`), a series name
+/// (`Music: Reborn`) or `*` for everything else; hashtags are checked first, in the
+/// track's order. Tracks nothing matches go to the General topic.
+#[derive(Default)]
+pub struct Topics {
+    tags: Vec<(String, ThreadId)>,
+    series: Vec<(String, ThreadId)>,
+    fallback: Option<ThreadId>,
+}
+
+impl Topics {
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Self> {
+        let mut topics = Topics::default();
+        for entry in raw.unwrap_or_default().split(',').map(str::trim) {
+            if entry.is_empty() {
+                continue;
+            }
+            let (key, id) = entry.rsplit_once('=').with_context(|| {
+                format!("FORUM_TOPICS entries must be key=topic id, not {}", entry)
+            })?;
+            let id: i32 = id
+                .trim()
+                .parse()
+                .with_context(|| format!("FORUM_TOPICS topic ids must be numbers, not {}", id))?;
+            let thread = ThreadId(MessageId(id));
+            match key.trim() {
+                "*" => topics.fallback = Some(thread),
+                key => match key.strip_prefix('#') {
+                    Some(tag) => topics.tags.push((tag.to_lowercase(), thread)),
+                    None => topics.series.push((key.to_string(), thread)),
+                },
+            }
+        }
+        Ok(topics)
+    }
+
+    /// The topic for a track of `series` with `tags`, which are lowercase and
+    /// without their `#`.
+    pub fn pick(&self, series: &str, tags: &[String]) -> Option<ThreadId> {
+        tags.iter()
+            .find_map(|tag| {
+                self.tags
+                    .iter()
+                    .find(|(key, _)| key == tag)
+                    .map(|&(_, thread)| thread)
+            })
+            .or_else(|| {
+                self.series
+                    .iter()
+                    .find(|(key, _)| key == series)
+                    .map(|&(_, thread)| thread)
+            })
+            .or(self.fallback)
+    }
+}
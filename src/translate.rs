@@ -0,0 +1,101 @@
+use anyhow::{Context, bail};
+use serde_json::{Value, json};
+use shuttle_runtime::SecretStore;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// The service captions are translated with, configured with `TRANSLATOR` (`deepl`
+/// or `libretranslate`) and `TRANSLATE_TO`, the target language code. DeepL takes
+/// its key in `TRANSLATOR_KEY`; LibreTranslate needs `TRANSLATOR_URL` and, depending
+/// on the instance, a key.
+pub enum Backend {
+    Deepl { key: String },
+    LibreTranslate { url: String, key: Option<String> },
+}
+
+pub struct Translator {
+    backend: Backend,
+    /// E.g. `uk` or `en`.
+    pub target: String,
+}
+
+impl Translator {
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Option<Self>> {
+        let Some(kind) = secrets.get("TRANSLATOR") else {
+            return Ok(None);
+        };
+        let target = secrets
+            .get("TRANSLATE_TO")
+            .context("TRANSLATE_TO must be set with TRANSLATOR")?
+            .trim()
+            .to_lowercase();
+        let backend = match kind.trim() {
+            "deepl" => Backend::Deepl {
+                key: secrets
+                    .get("TRANSLATOR_KEY")
+                    .context("TRANSLATOR_KEY must be set for DeepL")?,
+            },
+            "libretranslate" => Backend::LibreTranslate {
+                url: secrets
+                    .get("TRANSLATOR_URL")
+                    .context("TRANSLATOR_URL must be set for LibreTranslate")?
+                    .trim_end_matches('/')
+                    .to_string(),
+                key: secrets.get("TRANSLATOR_KEY"),
+            },
+            other => bail!("TRANSLATOR must be deepl or libretranslate, not {}", other),
+        };
+        Ok(Some(Translator { backend, target }))
+    }
+
+    /// `text` in the target language, whatever language it was written in.
+    pub async fn translate(&self, text: &str) -> Result<String, Error> {
+        let client = reqwest::Client::new();
+        let translated = match &self.backend {
+            Backend::Deepl { key } => {
+                // Free-plan keys end in ":fx" and have their own host.
+                let host = if key.ends_with(":fx") {
+                    "api-free.deepl.com"
+                } else {
+                    "api.deepl.com"
+                };
+                let response: Value = client
+                    .post(format!("https://{}/v2/translate", host))
+                    .header("Authorization", format!("DeepL-Auth-Key {}", key))
+                    .json(&json!({
+                        "text": [text],
+                        "target_lang": self.target.to_uppercase(),
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                response["translations"][0]["text"]
+                    .as_str()
+                    .map(str::to_string)
+            }
+            Backend::LibreTranslate { url, key } => {
+                let response: Value = client
+                    .post(format!("{}/translate", url))
+                    .json(&json!({
+                        "q": text,
+                        "source": "auto",
+                        "target": self.target,
+                        "format": "text",
+                        "api_key": key,
+                    }))
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                response["translatedText"].as_str().map(str::to_string)
+            }
+        };
+        translated
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+            .ok_or_else(|| "The translator returned nothing".into())
+    }
+}
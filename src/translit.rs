@@ -0,0 +1,156 @@
+use anyhow::bail;
+use sqlx::PgPool;
+
+/// How Cyrillic titles are spelled in Latin letters, set with `TRANSLITERATE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheme {
+    /// Ukrainian, the national system of 2010: "Львів" → "Lviv", "Їжак" → "Yizhak".
+    Ukrainian,
+    /// Russian, as in passports since 2013 without the diacritics: "Жизнь" → "Zhizn".
+    Russian,
+}
+
+impl Scheme {
+    /// `uk` or `ru`; unset turns transliteration off.
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match raw.map(str::trim) {
+            None | Some("") => Ok(None),
+            Some("uk") => Ok(Some(Scheme::Ukrainian)),
+            Some("ru") => Ok(Some(Scheme::Russian)),
+            Some(other) => bail!("TRANSLITERATE must be uk or ru, not {}", other),
+        }
+    }
+
+    /// One lowercase letter, `initial` when it starts a word. `None` for anything
+    /// that is not Cyrillic.
+    fn letter(self, c: char, initial: bool) -> Option<&'static str> {
+        let ukrainian = self == Scheme::Ukrainian;
+        Some(match c {
+            'а' => "a",
+            'б' => "b",
+            'в' => "v",
+            'г' if ukrainian => "h",
+            'г' => "g",
+            'ґ' => "g",
+            'д' => "d",
+            'е' => "e",
+            'ё' => "e",
+            'є' if initial => "ye",
+            'є' => "ie",
+            'ж' => "zh",
+            'з' => "z",
+            'и' if ukrainian => "y",
+            'и' => "i",
+            'і' => "i",
+            'ї' if initial => "yi",
+            'ї' => "i",
+            'й' if ukrainian && initial => "y",
+            'й' => "i",
+            'к' => "k",
+            'л' => "l",
+            'м' => "m",
+            'н' => "n",
+            'о' => "o",
+            'п' => "p",
+            'р' => "r",
+            'с' => "s",
+            'т' => "t",
+            'у' => "u",
+            'ф' => "f",
+            'х' => "kh",
+            'ц' => "ts",
+            'ч' => "ch",
+            'ш' => "sh",
+            'щ' => "shch",
+            'ъ' => "ie",
+            'ы' => "y",
+            'ь' => "",
+            'э' => "e",
+            'ю' if initial || !ukrainian => "yu",
+            'ю' => "iu",
+            'я' if initial || !ukrainian => "ya",
+            'я' => "ia",
+            'ў' => "u",
+            _ => return None,
+        })
+    }
+
+    /// `text` with its Cyrillic spelled out in Latin letters, keeping capitals, or
+    /// `None` when there is no Cyrillic in it.
+    pub fn latin(self, text: &str) -> Option<String> {
+        if !text.chars().any(is_cyrillic) {
+            return None;
+        }
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::with_capacity(text.len());
+        for (i, &c) in chars.iter().enumerate() {
+            let previous = i.checked_sub(1).map(|i| chars[i]);
+            let next = chars.get(i + 1).copied();
+            // The apostrophe in "м'ята" only marks how the letters around it sound.
+            if is_apostrophe(c)
+                && previous.is_some_and(is_cyrillic)
+                && next.is_some_and(is_cyrillic)
+            {
+                continue;
+            }
+            let lower = c.to_lowercase().next().unwrap_or(c);
+            let initial = previous.is_none_or(|p| !p.is_alphabetic() && !is_apostrophe(p));
+            let Some(latin) = self.letter(lower, initial) else {
+                out.push(c);
+                continue;
+            };
+            if c == lower {
+                out.push_str(latin);
+            } else if next.is_some_and(|n| n.is_uppercase())
+                || previous.is_some_and(|p| p.is_uppercase())
+            {
+                // Part of a word in capitals: "ЖИТТЯ" → "ZHYTTIA".
+                out.push_str(&latin.to_uppercase());
+            } else {
+                let mut letters = latin.chars();
+                if let Some(first) = letters.next() {
+                    out.extend(first.to_uppercase());
+                    out.push_str(letters.as_str());
+                }
+            }
+        }
+        Some(out)
+    }
+}
+
+fn is_apostrophe(c: char) -> bool {
+    matches!(c, '\'' | '’' | 'ʼ')
+}
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{0400}'..='\u{04FF}')
+}
+
+/// Fills in the Latin forms of tracks cataloged before transliteration was on, so
+/// they can be searched for that way too.
+pub async fn backfill(db: &PgPool, scheme: Scheme) -> sqlx::Result<()> {
+    let tracks: Vec<(i64, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT id, title, performer FROM tracks
+         WHERE title_latin IS NULL AND performer_latin IS NULL
+             AND (title ~ '[Ѐ-ӿ]' OR performer ~ '[Ѐ-ӿ]')",
+    )
+    .fetch_all(db)
+    .await?;
+    if tracks.is_empty() {
+        return Ok(());
+    }
+    for (id, title, performer) in &tracks {
+        sqlx::query("UPDATE tracks SET title_latin = $2, performer_latin = $3 WHERE id = $1")
+            .bind(id)
+            .bind(title.as_deref().and_then(|title| scheme.latin(title)))
+            .bind(
+                performer
+                    .as_deref()
+                    .and_then(|performer| scheme.latin(performer)),
+            )
+            .execute(db)
+            .await?;
+    }
+    tracing::info!("Transliterated {} cataloged track(s)", tracks.len());
+    Ok(())
+}
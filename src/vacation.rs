@@ -0,0 +1,141 @@
+use crate::{ServerSecretsState, cleanup, settings, telegram::OutgoingText};
+use chrono::{Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use teloxide::prelude::*;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const SETTING: &str = "vacation";
+
+/// A break from automatic posting, from the first to the last day inclusive. While
+/// it lasts the queue holds everything and daily jobs are skipped.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Vacation {
+    pub from: NaiveDate,
+    pub until: NaiveDate,
+    /// Whether to tell the channel when the break starts.
+    pub notice: bool,
+}
+
+impl Vacation {
+    fn covers(&self, date: NaiveDate) -> bool {
+        self.from <= date && date <= self.until
+    }
+
+    fn back_on(&self) -> NaiveDate {
+        self.until + Days::new(1)
+    }
+}
+
+pub async fn load(db: &sqlx::PgPool) -> sqlx::Result<Option<Vacation>> {
+    settings::get(db, SETTING).await
+}
+
+pub fn is_active(secrets: &ServerSecretsState) -> bool {
+    let today = Utc::now().date_naive();
+    secrets
+        .vacation
+        .read()
+        .expect("vacation lock poisoned")
+        .is_some_and(|vacation| vacation.covers(today))
+}
+
+async fn post_notice(secrets: &ServerSecretsState, vacation: &Vacation) -> Result<(), Error> {
+    let text = OutgoingText::plain(format!(
+        "🏖 Taking a short break — back on {}",
+        vacation.back_on().format("%B %-d")
+    ));
+    let channel_id = secrets.publish_channel_id();
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_message(channel_id, &text))
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Daily job posting the notice on the first day of a break.
+pub async fn run(secrets: &ServerSecretsState, date: NaiveDate) -> Result<(), Error> {
+    let vacation = *secrets.vacation.read().expect("vacation lock poisoned");
+    if let Some(vacation) = vacation
+        && vacation.notice
+        && vacation.from == date
+    {
+        post_notice(secrets, &vacation).await?;
+    }
+    Ok(())
+}
+
+fn usage() -> String {
+    "Usage: /vacation <from> <until> [notice], e.g. /vacation 2026-07-01 2026-07-14 notice\n\
+     /vacation off ends it, /vacation alone shows it."
+        .to_string()
+}
+
+/// `/vacation [from until [notice] | off]`.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let args = args.split_whitespace().collect::<Vec<_>>();
+    let reply = match args.as_slice() {
+        [] => match *secrets.vacation.read().expect("vacation lock poisoned") {
+            Some(vacation) => format!(
+                "On vacation from {} until {}{}",
+                vacation.from,
+                vacation.until,
+                if vacation.notice { ", with notice" } else { "" }
+            ),
+            None => "No vacation planned.".to_string(),
+        },
+        ["off"] => {
+            settings::clear(&secrets.db, SETTING).await?;
+            *secrets.vacation.write().expect("vacation lock poisoned") = None;
+            "Vacation cancelled, posting resumes.".to_string()
+        }
+        [from, until, rest @ ..] if rest.is_empty() || rest == ["notice"] => {
+            let (Ok(from), Ok(until)) = (
+                NaiveDate::parse_from_str(from, "%Y-%m-%d"),
+                NaiveDate::parse_from_str(until, "%Y-%m-%d"),
+            ) else {
+                cleanup::reply(bot, secrets, message.chat.id, usage()).await?;
+                return Ok(());
+            };
+            if until < from || until < Utc::now().date_naive() {
+                cleanup::reply(
+                    bot,
+                    secrets,
+                    message.chat.id,
+                    "A vacation has to end on or after its first day, and not in the past.",
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let vacation = Vacation {
+                from,
+                until,
+                notice: !rest.is_empty(),
+            };
+            settings::set(&secrets.db, SETTING, &vacation).await?;
+            *secrets.vacation.write().expect("vacation lock poisoned") = Some(vacation);
+            if vacation.notice && vacation.covers(Utc::now().date_naive()) {
+                post_notice(secrets, &vacation).await?;
+            }
+            format!(
+                "Automatic posting paused from {} until {}, back on {}.",
+                from,
+                until,
+                vacation.back_on()
+            )
+        }
+        _ => usage(),
+    };
+    cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+    Ok(())
+}
@@ -0,0 +1,611 @@
+use crate::{ServerSecretsState, archive, maintenance, media};
+use anyhow::{Context, bail};
+use chrono::Utc;
+use hmac::{Hmac, KeyInit, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::Method;
+use sha2::{Digest, Sha256};
+use shuttle_runtime::SecretStore;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use teloxide::prelude::*;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::{Duration, sleep};
+use url::Url;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+type HmacSha256 = Hmac<Sha256>;
+
+const DEFAULT_INTERVAL_MINUTES: u64 = 5;
+const DEFAULT_REGION: &str = "us-east-1";
+/// Where files go once they are queued, and where the ones Telegram would not take
+/// as audio go, next to the watched files.
+const PROCESSED: &str = "processed";
+const FAILED: &str = "failed";
+/// SHA-256 of an empty body; none of the S3 requests made here has one.
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+/// What SigV4 leaves unencoded in paths and queries.
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A folder of exported tracks outside Telegram, configured with `WATCH_FOLDER` as
+/// `s3://bucket/prefix` or `sftp://user@host[:port]/path`. Every few minutes
+/// (`WATCH_INTERVAL_MINUTES`) the audio files right in it are queued like uploads
+/// and moved to `processed/` beside them; files Telegram turns down go to `failed/`.
+/// Files still being written should carry a temporary extension such as `.part`
+/// until they are complete, which is what most upload tools do anyway.
+pub struct WatchFolder {
+    backend: Backend,
+    every: Duration,
+}
+
+enum Backend {
+    S3(S3),
+    Sftp(Sftp),
+}
+
+impl WatchFolder {
+    /// Parses `WATCH_FOLDER` and `WATCH_INTERVAL_MINUTES`, and the credentials of
+    /// whichever kind of folder it is: `WATCH_S3_ACCESS_KEY_ID`,
+    /// `WATCH_S3_SECRET_ACCESS_KEY`, `WATCH_S3_REGION` and `WATCH_S3_ENDPOINT` (for
+    /// S3-compatible storage), or `WATCH_SFTP_KEY` and `WATCH_SFTP_KNOWN_HOSTS`, which
+    /// an SFTP folder cannot do without.
+    pub fn from_secrets(secrets: &SecretStore) -> anyhow::Result<Option<Self>> {
+        let Some(raw) = secrets
+            .get("WATCH_FOLDER")
+            .filter(|raw| !raw.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let url = Url::parse(raw.trim()).context("WATCH_FOLDER must be an s3:// or sftp:// URL")?;
+        let backend = match url.scheme() {
+            "s3" => Backend::S3(S3::from_secrets(&url, secrets)?),
+            "sftp" => Backend::Sftp(Sftp::from_secrets(&url, secrets)?),
+            other => bail!(
+                "WATCH_FOLDER must be an s3:// or sftp:// URL, not {}://",
+                other
+            ),
+        };
+        let minutes = match secrets.get("WATCH_INTERVAL_MINUTES") {
+            Some(minutes) => minutes
+                .trim()
+                .parse()
+                .context("WATCH_INTERVAL_MINUTES must be a number of minutes")?,
+            None => DEFAULT_INTERVAL_MINUTES,
+        };
+        if minutes == 0 {
+            bail!("WATCH_INTERVAL_MINUTES must be at least 1");
+        }
+        Ok(Some(Self {
+            backend,
+            every: Duration::from_secs(minutes * 60),
+        }))
+    }
+
+    /// Looks in the folder every so often, except during maintenance, and tells the
+    /// owner what it queued.
+    pub fn spawn(self, bot: Arc<Bot>, secrets: Arc<ServerSecretsState>) {
+        tokio::spawn(async move {
+            // Files that were queued but could not be moved out of the way, so the
+            // next look does not queue them again.
+            let mut queued = HashSet::new();
+            loop {
+                sleep(self.every).await;
+                if maintenance::is_on(&secrets) {
+                    continue;
+                }
+                if let Err(e) = self.poll(&bot, &secrets, &mut queued).await {
+                    tracing::warn!("Failed to check the watch folder: {}", e);
+                }
+            }
+        });
+    }
+
+    async fn poll(
+        &self,
+        bot: &Arc<Bot>,
+        secrets: &Arc<ServerSecretsState>,
+        queued: &mut HashSet<String>,
+    ) -> Result<(), Error> {
+        let mut names: Vec<String> = self
+            .backend
+            .list()
+            .await?
+            .into_iter()
+            .filter(|name| !name.starts_with('.') && archive::is_audio(Path::new(name)))
+            .collect();
+        names.sort();
+
+        let mut report = Vec::new();
+        for name in names {
+            if queued.contains(&name) {
+                if self.backend.archive(&name, PROCESSED).await.is_ok() {
+                    queued.remove(&name);
+                }
+                continue;
+            }
+            let extension = Path::new(&name)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or_default();
+            let local = media::LocalFile::scratch(&format!(
+                "watch-{}.{}",
+                Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+                extension
+            ));
+            if let Err(e) = self.backend.download(&name, &local.path).await {
+                tracing::warn!("Failed to download {} from the watch folder: {}", name, e);
+                continue;
+            }
+            let title = Path::new(&name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| name.clone());
+            match media::queue_file(bot, secrets, &local.path, title, media::Metadata::default())
+                .await
+            {
+                Ok(position) => {
+                    tracing::info!("Queued {} from the watch folder", name);
+                    report.push(format!("✅ {} (#{} in queue)", name, position.position));
+                    if let Err(e) = self.backend.archive(&name, PROCESSED).await {
+                        tracing::warn!("Failed to move {} to {}/: {}", name, PROCESSED, e);
+                        queued.insert(name);
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to queue {} from the watch folder: {}", name, e);
+                    report.push(format!("❌ {}: {}", name, e));
+                    if let Err(e) = self.backend.archive(&name, FAILED).await {
+                        tracing::warn!("Failed to move {} to {}/: {}", name, FAILED, e);
+                    }
+                }
+            }
+        }
+
+        if !report.is_empty() {
+            bot.send_message(
+                secrets.me_id,
+                format!("📂 From the watch folder:\n{}", report.join("\n")),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+impl Backend {
+    /// The names of the files right in the folder, not in folders below it.
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        match self {
+            Backend::S3(s3) => s3.list().await,
+            Backend::Sftp(sftp) => sftp.list().await,
+        }
+    }
+
+    async fn download(&self, name: &str, to: &Path) -> Result<(), Error> {
+        match self {
+            Backend::S3(s3) => s3.download(name, to).await,
+            Backend::Sftp(sftp) => sftp.download(name, to).await,
+        }
+    }
+
+    /// Moves `name` into the folder `to` next to it.
+    async fn archive(&self, name: &str, to: &str) -> Result<(), Error> {
+        match self {
+            Backend::S3(s3) => s3.rename(name, &format!("{}/{}", to, name)).await,
+            Backend::Sftp(sftp) => sftp.archive(name, to).await,
+        }
+    }
+}
+
+/// A prefix in an S3 bucket, reached with plain signed requests. Buckets on AWS
+/// are addressed by host name, anything at `WATCH_S3_ENDPOINT` by path.
+struct S3 {
+    client: reqwest::Client,
+    /// The bucket's URL, without a trailing slash.
+    base: Url,
+    bucket: String,
+    /// Ends in a slash unless it is the whole bucket.
+    prefix: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+impl S3 {
+    fn from_secrets(url: &Url, secrets: &SecretStore) -> anyhow::Result<Self> {
+        let bucket = url
+            .host_str()
+            .filter(|bucket| !bucket.is_empty())
+            .context("WATCH_FOLDER must name a bucket, as in s3://bucket/prefix")?
+            .to_string();
+        let mut prefix = url.path().trim_matches('/').to_string();
+        if !prefix.is_empty() {
+            prefix.push('/');
+        }
+        let region = secrets
+            .get("WATCH_S3_REGION")
+            .unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let base = match secrets.get("WATCH_S3_ENDPOINT") {
+            Some(endpoint) => format!("{}/{}", endpoint.trim().trim_end_matches('/'), bucket),
+            None => format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            base: Url::parse(&base).context("WATCH_S3_ENDPOINT must be a URL")?,
+            bucket,
+            prefix,
+            region,
+            access_key_id: secrets
+                .get("WATCH_S3_ACCESS_KEY_ID")
+                .context("WATCH_S3_ACCESS_KEY_ID is required for an s3:// WATCH_FOLDER")?,
+            secret_access_key: secrets
+                .get("WATCH_S3_SECRET_ACCESS_KEY")
+                .context("WATCH_S3_SECRET_ACCESS_KEY is required for an s3:// WATCH_FOLDER")?,
+        })
+    }
+
+    /// A request signed with AWS Signature Version 4, for `key` or the bucket itself.
+    fn request(
+        &self,
+        method: Method,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        extra_headers: &[(&str, String)],
+    ) -> Result<reqwest::RequestBuilder, Error> {
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut url = self.base.clone();
+        let base_path = self.base.path().trim_end_matches('/');
+        url.set_path(&match key {
+            Some(key) => format!("{}/{}", base_path, encode_key(key)),
+            None => format!("{}/", base_path),
+        });
+        let mut pairs: Vec<String> = query
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(name, UNRESERVED),
+                    utf8_percent_encode(value, UNRESERVED)
+                )
+            })
+            .collect();
+        pairs.sort();
+        let canonical_query = pairs.join("&");
+        url.set_query((!canonical_query.is_empty()).then_some(canonical_query.as_str()));
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", EMPTY_SHA256.to_string()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        headers.extend(extra_headers.iter().cloned());
+        headers.sort();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            url.path(),
+            canonical_query,
+            canonical_headers,
+            signed_headers,
+            EMPTY_SHA256
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = format!("AWS4{}", self.secret_access_key).into_bytes();
+        for part in [date.as_str(), &self.region, "s3", "aws4_request"] {
+            signing_key = hmac(&signing_key, part)?;
+        }
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&signing_key, &string_to_sign)?)
+        );
+
+        let mut request = self
+            .client
+            .request(method, url)
+            .header("authorization", authorization);
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        Ok(request)
+    }
+
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type", "2"),
+                ("prefix", self.prefix.as_str()),
+                ("delimiter", "/"),
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token));
+            }
+            let body = self
+                .request(Method::GET, None, &query, &[])?
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            names.extend(
+                elements(&body, "Key")
+                    .into_iter()
+                    .filter_map(|key| key.strip_prefix(&self.prefix).map(str::to_string))
+                    .filter(|name| !name.is_empty() && !name.contains('/')),
+            );
+            token = elements(&body, "NextContinuationToken").into_iter().next();
+            if token.is_none() {
+                return Ok(names);
+            }
+        }
+    }
+
+    async fn download(&self, name: &str, to: &Path) -> Result<(), Error> {
+        let key = format!("{}{}", self.prefix, name);
+        let mut response = self
+            .request(Method::GET, Some(&key), &[], &[])?
+            .send()
+            .await?
+            .error_for_status()?;
+        let mut file = tokio::fs::File::create(to).await?;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// S3 has no moves, so this is a copy and a delete.
+    async fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let from = format!("{}{}", self.prefix, from);
+        let to = format!("{}{}", self.prefix, to);
+        let source = format!("/{}/{}", self.bucket, encode_key(&from));
+        let body = self
+            .request(
+                Method::PUT,
+                Some(&to),
+                &[],
+                &[("x-amz-copy-source", source)],
+            )?
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        // A copy can fail after the 200 has been sent, with the error in the body.
+        if body.contains("<Error>") {
+            return Err(format!("Copying {} failed: {}", from, body).into());
+        }
+        self.request(Method::DELETE, Some(&from), &[], &[])?
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &str) -> Result<Vec<u8>, Error> {
+    let mut mac = HmacSha256::new_from_slice(key)?;
+    mac.update(data.as_bytes());
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// An object key as it goes in a URL path, its slashes left alone.
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| utf8_percent_encode(segment, UNRESERVED).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The text of every `<name>` element in an S3 response, unescaped. The responses
+/// read here are flat enough that this does not need an XML parser.
+fn elements(xml: &str, name: &str) -> Vec<String> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        found.push(
+            rest[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+        rest = &rest[end + close.len()..];
+    }
+    found
+}
+
+/// A directory on an SFTP server, reached with the `sftp` client in batch mode like
+/// ffmpeg is run for audio, so it has to be installed.
+struct Sftp {
+    /// `user@host`.
+    destination: String,
+    port: Option<u16>,
+    dir: String,
+    /// `WATCH_SFTP_KEY`, written out for `sftp -i`; otherwise the default keys.
+    identity: Option<media::LocalFile>,
+    /// `WATCH_SFTP_KNOWN_HOSTS`, written out likewise. Required: a server whose key
+    /// is not in it is refused.
+    known_hosts: media::LocalFile,
+}
+
+impl Sftp {
+    fn from_secrets(url: &Url, secrets: &SecretStore) -> anyhow::Result<Self> {
+        let host = url
+            .host_str()
+            .context("WATCH_FOLDER must name a host, as in sftp://user@host/path")?;
+        let destination = match url.username() {
+            "" => host.to_string(),
+            user => format!("{}@{}", user, host),
+        };
+        let dir = percent_encoding::percent_decode_str(url.path())
+            .decode_utf8()
+            .context("WATCH_FOLDER path must be UTF-8")?
+            .into_owned();
+        Ok(Self {
+            destination,
+            port: url.port(),
+            dir: if dir.is_empty() { ".".to_string() } else { dir },
+            identity: write_secret(secrets, "WATCH_SFTP_KEY", "sftp-key")?,
+            known_hosts: write_secret(secrets, "WATCH_SFTP_KNOWN_HOSTS", "sftp-known-hosts")?
+                .context(
+                    "WATCH_SFTP_KNOWN_HOSTS must hold the server's host key, as printed by \
+                     ssh-keyscan; an SFTP watch folder is not used without it",
+                )?,
+        })
+    }
+
+    /// Runs `commands` in one session and returns what they printed.
+    async fn batch(&self, commands: String) -> Result<String, Error> {
+        let script = media::LocalFile::scratch(&format!(
+            "sftp-{}.batch",
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        tokio::fs::write(&script.path, commands).await?;
+
+        let mut command = Command::new("sftp");
+        command
+            .arg("-q")
+            .arg("-b")
+            .arg(&script.path)
+            .args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            command.arg("-P").arg(port.to_string());
+        }
+        if let Some(identity) = &self.identity {
+            command
+                .arg("-i")
+                .arg(&identity.path)
+                .args(["-o", "IdentitiesOnly=yes"]);
+        }
+        command
+            .arg("-o")
+            .arg(format!(
+                "UserKnownHostsFile={}",
+                self.known_hosts.path.display()
+            ))
+            .args(["-o", "StrictHostKeyChecking=yes"])
+            .arg(&self.destination);
+
+        let output = command.output().await?;
+        if !output.status.success() {
+            return Err(format!(
+                "sftp failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn path(&self, name: &str) -> String {
+        quote(&format!("{}/{}", self.dir.trim_end_matches('/'), name))
+    }
+
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let output = self
+            .batch(format!("cd {}\nls -1\n", quote(&self.dir)))
+            .await?;
+        Ok(output
+            .lines()
+            .filter(|line| !line.starts_with("sftp>"))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn download(&self, name: &str, to: &Path) -> Result<(), Error> {
+        self.batch(format!(
+            "get {} {}\n",
+            self.path(name),
+            quote(&to.to_string_lossy())
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Moves `name` into the folder `to`, made first if need be; a leading `-` keeps
+    /// sftp going when it already exists.
+    async fn archive(&self, name: &str, to: &str) -> Result<(), Error> {
+        self.batch(format!(
+            "-mkdir {}\nrename {} {}\n",
+            self.path(to),
+            self.path(name),
+            self.path(&format!("{}/{}", to, name))
+        ))
+        .await?;
+        Ok(())
+    }
+}
+
+/// A path quoted for an sftp batch file.
+fn quote(path: &str) -> String {
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes the secret `name` to a private scratch file, for tools that only read
+/// keys from files.
+fn write_secret(
+    secrets: &SecretStore,
+    name: &str,
+    file: &str,
+) -> anyhow::Result<Option<media::LocalFile>> {
+    let Some(mut contents) = secrets.get(name).filter(|value| !value.trim().is_empty()) else {
+        return Ok(None);
+    };
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    let local = media::LocalFile::scratch(file);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&local.path)
+        .and_then(|mut out| std::io::Write::write_all(&mut out, contents.as_bytes()))
+        .with_context(|| format!("Failed to write {}", name))?;
+    Ok(Some(local))
+}
@@ -0,0 +1,124 @@
+use crate::{ServerSecretsState, media, telegram};
+use anyhow::bail;
+use chrono::Utc;
+use std::path::PathBuf;
+use teloxide::{prelude::*, types::Audio};
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where the rendered waveform goes, configured with `WAVEFORM`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// A small square image used as the audio's thumbnail.
+    Thumbnail,
+    /// A wide image posted as a reply to the track.
+    Photo,
+}
+
+impl Mode {
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match raw.map(str::trim) {
+            None | Some("") | Some("off") => Ok(None),
+            Some("thumbnail") => Ok(Some(Mode::Thumbnail)),
+            Some("photo") => Ok(Some(Mode::Photo)),
+            Some(other) => bail!("WAVEFORM must be off, thumbnail or photo, not {}", other),
+        }
+    }
+
+    fn file_name(self, file_unique_id: &str) -> String {
+        match self {
+            Mode::Thumbnail => format!("{}-thumb.jpg", file_unique_id),
+            Mode::Photo => format!("{}.png", file_unique_id),
+        }
+    }
+
+    fn size(self) -> &'static str {
+        match self {
+            Mode::Thumbnail => "320x320",
+            Mode::Photo => "1280x320",
+        }
+    }
+}
+
+fn cache_dir() -> PathBuf {
+    std::env::temp_dir().join("ankh-waveforms")
+}
+
+/// Renders the waveform of `audio` with ffmpeg, reusing an earlier rendering of the
+/// same file if there is one. Renderings are cached by `file_unique_id`, which stays
+/// the same when a file is resent, and only land in the cache once complete.
+pub async fn render(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    audio: &Audio,
+    mode: Mode,
+) -> Result<PathBuf, Error> {
+    let path = cache_dir().join(mode.file_name(&audio.file.unique_id.0));
+    if tokio::fs::try_exists(&path).await? {
+        return Ok(path);
+    }
+
+    tokio::fs::create_dir_all(cache_dir()).await?;
+    let input = media::fetch(bot, secrets, &audio.file).await?;
+    let rendering = media::LocalFile::scratch(&format!(
+        "waveform-{}-{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        mode.file_name(&audio.file.unique_id.0)
+    ));
+    media::run(
+        Command::new("ffmpeg")
+            .args(["-y", "-v", "error", "-i"])
+            .arg(&input.path)
+            .arg("-filter_complex")
+            .arg(format!(
+                "showwavespic=s={}:split_channels=0:colors=white",
+                mode.size()
+            ))
+            .args(["-frames:v", "1"])
+            .arg(&rendering.path),
+    )
+    .await?;
+    rendering.persist(&path).await?;
+
+    Ok(path)
+}
+
+/// Renders the waveform for the thumbnail of a track that is about to be posted,
+/// logging rather than failing the post when that does not work out.
+pub async fn thumbnail(bot: &Bot, secrets: &ServerSecretsState, audio: &Audio) -> Option<PathBuf> {
+    if secrets.waveform != Some(Mode::Thumbnail) {
+        return None;
+    }
+    match render(bot, secrets, audio, Mode::Thumbnail).await {
+        Ok(path) => Some(path),
+        Err(e) => {
+            tracing::warn!("Failed to render waveform thumbnail: {}", e);
+            None
+        }
+    }
+}
+
+/// Replies to a freshly posted track with its waveform, if that is enabled.
+pub async fn post_photo(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    audio: &Audio,
+    track: &telegram::Sent,
+) -> Result<Option<telegram::Sent>, Error> {
+    if secrets.waveform != Some(Mode::Photo) {
+        return Ok(None);
+    }
+    let path = render(bot, secrets, audio, Mode::Photo).await?;
+    let photo = telegram::OutgoingPhoto {
+        path,
+        caption: None,
+        reply_to: Some(track.id),
+        silent: true,
+    };
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_photo(track.chat_id, &photo))
+        .await?;
+    Ok(Some(message))
+}
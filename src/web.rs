@@ -0,0 +1,283 @@
+//! What the two web backends share. Exactly one is compiled in: Rocket by default,
+//! or axum with `--no-default-features --features axum`. Handlers keep their logic
+//! in plain functions next to the routes, and each backend only adapts requests and
+//! responses to them.
+
+#[cfg(all(feature = "rocket", feature = "axum"))]
+compile_error!("the rocket and axum features are mutually exclusive");
+#[cfg(not(any(feature = "rocket", feature = "axum")))]
+compile_error!("enable either the rocket or the axum feature");
+
+use crate::{
+    ServerSecretsState, cors::Cors, graphql, rate_limit::RateLimiter, telemetry::Telemetry,
+};
+use std::sync::Arc;
+use teloxide::Bot;
+
+/// How many log lines `GET /logs` returns without `?limit=`.
+const DEFAULT_LOG_LINES: usize = 100;
+
+/// A failed request: a status code and a plain text body.
+#[derive(Debug)]
+pub struct HttpError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl HttpError {
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Logs `e` and answers 500 without exposing it.
+    pub fn internal(context: &str, e: impl std::fmt::Display) -> Self {
+        tracing::error!("{}: {}", context, e);
+        Self::new(500, context)
+    }
+}
+
+#[cfg(feature = "rocket")]
+impl<'r> rocket::response::Responder<'r, 'static> for HttpError {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let status = rocket::http::Status::from_code(self.status)
+            .unwrap_or(rocket::http::Status::InternalServerError);
+        (status, self.message).respond_to(req)
+    }
+}
+
+#[cfg(feature = "axum")]
+impl axum::response::IntoResponse for HttpError {
+    fn into_response(self) -> axum::response::Response {
+        let status = axum::http::StatusCode::from_u16(self.status)
+            .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        (status, self.message).into_response()
+    }
+}
+
+/// What `main` returns to Shuttle.
+#[cfg(feature = "rocket")]
+pub type Server = shuttle_rocket::ShuttleRocket;
+#[cfg(feature = "axum")]
+pub type Server = Result<AxumService, shuttle_runtime::Error>;
+
+/// Everything the server is built from.
+pub struct App {
+    pub bot: Arc<Bot>,
+    pub secrets: Arc<ServerSecretsState>,
+    pub schema: graphql::CatalogSchema,
+    pub cors: Option<Cors>,
+    pub rate_limiter: Option<RateLimiter>,
+    pub telemetry: Telemetry,
+}
+
+#[cfg(feature = "rocket")]
+pub use rocket_server::serve;
+
+#[cfg(feature = "rocket")]
+mod rocket_server {
+    use super::*;
+    use crate::{auth, logs::LogRecord};
+    use rocket::{
+        State,
+        data::{ByteUnit, Limits},
+        fairing::AdHoc,
+        get, post, routes,
+        serde::json::Json,
+    };
+    use teloxide::types::Update;
+
+    #[get("/")]
+    fn index_handler() -> &'static str {
+        "hi!"
+    }
+
+    #[get("/logs?<limit>")]
+    fn logs_handler(
+        _auth: auth::Authorized<auth::scope::Logs>,
+        secrets: &State<Arc<ServerSecretsState>>,
+        limit: Option<usize>,
+    ) -> Json<Vec<LogRecord>> {
+        Json(secrets.logs.tail(limit.unwrap_or(DEFAULT_LOG_LINES)))
+    }
+
+    #[post("/<_bot_token>", data = "<update>")]
+    fn webhook_handler(
+        bot: &State<Arc<Bot>>,
+        update: Json<Update>,
+        _bot_token: &str,
+        secrets: &State<Arc<ServerSecretsState>>,
+    ) -> &'static str {
+        crate::dispatch_update(
+            bot.inner().clone(),
+            update.into_inner(),
+            secrets.inner().clone(),
+        );
+        "OK"
+    }
+
+    pub fn serve(app: App) -> Server {
+        // Uploads to /api/v1/upload may be as large as the Bot API accepts.
+        let upload_limit = ByteUnit::from(app.secrets.bot_api_mode.upload_limit());
+        let figment = rocket::Config::figment().merge((
+            "limits",
+            Limits::default()
+                .limit("file", upload_limit)
+                .limit("data-form", upload_limit),
+        ));
+
+        let telemetry = app.telemetry;
+        let rocket = rocket::custom(figment)
+            .manage(app.bot)
+            .mount("/", routes![index_handler, logs_handler, webhook_handler])
+            .mount("/", crate::dashboard::routes())
+            .mount("/", crate::calendar::routes())
+            .mount("/", graphql::routes())
+            .mount("/api/v1", crate::api::routes())
+            .mount("/api/v1", crate::cors::routes())
+            .mount("/", crate::hooks::routes())
+            .mount("/", crate::rate_limit::routes())
+            .manage(app.schema)
+            .manage(app.secrets)
+            .attach(AdHoc::on_shutdown("Telemetry", move |_| {
+                Box::pin(async move { telemetry.shutdown() })
+            }));
+        let rocket = match app.cors {
+            Some(cors) => rocket.attach(cors),
+            None => rocket,
+        };
+        let rocket = match app.rate_limiter {
+            Some(rate_limiter) => rocket.attach(rate_limiter),
+            None => rocket,
+        };
+        Ok(rocket.into())
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use axum_server::serve;
+
+#[cfg(feature = "axum")]
+mod axum_server {
+    use super::*;
+    use crate::{auth, logs::LogRecord};
+    use axum::{
+        Json, Router,
+        extract::{Query, State},
+        middleware,
+        routing::{get, options, post},
+    };
+    use serde::Deserialize;
+    use teloxide::types::Update;
+
+    async fn index_handler() -> &'static str {
+        "hi!"
+    }
+
+    #[derive(Deserialize)]
+    struct LogsQuery {
+        limit: Option<usize>,
+    }
+
+    async fn logs_handler(
+        _auth: auth::Authorized<auth::scope::Logs>,
+        State(state): State<AppState>,
+        Query(query): Query<LogsQuery>,
+    ) -> Json<Vec<LogRecord>> {
+        Json(
+            state
+                .secrets
+                .logs
+                .tail(query.limit.unwrap_or(DEFAULT_LOG_LINES)),
+        )
+    }
+
+    async fn webhook_handler(
+        State(state): State<AppState>,
+        Json(update): Json<Update>,
+    ) -> &'static str {
+        crate::dispatch_update(state.bot, update, state.secrets);
+        "OK"
+    }
+
+    pub fn serve(app: App) -> Server {
+        let upload_limit = app.secrets.bot_api_mode.upload_limit() as usize;
+        let router = Router::new()
+            .route("/", get(index_handler))
+            .route("/logs", get(logs_handler))
+            .route("/{bot_token}", post(webhook_handler))
+            .nest(
+                "/api/v1",
+                crate::api::router(upload_limit).route("/{*path}", options(crate::cors::preflight)),
+            )
+            .merge(crate::dashboard::router())
+            .merge(crate::calendar::router())
+            .merge(graphql::router())
+            .merge(crate::hooks::router())
+            .with_state(AppState {
+                bot: app.bot,
+                secrets: app.secrets,
+                schema: app.schema,
+            });
+        // Layers wrap what is already there, so CORS headers also go on 429s.
+        let router = match app.rate_limiter {
+            Some(rate_limiter) => router.layer(middleware::from_fn_with_state(
+                Arc::new(rate_limiter),
+                crate::rate_limit::limit,
+            )),
+            None => router,
+        };
+        let router = match app.cors {
+            Some(cors) => router.layer(middleware::from_fn_with_state(
+                Arc::new(cors),
+                crate::cors::add_headers,
+            )),
+            None => router,
+        };
+
+        let telemetry = app.telemetry;
+        Ok(AxumService {
+            router,
+            on_shutdown: Box::new(move || telemetry.shutdown()),
+        })
+    }
+}
+
+/// Everything axum handlers get through `State`.
+#[cfg(feature = "axum")]
+#[derive(Clone)]
+pub struct AppState {
+    pub bot: Arc<Bot>,
+    pub secrets: Arc<ServerSecretsState>,
+    pub schema: graphql::CatalogSchema,
+}
+
+/// An axum router bound to the address Shuttle hands out, the counterpart of
+/// `shuttle_rocket::RocketService`.
+#[cfg(feature = "axum")]
+pub struct AxumService {
+    pub router: axum::Router,
+    /// Run once the server stops.
+    pub on_shutdown: Box<dyn FnOnce() + Send>,
+}
+
+#[cfg(feature = "axum")]
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for AxumService {
+    async fn bind(self, addr: std::net::SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(shuttle_runtime::CustomError::new)?;
+        let result = axum::serve(
+            listener,
+            self.router
+                .into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
+        .await;
+        (self.on_shutdown)();
+        result.map_err(shuttle_runtime::CustomError::new)?;
+        Ok(())
+    }
+}
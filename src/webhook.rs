@@ -0,0 +1,346 @@
+use crate::{ServerSecretsState, settings};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use teloxide::{RequestError, prelude::*, types::AllowedUpdate};
+use tokio::time::{Duration, sleep};
+use url::Url;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const SETTING: &str = "allowed_updates";
+
+/// How often the webhook is checked, unless `WEBHOOK_CHECK_MINUTES` says otherwise.
+const DEFAULT_CHECK_MINUTES: u64 = 5;
+/// More pending updates than this, and still growing, means Telegram cannot reach us.
+const DEFAULT_PENDING_LIMIT: u32 = 100;
+/// Pending updates growing for this many checks in a row is reported even below the
+/// limit, since nothing drains them while deliveries fail.
+const RISING_CHECKS: usize = 3;
+/// How long samples are kept in `webhook_checks`.
+const KEEP_CHECKS: chrono::Duration = chrono::Duration::days(7);
+
+/// The updates the bot handles, unless `/setwebhook` chose others.
+const DEFAULT_UPDATES: [AllowedUpdate; 10] = [
+    AllowedUpdate::Message,
+    AllowedUpdate::EditedMessage,
+    AllowedUpdate::ChannelPost,
+    AllowedUpdate::EditedChannelPost,
+    AllowedUpdate::InlineQuery,
+    AllowedUpdate::CallbackQuery,
+    AllowedUpdate::MessageReactionCount,
+    AllowedUpdate::ChatMember,
+    AllowedUpdate::ChatJoinRequest,
+    AllowedUpdate::Poll,
+];
+
+/// The update types Telegram is asked for: the ones set with `/setwebhook`, or the
+/// defaults.
+pub async fn allowed_updates(db: &PgPool) -> sqlx::Result<Vec<AllowedUpdate>> {
+    Ok(settings::get(db, SETTING)
+        .await?
+        .unwrap_or_else(|| DEFAULT_UPDATES.to_vec()))
+}
+
+/// Points Telegram at `url` for `updates`.
+pub async fn register(bot: &Bot, url: Url, updates: &[AllowedUpdate]) -> Result<(), RequestError> {
+    bot.set_webhook(url)
+        .allowed_updates(updates.iter().copied())
+        .await?;
+    Ok(())
+}
+
+/// "message", "callback_query": the names the Bot API uses.
+fn name(update: AllowedUpdate) -> String {
+    serde_json::to_value(update)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", update))
+}
+
+fn parse(name: &str) -> Option<AllowedUpdate> {
+    serde_json::from_value(serde_json::Value::String(name.to_lowercase())).ok()
+}
+
+/// What `/setwebhook` was asked to register for, starting from `current`: a whole new
+/// list, `+name`/`-name` changes to it, or `default`. `None` for names Telegram does
+/// not know.
+fn updates_for(args: &str, current: &[AllowedUpdate]) -> Option<Vec<AllowedUpdate>> {
+    let words: Vec<&str> = args
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        return Some(current.to_vec());
+    }
+    if words == ["default"] {
+        return Some(DEFAULT_UPDATES.to_vec());
+    }
+    let changes = words
+        .iter()
+        .all(|word| word.starts_with('+') || word.starts_with('-'));
+    let mut updates = if changes {
+        current.to_vec()
+    } else {
+        Vec::new()
+    };
+    for word in words {
+        if let Some(name) = word.strip_prefix('-') {
+            let update = parse(name)?;
+            updates.retain(|current| *current != update);
+        } else {
+            let update = parse(word.trim_start_matches('+'))?;
+            if !updates.contains(&update) {
+                updates.push(update);
+            }
+        }
+    }
+    Some(updates)
+}
+
+/// `/setwebhook [updates]`: registers the webhook again, for the same update types,
+/// a new comma-separated list of them, `+name`/`-name` changes, or `default`. The
+/// choice is kept, so the watchdog and restarts register for it too.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let current = allowed_updates(&secrets.db).await?;
+    let Some(updates) = updates_for(args, &current) else {
+        bot.send_message(
+            message.chat.id,
+            format!(
+                "Usage: /setwebhook [message,callback_query,… | +poll -chat_member | default]\n\
+                 Currently: {}",
+                current
+                    .iter()
+                    .copied()
+                    .map(name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )
+        .await?;
+        return Ok(());
+    };
+    if updates.is_empty() {
+        bot.send_message(message.chat.id, "The bot needs at least one update type.")
+            .await?;
+        return Ok(());
+    }
+
+    register(bot, secrets.webhook_url.clone(), &updates).await?;
+    if updates == DEFAULT_UPDATES {
+        settings::clear(&secrets.db, SETTING).await?;
+    } else {
+        settings::set(&secrets.db, SETTING, &updates).await?;
+    }
+    let names = updates.iter().copied().map(name).collect::<Vec<_>>();
+    tracing::info!("Webhook registered for {}", names.join(", "));
+    bot.send_message(
+        message.chat.id,
+        format!("🪝 Webhook registered for {}", names.join(", ")),
+    )
+    .await?;
+    Ok(())
+}
+
+pub struct Watchdog {
+    every: Duration,
+    pending_limit: u32,
+}
+
+impl Watchdog {
+    /// Parses `WEBHOOK_CHECK_MINUTES` (0 turns the watchdog off) and
+    /// `WEBHOOK_PENDING_LIMIT`.
+    pub fn from_secrets(
+        minutes: Option<&str>,
+        pending_limit: Option<&str>,
+    ) -> anyhow::Result<Option<Self>> {
+        let minutes = match minutes {
+            Some(minutes) => minutes
+                .trim()
+                .parse()
+                .context("WEBHOOK_CHECK_MINUTES must be a number of minutes")?,
+            None => DEFAULT_CHECK_MINUTES,
+        };
+        let pending_limit = match pending_limit {
+            Some(limit) => limit
+                .trim()
+                .parse()
+                .context("WEBHOOK_PENDING_LIMIT must be a number of updates")?,
+            None => DEFAULT_PENDING_LIMIT,
+        };
+        Ok((minutes > 0).then(|| Self {
+            every: Duration::from_secs(minutes * 60),
+            pending_limit,
+        }))
+    }
+
+    /// Checks the webhook every so often and registers it again when Telegram has
+    /// lost it, points it elsewhere, or has been failing to deliver since the last
+    /// check. The owner hears about every re-registration, and about updates piling
+    /// up below the limit too.
+    pub fn spawn(self, bot: Bot, db: PgPool, owner: Option<ChatId>, url: Url) {
+        tokio::spawn(async move {
+            let mut checked_at = Utc::now();
+            let mut last_pending = 0;
+            let mut backlog_reported = false;
+            loop {
+                sleep(self.every).await;
+                let info = match bot.get_webhook_info().await {
+                    Ok(info) => info,
+                    Err(e) => {
+                        tracing::warn!("Failed to check webhook: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = record(&db, &info).await {
+                    tracing::warn!("Failed to record webhook check: {}", e);
+                }
+
+                let problem = self.problem(&info, &url, last_pending, checked_at);
+                last_pending = info.pending_update_count;
+                checked_at = Utc::now();
+                let Some(problem) = problem else {
+                    if info.pending_update_count == 0 {
+                        backlog_reported = false;
+                    } else if !backlog_reported && let Some(text) = backlog(&db, &info).await {
+                        backlog_reported = true;
+                        tracing::warn!("{}", text);
+                        if let Some(owner) = owner
+                            && let Err(e) = bot.send_message(owner, text).await
+                        {
+                            tracing::warn!("Failed to send webhook alert: {}", e);
+                        }
+                    }
+                    continue;
+                };
+
+                tracing::warn!("Webhook unhealthy, registering it again: {}", problem);
+                let updates = match allowed_updates(&db).await {
+                    Ok(updates) => updates,
+                    Err(e) => {
+                        tracing::warn!("Failed to read allowed updates, using defaults: {}", e);
+                        DEFAULT_UPDATES.to_vec()
+                    }
+                };
+                let text = match register(&bot, url.clone(), &updates).await {
+                    Ok(()) => format!("⚠️ Webhook registered again: {}.", problem),
+                    Err(e) => format!(
+                        "⚠️ Webhook unhealthy ({}) and registering it again failed: {}",
+                        problem, e
+                    ),
+                };
+                if let Some(owner) = owner
+                    && let Err(e) = bot.send_message(owner, text).await
+                {
+                    tracing::warn!("Failed to send webhook alert: {}", e);
+                }
+            }
+        });
+    }
+
+    /// What is wrong with the webhook, if anything. The URL holds the bot token, so
+    /// only its host is ever mentioned.
+    fn problem(
+        &self,
+        info: &teloxide::types::WebhookInfo,
+        url: &Url,
+        last_pending: u32,
+        checked_at: DateTime<Utc>,
+    ) -> Option<String> {
+        match &info.url {
+            None => return Some("it was not set".to_string()),
+            Some(current) if current != url => {
+                return Some(format!(
+                    "it pointed at {}",
+                    current.host_str().unwrap_or("another URL")
+                ));
+            }
+            Some(_) => {}
+        }
+        if info.pending_update_count > self.pending_limit
+            && info.pending_update_count > last_pending
+        {
+            return Some(format!(
+                "{} updates were pending",
+                info.pending_update_count
+            ));
+        }
+        match info.last_error_date {
+            Some(error_at) if error_at > checked_at => Some(format!(
+                "delivery failed at {} UTC with \"{}\"",
+                error_at.format("%H:%M"),
+                info.last_error_message.as_deref().unwrap_or("no message")
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Stores what a check saw, and forgets checks older than a week.
+async fn record(db: &PgPool, info: &teloxide::types::WebhookInfo) -> sqlx::Result<()> {
+    sqlx::query(
+        "INSERT INTO webhook_checks (pending_update_count, last_error_message, last_error_at)
+         VALUES ($1, $2, $3)",
+    )
+    .bind(info.pending_update_count as i32)
+    .bind(&info.last_error_message)
+    .bind(info.last_error_date)
+    .execute(db)
+    .await?;
+    sqlx::query("DELETE FROM webhook_checks WHERE checked_at < $1")
+        .bind(Utc::now() - KEEP_CHECKS)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// An alert when pending updates grew at every one of the last few checks, e.g.
+/// "📬 Updates are piling up undelivered: 3 → 17 → 42 pending since 12:05 UTC".
+async fn backlog(db: &PgPool, info: &teloxide::types::WebhookInfo) -> Option<String> {
+    let checks: Vec<(DateTime<Utc>, i32)> = sqlx::query_as(
+        "SELECT checked_at, pending_update_count FROM webhook_checks
+         ORDER BY checked_at DESC LIMIT $1",
+    )
+    .bind(RISING_CHECKS as i64)
+    .fetch_all(db)
+    .await
+    .inspect_err(|e| tracing::warn!("Failed to read webhook checks: {}", e))
+    .ok()?;
+    let rising =
+        checks.len() == RISING_CHECKS && checks.windows(2).all(|pair| pair[0].1 > pair[1].1);
+    if !rising {
+        return None;
+    }
+    let (since, _) = checks.last()?;
+    let counts = checks
+        .iter()
+        .rev()
+        .map(|(_, count)| count.to_string())
+        .collect::<Vec<_>>()
+        .join(" → ");
+    let mut text = format!(
+        "📬 Updates are piling up undelivered: {} pending since {} UTC.",
+        counts,
+        since.format("%H:%M")
+    );
+    if let Some(message) = &info.last_error_message {
+        text.push_str(&format!(" Last delivery error: \"{}\"", message));
+    }
+    Some(text)
+}
+
+/// Pending updates at the first check in the last `ago`, for showing the trend.
+pub async fn pending_since(db: &PgPool, ago: chrono::Duration) -> sqlx::Result<Option<i32>> {
+    sqlx::query_scalar(
+        "SELECT pending_update_count FROM webhook_checks
+         WHERE checked_at >= $1 ORDER BY checked_at LIMIT 1",
+    )
+    .bind(Utc::now() - ago)
+    .fetch_optional(db)
+    .await
+}
@@ -0,0 +1,71 @@
+use crate::{ServerSecretsState, catalog, cleanup, settings};
+use teloxide::prelude::*;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const SETTING: &str = "welcome";
+
+/// The `/start` reply when neither `/setwelcome` nor `WELCOME_TEXT` says otherwise.
+pub const DEFAULT_TEXT: &str = "Welcome! Up and running.";
+
+const PLACEHOLDERS: &str = "{name} (who is asking), {bot} (the bot's username), \
+     {channel} (the channel link), {queue} (tracks waiting)";
+
+/// Fills in the placeholders a canned reply can use. Unknown ones are left as is.
+pub async fn render(template: &str, secrets: &ServerSecretsState, message: &Message) -> String {
+    let name = message
+        .from
+        .as_ref()
+        .map(|user| user.first_name.clone())
+        .unwrap_or_default();
+    let queue = secrets.message_queue.snapshot().await.len();
+    template
+        .replace("{name}", &name)
+        .replace("{bot}", &format!("@{}", secrets.bot_username))
+        .replace("{channel}", &catalog::channel_link())
+        .replace("{queue}", &queue.to_string())
+}
+
+/// The `/start` template: the one set with `/setwelcome`, else `WELCOME_TEXT`.
+async fn template(secrets: &ServerSecretsState) -> Result<String, Error> {
+    Ok(settings::get(&secrets.db, SETTING)
+        .await?
+        .unwrap_or_else(|| secrets.welcome_text.clone()))
+}
+
+pub async fn send(bot: &Bot, message: &Message, secrets: &ServerSecretsState) -> Result<(), Error> {
+    let text = render(&template(secrets).await?, secrets, message).await;
+    cleanup::reply(bot, secrets, message.chat.id, text).await?;
+    Ok(())
+}
+
+/// `/setwelcome [text | reset]`. Without text, shows the current template.
+pub async fn handle_command(
+    bot: &Bot,
+    message: &Message,
+    args: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let args = args.trim();
+    let reply = match args {
+        "" => format!(
+            "Current welcome:\n{}\n\nSet it with /setwelcome <text>, or /setwelcome reset. \
+             Placeholders: {}",
+            template(secrets).await?,
+            PLACEHOLDERS
+        ),
+        "reset" => {
+            settings::clear(&secrets.db, SETTING).await?;
+            format!("Welcome reset to:\n{}", secrets.welcome_text)
+        }
+        text => {
+            settings::set(&secrets.db, SETTING, &text).await?;
+            format!(
+                "Welcome set. It will look like:\n{}",
+                render(text, secrets, message).await
+            )
+        }
+    };
+    cleanup::reply(bot, secrets, message.chat.id, reply).await?;
+    Ok(())
+}
@@ -0,0 +1,490 @@
+use crate::{ServerSecretsState, catalog, commands, media, telegram};
+use anyhow::bail;
+use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+use sqlx::PgPool;
+use std::sync::atomic::Ordering;
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, ParseMode},
+    utils::markdown,
+};
+use tokio::process::Command;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub const CALLBACK_PREFIX: &str = "wrapped:";
+
+/// How many artists and posts the recap ranks.
+const TOP: i64 = 5;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+#[derive(Clone, Copy)]
+pub enum Mode {
+    /// Post the recap to the channel on December 31.
+    Publish,
+    /// DM the owner a preview with publish/skip buttons.
+    Approve,
+}
+
+impl Mode {
+    /// Parses the `YEAR_IN_REVIEW` secret; a missing value or `off` disables it.
+    pub fn from_secret(raw: Option<&str>) -> anyhow::Result<Option<Self>> {
+        match raw.map(str::trim) {
+            None | Some("") | Some("off") => Ok(None),
+            Some("publish") => Ok(Some(Mode::Publish)),
+            Some("approve") => Ok(Some(Mode::Approve)),
+            Some(other) => bail!(
+                "YEAR_IN_REVIEW must be off, publish or approve, not {}",
+                other
+            ),
+        }
+    }
+}
+
+/// "1 track", "12 tracks".
+fn tracks(count: i64) -> String {
+    match count {
+        1 => "1 track".to_string(),
+        count => format!("{} tracks", count),
+    }
+}
+
+/// What a year of the channel came to.
+struct Recap {
+    year: i32,
+    tracks: i64,
+    duration_secs: i64,
+    /// Tracks posted in each month, January first.
+    per_month: [i64; 12],
+    /// Performers by how many of their tracks were posted, most first.
+    top_artists: Vec<(String, i64)>,
+    /// Posts by their view count on the channel's public page, most first.
+    most_viewed: Vec<(catalog::Track, i64)>,
+    /// The first and last subscriber count of the year, when there were counts.
+    subscribers: Option<(i32, i32)>,
+}
+
+impl Recap {
+    async fn of(db: &PgPool, year: i32) -> sqlx::Result<Self> {
+        let from = Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap();
+        let until = Utc.with_ymd_and_hms(year + 1, 1, 1, 0, 0, 0).unwrap();
+
+        let (tracks, duration_secs): (i64, i64) = sqlx::query_as(
+            "SELECT count(*), coalesce(sum(duration_secs), 0)::bigint FROM tracks
+             WHERE deleted_at IS NULL AND posted_at >= $1 AND posted_at < $2",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_one(db)
+        .await?;
+
+        let months: Vec<(i32, i64)> = sqlx::query_as(
+            "SELECT extract(month FROM posted_at)::int, count(*) FROM tracks
+             WHERE deleted_at IS NULL AND posted_at >= $1 AND posted_at < $2
+             GROUP BY 1",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(db)
+        .await?;
+        let mut per_month = [0; 12];
+        for (month, count) in months {
+            per_month[(month as usize - 1).min(11)] = count;
+        }
+
+        let top_artists = sqlx::query_as(
+            "SELECT min(trim(performer)), count(*) FROM tracks
+             WHERE deleted_at IS NULL AND posted_at >= $1 AND posted_at < $2
+                 AND trim(performer) <> ''
+             GROUP BY lower(trim(performer))
+             ORDER BY count(*) DESC, min(trim(performer)) LIMIT $3",
+        )
+        .bind(from)
+        .bind(until)
+        .bind(TOP)
+        .fetch_all(db)
+        .await?;
+
+        let posted: Vec<catalog::Track> = sqlx::query_as(
+            "SELECT * FROM tracks
+             WHERE deleted_at IS NULL AND posted_at >= $1 AND posted_at < $2
+             ORDER BY posted_at",
+        )
+        .bind(from)
+        .bind(until)
+        .fetch_all(db)
+        .await?;
+        let most_viewed = most_viewed(posted).await;
+
+        let subscribers = sqlx::query_as(
+            "SELECT
+                 (SELECT count FROM subscriber_counts WHERE counted_on >= $1 AND counted_on < $2
+                  ORDER BY counted_on LIMIT 1),
+                 (SELECT count FROM subscriber_counts WHERE counted_on >= $1 AND counted_on < $2
+                  ORDER BY counted_on DESC LIMIT 1)",
+        )
+        .bind(from.date_naive())
+        .bind(until.date_naive())
+        .fetch_one(db)
+        .await
+        .map(|(first, last): (Option<i32>, Option<i32>)| first.zip(last))?;
+
+        Ok(Recap {
+            year,
+            tracks,
+            duration_secs,
+            per_month,
+            top_artists,
+            most_viewed,
+            subscribers,
+        })
+    }
+
+    /// The month with the most tracks, the earliest of a tie.
+    fn busiest_month(&self) -> Option<(&'static str, i64)> {
+        let (index, count) = self
+            .per_month
+            .iter()
+            .enumerate()
+            .max_by_key(|&(index, count)| (*count, std::cmp::Reverse(index)))?;
+        (*count > 0).then_some((MONTHS[index], *count))
+    }
+
+    /// The short line under the chart.
+    fn caption(&self) -> String {
+        format!(
+            "🎁 *{}*\n{}",
+            markdown::escape(&format!("{} in review", self.year)),
+            markdown::escape("Tracks posted each month")
+        )
+    }
+
+    fn render(&self) -> String {
+        let hours = self.duration_secs / 3600;
+        let minutes = self.duration_secs % 3600 / 60;
+        let mut text = format!(
+            "🎁 *{}*\n\n",
+            markdown::escape(&format!("{} in review", self.year))
+        );
+        text.push_str(&markdown::escape(&format!(
+            "🎵 {}, {} h {} min of music\n",
+            tracks(self.tracks),
+            hours,
+            minutes
+        )));
+        if let Some((month, count)) = self.busiest_month() {
+            text.push_str(&markdown::escape(&format!(
+                "📅 Busiest month: {}, with {}\n",
+                month,
+                tracks(count)
+            )));
+        }
+        if let Some((first, last)) = self.subscribers {
+            text.push_str(&markdown::escape(&format!(
+                "👥 Subscribers: {} → {} ({:+})\n",
+                first,
+                last,
+                last - first
+            )));
+        }
+
+        if !self.top_artists.is_empty() {
+            text.push_str("\n*Top artists*\n");
+            for (rank, (artist, count)) in self.top_artists.iter().enumerate() {
+                text.push_str(&markdown::escape(&format!(
+                    "{}. {} · {}\n",
+                    rank + 1,
+                    artist,
+                    tracks(*count)
+                )));
+            }
+        }
+        if !self.most_viewed.is_empty() {
+            text.push_str("\n*Most viewed*\n");
+            for (rank, (track, views)) in self.most_viewed.iter().enumerate() {
+                text.push_str(&format!(
+                    "{} {}{}\n",
+                    markdown::escape(&format!("{}.", rank + 1)),
+                    markdown::link(
+                        &markdown::escape_link_url(&catalog::permalink(track.message_id)),
+                        &markdown::escape(&track.label()),
+                    ),
+                    markdown::escape(&format!(" · {} views", views))
+                ));
+            }
+        }
+        text
+    }
+
+    /// A bar per month, the busiest one highlighted. Text is left to the caption, as
+    /// not every ffmpeg has `drawtext`.
+    async fn chart(&self) -> Result<media::LocalFile, Error> {
+        const WIDTH: i64 = 1200;
+        const HEIGHT: i64 = 600;
+        const MARGIN: i64 = 60;
+        let slot = (WIDTH - 2 * MARGIN) / 12;
+        let baseline = HEIGHT - MARGIN;
+        let max = self.per_month.iter().copied().max().unwrap_or(0).max(1);
+        let busiest = self
+            .busiest_month()
+            .and_then(|(month, _)| MONTHS.iter().position(|name| *name == month));
+
+        let mut filter = format!("color=c=0x14161c:s={}x{}:d=1", WIDTH, HEIGHT);
+        filter.push_str(&format!(
+            ",drawbox=x={}:y={}:w={}:h=2:color=0x5c6370:t=fill",
+            MARGIN,
+            baseline,
+            WIDTH - 2 * MARGIN
+        ));
+        for (index, count) in self.per_month.iter().enumerate() {
+            let height = (count * (baseline - MARGIN) / max).max(if *count > 0 { 4 } else { 0 });
+            if height == 0 {
+                continue;
+            }
+            let color = if busiest == Some(index) {
+                "0xffb86c"
+            } else {
+                "0x61afef"
+            };
+            filter.push_str(&format!(
+                ",drawbox=x={}:y={}:w={}:h={}:color={}:t=fill",
+                MARGIN + index as i64 * slot + slot / 8,
+                baseline - height,
+                slot * 3 / 4,
+                height,
+                color
+            ));
+        }
+
+        let output = media::LocalFile::scratch(&format!("wrapped-{}.png", self.year));
+        media::run(
+            Command::new("ffmpeg")
+                .args(["-y", "-v", "error", "-f", "lavfi", "-i"])
+                .arg(filter)
+                .args(["-frames:v", "1"])
+                .arg(&output.path),
+        )
+        .await?;
+        Ok(output)
+    }
+}
+
+/// Reads a post's view counter off its embed on the channel's public page, which is
+/// where Telegram shows it to bots. `None` when the page has no counter.
+async fn views(client: &reqwest::Client, message_id: i32) -> Result<Option<i64>, Error> {
+    let page = client
+        .get(format!("{}?embed=1", catalog::permalink(message_id)))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(page
+        .split_once("tgme_widget_message_views\">")
+        .and_then(|(_, rest)| rest.split_once('<'))
+        .and_then(|(count, _)| parse_views(count)))
+}
+
+/// "815", "1.2K", "3M" as the embed prints them.
+fn parse_views(count: &str) -> Option<i64> {
+    let count = count.trim();
+    let (number, scale) = match count.char_indices().last()? {
+        (at, 'K') => (&count[..at], 1_000.0),
+        (at, 'M') => (&count[..at], 1_000_000.0),
+        _ => (count, 1.0),
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * scale).round() as i64)
+}
+
+/// The `TOP` tracks with the most views. A track whose counter cannot be read is
+/// left out rather than failing the whole recap.
+async fn most_viewed(posted: Vec<catalog::Track>) -> Vec<(catalog::Track, i64)> {
+    let client = reqwest::Client::new();
+    let mut viewed = Vec::new();
+    for track in posted {
+        match views(&client, track.message_id).await {
+            Ok(Some(views)) => viewed.push((track, views)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("No view count for post {}: {}", track.message_id, e),
+        }
+    }
+    viewed.sort_by_key(|(_, views)| std::cmp::Reverse(*views));
+    viewed.truncate(TOP as usize);
+    viewed
+}
+
+/// Posts the recap unless this year's already went out, and reports whether it did
+/// post. The year is claimed before sending so a second tap on Publish cannot post it
+/// twice, and released again if sending fails.
+async fn publish(secrets: &ServerSecretsState, recap: &Recap) -> Result<bool, Error> {
+    let claimed =
+        sqlx::query("INSERT INTO wrapped_posts (year) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(recap.year)
+            .execute(&secrets.db)
+            .await?
+            .rows_affected()
+            > 0;
+    if !claimed {
+        return Ok(false);
+    }
+    if let Err(e) = send(secrets, recap).await {
+        sqlx::query("DELETE FROM wrapped_posts WHERE year = $1")
+            .bind(recap.year)
+            .execute(&secrets.db)
+            .await?;
+        return Err(e);
+    }
+    Ok(true)
+}
+
+async fn send(secrets: &ServerSecretsState, recap: &Recap) -> Result<(), Error> {
+    let channel_id = secrets.publish_channel_id();
+    match recap.chart().await {
+        Ok(chart) => {
+            let photo = telegram::OutgoingPhoto {
+                path: chart.path.clone(),
+                caption: Some(recap.caption()),
+                reply_to: None,
+                silent: false,
+            };
+            let message = secrets
+                .retry_policy
+                .run(|| secrets.telegram.send_photo(channel_id, &photo))
+                .await?;
+            secrets
+                .last_message_id
+                .store(message.id.0, Ordering::Relaxed);
+        }
+        Err(e) => tracing::warn!("Year in review without its chart: {}", e),
+    }
+    let text = telegram::OutgoingText::markdown(recap.render()).without_link_preview();
+    let message = secrets
+        .retry_policy
+        .run(|| secrets.telegram.send_message(channel_id, &text))
+        .await?;
+    secrets
+        .last_message_id
+        .store(message.id.0, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Daily job that, on December 31, sums up the year from the catalog, view counts and
+/// subscriber counts and publishes it or asks for approval, depending on `mode`.
+/// Does nothing on other days or after a year without tracks.
+pub async fn run(
+    bot: &Bot,
+    secrets: &ServerSecretsState,
+    mode: Mode,
+    date: NaiveDate,
+) -> Result<(), Error> {
+    if (date.month(), date.day()) != (12, 31) {
+        return Ok(());
+    }
+    let recap = Recap::of(&secrets.db, date.year()).await?;
+    if recap.tracks == 0 {
+        tracing::info!("No tracks posted in {}, no year in review", recap.year);
+        return Ok(());
+    }
+
+    match mode {
+        Mode::Publish => {
+            if !publish(secrets, &recap).await? {
+                tracing::info!("The {} year in review is already out", recap.year);
+            }
+        }
+        Mode::Approve => {
+            match recap.chart().await {
+                Ok(chart) => {
+                    bot.send_photo(secrets.me_id, InputFile::file(chart.path.clone()))
+                        .caption(recap.caption())
+                        .parse_mode(ParseMode::MarkdownV2)
+                        .await?;
+                }
+                Err(e) => tracing::warn!("Year in review without its chart: {}", e),
+            }
+            let keyboard = InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback(
+                    "Publish",
+                    format!("{}publish:{}", CALLBACK_PREFIX, recap.year),
+                ),
+                InlineKeyboardButton::callback(
+                    "Skip",
+                    format!("{}skip:{}", CALLBACK_PREFIX, recap.year),
+                ),
+            ]]);
+            bot.send_message(secrets.me_id, recap.render())
+                .parse_mode(ParseMode::MarkdownV2)
+                .link_preview_options(commands::no_link_preview())
+                .reply_markup(keyboard)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Handles the publish/skip buttons of an approval request.
+pub async fn handle_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    data: &str,
+    secrets: &ServerSecretsState,
+) -> Result<(), Error> {
+    let (action, year) = data.split_once(':').ok_or("Malformed callback data")?;
+    let year: i32 = year.parse()?;
+
+    let status = match action {
+        "publish" => {
+            let already = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (SELECT 1 FROM wrapped_posts WHERE year = $1)",
+            )
+            .bind(year)
+            .fetch_one(&secrets.db)
+            .await?;
+            if !already && publish(secrets, &Recap::of(&secrets.db, year).await?).await? {
+                "Published ✅"
+            } else {
+                "Already published"
+            }
+        }
+        "skip" => "Skipped",
+        _ => return Err("Unknown year in review action".into()),
+    };
+
+    bot.answer_callback_query(query.id.clone())
+        .text(status)
+        .await?;
+    if let Some(message) = query.regular_message() {
+        bot.edit_message_reply_markup(message.chat.id, message.id)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_counts_read_as_the_embed_prints_them() {
+        assert_eq!(parse_views("815"), Some(815));
+        assert_eq!(parse_views("1.2K"), Some(1200));
+        assert_eq!(parse_views(" 3M "), Some(3_000_000));
+        assert_eq!(parse_views(""), None);
+        assert_eq!(parse_views("many"), None);
+    }
+}